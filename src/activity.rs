@@ -0,0 +1,128 @@
+use itertools::Itertools;
+use std::{
+    fmt::{self, Display},
+    time::{Duration, Instant},
+};
+
+const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const FRAME_TIME: Duration = Duration::from_millis(80);
+
+/// Work running off the main thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Task {
+    /// A background command by id
+    Command(usize),
+    Scratch,
+    ContentSearch,
+    /// The walk of a fuzzy finder by id
+    FindFiles(usize),
+    Move,
+    /// A command run for each of many files
+    Batch,
+}
+
+impl Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Task::Command(_) => "command",
+            Task::Scratch => "scratch command",
+            Task::ContentSearch => "content search",
+            Task::FindFiles(_) => "file search",
+            Task::Move => "move",
+            Task::Batch => "batch command",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Tasks in flight, shown with a spinner while there are any
+#[derive(Debug, Default)]
+pub struct Activity {
+    tasks: Vec<Task>,
+    /// When the spinner started turning, so its frames stay steady as tasks come and go
+    since: Option<Instant>,
+}
+
+impl Activity {
+    pub fn start(&mut self, task: Task, now: Instant) {
+        self.since.get_or_insert(now);
+        self.tasks.push(task);
+    }
+
+    /// Forget one run of `task`, if it was started at all
+    pub fn finish(&mut self, task: Task) {
+        if let Some(index) = self.tasks.iter().position(|running| *running == task) {
+            self.tasks.remove(index);
+        }
+        if self.tasks.is_empty() {
+            self.since = None;
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        !self.tasks.is_empty()
+    }
+
+    /// Time until the spinner should turn
+    pub fn next_timeout(&self, now: Instant) -> Option<Duration> {
+        let since = self.since?;
+        let elapsed = now.saturating_duration_since(since).as_millis() % FRAME_TIME.as_millis();
+        Some(FRAME_TIME - Duration::from_millis(elapsed as u64))
+    }
+
+    /// The spinner along with what is running, like `⠙ 2 commands, content search`
+    pub fn label(&self, now: Instant) -> Option<String> {
+        let since = self.since?;
+        let frame = now.saturating_duration_since(since).as_millis() / FRAME_TIME.as_millis();
+        let tasks = self
+            .tasks
+            .iter()
+            .map(ToString::to_string)
+            .sorted()
+            .dedup_with_count()
+            .map(|(count, name)| match count {
+                1 => name,
+                count => format!("{count} {name}s"),
+            })
+            .join(", ");
+        Some(format!("{} {tasks}", FRAMES[frame as usize % FRAMES.len()]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn spins_while_any_task_runs() {
+        let now = Instant::now();
+        let mut activity = Activity::default();
+        assert_eq!(None, activity.label(now));
+        assert_eq!(None, activity.next_timeout(now));
+
+        activity.start(Task::Command(0), now);
+        activity.start(Task::ContentSearch, now);
+        activity.start(Task::Command(1), now);
+        assert_eq!(
+            Some("⠋ 2 commands, content search".to_owned()),
+            activity.label(now)
+        );
+        assert_eq!(
+            Some("⠙ 2 commands, content search".to_owned()),
+            activity.label(now + FRAME_TIME)
+        );
+        assert_eq!(
+            Some(Duration::from_millis(70)),
+            activity.next_timeout(now + Duration::from_millis(10))
+        );
+
+        activity.finish(Task::Command(0));
+        activity.finish(Task::ContentSearch);
+        activity.finish(Task::Move);
+        assert_eq!(Some("⠋ command".to_owned()), activity.label(now));
+        activity.finish(Task::Command(1));
+        assert!(!activity.is_busy());
+        assert_eq!(None, activity.label(now));
+    }
+}
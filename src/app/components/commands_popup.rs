@@ -0,0 +1,247 @@
+use crate::{
+    app::component::{Component, Drawable},
+    config::{Config, Key},
+    external_event::ExternalEvent,
+    queue::{AppEvent, Queue},
+    ui,
+};
+use anyhow::Result;
+use crossterm::event::Event;
+use easy_switch::switch;
+use itertools::Itertools;
+use std::{cell::Cell, rc::Rc};
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState},
+    Frame,
+};
+
+/// A command running in the background
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunningCommand {
+    pub id: usize,
+    pub name: String,
+    pub pid: Option<u32>,
+}
+
+/// Lists the commands running in the background, so they can be killed one at a time
+pub struct CommandsPopup {
+    commands: Vec<RunningCommand>,
+    queue: Queue,
+    open: bool,
+    config: Rc<Config>,
+    state: Cell<ListState>,
+}
+
+impl Default for CommandsPopup {
+    fn default() -> Self {
+        Self::new(Queue::new(), Rc::new(Config::default()))
+    }
+}
+
+impl CommandsPopup {
+    pub fn new(queue: Queue, config: Rc<Config>) -> Self {
+        let mut state = ListState::default();
+        state.select(Some(0));
+        Self {
+            commands: Vec::new(),
+            queue,
+            config,
+            state: state.into(),
+            open: false,
+        }
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    pub fn close(&mut self) {
+        self.state.get_mut().select(Some(0));
+        self.open = false;
+    }
+
+    pub fn add(&mut self, command: RunningCommand) {
+        self.commands.push(command);
+    }
+
+    /// Forget a command once it has finished
+    pub fn remove(&mut self, id: usize) {
+        self.commands.retain(|command| command.id != id);
+        if self.selected() >= self.commands.len() {
+            self.select_last();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn name(&self, id: usize) -> Option<&str> {
+        self.commands
+            .iter()
+            .find(|command| command.id == id)
+            .map(|command| command.name.as_str())
+    }
+
+    fn selected(&self) -> usize {
+        let state = self.state.take();
+        let selected = state.selected().expect("should have something selected");
+        self.state.set(state);
+        selected
+    }
+
+    fn select_next(&mut self) {
+        let current = self.selected();
+        if current + 1 >= self.commands.len() {
+            return;
+        }
+        self.state.get_mut().select(Some(current + 1));
+    }
+
+    fn select_prev(&mut self) {
+        let current = self.selected();
+        if current == 0 {
+            return;
+        }
+        self.state.get_mut().select(Some(current - 1));
+    }
+
+    fn select_first(&mut self) {
+        self.state.get_mut().select(Some(0));
+    }
+
+    fn select_last(&mut self) {
+        self.state
+            .get_mut()
+            .select(Some(self.commands.len().saturating_sub(1)));
+    }
+}
+
+impl Drawable for CommandsPopup {
+    fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Result<()> {
+        if !self.visible() {
+            return Ok(());
+        }
+
+        let commands = self
+            .commands
+            .iter()
+            .map(|command| {
+                ListItem::new(command.pid.map_or_else(
+                    || command.name.clone(),
+                    |pid| format!("{} (pid {pid})", command.name),
+                ))
+            })
+            .collect_vec();
+        let list = List::new(commands)
+            .highlight_style(self.config.selected.into())
+            .highlight_symbol(&self.config.selected_symbol)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(self.config.popup_border_style.into())
+                    .title("Running commands"),
+            );
+        let area = ui::centered_rect_absolute(50, 15, area);
+        f.render_widget(Clear, area);
+        let mut state = self.state.take();
+        f.render_stateful_widget(list, area, &mut state);
+        self.state.set(state);
+
+        Ok(())
+    }
+}
+
+impl Component for CommandsPopup {
+    fn visible(&self) -> bool {
+        self.open
+    }
+
+    fn handle_event(&mut self, ev: &ExternalEvent) -> Result<()> {
+        if !self.visible() {
+            return Ok(());
+        }
+
+        if let ExternalEvent::Crossterm(Event::Key(key)) = ev {
+            switch! { key;
+                self.config.quit => self.close(),
+                Key::esc() => self.close(),
+                self.config.down => self.select_next(),
+                self.config.up => self.select_prev(),
+                self.config.all_up => self.select_first(),
+                self.config.all_down => self.select_last(),
+                self.config.kill_command => {
+                    // Will be `None` if nothing is running
+                    if let Some(command) = self.commands.get(self.selected()) {
+                        self.queue.add(AppEvent::StopCommand(command.id));
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::components::testing::*;
+    use test_log::test;
+
+    fn command(id: usize) -> RunningCommand {
+        RunningCommand {
+            id,
+            name: format!("sleep {id}"),
+            pid: None,
+        }
+    }
+
+    fn test_popup() -> CommandsPopup {
+        let mut popup = CommandsPopup::default();
+        popup.add(command(0));
+        popup.add(command(1));
+        popup.open();
+        popup
+    }
+
+    #[test]
+    fn kill_stops_selected_command() {
+        let mut popup = test_popup();
+        popup.select_next();
+        popup
+            .handle_event(&input_event!(KeyCode::Char('d')))
+            .unwrap();
+        assert_eq!(Some(AppEvent::StopCommand(1)), popup.queue.pop());
+    }
+
+    #[test]
+    fn removing_finished_command_keeps_selection_in_range() {
+        let mut popup = test_popup();
+        popup.select_last();
+        popup.remove(1);
+        assert_eq!(0, popup.selected());
+        assert_eq!(vec![command(0)], popup.commands);
+    }
+
+    #[test]
+    fn does_not_panic_with_nothing_running() {
+        let mut popup = test_popup();
+        popup.remove(0);
+        popup.remove(1);
+        assert!(popup.is_empty());
+        let events = input_events!(
+            KeyCode::Char('j'),
+            KeyCode::Char('k'),
+            KeyCode::Char('g'),
+            KeyCode::Char('G'); KeyModifiers::SHIFT,
+            KeyCode::Char('d')
+        );
+        for event in events {
+            assert!(popup.handle_event(&event).is_ok());
+        }
+        assert_eq!(None, popup.queue.pop());
+    }
+}
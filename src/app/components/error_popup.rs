@@ -0,0 +1,151 @@
+use crate::{
+    app::component::{Component, Drawable},
+    config::{Config, Key},
+    external_event::ExternalEvent,
+    queue::{AppEvent, Queue},
+    ui,
+};
+use anyhow::Result;
+use crossterm::event::Event;
+use easy_switch::switch;
+use std::{cell::Cell, collections::VecDeque, rc::Rc};
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+
+/// Shows errors in full, one at a time. Errors arriving while one is shown wait their turn.
+pub struct ErrorPopup {
+    errors: VecDeque<String>,
+    scroll_y: Cell<u16>,
+    queue: Queue,
+    config: Rc<Config>,
+}
+
+impl Default for ErrorPopup {
+    fn default() -> Self {
+        Self::new(Queue::new(), Rc::new(Config::default()))
+    }
+}
+
+impl ErrorPopup {
+    pub fn new(queue: Queue, config: Rc<Config>) -> Self {
+        Self {
+            errors: VecDeque::new(),
+            scroll_y: 0.into(),
+            queue,
+            config,
+        }
+    }
+
+    pub fn push(&mut self, error: String) {
+        self.errors.push_back(error);
+    }
+
+    /// Move on to the next error
+    fn close(&mut self) {
+        self.errors.pop_front();
+        self.scroll_y.set(0);
+    }
+}
+
+impl Drawable for ErrorPopup {
+    fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Result<()> {
+        let Some(error) = self.errors.front() else {
+            return Ok(());
+        };
+
+        let title = if self.errors.len() > 1 {
+            format!("Error (1/{})", self.errors.len())
+        } else {
+            "Error".to_owned()
+        };
+        let area = ui::centered_rect(70, 50, area);
+        // Scrolling is clamped by the number of lines, wrapped lines may still be cut off
+        let max_scroll = u16::try_from(error.lines().count())
+            .unwrap_or(u16::MAX)
+            .saturating_sub(area.height.saturating_sub(2));
+        let scroll = self.scroll_y.get().min(max_scroll);
+        self.scroll_y.set(scroll);
+        let paragraph = Paragraph::new(error.as_str())
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(title)
+                    .border_style(self.config.popup_border_style.into()),
+            )
+            .scroll((scroll, 0));
+
+        f.render_widget(Clear, area);
+        f.render_widget(paragraph, area);
+
+        Ok(())
+    }
+}
+
+impl Component for ErrorPopup {
+    fn visible(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    fn handle_event(&mut self, ev: &ExternalEvent) -> Result<()> {
+        let Some(error) = self.errors.front() else {
+            return Ok(());
+        };
+
+        if let ExternalEvent::Crossterm(Event::Key(key)) = ev {
+            switch! { key;
+                self.config.down => *self.scroll_y.get_mut() += 1,
+                self.config.up => *self.scroll_y.get_mut() = self.scroll_y.get().saturating_sub(1),
+                self.config.all_up => *self.scroll_y.get_mut() = 0,
+                self.config.all_down => *self.scroll_y.get_mut() = u16::MAX,
+                self.config.copy_error => self.queue.add(AppEvent::CopyText(error.clone())),
+                self.config.quit => self.close(),
+                Key::esc() => self.close(),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::components::testing::*;
+    use test_log::test;
+
+    #[test]
+    fn errors_wait_for_the_one_shown() {
+        let mut popup = ErrorPopup::default();
+        assert!(!popup.visible());
+        popup.push("first".to_owned());
+        popup.push("second".to_owned());
+
+        popup.handle_event(&input_event!(KeyCode::Esc)).unwrap();
+        assert_eq!(Some(&"second".to_owned()), popup.errors.front());
+        popup
+            .handle_event(&input_event!(KeyCode::Char('q')))
+            .unwrap();
+        assert!(!popup.visible());
+    }
+
+    #[test]
+    fn copies_shown_error() {
+        let mut popup = ErrorPopup::default();
+        popup.push("failed\n\nCaused by:\n    nope".to_owned());
+        popup
+            .handle_event(&input_event!(KeyCode::Char('y')))
+            .unwrap();
+        assert_eq!(
+            Some(AppEvent::CopyText(
+                "failed\n\nCaused by:\n    nope".to_owned()
+            )),
+            popup.queue.pop()
+        );
+        assert!(popup.visible());
+    }
+}
@@ -1,7 +1,9 @@
 use crate::{
     app::component::{Component, Drawable},
+    command::{self, RunOptions},
     config::{Config, Key},
     external_event::ExternalEvent,
+    last_commands::LastCommands,
     queue::{AppEvent, Queue},
     ui,
 };
@@ -10,7 +12,11 @@ use crossterm::event::Event;
 use easy_switch::switch;
 use globset::{Glob, GlobMatcher};
 use itertools::Itertools;
-use std::{cell::Cell, path::PathBuf, rc::Rc};
+use std::{
+    cell::Cell,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 use tui::{
     backend::Backend,
@@ -44,6 +50,9 @@ pub struct FileCmdPopup {
     registry: Vec<FileCommand>,
     queue: Queue,
     opened: Option<(FileCommand, PathBuf)>,
+    last: LastCommands,
+    /// Amount of commands that fit in the popup when last drawn
+    height: Cell<usize>,
     config: Rc<Config>,
 }
 
@@ -76,22 +85,74 @@ impl FileCmdPopup {
             registry,
             state: state.into(),
             opened: None,
+            last: LastCommands::default(),
+            height: 0.into(),
             config,
         }
     }
 
+    /// Remember chosen commands with `last`, instead of only until quitting
+    pub fn with_last_commands(mut self, last: LastCommands) -> Self {
+        self.last = last;
+        self
+    }
+
+    /// Open with the last command chosen for this kind of file selected
     pub fn open_for(&mut self, path: PathBuf) -> MatchState {
-        self.state.get_mut().select(Some(0));
-        let position = self
-            .registry
+        let Some(pos) = self.position_for(&path) else {
+            return MatchState::NotMatched;
+        };
+        let file_command = self.registry.remove(pos);
+        let last = self
+            .last
+            .get(file_command.pattern.glob().glob(), &file_command.commands);
+        self.state.get_mut().select(Some(last.unwrap_or_default()));
+        self.opened = Some((file_command, path));
+        MatchState::Matched
+    }
+
+    /// Run the last command chosen for this kind of file, opening the popup if there is none
+    pub fn run_last_for(&mut self, path: PathBuf) -> Result<MatchState> {
+        let Some(pos) = self.position_for(&path) else {
+            return Ok(MatchState::NotMatched);
+        };
+        let file_command = &self.registry[pos];
+        match self
+            .last
+            .get(file_command.pattern.glob().glob(), &file_command.commands)
+        {
+            Some(last) => {
+                let file_command = file_command.clone();
+                self.run(&file_command, last, &path)?;
+                Ok(MatchState::Matched)
+            }
+            None => Ok(self.open_for(path)),
+        }
+    }
+
+    fn position_for(&self, path: &Path) -> Option<usize> {
+        self.registry
             .iter()
-            .position(|file_command| file_command.pattern.is_match(&path));
-        if let Some(pos) = position {
-            self.opened = Some((self.registry.remove(pos), path));
-            MatchState::Matched
+            .position(|file_command| file_command.pattern.is_match(path))
+    }
+
+    fn run(&mut self, file_command: &FileCommand, index: usize, path: &Path) -> Result<()> {
+        let option = &file_command.commands[index];
+        let replaced = command::fill_path(option, path);
+        if replaced.contains("{...}") {
+            self.queue
+                .add(AppEvent::OpenInput(InputOperation::SpecialCommand(
+                    replaced,
+                    RunOptions::default(),
+                )));
         } else {
-            MatchState::NotMatched
+            self.queue.add(AppEvent::RunCommand(replaced));
         }
+        self.last.remember(
+            file_command.pattern.glob().glob(),
+            &file_command.commands,
+            index,
+        )
     }
 
     fn selected(&self) -> usize {
@@ -133,6 +194,19 @@ impl FileCmdPopup {
             .select(Some(opened.0.commands.len() - 1));
     }
 
+    fn page_down(&mut self) {
+        let Some(opened) = &self.opened else {
+            panic!("cannot call with no opened items")
+        };
+        let next = (self.selected() + self.height.get().max(1)).min(opened.0.commands.len() - 1);
+        self.state.get_mut().select(Some(next));
+    }
+
+    fn page_up(&mut self) {
+        let prev = self.selected().saturating_sub(self.height.get().max(1));
+        self.state.get_mut().select(Some(prev));
+    }
+
     fn close(&mut self) {
         let Some(opened) = self.opened.take() else {
             return;
@@ -160,23 +234,17 @@ impl Component for FileCmdPopup {
                 self.config.up => self.select_prev(),
                 self.config.all_up => self.select_first(),
                 self.config.all_down => self.select_last(),
+                self.config.page_down => self.page_down(),
+                self.config.page_up => self.page_up(),
                 self.config.quit => self.close(),
                 Key::esc() => self.close(),
                 self.config.open => {
                     let Some(opened) = self.opened.take() else {
                         unreachable!("checked at top of method");
                     };
-                    let option = &opened.0.commands[self.selected()];
-                    let replaced = option.replace("{}", &opened.1.display().to_string());
-                    if replaced.contains("{...}") {
-                        self.queue
-                            .add(AppEvent::OpenInput(InputOperation::SpecialCommand(
-                                replaced,
-                            )));
-                    } else {
-                        self.queue.add(AppEvent::RunCommand(replaced));
-                    }
+                    let ran = self.run(&opened.0, self.selected(), &opened.1);
                     self.registry.push(opened.0);
+                    ran?;
                 }
             }
         }
@@ -196,15 +264,28 @@ impl Drawable for FileCmdPopup {
             .iter()
             .map(|command| ListItem::new(command.as_str()))
             .collect_vec();
+        let area = ui::centered_rect_absolute(50, 10, area);
+        let height = usize::from(area.height.saturating_sub(2));
+        self.height.set(height);
+        // The list keeps the selection in view, this only shows where it is
+        let title = if commands.len() > height {
+            format!(
+                "Special Commands ({}/{})",
+                self.selected() + 1,
+                commands.len()
+            )
+        } else {
+            "Special Commands".to_owned()
+        };
         let list = List::new(commands)
             .highlight_style(self.config.selected.into())
+            .highlight_symbol(&self.config.selected_symbol)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
                     .border_style(self.config.popup_border_style.into())
-                    .title("Special Commands"),
+                    .title(title),
             );
-        let area = ui::centered_rect_absolute(50, 10, area);
         f.render_widget(Clear, area);
         let mut state = self.state.take();
         f.render_stateful_widget(list, area, &mut state);
@@ -325,9 +406,64 @@ mod tests {
         let mut popup = test_popup();
         let event = input_event!(KeyCode::Enter);
         popup.handle_event(&event).unwrap();
-        assert!(popup
-            .queue
-            .contains(&AppEvent::RunCommand("command test.txt".to_owned())));
+        assert!(popup.queue.contains(&AppEvent::RunCommand(format!(
+            "command {}",
+            command::quote("test.txt")
+        ))));
+    }
+
+    #[test]
+    fn pages_through_long_lists() {
+        let config = Config {
+            special_commands: collect![_:
+                ("*".to_owned(), (0..20).map(|i| format!("command{i}")).collect())
+            ],
+            ..Default::default()
+        };
+        let mut popup = FileCmdPopup::new(Queue::new(), config.into());
+        popup.open_for("test.txt".into());
+        popup.height.set(8);
+
+        let page_down = input_event!(KeyCode::Char('d'); KeyModifiers::CONTROL);
+        popup.handle_event(&page_down).unwrap();
+        assert_eq!(8, popup.selected());
+        popup.handle_event(&page_down).unwrap();
+        popup.handle_event(&page_down).unwrap();
+        assert_eq!(19, popup.selected());
+        popup
+            .handle_event(&input_event!(KeyCode::Char('u'); KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(11, popup.selected());
+    }
+
+    #[test]
+    fn reopening_selects_last_command() {
+        let mut popup = test_popup();
+        popup.select_last();
+        popup.handle_event(&input_event!(KeyCode::Enter)).unwrap();
+        popup.queue.pop();
+
+        popup.open_for("other.txt".into());
+        assert_eq!(2, popup.selected());
+        popup.close();
+        assert_eq!(
+            MatchState::Matched,
+            popup.run_last_for("other.txt".into()).unwrap()
+        );
+        assert!(!popup.visible());
+        assert_eq!(
+            Some(AppEvent::RunCommand("command3".to_owned())),
+            popup.queue.pop()
+        );
+    }
+
+    #[test]
+    fn running_last_without_one_opens_popup() {
+        let mut popup = test_popup();
+        popup.close();
+        popup.run_last_for("test.txt".into()).unwrap();
+        assert!(popup.visible());
+        assert_eq!(0, popup.selected());
     }
 
     #[test]
@@ -339,7 +475,8 @@ mod tests {
         assert!(popup
             .queue
             .contains(&AppEvent::OpenInput(InputOperation::SpecialCommand(
-                "command2 test.txt {...}".to_owned()
+                format!("command2 {} {{...}}", command::quote("test.txt")),
+                RunOptions::default()
             ))));
     }
 }
@@ -1,42 +1,77 @@
 use crate::{
-    app::{component::*, FuzzyOperation, InputOperation, PendingOperation},
-    config::Config,
-    external_event::{ExternalEvent, RefreshData},
-    filelisting::{FileListing, Item},
+    app::{component::*, FuzzyOperation, GitBranch, InputOperation, PendingOperation},
+    command::{self, RunOptions},
+    config::{Config, EnterAction, Key, TimeFormat},
+    external_event::{ExternalEvent, RefreshData, WalkFilters},
+    filelisting::{FileListing, Item, SortOrder},
     marks::Marks,
-    queue::{AppEvent, Queue},
+    queue::{AppEvent, NamePart, Queue, TmuxCommand},
+    search::{self, ContentMatch},
+    ui,
 };
 use anyhow::{bail, Context, Result};
-use crossterm::event::Event;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind};
 use easy_switch::switch;
 use git2::{Repository, Status};
+use globset::{Glob, GlobSetBuilder};
 use ignore::{
     overrides::{Override, OverrideBuilder},
-    Walk, WalkBuilder,
+    Walk,
 };
 use itertools::Itertools;
 use log::{debug, info, warn};
 use std::{
     cell::{Cell, RefCell},
-    collections::HashMap,
-    iter,
-    path::{Path, PathBuf},
+    collections::{BTreeSet, HashMap, HashSet},
+    fs, iter,
+    ops::Range,
+    path::{Path, PathBuf, MAIN_SEPARATOR},
     rc::Rc,
+    time::{Duration, Instant, SystemTime},
 };
 use tui::{
     backend::Backend,
     layout::Rect,
     style::Style,
+    text::{Span, Spans},
     widgets::{Block, Borders, List, ListItem, ListState},
     Frame,
 };
 
+/// Most directories kept in the list of recently visited ones
+const MAX_RECENT_DIRS: usize = 50;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum HiddenVisibility {
     Visible,
     Hidden,
 }
 
+/// Where the selection is scrolled to in the view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ViewPosition {
+    Top,
+    Center,
+    Bottom,
+}
+
+/// Inline filter that narrows the tree while typing
+struct QuickFilter {
+    query: String,
+    /// The full listing, restored once the filter ends
+    listing: FileListing,
+}
+
+/// What narrows the tree down, kept to narrow it again once the tree is read from disk
+enum ViewFilter {
+    /// Items and everything under them
+    Include(Vec<PathBuf>),
+    /// Files with these extensions, looked up again so new ones show up
+    Extensions(String),
+    /// Files and the directories leading to them
+    Only(HashSet<PathBuf>),
+}
+
 pub struct Filetree {
     is_focused: bool,
     listing: FileListing,
@@ -44,10 +79,51 @@ pub struct Filetree {
     queue: Queue,
     repo: Option<Repository>,
     status_cache: Option<HashMap<PathBuf, Status>>,
+    /// Read along with the status cache
+    branch: Option<GitBranch>,
     config: Rc<Config>,
     state: Cell<ListState>,
+    /// Index of the first item in view
+    offset: Cell<usize>,
+    /// Amount of items that fit in view when last drawn
+    height: Cell<usize>,
+    /// Where the tree was last drawn, to find the row that was clicked
+    area: Cell<Rect>,
+    /// Drawn with a thick border, as the pane keys go to
+    pub highlight: Cell<bool>,
+    /// Item and time of the last click, to tell double clicks apart
+    last_click: Option<(usize, Instant)>,
     marks: Rc<RefCell<Marks>>,
     is_showing_hidden: bool,
+    /// Whether ignore files are left unread, to show what they leave out
+    is_showing_ignored: bool,
+    /// Start out as configured, can be toggled at runtime
+    dirs_first: bool,
+    reverse: bool,
+    quick_filter: Option<QuickFilter>,
+    /// The tree behind the list of every file, while that is shown instead
+    flat: Option<FileListing>,
+    /// Query of the content search the tree is filtered by, used to open files at the match
+    search_query: Option<String>,
+    /// Matching lines found by the content search in each file, summed up for the directories
+    /// holding them
+    match_counts: HashMap<PathBuf, usize>,
+    /// Directories opened by `expand_matching`, closed again when clearing
+    expanded: Vec<PathBuf>,
+    /// Set while only some of the files are listed, by a filter or a search
+    view_filter: Option<ViewFilter>,
+    /// Items picked out for commands using `{paths}`
+    multi_selection: BTreeSet<PathBuf>,
+    /// Extensions filtered by before, newest first
+    recent_extensions: Vec<String>,
+    /// Directories navigated into, newest first
+    recent_dirs: Vec<PathBuf>,
+    /// Directories seen in the last walk of the tree, to tell empty ones apart from files
+    walked_dirs: RefCell<HashSet<PathBuf>>,
+    /// Directories the last walk failed to read
+    unreadable: RefCell<HashSet<PathBuf>>,
+    /// Directories the last walk did not go into, because they are at `max_depth`
+    too_deep: RefCell<HashSet<PathBuf>>,
 }
 
 impl Filetree {
@@ -58,6 +134,7 @@ impl Filetree {
             queue: queue.clone(),
             repo: Repository::open(path.as_ref().join(".git")).ok(),
             status_cache: None,
+            branch: None,
             config: Rc::new(Config::default()),
             marks: Default::default(),
             listing: FileListing::new(
@@ -67,7 +144,27 @@ impl Filetree {
                     .collect_vec(),
             ),
             state: ListState::default().into(),
+            offset: 0.into(),
+            height: 0.into(),
+            area: Rect::default().into(),
+            highlight: Cell::new(false),
+            last_click: None,
             is_showing_hidden: false,
+            is_showing_ignored: false,
+            dirs_first: SortOrder::default().dirs_first,
+            reverse: false,
+            quick_filter: None,
+            flat: None,
+            search_query: None,
+            match_counts: HashMap::new(),
+            expanded: Vec::new(),
+            view_filter: None,
+            multi_selection: BTreeSet::new(),
+            recent_extensions: Vec::new(),
+            recent_dirs: Vec::new(),
+            walked_dirs: RefCell::default(),
+            unreadable: RefCell::default(),
+            too_deep: RefCell::default(),
         };
         tree.populate_status_cache();
         if let Some(item) = tree.get_selected() {
@@ -84,50 +181,164 @@ impl Filetree {
         config: Rc<Config>,
         marks: Rc<RefCell<Marks>>,
     ) -> Result<Self> {
-        let overrides = build_override_ignorer(&path, &config.filetree.ignore)?;
-        let mut listing = FileListing::new(
-            &WalkBuilder::new(path.as_ref())
-                .overrides(overrides)
-                .hidden(!config.filetree.show_hidden_by_default)
-                .build()
-                .filter_map(|entry| entry.ok().map(|entry| entry.into_path()))
-                .filter(|entry_path| entry_path != path.as_ref()) // Ignore root
-                .collect_vec(),
-        );
-        listing.fold_all();
-
-        Ok(Filetree {
+        let mut tree = Filetree {
             repo: if config.filetree.use_git {
                 Repository::open(path.as_ref().join(".git")).ok()
             } else {
                 None
             },
-            listing,
+            dirs_first: config.filetree.dirs_first,
+            reverse: config.filetree.reverse,
             config: Rc::clone(&config),
             marks,
             ..Self::from_dir(path, queue)?
-        })
+        };
+        tree.branch = tree.repo.as_ref().and_then(GitBranch::read);
+        let visibility = if config.filetree.show_hidden_by_default {
+            HiddenVisibility::Visible
+        } else {
+            HiddenVisibility::Hidden
+        };
+        let items = tree.build_walkbuilder(visibility)?.collect_vec();
+        tree.listing = FileListing::with_order(&items, tree.order());
+        tree.listing.fold_all();
+
+        Ok(tree)
     }
 
     pub fn refresh(&mut self) -> Result<()> {
-        let mut listing = FileListing::new(
+        self.quick_filter = None;
+        self.search_query = None;
+        self.match_counts.clear();
+        self.expanded.clear();
+        let mut listing = FileListing::with_order(
             &self
                 .build_walkbuilder(HiddenVisibility::Hidden)?
                 .collect_vec(),
+            self.order(),
         );
         listing.fold_all();
-        self.listing = listing;
+        self.set_listing(listing);
+        self.populate_status_cache();
+
+        Ok(())
+    }
+
+    /// Rebuild the tree from disk, keeping opened directories and the selection when their paths
+    /// still exist. Filters, and the quick filter being typed, narrow the new tree down again.
+    pub fn full_refresh(&mut self) -> Result<()> {
+        let selected = self.get_selected().map(|item| item.path().to_path_buf());
+        let unfolded = self.open_dirs();
+        let query = self.quick_filter.take().map(|filter| {
+            self.listing = filter.listing;
+            filter.query
+        });
+
+        if let Some(filter) = self.view_filter.take() {
+            self.apply_view_filter(filter)?;
+        } else {
+            let mut listing = FileListing::with_order(
+                &self
+                    .build_walkbuilder(self.hidden_visibility())?
+                    .collect_vec(),
+                self.order(),
+            );
+            listing.fold_all();
+            self.set_listing(listing);
+        }
+        // Results of a content search only make sense while the tree is narrowed down to them
+        if !matches!(self.view_filter, Some(ViewFilter::Only(_))) {
+            self.search_query = None;
+            self.match_counts.clear();
+        }
+        for dir in &unfolded {
+            self.listing.unfold(dir.as_path());
+        }
         self.populate_status_cache();
+        if let Some(query) = query {
+            self.start_quick_filter();
+            if let Some(filter) = &mut self.quick_filter {
+                filter.query = query;
+            }
+            self.apply_quick_filter();
+        }
+        if let Some(selected) = selected.filter(|path| {
+            self.listing
+                .all_items()
+                .iter()
+                .any(|item| item.path() == path)
+        }) {
+            self.listing.select(selected.as_path());
+        }
+        self.sync_selected();
+        info!("reloaded filetree from disk");
 
         Ok(())
     }
 
+    /// Directories opened in the tree, including the one behind a filter or the flat list
+    pub fn open_dirs(&self) -> Vec<PathBuf> {
+        let tree = self
+            .flat
+            .as_ref()
+            .or_else(|| self.quick_filter.as_ref().map(|filter| &filter.listing))
+            .unwrap_or(&self.listing);
+        tree.all_items()
+            .iter()
+            .filter(|item| !item.is_file() && tree.is_folded(item.path()) == Some(false))
+            .map(|item| item.path().to_path_buf())
+            .collect_vec()
+    }
+
+    /// Open `dirs` and select `selected` again, skipping whichever are no longer in the tree
+    pub fn restore(&mut self, dirs: &[PathBuf], selected: Option<&Path>) {
+        for dir in dirs {
+            self.listing.unfold(dir.as_path());
+        }
+        if let Some(selected) = selected {
+            if self.listing.select(selected).is_some() {
+                self.queue
+                    .add(AppEvent::PreviewFile(selected.to_path_buf()));
+            }
+        }
+        self.sync_selected();
+    }
+
     pub fn partial_refresh(&mut self, refresh_data: &RefreshData) -> Result<()> {
+        // Refresh the full tree behind the filter, then filter it again
+        if let Some(filter) = self.quick_filter.take() {
+            let selected = self.get_selected().map(|item| item.path().to_path_buf());
+            self.listing = filter.listing;
+            let result = self.partial_refresh(refresh_data);
+            self.quick_filter = Some(QuickFilter {
+                query: filter.query,
+                listing: std::mem::take(&mut self.listing),
+            });
+            self.apply_quick_filter();
+            if let Some(selected) = selected.filter(|path| {
+                self.listing
+                    .all_items()
+                    .iter()
+                    .any(|item| item.path() == path)
+            }) {
+                self.listing.select(selected.as_path());
+            }
+            self.sync_selected();
+            return result;
+        }
+        if self.flat.is_some() {
+            return self.change_tree(|tree| tree.partial_refresh(refresh_data));
+        }
+
         match refresh_data {
             RefreshData::Delete(path) => {
+                let was_selected = self
+                    .get_selected()
+                    .is_some_and(|item| item.path().starts_with(path));
                 self.listing.remove(path.as_path())?;
-                if self.get_selected().is_some_and(|item| item.path() == path) {
-                    self.queue.add(AppEvent::PreviewFile(path.clone()));
+                if let Some(item) = self.get_selected().filter(|_| was_selected) {
+                    self.queue
+                        .add(AppEvent::PreviewFile(item.path().to_path_buf()));
                 }
             }
             RefreshData::Add(path) => {
@@ -145,22 +356,39 @@ impl Filetree {
                 }
             }
         }
+        self.sync_selected();
 
         Ok(())
     }
 
+    /// Move an item into the directory `new`, which is opened to show the selection if it moved
     pub fn move_item(&mut self, old: impl AsRef<Path>, new: impl AsRef<Path>) -> Result<()> {
+        if self.flat.is_some() {
+            return self.change_tree(|tree| tree.move_item(old, new));
+        }
         self.listing
             .mv(old.as_ref(), &new)
             .context("error moving item")?;
         self.populate_status_cache();
-        self.open_path(new)?;
+        if let Some(selected) = self
+            .get_selected()
+            .map(|item| item.path().to_path_buf())
+            .filter(|path| path.starts_with(new.as_ref()))
+        {
+            self.open_path(selected)?;
+        } else {
+            self.sync_selected();
+        }
         Ok(())
     }
 
     pub fn rename(&mut self, old: impl AsRef<Path>, new: impl AsRef<Path>) -> Result<()> {
+        if self.flat.is_some() {
+            return self.change_tree(|tree| tree.rename(old, new));
+        }
         self.listing.rename(old.as_ref(), new.as_ref())?;
         self.populate_status_cache();
+        self.sync_selected();
         Ok(())
     }
 
@@ -177,10 +405,154 @@ impl Filetree {
         self.queue
             .add(AppEvent::PreviewFile(path.as_ref().to_path_buf()));
         self.sync_selected();
+        let dir = if path.as_ref().is_dir() {
+            Some(path.as_ref())
+        } else {
+            path.as_ref().parent()
+        };
+        if let Some(dir) = dir {
+            self.visit_dir(dir);
+        }
 
         Ok(())
     }
 
+    /// Do what `open` is configured to do for the kind of `item`, the selected one
+    fn enter(&mut self, item: &Item) {
+        let config = Rc::clone(&self.config);
+        let action = match item {
+            Item::Dir(_) => &config.filetree.dir_enter_action,
+            Item::File(_) => &config.filetree.file_enter_action,
+        };
+        match (action, item) {
+            (EnterAction::Toggle, Item::Dir(_)) => self.toggle_selected_dir(),
+            (EnterAction::Toggle | EnterAction::Open, Item::File(file)) => {
+                // A multi-selection of files opens together
+                let files = self
+                    .multi_selection()
+                    .into_iter()
+                    .filter(|path| path.is_file())
+                    .collect_vec();
+                if files.len() > 1 {
+                    self.queue.add(AppEvent::OpenFiles(files));
+                    return;
+                }
+                let found = self.search_query.as_ref().and_then(|query| {
+                    search::first_match(file, query, self.config.preview.max_size)
+                });
+                self.queue.add(found.map_or_else(
+                    || AppEvent::OpenFile(file.clone()),
+                    |(line, column)| AppEvent::OpenFileAt(file.clone(), line, Some(column)),
+                ));
+            }
+            (EnterAction::Open, Item::Dir(dir)) => self.queue.add(AppEvent::OpenFile(dir.clone())),
+            (EnterAction::Reroot, Item::Dir(dir)) => {
+                self.queue.add(AppEvent::Reroot(dir.clone(), dir.clone()));
+            }
+            (EnterAction::Reroot, Item::File(file)) => {
+                if let Some(parent) = file.parent() {
+                    self.queue
+                        .add(AppEvent::Reroot(parent.to_path_buf(), file.clone()));
+                }
+            }
+            (EnterAction::Run(cmd), item) => self
+                .queue
+                .add(AppEvent::RunCommand(command::fill_path(cmd, item.path()))),
+        }
+    }
+
+    /// Open or close the selected directory, remembering it when it opens
+    fn toggle_selected_dir(&mut self) {
+        self.listing.toggle_fold();
+        let Some(selected) = self.listing.selected() else {
+            return;
+        };
+        if self.listing.is_folded(selected) == Some(false) {
+            if let Some(item) = self.listing.selected_item() {
+                let dir = item.path().to_path_buf();
+                self.visit_dir(&dir);
+            }
+        }
+    }
+
+    fn visit_dir(&mut self, dir: &Path) {
+        if dir == self.root_path || !dir.starts_with(&self.root_path) {
+            return;
+        }
+        self.recent_dirs.retain(|recent| recent != dir);
+        self.recent_dirs.insert(0, dir.to_path_buf());
+        self.recent_dirs.truncate(MAX_RECENT_DIRS);
+    }
+
+    /// Directories navigated into, newest first
+    pub fn recent_dirs(&self) -> &[PathBuf] {
+        &self.recent_dirs
+    }
+
+    pub fn set_recent_dirs(&mut self, dirs: Vec<PathBuf>) {
+        self.recent_dirs = dirs;
+        self.recent_dirs.truncate(MAX_RECENT_DIRS);
+    }
+
+    /// Pick one of the recent directories still in the tree to go to
+    fn open_recent_dirs(&self) {
+        // Ties are listed in reverse by the fuzzy finder, so the newest ends up next to the
+        // input and selected
+        let dirs: Vec<String> = self
+            .recent_dirs
+            .iter()
+            .rev()
+            .filter(|dir| dir.is_dir())
+            .filter_map(|dir| dir.strip_prefix(&self.root_path).ok())
+            .map(|dir| dir.display().to_string())
+            .collect();
+        if dirs.is_empty() {
+            info!("no recently visited directories");
+            return;
+        }
+        self.queue
+            .add(AppEvent::OpenFuzzy(dirs, FuzzyOperation::OpenFile));
+    }
+
+    /// Whether a position is inside the border of the tree when it was last drawn
+    fn is_under(&self, column: u16, row: u16) -> bool {
+        let area = self.area.get();
+        column > area.x && column + 1 < area.right() && row > area.y && row + 1 < area.bottom()
+    }
+
+    /// Select the item drawn at a position. Directories open or close, and files open on a double
+    /// click.
+    fn click(&mut self, column: u16, row: u16) {
+        const DOUBLE_CLICK: Duration = Duration::from_millis(500);
+
+        if !self.is_under(column, row) {
+            return;
+        }
+        let index = self.offset.get() + usize::from(row - self.area.get().y - 1);
+        if index >= self.listing.len() {
+            return;
+        }
+        let now = Instant::now();
+        let double_click = self
+            .last_click
+            .replace((index, now))
+            .is_some_and(|(last, at)| last == index && now - at < DOUBLE_CLICK);
+
+        self.listing.select(index);
+        match self.get_selected() {
+            Some(Item::Dir(_)) => self.toggle_selected_dir(),
+            Some(Item::File(file)) if double_click => {
+                self.queue.add(AppEvent::OpenFile(file.clone()));
+                self.last_click = None;
+            }
+            _ => {}
+        }
+        if let Some(item) = self.get_selected() {
+            self.queue
+                .add(AppEvent::PreviewFile(item.path().to_owned()));
+        }
+    }
+
     pub fn open_all(&mut self) {
         self.listing.unfold_all();
     }
@@ -201,7 +573,169 @@ impl Filetree {
             .map(|selected| self.listing.fold_under(selected));
     }
 
+    /// Open every directory whose path under the root matches `pattern`, along with its parents.
+    /// Returns how many directories were opened.
+    pub fn expand_matching(&mut self, pattern: &str) -> Result<usize> {
+        let glob = Glob::new(&format!("**/{pattern}"))
+            .with_context(|| format!("invalid glob \"{pattern}\""))?
+            .compile_matcher();
+        let to_open: HashSet<&Path> = self
+            .listing
+            .all_items()
+            .iter()
+            .filter(|item| !item.is_file())
+            .map(Item::path)
+            .filter(|path| {
+                path.strip_prefix(&self.root_path)
+                    .is_ok_and(|relative| glob.is_match(relative))
+            })
+            .flat_map(|path| {
+                path.ancestors()
+                    .take_while(|ancestor| *ancestor != self.root_path.as_path())
+            })
+            .collect();
+        // Parents come before their children, so each directory is visible by the time it is opened
+        let to_open = self
+            .listing
+            .all_items()
+            .iter()
+            .map(Item::path)
+            .filter(|path| to_open.contains(path))
+            .map(Path::to_path_buf)
+            .collect_vec();
+        let mut opened = 0;
+        for dir in to_open {
+            if self.listing.is_folded(dir.as_path()) == Some(true) {
+                self.listing.unfold(dir.as_path());
+                self.expanded.push(dir);
+                opened += 1;
+            }
+        }
+        self.sync_selected();
+        Ok(opened)
+    }
+
+    /// Close the directories opened by `expand_matching`. Returns false if there were none.
+    fn collapse_expanded(&mut self) -> bool {
+        if self.expanded.is_empty() {
+            return false;
+        }
+        // Children first, closing a parent hides them
+        for dir in std::mem::take(&mut self.expanded).into_iter().rev() {
+            self.listing.fold(dir.as_path());
+        }
+        self.sync_selected();
+        true
+    }
+
+    /// Whether clearing would do more than read the tree again
+    pub fn has_something_to_clear(&self) -> bool {
+        !self.multi_selection.is_empty() || !self.expanded.is_empty() || self.view_filter.is_some()
+    }
+
+    /// Empty the multi-selection, or else close what `expand_matching` opened, or else read the
+    /// tree again without filters
+    fn clear(&mut self) -> Result<()> {
+        if !self.multi_selection.is_empty() {
+            self.multi_selection.clear();
+            return Ok(());
+        }
+        if self.collapse_expanded() {
+            return Ok(());
+        }
+        info!("refreshed filetree");
+        let selected = self.get_selected().map(|item| item.path().to_path_buf());
+        self.refresh().context("problem refreshing filetree")?;
+        // Return to the item that was selected while filtering
+        if let Some(selected) = selected {
+            self.open_path(selected)?;
+        }
+        Ok(())
+    }
+
+    /// Add the selected item to the multi-selection, or take it out if it is already there
+    pub fn toggle_multi_select(&mut self) {
+        let Some(selected) = self.get_selected().map(|item| item.path().to_path_buf()) else {
+            return;
+        };
+        if !self.multi_selection.remove(&selected) {
+            self.multi_selection.insert(selected);
+        }
+    }
+
+    /// Unpick every item of the multi-selection
+    pub fn clear_multi_selection(&mut self) {
+        self.multi_selection.clear();
+    }
+
+    /// Every multi-selected item that still exists, or the selected item if there are none
+    pub fn multi_selection(&self) -> Vec<PathBuf> {
+        let selection = self
+            .multi_selection
+            .iter()
+            .filter(|path| path.exists())
+            .cloned()
+            .collect_vec();
+        if selection.is_empty() {
+            self.get_selected()
+                .map(|item| item.path().to_path_buf())
+                .into_iter()
+                .collect()
+        } else {
+            selection
+        }
+    }
+
+    fn copy_name_part(&self, part: NamePart) {
+        if let Some(selected) = self.get_selected() {
+            self.queue
+                .add(AppEvent::CopyNamePart(selected.path().to_path_buf(), part));
+        }
+    }
+
+    /// Select the next (or previous) directory with the same parent as the selection, wrapping
+    /// around at the ends. Files start from their parent directory.
+    pub fn select_sibling_dir(&mut self, forward: bool) {
+        let Some(selected) = self.get_selected() else {
+            return;
+        };
+        let items = self.listing.items();
+        let anchor = match selected {
+            Item::File(file) => file
+                .parent()
+                .filter(|parent| items.iter().any(|item| item.path() == *parent))
+                .unwrap_or(file),
+            Item::Dir(dir) => dir,
+        };
+        let Some(anchor_index) = items.iter().position(|item| item.path() == anchor) else {
+            return;
+        };
+        let siblings = items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| !item.is_file() && item.path().parent() == anchor.parent())
+            .map(|(index, _)| index)
+            .collect_vec();
+        let target = if forward {
+            siblings
+                .iter()
+                .find(|index| **index > anchor_index)
+                .or_else(|| siblings.first())
+        } else {
+            siblings
+                .iter()
+                .rev()
+                .find(|index| **index < anchor_index)
+                .or_else(|| siblings.last())
+        };
+        if let Some(&target) = target {
+            self.listing.select(target);
+        }
+    }
+
     pub fn filter_include(&mut self, items: &[PathBuf]) -> Result<()> {
+        let selected = self.get_selected().map(|item| item.path().to_path_buf());
+        let items_to_keep = items.to_vec();
         let items = self
             .build_walkbuilder(HiddenVisibility::Hidden)?
             .filter(|entry_path| {
@@ -211,729 +745,2590 @@ impl Filetree {
             })
             .collect_vec();
 
-        self.listing = FileListing::new(&items);
+        self.set_listing(FileListing::with_order(&items, self.order()));
+        self.view_filter = Some(ViewFilter::Include(items_to_keep));
+        self.search_query = None;
+        self.match_counts.clear();
+        self.expanded.clear();
+        // Everything left is a match
+        self.select_filtered(selected, |_| true);
 
         Ok(())
     }
 
-    pub fn toggle_dotfiles(&mut self) -> Result<()> {
-        let items = self
-            .build_walkbuilder(if self.is_showing_hidden {
-                HiddenVisibility::Hidden
-            } else {
-                HiddenVisibility::Visible
-            })?
-            .collect_vec();
-        self.is_showing_hidden = !self.is_showing_hidden;
+    /// Only show the files with one of the extensions in `input`, as in `rs, toml` or `*.rs`.
+    /// Returns the amount of files shown.
+    pub fn filter_extensions(&mut self, input: &str) -> Result<usize> {
+        let (input, files) = self.files_with_extensions(input)?;
+        self.filter_include(&files)?;
+        self.view_filter = Some(ViewFilter::Extensions(input.clone()));
+
+        self.recent_extensions.retain(|recent| *recent != input);
+        self.recent_extensions.insert(0, input);
+        self.recent_extensions.truncate(10);
+        Ok(files.len())
+    }
 
-        self.listing = FileListing::new(&items);
-        self.listing.fold_all();
+    /// The extensions in `input` in the form they are listed in, with the files having them
+    fn files_with_extensions(&self, input: &str) -> Result<(String, Vec<PathBuf>)> {
+        let extensions = input
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .map(|extension| extension.trim_start_matches('*').trim_start_matches('.'))
+            .filter(|extension| !extension.is_empty())
+            .collect_vec();
+        if extensions.is_empty() {
+            bail!("no extensions in \"{input}\"");
+        }
+        let mut builder = GlobSetBuilder::new();
+        for extension in &extensions {
+            builder.add(
+                Glob::new(&format!("**/*.{extension}"))
+                    .with_context(|| format!("invalid extension \"{extension}\""))?,
+            );
+        }
+        let globs = builder.build()?;
+        let files = self
+            .build_walkbuilder(self.hidden_visibility())?
+            .filter(|path| {
+                path.is_file()
+                    && path
+                        .strip_prefix(&self.root_path)
+                        .is_ok_and(|relative| globs.is_match(relative))
+            })
+            .collect_vec();
+        Ok((extensions.join(", "), files))
+    }
 
-        info!("toggling visibility of dotfiles");
+    fn apply_view_filter(&mut self, filter: ViewFilter) -> Result<()> {
+        match filter {
+            ViewFilter::Include(items) => self.filter_include(&items),
+            ViewFilter::Extensions(input) => {
+                let (input, files) = self.files_with_extensions(&input)?;
+                self.filter_include(&files)?;
+                self.view_filter = Some(ViewFilter::Extensions(input));
+                Ok(())
+            }
+            ViewFilter::Only(files) => self.show_files(files),
+        }
+    }
 
+    /// Show the first results of a content search for `query`, opened files jump to the first
+    /// match
+    pub fn show_search_results(&mut self, query: &str, files: &[PathBuf]) -> Result<()> {
+        self.show_only(files)?;
+        self.search_query = Some(query.to_owned());
         Ok(())
     }
 
-    fn populate_status_cache(&mut self) {
-        self.status_cache = self.repo.as_ref().and_then(|repo| {
-            repo.statuses(None).ok().map(|statuses| {
-                statuses
-                    .iter()
-                    .map(|status| (self.root_path.join(status.path().unwrap()), status.status()))
-                    .collect::<HashMap<PathBuf, Status>>()
-            })
-        });
+    /// Add more results of the content search being shown, without reading the tree again. Stays
+    /// on the item picked while the rest are found.
+    pub fn add_search_results(&mut self, files: &[PathBuf]) {
+        if self.search_query.is_none() {
+            return;
+        }
+        let Some(ViewFilter::Only(mut shown)) = self.view_filter.take() else {
+            return;
+        };
+        let selected = self.get_selected().map(|item| item.path().to_path_buf());
+        let tree = self.flat.as_ref().unwrap_or(&self.listing);
+        let folded = tree
+            .all_items()
+            .iter()
+            .filter(|item| !item.is_file() && tree.is_folded(item.path()) == Some(true))
+            .map(|item| item.path().to_path_buf())
+            .collect_vec();
+        let mut items: HashSet<PathBuf> = tree
+            .all_items()
+            .iter()
+            .map(|item| item.path().to_path_buf())
+            .collect();
+        for file in files {
+            items.extend(
+                file.ancestors()
+                    .take_while(|path| *path != self.root_path.as_path())
+                    .map(Path::to_path_buf),
+            );
+        }
+        shown.extend(files.iter().cloned());
+        // Directories have to come before what is in them
+        let items = items.into_iter().sorted().collect_vec();
+        let mut listing = FileListing::with_order(&items, self.order());
+        for dir in &folded {
+            listing.fold(dir.as_path());
+        }
+        self.set_listing(listing);
+        self.view_filter = Some(ViewFilter::Only(shown));
+        if let Some(selected) = selected {
+            self.listing.select(selected.as_path());
+        }
+        self.sync_selected();
     }
 
-    fn sync_selected(&mut self) {
-        self.state.get_mut().select(self.listing.selected());
+    /// Add the matches of files just found by the content search to the counts shown next to them
+    /// and their directories
+    pub fn count_matches(&mut self, found: &[ContentMatch]) {
+        for found in found {
+            let count = found.match_count;
+            for path in found
+                .path
+                .ancestors()
+                .take_while(|path| *path != self.root_path.as_path())
+            {
+                *self.match_counts.entry(path.to_path_buf()).or_default() += count;
+            }
+        }
     }
 
-    fn build_walkbuilder(
-        &self,
-        show_dotfiles: HiddenVisibility,
-    ) -> Result<impl Iterator<Item = PathBuf> + '_> {
-        let overrides = build_override_ignorer(&self.root_path, &self.config.filetree.ignore)?;
-        Ok(WalkBuilder::new(&self.root_path)
-            .overrides(overrides)
-            .hidden(show_dotfiles != HiddenVisibility::Visible)
-            .build()
-            .filter_map(|entry| entry.ok().map(|entry| entry.into_path()))
-            .filter(|entry_path| entry_path != &self.root_path))
+    pub fn clear_match_counts(&mut self) {
+        self.match_counts.clear();
     }
-}
-
-impl Drawable for Filetree {
-    fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Result<()> {
-        let mut state = self.state.take();
-        let list = List::new(
-            self.listing
-                .items()
-                .into_iter()
-                .map(|item| {
-                    let file_name = item
-                        .path()
-                        .file_name()
-                        .expect("path should have name")
-                        .to_string_lossy();
-                    // Calculate depth of indent
-                    let indent_amount =
-                        item.path().components().count() - self.listing.root().components().count();
-                    const INDENT: usize = 2;
-
-                    const CLOSED_SYMBOL: char = '\u{25b6}';
-                    const OPENED_SYMBOL: char = '\u{25bc}';
-                    let icon = if !item.is_file() {
-                        if self
-                            .listing
-                            .is_folded(item.path())
-                            .expect("item should be in folded")
-                        {
-                            CLOSED_SYMBOL
-                        } else {
-                            OPENED_SYMBOL
-                        }
-                    } else {
-                        self.status_cache.as_ref().map_or(' ', |cache| {
-                            cache.get(item.path()).map_or(' ', |status| match *status {
-                                Status::WT_NEW => '+',
-                                Status::INDEX_MODIFIED
-                                | Status::INDEX_NEW
-                                | Status::WT_MODIFIED => '~',
-                                _ => ' ',
-                            })
-                        })
-                    };
-                    let mut style = if !self
-                        .marks
-                        .borrow()
-                        .marks
-                        .iter()
-                        .any(|path| path == item.path())
-                    {
-                        self.status_cache
-                            .as_ref()
-                            .map_or(Style::default(), |cache| {
-                                cache.get(item.path()).map_or(Style::default(), |status| {
-                                    match *status {
-                                        Status::WT_NEW => {
-                                            Style::from(self.config.filetree.git_new_style)
-                                        }
-                                        Status::INDEX_MODIFIED => {
-                                            Style::from(self.config.filetree.git_added_style)
-                                        }
-                                        Status::WT_MODIFIED => {
-                                            Style::from(self.config.filetree.git_modified_style)
-                                        }
-                                        _ => Style::default(),
-                                    }
-                                })
-                            })
-                    } else {
-                        self.config.filetree.marks_style.into()
-                    };
-                    if style == Style::default() && !item.is_file() {
-                        style = self.config.filetree.dir_style.into();
-                    }
-                    ListItem::new(format!(
-                        "{}{icon} {file_name}",
-                        " ".repeat(indent_amount * INDENT)
-                    ))
-                    .style(style)
-                })
-                .collect_vec(),
-        )
-        .highlight_style(self.config.selected.into())
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(self.config.filetree.border_color.into()),
-        );
-        f.render_stateful_widget(list, area, &mut state);
-        self.state.set(state);
 
-        Ok(())
+    pub fn is_showing_search_results(&self) -> bool {
+        self.search_query.is_some()
     }
-}
 
-impl Component for Filetree {
-    fn visible(&self) -> bool {
-        true
+    /// Only show `files` and the directories leading to them
+    pub fn show_only(&mut self, files: &[PathBuf]) -> Result<()> {
+        self.show_files(files.iter().cloned().collect())
     }
 
-    fn focus(&mut self, focus: bool) {
-        self.is_focused = focus;
+    fn show_files(&mut self, files: HashSet<PathBuf>) -> Result<()> {
+        let selected = self.get_selected().map(|item| item.path().to_path_buf());
+        let keep: HashSet<&Path> = files
+            .iter()
+            .flat_map(|file| {
+                file.ancestors()
+                    .take_while(|path| *path != self.root_path.as_path())
+            })
+            .collect();
+        let items = self
+            .build_walkbuilder(self.hidden_visibility())?
+            .filter(|entry_path| keep.contains(entry_path.as_path()))
+            .collect_vec();
+        self.set_listing(FileListing::with_order(&items, self.order()));
+        self.expanded.clear();
+        self.select_filtered(selected, |path| files.contains(path));
+        self.view_filter = Some(ViewFilter::Only(files));
+
+        Ok(())
     }
-    fn focused(&self) -> bool {
-        self.is_focused
+
+    /// Select `previous` again if the filter kept it as a match, or else the first match, opening
+    /// the directories leading to it
+    fn select_filtered(&mut self, previous: Option<PathBuf>, is_match: impl Fn(&Path) -> bool) {
+        let items = self.listing.all_items();
+        let target = previous
+            .filter(|previous| {
+                is_match(previous) && items.iter().any(|item| item.path() == previous)
+            })
+            .or_else(|| {
+                items
+                    .iter()
+                    .map(Item::path)
+                    .find(|path| is_match(path))
+                    .map(Path::to_path_buf)
+            });
+        if let Some(target) = target {
+            self.listing.select(target.as_path());
+        }
+        self.sync_selected();
     }
 
-    fn handle_event(&mut self, ev: &ExternalEvent) -> Result<()> {
-        if !self.focused() {
-            return Ok(());
+    /// Every file under the root that a content search should look through
+    pub fn searchable_files(&self) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .build_walkbuilder(self.hidden_visibility())?
+            .filter(|path| path.is_file())
+            .collect())
+    }
+
+    /// Every file the tree lists now, as narrowed by its filters, in closed directories too
+    pub fn listed_files(&self) -> Vec<PathBuf> {
+        self.listing
+            .all_items()
+            .iter()
+            .filter(|item| item.is_file())
+            .map(|item| item.path().to_path_buf())
+            .collect()
+    }
+
+    /// What the tree leaves out, for walking it somewhere else
+    pub fn walk_filters(&self) -> Result<WalkFilters> {
+        self.filters(self.hidden_visibility())
+    }
+
+    fn filters(&self, show_dotfiles: HiddenVisibility) -> Result<WalkFilters> {
+        Ok(WalkFilters {
+            overrides: build_override_ignorer(&self.root_path, &self.config.filetree.ignore)?,
+            show_hidden: show_dotfiles == HiddenVisibility::Visible,
+            ignore_files: self.config.filetree.ignore_files.clone(),
+            use_gitignore: self.config.filetree.use_gitignore,
+            read_ignore_files: !self.is_showing_ignored,
+        })
+    }
+
+    /// Read the tree again with or without the ignore files, keeping what is open
+    pub fn toggle_ignored(&mut self) -> Result<()> {
+        self.is_showing_ignored = !self.is_showing_ignored;
+        if self.is_showing_ignored {
+            info!("showing files left out by ignore files");
+        } else {
+            info!("leaving out files listed in ignore files again");
         }
+        self.full_refresh()
+    }
 
-        const JUMP_DOWN_AMOUNT: u8 = 3;
-        match ev {
-            ExternalEvent::RefreshFiletree => self.refresh().context("problem refreshing tree")?,
-            ExternalEvent::PartialRefresh(data) => {
-                for refresh_data in data {
-                    if let Err(err) = self.partial_refresh(refresh_data).with_context(|| {
-                        format!("problem partially refreshing tree with data: \"{data:?}\"")
-                    }) {
-                        // Caused by weird fsevent bug, see https://github.com/notify-rs/notify/issues/272
-                        // for more info.
-                        if err.root_cause().to_string() == "invalid remove target" {
-                            debug!("swallowed invalid remove target error");
-                            continue;
-                        }
-                        bail!(err)
-                    };
-                }
-            }
-            ExternalEvent::Crossterm(Event::Key(key)) => {
-                let mut refresh_preview = true;
-                let not_empty = !self.listing.is_empty();
-                switch! { key;
-                    self.config.all_up => self.listing.select_first(),
-                    self.config.all_down => self.listing.select_last(),
-                    self.config.down, not_empty => self.listing.select_next(),
-                    self.config.up, not_empty => self.listing.select_prev(),
-                    self.config.filetree.down_three, not_empty => self.listing.select_next_n(JUMP_DOWN_AMOUNT as usize),
-                    self.config.filetree.up_three, not_empty => self.listing.select_prev_n(JUMP_DOWN_AMOUNT as usize),
-                    self.config.filetree.exec_cmd, not_empty => {
-                        if let Some(item) = self.get_selected() {
-                             self.queue.add(AppEvent::OpenInput(InputOperation::Command {
-                                 to: item.path().to_path_buf(),
-                             }));
-                         }
-                    },
-                    self.config.filetree.delete => {
-                        if let Some(item) = self.listing.selected_item() {
-                            self.queue.add(AppEvent::OpenPopup(PendingOperation::DeleteFile(item.path().to_path_buf())));
-                        }
-                    },
-                    self.config.filetree.diff_mode => self.queue.add(AppEvent::TogglePreviewMode),
-                    self.config.filetree.git_filter => {
-                        if let Some(cache) = self.status_cache.as_ref() {
-                            info!("filtered for modified files");
-                            self.filter_include(cache.keys().cloned().collect_vec().as_ref())?;
-                        } else {
-                            warn!("no git status to filter for");
-                        }
-                    },
-                    self.config.filetree.search => self.queue.add(AppEvent::SearchFiles(self.listing.all_items().iter().map(|item| item.path().to_path_buf()).collect())),
-                    self.config.filetree.clear => {
-                        info!("refreshed filetree");
-                        self.refresh().context("problem refreshing filetree")?;
-                    },
-                    self.config.open => match self.get_selected() {
-                        Some(Item::Dir(_)) => self.listing.toggle_fold(),
-                        Some(Item::File(file)) => self
-                            .queue
-                            .add(AppEvent::OpenFile(file.clone())),
-                        None => {}
-                    },
-                    self.config.filetree.new_file => {
-                        if let Some(selected) = self.listing.selected() {
-                            let is_folded = self.listing.is_folded(selected).unwrap();
-                            let add_path = match self.listing.selected_item().expect("should exist, checked at top of block") {
-                                Item::Dir(dir) if !is_folded => dir,
-                                item => item.path().parent().expect("item should have parent"),
-                            };
-                            self.queue
-                                .add(AppEvent::OpenInput(InputOperation::NewFile { at: add_path.to_path_buf() }));
-                        }
-                    },
-                    self.config.filetree.new_dir => {
-                        if let Some(selected) = self.listing.selected() {
-                            let is_folded = self.listing.is_folded(selected).unwrap();
-                            let add_path = match self.listing.selected_item().expect("should exist, checked at top of block") {
-                                Item::Dir(dir) if !is_folded => dir,
-                                item => item.path().parent().expect("item should have parent"),
-                            };
-                            self.queue
-                                .add(AppEvent::OpenInput(InputOperation::NewDir { at: add_path.to_path_buf() }));
-                        }
-                    },
-                    self.config.filetree.close_all => self.close_all(),
-                    self.config.filetree.open_all => self.open_all(),
-                    self.config.filetree.special_command => {
-                        if let Some(selected) = self.get_selected() {
-                            self.queue.add(AppEvent::SpecialCommand(selected.path().to_path_buf()));
-                        }
-                    },
-                    self.config.filetree.mark_selected => {
-                        if let Some(selected) = self.get_selected() {
-                            self.queue.add(AppEvent::Mark(selected.path().to_path_buf()));
-                        }
-                    },
-                    self.config.filetree.open_under => self.open_under(),
-                    self.config.filetree.close_under => self.close_under(),
-                    self.config.filetree.show_dotfiles => self.toggle_dotfiles()?,
-                    self.config.filetree.rename => {
-                        if let Some(selected) = self.get_selected() {
-                            self.queue.add(AppEvent::OpenInput(InputOperation::Rename { to: selected.path().to_path_buf() }));
-                        }
-                    },
-                    self.config.filetree.move_path => {
-                        if let Some(selected) = self.get_selected() {
-                            let vis = if self.is_showing_hidden {
-                                HiddenVisibility::Visible
-                            } else {
-                                HiddenVisibility::Hidden
-                            };
-                            let items = self.build_walkbuilder(vis)?
-                                .filter_map(|item| item.is_dir().then_some(item))
-                                .map(|p| p.display().to_string())
-                                .collect();
-                            self.queue.add(AppEvent::OpenFuzzy(items, FuzzyOperation::MoveFile(selected.path().to_path_buf())));
-                        }
-                    },
-                    _ => {
-                        let key: crate::config::Key = key.into();
-                        if let Some(cmd) = self.config.commands.get(&key) {
-                            if let Some(selected) = self.get_selected() {
-                                let new_cmd =
-                                    cmd.replace("{}", &selected.path().as_os_str().to_string_lossy());
-                                if new_cmd.contains("{...}") {
-                                    self.queue
-                                        .add(AppEvent::OpenInput(InputOperation::SpecialCommand(new_cmd)));
-                                } else {
-                                    self.queue.add(AppEvent::RunCommand(new_cmd));
-                                }
-                            }
-                        };
+    fn hidden_visibility(&self) -> HiddenVisibility {
+        if self.is_showing_hidden {
+            HiddenVisibility::Visible
+        } else {
+            HiddenVisibility::Hidden
+        }
+    }
 
-                        refresh_preview = false;
-                    },
-                }
-                if !refresh_preview {
-                    self.state.get_mut().select(self.listing.selected());
-                    return Ok(());
-                }
+    pub fn is_filtering(&self) -> bool {
+        self.quick_filter.is_some()
+    }
+
+    pub fn start_quick_filter(&mut self) {
+        if self.is_filtering() {
+            return;
+        }
+        self.quick_filter = Some(QuickFilter {
+            query: String::new(),
+            listing: std::mem::take(&mut self.listing),
+        });
+        self.apply_quick_filter();
+    }
+
+    /// Leave filter mode, restoring the full tree. If `keep_selection` is set, the item selected
+    /// while filtering stays selected.
+    pub fn end_quick_filter(&mut self, keep_selection: bool) -> Result<()> {
+        let Some(filter) = self.quick_filter.take() else {
+            return Ok(());
+        };
+        let selected = self.get_selected().map(|item| item.path().to_path_buf());
+        self.listing = filter.listing;
+        match selected {
+            Some(selected) if keep_selection => self.open_path(selected)?,
+            _ => {
                 if let Some(item) = self.get_selected() {
                     self.queue
                         .add(AppEvent::PreviewFile(item.path().to_owned()));
                 }
+                self.sync_selected();
             }
-            _ => {}
         }
 
+        Ok(())
+    }
+
+    /// Rebuild the filtered listing from the query, keeping the ancestors of every match
+    fn apply_quick_filter(&mut self) {
+        let Some(filter) = &self.quick_filter else {
+            return;
+        };
+        let keep: HashSet<&Path> = filter
+            .listing
+            .all_items()
+            .iter()
+            .map(Item::path)
+            .filter(|path| quick_filter_match(path, &filter.query).is_some())
+            .flat_map(Path::ancestors)
+            .collect();
+        let items = filter
+            .listing
+            .all_items()
+            .iter()
+            .map(Item::path)
+            .filter(|path| keep.contains(path))
+            .collect_vec();
+        self.listing = if filter.listing.is_flat() {
+            FileListing::flat(&items, &self.root_path, self.order())
+        } else {
+            FileListing::with_order(&items, self.order())
+        };
+        if let Some(index) = self.find_match(0..self.listing.len()) {
+            self.listing.select(index);
+        }
+    }
+
+    /// First of `indices` whose item matches the quick filter
+    fn find_match(&self, mut indices: impl Iterator<Item = usize>) -> Option<usize> {
+        let filter = self.quick_filter.as_ref()?;
+        let items = self.listing.items();
+        indices.find(|index| quick_filter_match(items[*index].path(), &filter.query).is_some())
+    }
+
+    /// Select the next (or previous) item matching the quick filter, skipping ancestors that are
+    /// only shown for context
+    fn select_match(&mut self, forward: bool) {
+        let current = self.listing.selected().unwrap_or(0);
+        let found = if forward {
+            self.find_match(current + 1..self.listing.len())
+        } else {
+            self.find_match((0..current).rev())
+        };
+        if let Some(index) = found {
+            self.listing.select(index);
+        }
+    }
+
+    /// Handle a key while filtering. Returns false if the key is not used by the filter.
+    fn handle_quick_filter_key(&mut self, key: &KeyEvent) -> Result<bool> {
+        let Some(filter) = &mut self.quick_filter else {
+            return Ok(false);
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => self.end_quick_filter(false)?,
+            KeyCode::Enter => self.end_quick_filter(true)?,
+            KeyCode::Backspace => {
+                filter.query.pop();
+                self.apply_quick_filter();
+            }
+            KeyCode::Down => self.select_match(true),
+            KeyCode::Char('n') if ctrl => self.select_match(true),
+            KeyCode::Up => self.select_match(false),
+            KeyCode::Char('p') if ctrl => self.select_match(false),
+            KeyCode::Char(c) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => {
+                filter.query.push(c);
+                self.apply_quick_filter();
+            }
+            _ => return Ok(false),
+        }
+        if let Some(item) = self.get_selected() {
+            self.queue
+                .add(AppEvent::PreviewFile(item.path().to_owned()));
+        }
         self.sync_selected();
 
+        Ok(true)
+    }
+
+    /// Switch between the tree and a list of every file in it at one level, keeping the selection
+    pub fn toggle_flat_mode(&mut self) -> Result<()> {
+        self.end_quick_filter(true)?;
+        let selected = self.get_selected().map(|item| item.path().to_path_buf());
+        if let Some(tree) = self.flat.take() {
+            self.listing = tree;
+            match selected {
+                Some(selected) => self.open_path(selected)?,
+                None => self.sync_selected(),
+            }
+            info!("showing the tree");
+        } else {
+            let tree = std::mem::take(&mut self.listing);
+            self.show_flat(tree);
+            if let Some(selected) = selected {
+                self.listing.select(selected.as_path());
+            }
+            if let Some(item) = self.get_selected() {
+                self.queue
+                    .add(AppEvent::PreviewFile(item.path().to_owned()));
+            }
+            self.sync_selected();
+            info!("listing every file at one level");
+        }
+
         Ok(())
     }
-}
 
-/// Builds an `Override` that ignores certain paths
-fn build_override_ignorer(root: impl AsRef<Path>, ignore: &[String]) -> Result<Override> {
-    let mut override_builder = OverrideBuilder::new(root.as_ref());
+    pub fn is_flat(&self) -> bool {
+        self.flat.is_some()
+    }
 
-    for pat in ignore.iter().map(|x| x.as_str()).chain(iter::once("/.git")) {
-        override_builder
-            .add(&format!("!{pat}"))
-            .with_context(|| format!("failed to add glob for: \"!{pat}\""))?
-            .add(&format!("!{pat}/**"))
-            .with_context(|| format!("failed to add glob for: \"!{pat}/**\""))?;
+    /// Show `listing`, or the files in it at one level while in flat mode
+    fn set_listing(&mut self, listing: FileListing) {
+        self.view_filter = None;
+        if self.is_flat() {
+            self.show_flat(listing);
+        } else {
+            self.listing = listing;
+        }
     }
-    override_builder
-        .build()
-        .context("failed to build override ignorer")
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{app::components::testing::*, config::FiletreeConfig};
-    use collect_all::collect;
-    use smallvec::smallvec;
-    use test_log::test;
+    fn show_flat(&mut self, tree: FileListing) {
+        let walked_dirs = self.walked_dirs.borrow();
+        let files = tree
+            .all_items()
+            .iter()
+            .filter(|item| item.is_file() && !walked_dirs.contains(item.path()))
+            .map(Item::path)
+            .collect_vec();
+        let listing = FileListing::flat(&files, &self.root_path, self.order());
+        drop(walked_dirs);
+        self.listing = listing;
+        self.flat = Some(tree);
+    }
 
-    /// Create temporary files and return the temp dir
-    macro_rules! temp_files {
-    ($($name:expr),*) => {
-            {
-                #[allow(unused_imports)]
-                use ::assert_fs::prelude::*;
+    /// Make `change` to the tree behind the flat list, then list its files again. The tree
+    /// follows the selection of the list, so the change moves it along as usual.
+    fn change_tree<R>(&mut self, change: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        let Some(mut tree) = self.flat.take() else {
+            return change(self);
+        };
+        let index = self.listing.selected().unwrap_or_default();
+        if let Some(selected) = self.get_selected() {
+            tree.select(selected.path());
+        }
+        self.listing = tree;
+        let result = change(self);
+        let selected = self.get_selected().map(|item| item.path().to_path_buf());
+        let tree = std::mem::take(&mut self.listing);
+        self.show_flat(tree);
+        if selected
+            .and_then(|selected| self.listing.select(selected.as_path()))
+            .is_none()
+        {
+            self.listing
+                .select(index.min(self.listing.len().saturating_sub(1)));
+        }
+        self.sync_selected();
+        result
+    }
 
-                let __temp = ::assert_fs::TempDir::new().unwrap();
-                $(
-                    __temp.child($name).touch().unwrap();
-                 )*
-                __temp
+    fn order(&self) -> SortOrder {
+        SortOrder {
+            mode: self.config.filetree.sort,
+            dirs_first: self.dirs_first,
+            reverse: self.reverse,
+            reverse_groups: self.config.filetree.reverse_groups,
+        }
+    }
+
+    /// Sort the tree again after the order changed, keeping the selection
+    fn reorder(&mut self) {
+        let order = self.order();
+        self.listing.reorder(order);
+        if let Some(filter) = &mut self.quick_filter {
+            filter.listing.reorder(order);
+        }
+        if let Some(tree) = &mut self.flat {
+            tree.reorder(order);
+        }
+        self.sync_selected();
+    }
+
+    /// Switch between directories before files and both mixed together, keeping the selection
+    pub fn toggle_dirs_first(&mut self) {
+        self.dirs_first = !self.dirs_first;
+        self.reorder();
+        info!(
+            "{}",
+            if self.dirs_first {
+                "grouping directories first"
+            } else {
+                "ordering directories with files"
             }
-        };
+        );
     }
 
-    #[test]
-    fn new_filetree_selects_first() {
-        let temp = temp_files!("test.txt");
-        let path = temp.path().to_owned();
-        let filetree =
-            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
-        assert_eq!(
-            path.join("test.txt"),
-            filetree.get_selected().unwrap().path()
+    pub fn toggle_reverse(&mut self) {
+        self.reverse = !self.reverse;
+        self.reorder();
+        info!(
+            "{}",
+            if self.reverse {
+                "reversing the order"
+            } else {
+                "ordering forwards"
+            }
         );
     }
 
-    #[test]
-    fn sends_delete_event() {
+    pub fn toggle_dotfiles(&mut self) -> Result<()> {
+        let items = self
+            .build_walkbuilder(if self.is_showing_hidden {
+                HiddenVisibility::Hidden
+            } else {
+                HiddenVisibility::Visible
+            })?
+            .collect_vec();
+        self.is_showing_hidden = !self.is_showing_hidden;
+
+        let mut listing = FileListing::with_order(&items, self.order());
+        listing.fold_all();
+        self.set_listing(listing);
+
+        info!("toggling visibility of dotfiles");
+
+        Ok(())
+    }
+
+    pub fn in_repo(&self) -> bool {
+        self.repo.is_some()
+    }
+
+    /// Read the git status of every file again, after something outside the tree changed it
+    pub fn refresh_git_status(&mut self) {
+        self.populate_status_cache();
+    }
+
+    pub fn branch(&self) -> Option<&GitBranch> {
+        self.branch.as_ref()
+    }
+
+    fn populate_status_cache(&mut self) {
+        self.branch = self.repo.as_ref().and_then(GitBranch::read);
+        self.status_cache = self.repo.as_ref().and_then(|repo| {
+            repo.statuses(None).ok().map(|statuses| {
+                statuses
+                    .iter()
+                    .map(|status| (self.root_path.join(status.path().unwrap()), status.status()))
+                    .collect::<HashMap<PathBuf, Status>>()
+            })
+        });
+    }
+
+    /// Scroll so the selection is at `position` of the view, like `zz`, `zt` and `zb` in vim
+    fn scroll_selected_to(&mut self, position: ViewPosition) {
+        let Some(selected) = self.listing.selected() else {
+            return;
+        };
+        let height = self.height.get();
+        let margin = self.scroll_margin();
+        let offset = match position {
+            ViewPosition::Top => selected.saturating_sub(margin),
+            ViewPosition::Center => selected.saturating_sub(height / 2),
+            ViewPosition::Bottom => (selected + 1 + margin).saturating_sub(height),
+        };
+        self.offset.set(offset);
+    }
+
+    /// Rows to keep around the selection, at most what still lets it move within the view
+    fn scroll_margin(&self) -> usize {
+        self.config
+            .filetree
+            .scroll_margin
+            .min(self.height.get().saturating_sub(1) / 2)
+    }
+
+    /// Offset that keeps the selection and the scroll margin around it in view, moving as little
+    /// as possible
+    fn visible_offset(&self, len: usize) -> usize {
+        let height = self.height.get().max(1);
+        let margin = self.scroll_margin();
+        let mut offset = self.offset.get().min(len.saturating_sub(1));
+        if let Some(selected) = self.listing.selected() {
+            if selected < offset + margin {
+                offset = selected.saturating_sub(margin);
+            } else if selected + margin >= offset + height {
+                // The margin never scrolls past the last item
+                offset = (selected + margin + 1 - height).min(len.saturating_sub(height));
+            }
+        }
+        offset
+    }
+
+    /// Indent, icon and, in the flat list, parent directory drawn before an item's name
+    fn prefix(&self, item: &Item) -> String {
+        const INDENT: usize = 2;
+        const CLOSED_SYMBOL: char = '\u{25b6}';
+        const OPENED_SYMBOL: char = '\u{25bc}';
+
+        let indent_amount = if self.listing.is_flat() {
+            0
+        } else {
+            item.path().components().count() - self.listing.root().components().count()
+        };
+        let icon = if !item.is_file() {
+            if self
+                .listing
+                .is_folded(item.path())
+                .expect("item should be in folded")
+            {
+                CLOSED_SYMBOL
+            } else {
+                OPENED_SYMBOL
+            }
+        } else {
+            self.status_cache.as_ref().map_or(' ', |cache| {
+                cache.get(item.path()).map_or(' ', |status| match *status {
+                    Status::WT_NEW => '+',
+                    Status::INDEX_MODIFIED | Status::INDEX_NEW | Status::WT_MODIFIED => '~',
+                    _ => ' ',
+                })
+            })
+        };
+        // The flat list has no directories, so each file shows the one it is in
+        let dir = item
+            .path()
+            .parent()
+            .filter(|_| self.listing.is_flat())
+            .and_then(|parent| parent.strip_prefix(self.listing.root()).ok())
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map_or(String::new(), |parent| {
+                format!("{}{MAIN_SEPARATOR}", parent.display())
+            });
+        format!("{}{icon} {dir}", " ".repeat(indent_amount * INDENT))
+    }
+
+    /// Where the name of `path` was last drawn, if it is the selected item and was in view
+    pub fn name_area(&self, path: &Path) -> Option<Rect> {
+        let item = self.get_selected().filter(|item| item.path() == path)?;
+        let row = self.listing.selected()?.checked_sub(self.offset.get())?;
+        if row >= self.height.get() {
+            return None;
+        }
+        let area = self.area.get();
+        let row = u16::try_from(row).ok()?;
+        let indent = u16::try_from(self.prefix(item).chars().count()).ok()?;
+        let width = area.width.saturating_sub(2).checked_sub(indent)?;
+        (width > 0).then(|| Rect::new(area.x + 1 + indent, area.y + 1 + row, width, 1))
+    }
+
+    /// Marker and style of a directory that could not be read or is empty. These end up as
+    /// files, since nothing is found in them.
+    fn dir_marker(&self, item: &Item) -> Option<(&'static str, crate::config::Style)> {
+        if !item.is_file() {
+            None
+        } else if self.unreadable.borrow().contains(item.path()) {
+            Some((" (unreadable)", self.config.filetree.unreadable_dir_style))
+        } else if self.too_deep.borrow().contains(item.path()) {
+            Some((" (too deep)", self.config.filetree.empty_dir_style))
+        } else if self.walked_dirs.borrow().contains(item.path()) {
+            Some((" (empty)", self.config.filetree.empty_dir_style))
+        } else {
+            None
+        }
+    }
+
+    /// Size of the file at `path` to show after its name, with the style of its band
+    fn size_label(&self, path: &Path) -> Option<(String, Style)> {
+        if !self.config.filetree.show_sizes {
+            return None;
+        }
+        let size = fs::metadata(path).ok().filter(fs::Metadata::is_file)?.len();
+        let style = self
+            .config
+            .filetree
+            .size_bands
+            .iter()
+            .find(|band| band.below.map_or(true, |below| size < below))
+            .map_or(Style::default(), |band| band.style.into());
+        Some((format!(" {}", ui::format_bytes(size)), style))
+    }
+
+    /// When the file at `path` was modified, to show after its name
+    fn modified_label(&self, path: &Path, now: SystemTime) -> Option<String> {
+        if !self.config.filetree.show_modified {
+            return None;
+        }
+        let modified = fs::metadata(path)
+            .ok()
+            .filter(fs::Metadata::is_file)?
+            .modified()
+            .ok()?;
+        let label = match self.config.filetree.modified_format {
+            TimeFormat::Absolute => ui::format_time(modified),
+            // Files from the future, as with a skewed clock, were just modified
+            TimeFormat::Relative => {
+                ui::format_age(now.duration_since(modified).unwrap_or_default())
+            }
+        };
+        Some(format!(" {label}"))
+    }
+
+    fn sync_selected(&mut self) {
+        self.state.get_mut().select(self.listing.selected());
+    }
+
+    fn build_walkbuilder(
+        &self,
+        show_dotfiles: HiddenVisibility,
+    ) -> Result<impl Iterator<Item = PathBuf> + '_> {
+        let mut walker = self.filters(show_dotfiles)?.walker(&self.root_path);
+        self.walked_dirs.borrow_mut().clear();
+        let known = self.unreadable.take();
+        let max_depth = self.config.filetree.max_depth;
+        let was_too_deep = self.too_deep.take();
+        Ok(walker
+            .max_depth((max_depth > 0).then_some(max_depth))
+            .build()
+            .filter_map(move |entry| match entry {
+                Ok(entry) => {
+                    if entry.file_type().is_some_and(|kind| kind.is_dir()) {
+                        self.walked_dirs
+                            .borrow_mut()
+                            .insert(entry.path().to_path_buf());
+                        if entry.depth() == max_depth {
+                            let mut too_deep = self.too_deep.borrow_mut();
+                            // Once per walk, and only when the cap starts cutting off more
+                            if too_deep.is_empty() && !was_too_deep.contains(entry.path()) {
+                                info!(
+                                    "not reading below \"{}\" and other directories at the max_depth of {max_depth}",
+                                    entry.path().display()
+                                );
+                            }
+                            too_deep.insert(entry.path().to_path_buf());
+                        }
+                    }
+                    Some(entry.into_path())
+                }
+                Err(err) => {
+                    if let Some(path) = error_path(&err) {
+                        if !known.contains(path) {
+                            warn!("could not read \"{}\": {err}", path.display());
+                        }
+                        self.unreadable.borrow_mut().insert(path.to_path_buf());
+                    }
+                    None
+                }
+            })
+            .filter(|entry_path| entry_path != &self.root_path))
+    }
+}
+
+impl Drawable for Filetree {
+    fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Result<()> {
+        let items = self.listing.items();
+        self.area.set(area);
+        self.height.set(usize::from(area.height.saturating_sub(2)));
+        let offset = self.visible_offset(items.len());
+        self.offset.set(offset);
+        let now = SystemTime::now();
+        // Only the items in view are given to the list, so it cannot scroll on its own
+        let state = self.state.take();
+        let mut view_state = ListState::default();
+        view_state.select(
+            state
+                .selected()
+                .and_then(|selected| selected.checked_sub(offset)),
+        );
+        let list = List::new(
+            items
+                .into_iter()
+                .skip(offset)
+                .take(self.height.get())
+                .map(|item| {
+                    let file_name = item
+                        .path()
+                        .file_name()
+                        .expect("path should have name")
+                        .to_string_lossy();
+                    let prefix = self.prefix(item);
+                    let marked = self
+                        .marks
+                        .borrow()
+                        .marks
+                        .iter()
+                        .any(|path| path == item.path());
+                    let mut style = if !marked {
+                        self.status_cache
+                            .as_ref()
+                            .map_or(Style::default(), |cache| {
+                                cache.get(item.path()).map_or(Style::default(), |status| {
+                                    match *status {
+                                        Status::WT_NEW => {
+                                            Style::from(self.config.filetree.git_new_style)
+                                        }
+                                        Status::INDEX_MODIFIED => {
+                                            Style::from(self.config.filetree.git_added_style)
+                                        }
+                                        Status::WT_MODIFIED => {
+                                            Style::from(self.config.filetree.git_modified_style)
+                                        }
+                                        _ => Style::default(),
+                                    }
+                                })
+                            })
+                    } else {
+                        self.config.filetree.marks_style.into()
+                    };
+                    if style == Style::default() && !item.is_file() {
+                        style = self.config.filetree.dir_style.into();
+                    }
+                    let marker = match self.dir_marker(item) {
+                        Some((marker, marker_style)) => {
+                            style = marker_style.into();
+                            marker
+                        }
+                        None => "",
+                    };
+                    if self.multi_selection.contains(item.path()) {
+                        style = self.config.filetree.multi_select_style.into();
+                    }
+                    const MARK_SYMBOL: &str = " \u{2605}";
+                    let matches = self
+                        .search_query
+                        .as_ref()
+                        .and_then(|_| self.match_counts.get(item.path()));
+                    // Counting matches takes the place of counting what is in a directory
+                    let count = if matches.is_none()
+                        && self.config.filetree.show_dir_counts
+                        && !item.is_file()
+                    {
+                        format!(" ({})", self.listing.child_count(item.path()))
+                    } else {
+                        String::new()
+                    };
+                    let suffix =
+                        format!("{count}{marker}{}", if marked { MARK_SYMBOL } else { "" });
+                    let matched = self.quick_filter.as_ref().and_then(|filter| {
+                        quick_filter_match(item.path(), &filter.query)
+                            .filter(|range| !range.is_empty())
+                    });
+                    let mut spans = match matched {
+                        Some(range) => vec![
+                            Span::raw(prefix),
+                            Span::raw(file_name[..range.start].to_owned()),
+                            Span::styled(
+                                file_name[range.clone()].to_owned(),
+                                self.config.filetree.filter_match_style.into(),
+                            ),
+                            Span::raw(file_name[range.end..].to_owned()),
+                        ],
+                        None => vec![Span::raw(format!("{prefix}{file_name}"))],
+                    };
+                    if let Some(matches) = matches {
+                        spans.push(Span::styled(
+                            format!(" ({matches})"),
+                            self.config.filetree.filter_match_style.into(),
+                        ));
+                    }
+                    if let Some((size, size_style)) = self.size_label(item.path()) {
+                        spans.push(Span::styled(size, size_style));
+                    }
+                    if let Some(modified) = self.modified_label(item.path(), now) {
+                        spans.push(Span::raw(modified));
+                    }
+                    spans.push(Span::raw(suffix));
+                    ListItem::new(Spans::from(spans)).style(style)
+                })
+                .collect_vec(),
+        )
+        .highlight_style(self.config.selected.into())
+        .highlight_symbol(&self.config.selected_symbol)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(
+                    self.quick_filter
+                        .as_ref()
+                        .map_or(String::new(), |filter| format!("Filter: {}", filter.query)),
+                )
+                .border_style(self.config.filetree.border_color.into())
+                .border_type(ui::pane_border(self.highlight.get())),
+        );
+        f.render_stateful_widget(list, area, &mut view_state);
+        self.state.set(state);
+
+        Ok(())
+    }
+}
+
+impl Component for Filetree {
+    fn visible(&self) -> bool {
+        true
+    }
+
+    fn focus(&mut self, focus: bool) {
+        self.is_focused = focus;
+    }
+    fn focused(&self) -> bool {
+        self.is_focused
+    }
+
+    fn handle_event(&mut self, ev: &ExternalEvent) -> Result<()> {
+        if !self.focused() {
+            return Ok(());
+        }
+
+        const JUMP_DOWN_AMOUNT: u8 = 3;
+        match ev {
+            ExternalEvent::RefreshFiletree => self.refresh().context("problem refreshing tree")?,
+            ExternalEvent::PartialRefresh(data) => {
+                for refresh_data in data {
+                    if let Err(err) = self.partial_refresh(refresh_data).with_context(|| {
+                        format!("problem partially refreshing tree with data: \"{data:?}\"")
+                    }) {
+                        // Caused by weird fsevent bug, see https://github.com/notify-rs/notify/issues/272
+                        // for more info.
+                        if err.root_cause().to_string() == "invalid remove target" {
+                            debug!("swallowed invalid remove target error");
+                            continue;
+                        }
+                        bail!(err)
+                    };
+                }
+            }
+            ExternalEvent::Crossterm(Event::Mouse(mouse)) if self.config.mouse => {
+                match mouse.kind {
+                    MouseEventKind::Down(MouseButton::Left) => self.click(mouse.column, mouse.row),
+                    MouseEventKind::ScrollDown | MouseEventKind::ScrollUp
+                        if self.is_under(mouse.column, mouse.row) && !self.listing.is_empty() =>
+                    {
+                        if mouse.kind == MouseEventKind::ScrollDown {
+                            self.listing.select_next();
+                        } else {
+                            self.listing.select_prev();
+                        }
+                        if let Some(item) = self.get_selected() {
+                            self.queue
+                                .add(AppEvent::PreviewFile(item.path().to_owned()));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            ExternalEvent::Crossterm(Event::Key(key)) if self.is_filtering() => {
+                self.handle_quick_filter_key(key)?;
+                return Ok(());
+            }
+            ExternalEvent::Crossterm(Event::Key(key)) => {
+                let mut refresh_preview = true;
+                let not_empty = !self.listing.is_empty();
+                switch! { key;
+                    self.config.all_up => self.listing.select_first(),
+                    self.config.all_down => self.listing.select_last(),
+                    self.config.down, not_empty => self.listing.select_next(),
+                    self.config.up, not_empty => self.listing.select_prev(),
+                    self.config.filetree.down_three, not_empty => self.listing.select_next_n(JUMP_DOWN_AMOUNT as usize),
+                    self.config.filetree.up_three, not_empty => self.listing.select_prev_n(JUMP_DOWN_AMOUNT as usize),
+                    self.config.filetree.exec_cmd, not_empty => {
+                        if let Some(item) = self.get_selected() {
+                             self.queue.add(AppEvent::OpenInput(InputOperation::Command {
+                                 to: item.path().to_path_buf(),
+                             }));
+                         }
+                    },
+                    self.config.filetree.batch_cmd, not_empty => self.queue.add(AppEvent::OpenInput(InputOperation::BatchCommand)),
+                    self.config.filetree.delete => {
+                        if let Some(item) = self.listing.selected_item() {
+                            self.queue.add(AppEvent::OpenPopup(PendingOperation::DeleteFile(item.path().to_path_buf())));
+                        }
+                    },
+                    self.config.filetree.diff_mode => self.queue.add(AppEvent::TogglePreviewMode),
+                    self.config.filetree.staged_diff_mode => self.queue.add(AppEvent::ToggleStagedDiff),
+                    self.config.filetree.blame_mode => self.queue.add(AppEvent::ToggleBlame),
+                    self.config.filetree.show_at_ref => {
+                        if let Some(item) = self.get_selected() {
+                            self.queue.add(AppEvent::OpenInput(InputOperation::ShowAtRef { path: item.path().to_path_buf() }));
+                        }
+                    },
+                    self.config.filetree.stage => self.queue.add(AppEvent::Stage(self.multi_selection())),
+                    self.config.filetree.unstage => self.queue.add(AppEvent::Unstage(self.multi_selection())),
+                    self.config.filetree.stage_all => self.queue.add(AppEvent::StageAll),
+                    self.config.filetree.git_filter => {
+                        if let Some(cache) = self.status_cache.as_ref() {
+                            info!("filtered for modified files");
+                            self.filter_include(cache.keys().cloned().collect_vec().as_ref())?;
+                        } else {
+                            warn!("no git status to filter for");
+                        }
+                    },
+                    self.config.filetree.search => self.queue.add(AppEvent::SearchFiles),
+                    self.config.filetree.quick_filter => self.start_quick_filter(),
+                    self.config.filetree.content_search => self.queue.add(AppEvent::OpenInput(InputOperation::SearchContents)),
+                    self.config.filetree.clear => self.clear()?,
+                    // Only quits once there is nothing left to clear
+                    Key::esc(), self.has_something_to_clear() => self.clear()?,
+                    self.config.open => {
+                        if let Some(item) = self.get_selected().cloned() {
+                            self.enter(&item);
+                        }
+                    },
+                    self.config.filetree.new_file => {
+                        if let Some(selected) = self.listing.selected() {
+                            let is_folded = self.listing.is_folded(selected).unwrap();
+                            let add_path = match self.listing.selected_item().expect("should exist, checked at top of block") {
+                                Item::Dir(dir) if !is_folded => dir,
+                                item => item.path().parent().expect("item should have parent"),
+                            };
+                            self.queue
+                                .add(AppEvent::OpenInput(InputOperation::NewFile { at: add_path.to_path_buf() }));
+                        }
+                    },
+                    self.config.filetree.new_dir => {
+                        if let Some(selected) = self.listing.selected() {
+                            let is_folded = self.listing.is_folded(selected).unwrap();
+                            let add_path = match self.listing.selected_item().expect("should exist, checked at top of block") {
+                                Item::Dir(dir) if !is_folded => dir,
+                                item => item.path().parent().expect("item should have parent"),
+                            };
+                            self.queue
+                                .add(AppEvent::OpenInput(InputOperation::NewDir { at: add_path.to_path_buf() }));
+                        }
+                    },
+                    self.config.filetree.close_all => self.close_all(),
+                    self.config.filetree.open_all => self.open_all(),
+                    self.config.filetree.center_selected => {
+                        self.scroll_selected_to(ViewPosition::Center);
+                        refresh_preview = false;
+                    },
+                    self.config.filetree.selected_to_top => {
+                        self.scroll_selected_to(ViewPosition::Top);
+                        refresh_preview = false;
+                    },
+                    self.config.filetree.selected_to_bottom => {
+                        self.scroll_selected_to(ViewPosition::Bottom);
+                        refresh_preview = false;
+                    },
+                    self.config.filetree.special_command => {
+                        if let Some(selected) = self.get_selected() {
+                            self.queue.add(AppEvent::SpecialCommand(selected.path().to_path_buf()));
+                        }
+                    },
+                    self.config.filetree.run_last_special_command => {
+                        if let Some(selected) = self.get_selected() {
+                            self.queue.add(AppEvent::RunLastSpecialCommand(selected.path().to_path_buf()));
+                        }
+                    },
+                    self.config.filetree.mark_selected => {
+                        if let Some(selected) = self.get_selected() {
+                            self.queue.add(AppEvent::Mark(selected.path().to_path_buf()));
+                        }
+                    },
+                    self.config.filetree.toggle_mark => {
+                        if let Some(selected) = self.get_selected() {
+                            let path = selected.path().to_path_buf();
+                            if self.marks.borrow().marks.contains(&path) {
+                                self.queue.add(AppEvent::Unmark(path));
+                            } else {
+                                self.queue.add(AppEvent::Mark(path));
+                            }
+                        }
+                    },
+                    self.config.filetree.multi_select => {
+                        self.toggle_multi_select();
+                        self.listing.select_next();
+                    },
+                    self.config.filetree.full_refresh => self.full_refresh()?,
+                    self.config.filetree.expand_matching => self.queue.add(AppEvent::OpenInput(InputOperation::ExpandMatching)),
+                    self.config.filetree.recent_dirs => self.open_recent_dirs(),
+                    self.config.filetree.flat_mode => self.toggle_flat_mode()?,
+                    self.config.filetree.extension_filter => self.queue.add(AppEvent::OpenInput(InputOperation::FilterExtensions { recent: self.recent_extensions.clone() })),
+                    self.config.filetree.goto => self.queue.add(AppEvent::OpenInput(InputOperation::Goto { root: self.root_path.clone() })),
+                    self.config.filetree.next_dir => self.select_sibling_dir(true),
+                    self.config.filetree.prev_dir => self.select_sibling_dir(false),
+                    self.config.filetree.open_under => self.open_under(),
+                    self.config.filetree.close_under => self.close_under(),
+                    self.config.filetree.show_dotfiles => self.toggle_dotfiles()?,
+                    self.config.filetree.show_ignored => self.toggle_ignored()?,
+                    self.config.filetree.toggle_dirs_first => self.toggle_dirs_first(),
+                    self.config.filetree.toggle_reverse => self.toggle_reverse(),
+                    self.config.filetree.rename => {
+                        if let Some(selected) = self.get_selected() {
+                            self.queue.add(AppEvent::OpenInput(InputOperation::Rename { to: selected.path().to_path_buf() }));
+                        }
+                    },
+                    self.config.filetree.copy_contents => {
+                        if let Some(Item::File(file)) = self.get_selected() {
+                            self.queue.add(AppEvent::CopyContents(file.clone()));
+                        }
+                    },
+                    self.config.filetree.copy_paths => {
+                        let paths = self.multi_selection();
+                        if !paths.is_empty() {
+                            self.queue.add(AppEvent::CopyPaths(paths));
+                        }
+                    },
+                    self.config.filetree.copy_name => self.copy_name_part(NamePart::Name),
+                    self.config.filetree.copy_stem => self.copy_name_part(NamePart::Stem),
+                    self.config.filetree.copy_extension => self.copy_name_part(NamePart::Extension),
+                    self.config.filetree.paste_path => self.queue.add(AppEvent::PastePath),
+                    self.config.filetree.move_path => {
+                        if let Some(selected) = self.get_selected() {
+                            let vis = if self.is_showing_hidden {
+                                HiddenVisibility::Visible
+                            } else {
+                                HiddenVisibility::Hidden
+                            };
+                            let items = self.build_walkbuilder(vis)?
+                                .filter_map(|item| item.is_dir().then_some(item))
+                                .map(|p| p.display().to_string())
+                                .collect();
+                            let operation = if self.multi_selection.is_empty() {
+                                FuzzyOperation::MoveFile(selected.path().to_path_buf())
+                            } else {
+                                FuzzyOperation::MoveFiles(self.multi_selection())
+                            };
+                            self.queue.add(AppEvent::OpenFuzzy(items, operation));
+                        }
+                    },
+                    _ => {
+                        let key: Key = key.into();
+                        if let Some(bound) = self.config.commands.get(&key) {
+                            if let Some(selected) = self.get_selected() {
+                                let new_cmd = command::fill_path(bound.cmd(), selected.path());
+                                if new_cmd.contains("{...}") {
+                                    self.queue
+                                        .add(AppEvent::OpenInput(InputOperation::SpecialCommand(new_cmd, bound.options())));
+                                } else if bound.options() == RunOptions::default() {
+                                    self.queue.add(AppEvent::RunCommand(new_cmd));
+                                } else {
+                                    self.queue.add(AppEvent::RunCommandWith(new_cmd, bound.options()));
+                                }
+                            }
+                        } else if let Some(command) = self.config.tmux_commands.get(&key) {
+                            if let Some(selected) = self.get_selected() {
+                                let cmd = command::fill_path(&command.cmd, selected.path());
+                                self.queue.add(AppEvent::RunTmuxCommand(TmuxCommand {
+                                    cmd,
+                                    ..command.clone()
+                                }));
+                            }
+                        }
+
+                        refresh_preview = false;
+                    },
+                }
+                if !refresh_preview {
+                    self.state.get_mut().select(self.listing.selected());
+                    return Ok(());
+                }
+                if let Some(item) = self.get_selected() {
+                    self.queue
+                        .add(AppEvent::PreviewFile(item.path().to_owned()));
+                }
+            }
+            _ => {}
+        }
+
+        self.sync_selected();
+
+        Ok(())
+    }
+}
+
+/// Byte range of the name of `path` that matches `query`, ignoring ASCII case
+fn quick_filter_match(path: &Path, query: &str) -> Option<Range<usize>> {
+    let name = path.file_name()?.to_string_lossy();
+    let start = name
+        .to_ascii_lowercase()
+        .find(&query.to_ascii_lowercase())?;
+    Some(start..start + query.len())
+}
+
+/// The path an error of walking the tree is about
+fn error_path(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            error_path(err)
+        }
+        _ => None,
+    }
+}
+
+/// Builds an `Override` that ignores certain paths
+fn build_override_ignorer(root: impl AsRef<Path>, ignore: &[String]) -> Result<Override> {
+    let mut override_builder = OverrideBuilder::new(root.as_ref());
+
+    for pat in ignore.iter().map(|x| x.as_str()).chain(iter::once("/.git")) {
+        override_builder
+            .add(&format!("!{pat}"))
+            .with_context(|| format!("failed to add glob for: \"!{pat}\""))?
+            .add(&format!("!{pat}/**"))
+            .with_context(|| format!("failed to add glob for: \"!{pat}/**\""))?;
+    }
+    override_builder
+        .build()
+        .context("failed to build override ignorer")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app::components::testing::*, config::FiletreeConfig, queue::TmuxOpts};
+    use collect_all::collect;
+    use smallvec::smallvec;
+    use test_log::test;
+    use tui::{backend::TestBackend, Terminal};
+
+    /// Create temporary files and return the temp dir
+    macro_rules! temp_files {
+    ($($name:expr),*) => {
+            {
+                #[allow(unused_imports)]
+                use ::assert_fs::prelude::*;
+
+                let __temp = ::assert_fs::TempDir::new().unwrap();
+                $(
+                    __temp.child($name).touch().unwrap();
+                 )*
+                __temp
+            }
+        };
+    }
+
+    #[test]
+    fn new_filetree_selects_first() {
+        let temp = temp_files!("test.txt");
+        let path = temp.path().to_owned();
+        let filetree =
+            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+        assert_eq!(
+            path.join("test.txt"),
+            filetree.get_selected().unwrap().path()
+        );
+    }
+
+    #[test]
+    fn sends_delete_event() {
+        let temp = temp_files!("test.txt");
+        let path = temp.path().to_owned();
+        let mut filetree =
+            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+
+        let d = input_event!(KeyCode::Char('d'));
+        filetree
+            .handle_event(&d)
+            .expect("should be able to handle keypress");
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::OpenPopup(PendingOperation::DeleteFile(
+                path.join("test.txt")
+            ))));
+    }
+
+    #[test]
+    fn sends_new_file_and_new_dir_events() {
+        let temp = temp_files!("test.txt");
+        let path = temp.path().to_owned();
+        let mut filetree =
+            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+
+        let n = input_event!(KeyCode::Char('n'));
+        filetree
+            .handle_event(&n)
+            .expect("should be able to handle keypress");
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::OpenInput(InputOperation::NewFile {
+                at: path.clone()
+            })));
+
+        let caps_n = input_event!(KeyCode::Char('N'); KeyModifiers::SHIFT);
+        filetree
+            .handle_event(&caps_n)
+            .expect("should be able to handle keypress");
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::OpenInput(InputOperation::NewDir { at: path })));
+    }
+
+    #[test]
+    fn makes_new_file_as_sibling_when_selected_dir_is_closed() {
+        let temp = temp_files!("test/test.txt");
+        let path = temp.path().to_owned();
+        let mut filetree =
+            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+        assert_eq!(
+            path.join("test"),
+            filetree.listing.selected_item().unwrap().path()
+        );
+
+        let n = input_event!(KeyCode::Char('n'));
+        filetree
+            .handle_event(&n)
+            .expect("should be able to handle keypress");
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::OpenInput(InputOperation::NewFile { at: path })));
+    }
+
+    #[test]
+    fn makes_new_file_as_child_when_selected_dir_is_open() {
+        let temp = temp_files!("test/test.txt");
+        let path = temp.path().to_owned();
+        let mut filetree =
+            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
+        // Opens dir
+        filetree.listing.toggle_fold();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+
+        let n = input_event!(KeyCode::Char('n'));
+        filetree
+            .handle_event(&n)
+            .expect("should be able to handle keypress");
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::OpenInput(InputOperation::NewFile {
+                at: path.join("test")
+            })));
+    }
+
+    #[test]
+    fn enter_opens_when_over_dir() {
+        let temp = temp_files!("test/test.txt");
+        let mut filetree =
+            Filetree::from_dir(temp.path(), Queue::new()).expect("should be able to make filetree");
+        filetree.listing.toggle_fold();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+
+        let enter = input_event!(KeyCode::Enter);
+        filetree
+            .handle_event(&enter)
+            .expect("should be able to handle keypress");
+        assert!(filetree
+            .listing
+            .is_folded(filetree.listing.selected().unwrap())
+            .unwrap());
+    }
+
+    #[test]
+    fn enter_sends_open_file_when_over_files() {
         let temp = temp_files!("test.txt");
         let path = temp.path().to_owned();
         let mut filetree =
             Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
         scopeguard::guard(temp, |temp| temp.close().unwrap());
 
-        let d = input_event!(KeyCode::Char('d'));
+        let enter = input_event!(KeyCode::Enter);
+        filetree
+            .handle_event(&enter)
+            .expect("should be able to handle keypress");
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::OpenFile(path.join("test.txt"))));
+    }
+
+    #[test]
+    fn enter_does_the_configured_action_for_the_kind() {
+        let temp = temp_files!("dir/test.txt");
+        let mut config = Config::default();
+        config.filetree.dir_enter_action = EnterAction::Reroot;
+        config.filetree.file_enter_action = EnterAction::Run("echo {}".to_owned());
+        let mut filetree = Filetree::from_dir_with_config(
+            temp.path(),
+            Queue::new(),
+            Rc::new(config),
+            Rc::default(),
+        )
+        .unwrap();
+        let dir = temp.join("dir");
+        let file = dir.join("test.txt");
+
+        let enter = input_event!(KeyCode::Enter);
+        filetree.handle_event(&enter).unwrap();
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::Reroot(dir.clone(), dir.clone())));
+        filetree.open_path(&file).unwrap();
+        filetree.handle_event(&enter).unwrap();
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::RunCommand(command::fill_path("echo {}", &file))));
+    }
+
+    #[test]
+    fn opening_search_result_jumps_to_match() {
+        use assert_fs::prelude::*;
+
+        let temp = temp_files!();
+        temp.child("found.txt")
+            .write_str("nothing\nsomething here")
+            .unwrap();
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree
+            .show_search_results("something", &[temp.join("found.txt")])
+            .unwrap();
+
+        filetree
+            .handle_event(&input_event!(KeyCode::Enter))
+            .unwrap();
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::OpenFileAt(temp.join("found.txt"), 2, Some(1))));
+    }
+
+    #[test]
+    fn can_jump_down_by_three() {
+        let temp = temp_files!("test.txt", "test2.txt", "test3.txt", "test4.txt");
+        let mut filetree =
+            Filetree::from_dir(temp.path(), Queue::new()).expect("should be able to make filetree");
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+
+        let ctrl_n = input_event!(KeyCode::Char('n'); KeyModifiers::CONTROL);
+        filetree
+            .handle_event(&ctrl_n)
+            .expect("should be able to handle keypress");
+        assert_eq!(3, filetree.listing.selected().unwrap());
+    }
+
+    #[test]
+    fn can_jump_up_by_three() {
+        let temp = temp_files!("test.txt", "test2.txt", "test3.txt", "test4.txt");
+        let mut filetree =
+            Filetree::from_dir(temp.path(), Queue::new()).expect("should be able to make filetree");
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+
+        let inputs = input_events!(KeyCode::Char('G'); KeyModifiers::SHIFT, KeyCode::Char('p'); KeyModifiers::CONTROL);
+        for input in inputs {
+            filetree
+                .handle_event(&input)
+                .expect("should be able to handle keypress");
+        }
+        assert_eq!(0, filetree.listing.selected().unwrap());
+    }
+
+    #[test]
+    fn can_send_run_cmd() {
+        let temp = temp_files!("test.txt");
+        let mut filetree =
+            Filetree::from_dir(temp.path(), Queue::new()).expect("should be able to make filetree");
+        let path = temp.to_path_buf();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+
+        let e = input_event!(KeyCode::Char('e'));
+        filetree
+            .handle_event(&e)
+            .expect("should be able to handle event");
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::OpenInput(InputOperation::Command {
+                to: path.join("test.txt")
+            })));
+    }
+
+    #[test]
+    fn can_send_search_cmd() {
+        let temp = temp_files!();
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+
+        let slash = input_event!(KeyCode::Char('/'));
+        filetree
+            .handle_event(&slash)
+            .expect("should be able to handle event");
+        assert!(filetree.queue.contains(&AppEvent::SearchFiles));
+    }
+
+    #[test]
+    fn can_send_toggle_preview_cmd() {
+        let temp = temp_files!();
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+
+        let slash = input_event!(KeyCode::Char('t'));
+        filetree
+            .handle_event(&slash)
+            .expect("should be able to handle event");
+        assert!(filetree.queue.contains(&AppEvent::TogglePreviewMode));
+    }
+
+    #[test]
+    fn staging_uses_multi_selection() {
+        let temp = temp_files!("a.txt", "b.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.open_path(temp.join("a.txt")).unwrap();
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('s')))
+            .unwrap();
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::Stage(vec![temp.join("a.txt")])));
+
+        filetree.toggle_multi_select();
+        filetree.open_path(temp.join("b.txt")).unwrap();
+        filetree.toggle_multi_select();
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('u')))
+            .unwrap();
+        assert!(filetree.queue.contains(&AppEvent::Unstage(vec![
+            temp.join("a.txt"),
+            temp.join("b.txt")
+        ])));
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('S'); KeyModifiers::SHIFT))
+            .unwrap();
+        assert!(filetree.queue.contains(&AppEvent::StageAll));
+    }
+
+    #[test]
+    fn multi_selected_files_open_together() {
+        let temp = temp_files!("a.txt", "b.txt", "dir/c.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        let enter = input_event!(KeyCode::Enter);
+        for path in ["a.txt", "dir", "b.txt"] {
+            filetree.open_path(temp.join(path)).unwrap();
+            filetree.toggle_multi_select();
+        }
+        filetree.handle_event(&enter).unwrap();
+        assert!(filetree.queue.contains(&AppEvent::OpenFiles(vec![
+            temp.join("a.txt"),
+            temp.join("b.txt")
+        ])));
+    }
+
+    #[test]
+    fn copying_paths_uses_multi_selection() {
+        let temp = temp_files!("a.txt", "b.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        let alt_y = input_event!(KeyCode::Char('y'); KeyModifiers::ALT);
+        filetree.open_path(temp.join("a.txt")).unwrap();
+        filetree.handle_event(&alt_y).unwrap();
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::CopyPaths(vec![temp.join("a.txt")])));
+
+        filetree.toggle_multi_select();
+        filetree.open_path(temp.join("b.txt")).unwrap();
+        filetree.toggle_multi_select();
+        filetree.handle_event(&alt_y).unwrap();
+        assert!(filetree.queue.contains(&AppEvent::CopyPaths(vec![
+            temp.join("a.txt"),
+            temp.join("b.txt")
+        ])));
+    }
+
+    #[test]
+    fn selection_is_drawn_with_its_symbol() {
+        let temp = temp_files!("a.txt", "b.txt");
+        let config = Config {
+            selected_symbol: "> ".to_owned(),
+            ..Default::default()
+        };
+        let mut filetree = Filetree::from_dir_with_config(
+            temp.path(),
+            Queue::new(),
+            Rc::new(config),
+            Rc::default(),
+        )
+        .unwrap();
+        filetree.open_path(temp.join("b.txt")).unwrap();
+        let mut terminal = Terminal::new(TestBackend::new(20, 4)).unwrap();
+        let frame = terminal
+            .draw(|f| filetree.draw(f, f.size()).unwrap())
+            .unwrap();
+        let row = |y: usize| -> String {
+            frame.buffer.content[y * 20 + 1..y * 20 + 19]
+                .iter()
+                .map(|cell| cell.symbol.as_str())
+                .collect()
+        };
+        assert!(row(1).starts_with("  ") && row(1).contains("a.txt"));
+        assert!(row(2).starts_with("> ") && row(2).contains("b.txt"));
+    }
+
+    #[test]
+    fn later_search_results_are_added_to_those_shown() {
+        let temp = temp_files!("a/one.txt", "a/two.txt", "b/three.txt", "top.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree
+            .show_search_results("x", &[temp.join("a/one.txt"), temp.join("top.txt")])
+            .unwrap();
+        filetree.open_path(temp.join("top.txt")).unwrap();
+        filetree.listing.fold(temp.join("a").as_path());
+
+        filetree.add_search_results(&[temp.join("a/two.txt"), temp.join("b/three.txt")]);
+        assert_eq!(
+            vec![
+                temp.join("a"),
+                temp.join("a/one.txt"),
+                temp.join("a/two.txt"),
+                temp.join("b"),
+                temp.join("b/three.txt"),
+                temp.join("top.txt")
+            ],
+            filetree
+                .listing
+                .all_items()
+                .iter()
+                .map(|item| item.path().to_path_buf())
+                .collect_vec()
+        );
+        assert_eq!(Some(true), filetree.listing.is_folded(temp.join("a")));
+        assert_eq!(
+            temp.join("top.txt"),
+            filetree.get_selected().unwrap().path()
+        );
+
+        // Reading the tree again keeps every result
+        filetree.full_refresh().unwrap();
+        assert_eq!(6, filetree.listing.all_items().len());
+    }
+
+    #[test]
+    fn search_matches_are_counted_for_files_and_their_dirs() {
+        let temp = temp_files!("dir/a.txt", "dir/b.txt", "top.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        let found = |file: &str, lines: &[bool]| ContentMatch {
+            path: temp.join(file),
+            groups: vec![lines
+                .iter()
+                .enumerate()
+                .map(|(number, &matches)| search::ExcerptLine {
+                    number: number + 1,
+                    text: String::new(),
+                    matches,
+                })
+                .collect()],
+            match_count: lines.iter().filter(|&&matches| matches).count(),
+        };
+
+        let files = [temp.join("dir/a.txt"), temp.join("top.txt")];
+        filetree.show_search_results("x", &files).unwrap();
+        filetree.count_matches(&[
+            found("dir/a.txt", &[true, false, true]),
+            found("top.txt", &[true]),
+        ]);
+        // Streamed in later
+        filetree.count_matches(&[found("dir/b.txt", &[true])]);
+        assert_eq!(Some(&2), filetree.match_counts.get(&temp.join("dir/a.txt")));
+        assert_eq!(Some(&3), filetree.match_counts.get(&temp.join("dir")));
+        assert_eq!(Some(&1), filetree.match_counts.get(&temp.join("top.txt")));
+        assert!(!filetree.match_counts.contains_key(temp.path()));
+
+        let mut terminal = Terminal::new(TestBackend::new(30, 6)).unwrap();
+        let frame = terminal
+            .draw(|f| filetree.draw(f, f.size()).unwrap())
+            .unwrap();
+        let text: String = frame
+            .buffer
+            .content
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect();
+        assert!(text.contains("dir (3)"));
+        assert!(text.contains("top.txt (1)"));
+
+        // Reading the tree again keeps showing the results, with their counts
+        filetree.full_refresh().unwrap();
+        assert!(filetree.is_showing_search_results());
+        assert_eq!(3, filetree.listing.all_items().len());
+        assert_eq!(Some(&3), filetree.match_counts.get(&temp.join("dir")));
+        // Unless something else took the place of the search
+        filetree.toggle_dotfiles().unwrap();
+        filetree.full_refresh().unwrap();
+        assert!(!filetree.is_showing_search_results());
+        assert!(filetree.match_counts.is_empty());
+
+        filetree.show_search_results("x", &files).unwrap();
+        filetree.count_matches(&[found("top.txt", &[true])]);
+        filetree.refresh().unwrap();
+        assert!(filetree.match_counts.is_empty());
+    }
+
+    #[test]
+    fn copying_a_name_part_takes_the_selection() {
+        let temp = temp_files!("a.txt", "b.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.open_path(temp.join("b.txt")).unwrap();
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('.'); KeyModifiers::ALT))
+            .unwrap();
+        assert!(filetree.queue.contains(&AppEvent::CopyNamePart(
+            temp.join("b.txt"),
+            NamePart::Extension
+        )));
+    }
+
+    #[test]
+    fn moving_uses_multi_selection() {
+        let temp = temp_files!("a.txt", "b.txt", "dest/c.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.open_path(temp.join("a.txt")).unwrap();
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('R'); KeyModifiers::SHIFT))
+            .unwrap();
+        assert!(iter::from_fn(|| filetree.queue.pop()).any(|event| matches!(
+            event,
+            AppEvent::OpenFuzzy(_, FuzzyOperation::MoveFile(path)) if *path == temp.join("a.txt")
+        )));
+
+        filetree.toggle_multi_select();
+        filetree.open_path(temp.join("b.txt")).unwrap();
+        filetree.toggle_multi_select();
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('R'); KeyModifiers::SHIFT))
+            .unwrap();
+        assert!(iter::from_fn(|| filetree.queue.pop()).any(|event| matches!(
+            event,
+            AppEvent::OpenFuzzy(_, FuzzyOperation::MoveFiles(paths))
+                if *paths == vec![temp.join("a.txt"), temp.join("b.txt")]
+        )));
+    }
+
+    #[test]
+    fn recent_dirs_are_newest_first() {
+        let temp = temp_files!("a/x.txt", "b/y.txt", "c/z.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.open_path(temp.join("a/x.txt")).unwrap();
+        filetree.open_path(temp.join("b")).unwrap();
+        filetree.listing.select(temp.join("c").as_path());
+        filetree
+            .handle_event(&input_event!(KeyCode::Enter))
+            .unwrap();
+        filetree.open_path(temp.join("a/x.txt")).unwrap();
+        assert_eq!(
+            vec![temp.join("a"), temp.join("c"), temp.join("b")],
+            filetree.recent_dirs()
+        );
+
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('H'); KeyModifiers::SHIFT))
+            .unwrap();
+        assert!(filetree.queue.contains(&AppEvent::OpenFuzzy(
+            vec!["b".to_owned(), "c".to_owned(), "a".to_owned()],
+            FuzzyOperation::OpenFile
+        )));
+    }
+
+    #[test]
+    fn flat_mode_lists_files_and_restores_the_tree() {
+        let temp = temp_files!("b/c/deep.txt", "b/one.txt", "top.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('f'); KeyModifiers::ALT))
+            .unwrap();
+        assert!(filetree.is_flat());
+        assert_eq!(
+            vec![
+                &Item::File(temp.join("b/c/deep.txt")),
+                &Item::File(temp.join("b/one.txt")),
+                &Item::File(temp.join("top.txt"))
+            ],
+            filetree.listing.items()
+        );
+
+        std::fs::File::create(temp.join("b/c/new.txt")).unwrap();
+        filetree
+            .partial_refresh(&RefreshData::Add(temp.join("b/c/new.txt")))
+            .unwrap();
+        assert_eq!(4, filetree.listing.len());
+        filetree.listing.select(temp.join("b/one.txt").as_path());
+
+        filetree.toggle_flat_mode().unwrap();
+        assert!(!filetree.is_flat());
+        assert_eq!(
+            Some(&Item::File(temp.join("b/one.txt"))),
+            filetree.get_selected()
+        );
+        assert!(filetree
+            .listing
+            .all_items()
+            .contains(&Item::File(temp.join("b/c/new.txt"))));
+    }
+
+    #[test]
+    fn partial_refresh_delete_goes_to_same_item() {
+        let temp = temp_files!("test/test.txt", "test/test2.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+        filetree.listing.unfold_all();
+        filetree.listing.select(1);
+        filetree
+            .partial_refresh(&RefreshData::Delete(
+                filetree
+                    .listing
+                    .selected_item()
+                    .unwrap()
+                    .path()
+                    .to_path_buf(),
+            ))
+            .unwrap();
+        assert_eq!(1, filetree.listing.selected().unwrap());
+    }
+
+    #[test]
+    fn partial_refresh_goes_to_parent_if_only_child() {
+        let temp = temp_files!("test/test.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+        filetree.listing.unfold_all();
+        filetree.listing.select(1);
         filetree
-            .handle_event(&d)
-            .expect("should be able to handle keypress");
+            .partial_refresh(&RefreshData::Delete(
+                filetree
+                    .listing
+                    .selected_item()
+                    .unwrap()
+                    .path()
+                    .to_path_buf(),
+            ))
+            .unwrap();
+        assert_eq!(0, filetree.listing.selected().unwrap());
+    }
+
+    #[test]
+    fn partial_refresh_and_rename_keep_selected_path() {
+        let temp = temp_files!("dir/a.txt", "dir/b.txt", "other/c.txt", "top.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.listing.unfold_all();
+        filetree.open_path(temp.join("top.txt")).unwrap();
+        let selected_in_state = |filetree: &mut Filetree| {
+            let index = filetree.state.get_mut().selected().unwrap();
+            filetree.listing.items()[index].path().to_path_buf()
+        };
+
+        std::fs::File::create(temp.join("dir/new.txt")).unwrap();
+        filetree
+            .partial_refresh(&RefreshData::Add(temp.join("dir/new.txt")))
+            .unwrap();
+        assert_eq!(temp.join("top.txt"), selected_in_state(&mut filetree));
+
+        std::fs::rename(temp.join("dir"), temp.join("renamed")).unwrap();
+        filetree
+            .rename(temp.join("dir"), temp.join("renamed"))
+            .unwrap();
+        assert_eq!(temp.join("top.txt"), selected_in_state(&mut filetree));
+        assert!(!filetree
+            .listing
+            .is_folded(temp.join("renamed").as_path())
+            .unwrap());
+
+        filetree.open_path(temp.join("renamed/b.txt")).unwrap();
+        filetree.listing.fold(temp.join("other").as_path());
+        std::fs::remove_file(temp.join("renamed/b.txt")).unwrap();
+        filetree
+            .partial_refresh(&RefreshData::Delete(temp.join("renamed/b.txt")))
+            .unwrap();
+        assert_eq!(temp.join("renamed/a.txt"), selected_in_state(&mut filetree));
         assert!(filetree
             .queue
-            .contains(&AppEvent::OpenPopup(PendingOperation::DeleteFile(
-                path.join("test.txt")
-            ))));
+            .contains(&AppEvent::PreviewFile(temp.join("renamed/a.txt"))));
+        assert!(filetree
+            .listing
+            .is_folded(temp.join("other").as_path())
+            .unwrap());
+    }
+
+    #[test]
+    fn can_open_all() {
+        let temp = temp_files!("test.txt", "test/test2.txt", "test2/test4/test.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+        filetree.open_all();
+        assert_eq!(6, filetree.listing.len());
+    }
+
+    #[test]
+    fn can_mark_selected() {
+        let temp = temp_files!("test.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        let path = temp.path().to_path_buf();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+        let event = input_event!(KeyCode::Char('m'));
+        assert!(filetree.handle_event(&event).is_ok());
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::Mark(path.join("test.txt"))));
+    }
+
+    #[test]
+    fn toggling_mark_unmarks_marked() {
+        let temp = temp_files!("test.txt");
+        let marks = Rc::new(RefCell::new(Marks::default()));
+        let mut filetree = Filetree::from_dir_with_config(
+            temp.path(),
+            Queue::new(),
+            Rc::new(Config::default()),
+            Rc::clone(&marks),
+        )
+        .unwrap();
+        let path = temp.join("test.txt");
+        let toggle = input_event!(KeyCode::Char('m'); KeyModifiers::ALT);
+
+        filetree.handle_event(&toggle).unwrap();
+        assert!(filetree.queue.contains(&AppEvent::Mark(path.clone())));
+        assert!(!filetree.queue.contains(&AppEvent::Unmark(path.clone())));
+        marks.borrow_mut().marks.push(path.clone());
+        filetree.handle_event(&toggle).unwrap();
+        assert!(filetree.queue.contains(&AppEvent::Unmark(path)));
+    }
+
+    #[test]
+    fn swallow_invalid_delete_external_events() {
+        let temp = temp_files!("test.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+        let event = ExternalEvent::PartialRefresh(smallvec![RefreshData::Delete(
+            "does_not_exist.txt".into()
+        )]);
+        assert!(filetree.handle_event(&event).is_ok());
+    }
+
+    #[test]
+    fn opening_path_adds_preview_event_to_queue() {
+        let temp = temp_files!("test.txt");
+        let path = temp.path().to_path_buf();
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+        filetree.open_path(path.join("test.txt")).unwrap();
+
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::PreviewFile(path.join("test.txt"))));
+    }
+
+    #[test]
+    fn can_ignore() {
+        let temp = temp_files!("test.txt", "test2.txt");
+        let config = Config {
+            filetree: FiletreeConfig {
+                ignore: vec!["test2.txt".to_owned()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let filetree = Filetree::from_dir_with_config(
+            temp.path(),
+            Queue::new(),
+            Rc::new(config),
+            Rc::new(RefCell::new(Marks::default())),
+        )
+        .unwrap();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+
+        assert_eq!(1, filetree.listing.items().len());
+        assert_eq!(
+            Path::new("test.txt"),
+            filetree.listing.items()[0].path().file_name().unwrap()
+        );
+    }
+
+    #[test]
+    fn can_full_refresh_to_account_for_new_files() {
+        let temp = temp_files!("test.txt", "test2.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        std::fs::File::create(temp.path().join("new.txt")).unwrap();
+
+        assert_eq!(2, filetree.listing.len());
+        assert!(filetree.refresh().is_ok());
+        assert_eq!(3, filetree.listing.len());
+    }
+
+    #[test]
+    fn can_partial_refresh_to_add_files() {
+        let temp = temp_files!("test.txt", "test2.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        std::fs::File::create(temp.path().join("new.txt")).unwrap();
+
+        assert_eq!(2, filetree.listing.len());
+        assert!(filetree
+            .partial_refresh(&RefreshData::Add(temp.join("new.txt")))
+            .is_ok());
+        assert_eq!(
+            Path::new("new.txt"),
+            filetree
+                .listing
+                .items()
+                .first()
+                .unwrap()
+                .path()
+                .file_name()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn can_filter_include_certain_files() {
+        let temp = temp_files!("test.txt", "test2.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+
+        assert!(filetree
+            .filter_include(&[temp.path().join("test.txt")])
+            .is_ok());
+        assert_eq!(
+            vec![&Item::File(temp.path().join("test.txt"))],
+            filetree.listing.items()
+        );
     }
 
     #[test]
-    fn sends_new_file_and_new_dir_events() {
-        let temp = temp_files!("test.txt");
-        let path = temp.path().to_owned();
-        let mut filetree =
-            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
-
-        let n = input_event!(KeyCode::Char('n'));
-        filetree
-            .handle_event(&n)
-            .expect("should be able to handle keypress");
-        assert!(filetree
-            .queue
-            .contains(&AppEvent::OpenInput(InputOperation::NewFile {
-                at: path.clone()
-            })));
+    fn show_only_keeps_parent_dirs() {
+        let temp = temp_files!("dir/test.txt", "dir/test2.txt", "other.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
 
-        let caps_n = input_event!(KeyCode::Char('N'); KeyModifiers::SHIFT);
-        filetree
-            .handle_event(&caps_n)
-            .expect("should be able to handle keypress");
         assert!(filetree
-            .queue
-            .contains(&AppEvent::OpenInput(InputOperation::NewDir { at: path })));
+            .show_only(&[temp.path().join("dir/test.txt")])
+            .is_ok());
+        assert_eq!(
+            vec![
+                &Item::Dir(temp.path().join("dir")),
+                &Item::File(temp.path().join("dir/test.txt"))
+            ],
+            filetree.listing.items()
+        );
     }
 
     #[test]
-    fn makes_new_file_as_sibling_when_selected_dir_is_closed() {
-        let temp = temp_files!("test/test.txt");
-        let path = temp.path().to_owned();
-        let mut filetree =
-            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
+    fn filtering_selects_a_visible_match() {
+        let temp = temp_files!("a/x.txt", "b/dir/match.txt", "b/other.txt", "top.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.open_path(temp.join("a/x.txt")).unwrap();
+
+        filetree.show_only(&[temp.join("b/dir/match.txt")]).unwrap();
+        let selected = filetree.get_selected().unwrap().path().to_path_buf();
+        assert_eq!(temp.join("b/dir/match.txt"), selected);
+        assert!(filetree
+            .listing
+            .items()
+            .iter()
+            .any(|item| item.path() == selected));
         assert_eq!(
-            path.join("test"),
-            filetree.listing.selected_item().unwrap().path()
+            filetree.listing.selected(),
+            filetree.state.get_mut().selected()
         );
 
-        let n = input_event!(KeyCode::Char('n'));
         filetree
-            .handle_event(&n)
-            .expect("should be able to handle keypress");
-        assert!(filetree
-            .queue
-            .contains(&AppEvent::OpenInput(InputOperation::NewFile { at: path })));
+            .filter_include(&[temp.join("b"), temp.join("top.txt")])
+            .unwrap();
+        assert_eq!(selected, filetree.get_selected().unwrap().path());
+        filetree.filter_include(&[temp.join("a")]).unwrap();
+        assert_eq!(temp.join("a"), filetree.get_selected().unwrap().path());
     }
 
     #[test]
-    fn makes_new_file_as_child_when_selected_dir_is_open() {
-        let temp = temp_files!("test/test.txt");
-        let path = temp.path().to_owned();
-        let mut filetree =
-            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
-        // Opens dir
-        filetree.listing.toggle_fold();
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
+    fn git_filter_only_includes_given_paths() {
+        let temp = temp_files!("dir/test.txt", "dir/test2.txt", "other.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
 
-        let n = input_event!(KeyCode::Char('n'));
-        filetree
-            .handle_event(&n)
-            .expect("should be able to handle keypress");
         assert!(filetree
-            .queue
-            .contains(&AppEvent::OpenInput(InputOperation::NewFile {
-                at: path.join("test")
-            })));
+            .filter_include(&[temp.path().join("dir/test.txt")])
+            .is_ok());
+        assert_eq!(
+            vec![&Item::File(temp.path().join("dir/test.txt"))],
+            filetree.listing.items()
+        );
     }
 
     #[test]
-    fn enter_opens_when_over_dir() {
-        let temp = temp_files!("test/test.txt");
-        let mut filetree =
-            Filetree::from_dir(temp.path(), Queue::new()).expect("should be able to make filetree");
-        filetree.listing.toggle_fold();
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
+    fn full_refresh_keeps_opened_dirs_and_selection() {
+        let temp = temp_files!("open/file.txt", "closed/file.txt", "top.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.open_path(temp.join("open/file.txt")).unwrap();
+        std::fs::File::create(temp.join("open/new.txt")).unwrap();
 
-        let enter = input_event!(KeyCode::Enter);
-        filetree
-            .handle_event(&enter)
-            .expect("should be able to handle keypress");
+        filetree.full_refresh().unwrap();
+        assert_eq!(
+            temp.join("open/file.txt"),
+            filetree.get_selected().unwrap().path()
+        );
+        assert_eq!(Some(false), filetree.listing.is_folded(temp.join("open")));
+        assert_eq!(Some(true), filetree.listing.is_folded(temp.join("closed")));
         assert!(filetree
             .listing
-            .is_folded(filetree.listing.selected().unwrap())
-            .unwrap());
+            .items()
+            .contains(&&Item::File(temp.join("open/new.txt"))));
     }
 
     #[test]
-    fn enter_sends_open_file_when_over_files() {
-        let temp = temp_files!("test.txt");
-        let path = temp.path().to_owned();
-        let mut filetree =
-            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
+    fn restore_reopens_dirs_and_selection() {
+        let temp = temp_files!("a/b/c.txt", "d/e.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.open_path(temp.join("a/b/c.txt")).unwrap();
+        let (dirs, selected) = (
+            filetree.open_dirs(),
+            filetree
+                .get_selected()
+                .map(|item| item.path().to_path_buf()),
+        );
+        assert_eq!(vec![temp.join("a"), temp.join("a/b")], dirs);
 
-        let enter = input_event!(KeyCode::Enter);
-        filetree
-            .handle_event(&enter)
-            .expect("should be able to handle keypress");
-        assert!(filetree
-            .queue
-            .contains(&AppEvent::OpenFile(path.join("test.txt"))));
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.restore(
+            &[dirs, vec![temp.join("gone")]].concat(),
+            selected.as_deref(),
+        );
+        assert_eq!(vec![temp.join("a"), temp.join("a/b")], filetree.open_dirs());
+        assert_eq!(
+            Some(&Item::File(temp.join("a/b/c.txt"))),
+            filetree.get_selected()
+        );
     }
 
     #[test]
-    fn can_jump_down_by_three() {
-        let temp = temp_files!("test.txt", "test2.txt", "test3.txt", "test4.txt");
-        let mut filetree =
-            Filetree::from_dir(temp.path(), Queue::new()).expect("should be able to make filetree");
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
+    fn full_refresh_drops_selection_of_removed_file() {
+        let temp = temp_files!("gone.txt", "kept.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.open_path(temp.join("gone.txt")).unwrap();
+        std::fs::remove_file(temp.join("gone.txt")).unwrap();
 
-        let ctrl_n = input_event!(KeyCode::Char('n'); KeyModifiers::CONTROL);
-        filetree
-            .handle_event(&ctrl_n)
-            .expect("should be able to handle keypress");
-        assert_eq!(3, filetree.listing.selected().unwrap());
+        filetree.full_refresh().unwrap();
+        assert_eq!(1, filetree.listing.len());
+        assert_eq!(
+            temp.join("kept.txt"),
+            filetree.get_selected().unwrap().path()
+        );
     }
 
     #[test]
-    fn can_jump_up_by_three() {
-        let temp = temp_files!("test.txt", "test2.txt", "test3.txt", "test4.txt");
-        let mut filetree =
-            Filetree::from_dir(temp.path(), Queue::new()).expect("should be able to make filetree");
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
-
-        let inputs = input_events!(KeyCode::Char('G'); KeyModifiers::SHIFT, KeyCode::Char('p'); KeyModifiers::CONTROL);
-        for input in inputs {
+    fn full_refresh_filters_the_new_tree_again() {
+        let temp = temp_files!("dir/a.rs", "dir/b.txt", "top.rs");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        let paths = |filetree: &Filetree| {
             filetree
-                .handle_event(&input)
-                .expect("should be able to handle keypress");
+                .listing
+                .all_items()
+                .iter()
+                .map(|item| item.path().to_path_buf())
+                .collect_vec()
+        };
+
+        filetree.filter_extensions("rs").unwrap();
+        std::fs::File::create(temp.join("dir/new.rs")).unwrap();
+        std::fs::File::create(temp.join("new.txt")).unwrap();
+        filetree.full_refresh().unwrap();
+        assert!(filetree.has_something_to_clear());
+        assert_eq!(
+            vec![
+                temp.join("dir"),
+                temp.join("dir/a.rs"),
+                temp.join("dir/new.rs"),
+                temp.join("top.rs")
+            ],
+            paths(&filetree)
+        );
+
+        filetree.filter_include(&[temp.join("dir")]).unwrap();
+        filetree.full_refresh().unwrap();
+        assert_eq!(4, paths(&filetree).len());
+
+        filetree.start_quick_filter();
+        for event in input_events!(KeyCode::Char('n'), KeyCode::Char('e')) {
+            filetree.handle_event(&event).unwrap();
         }
-        assert_eq!(0, filetree.listing.selected().unwrap());
+        filetree.full_refresh().unwrap();
+        assert!(filetree.is_filtering());
+        assert_eq!(
+            vec![temp.join("dir"), temp.join("dir/new.rs")],
+            paths(&filetree)
+        );
+        filetree.end_quick_filter(false).unwrap();
+        assert_eq!(4, paths(&filetree).len());
     }
 
     #[test]
-    fn can_send_run_cmd() {
-        let temp = temp_files!("test.txt");
-        let mut filetree =
-            Filetree::from_dir(temp.path(), Queue::new()).expect("should be able to make filetree");
-        let path = temp.to_path_buf();
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
+    fn expand_matching_opens_matches_and_parents() {
+        let temp = temp_files!("a/src/main.rs", "b/src/lib.rs", "c/docs/readme.md");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
 
-        let e = input_event!(KeyCode::Char('e'));
-        filetree
-            .handle_event(&e)
-            .expect("should be able to handle event");
-        assert!(filetree
-            .queue
-            .contains(&AppEvent::OpenInput(InputOperation::Command {
-                to: path.join("test.txt")
-            })));
+        assert_eq!(4, filetree.expand_matching("src").unwrap());
+        for dir in ["a", "a/src", "b", "b/src"] {
+            assert_eq!(Some(false), filetree.listing.is_folded(temp.join(dir)));
+        }
+        assert_eq!(Some(true), filetree.listing.is_folded(temp.join("c")));
+        assert!(filetree.expand_matching("[").is_err());
     }
 
     #[test]
-    fn can_send_search_cmd() {
-        let temp = temp_files!();
+    fn filters_by_extensions_and_remembers_them() {
+        let temp = temp_files!("src/main.rs", "Cargo.toml", "README.md", "docs/a.md");
         let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
 
-        let slash = input_event!(KeyCode::Char('/'));
-        filetree
-            .handle_event(&slash)
-            .expect("should be able to handle event");
-        assert!(filetree.queue.contains(&AppEvent::SearchFiles(Vec::new())));
+        assert_eq!(2, filetree.filter_extensions("rs, .toml").unwrap());
+        let shown = filetree
+            .listing
+            .all_items()
+            .iter()
+            .map(|item| item.path().strip_prefix(temp.path()).unwrap().to_owned())
+            .collect_vec();
+        assert_eq!(
+            vec![
+                Path::new("src"),
+                Path::new("src/main.rs"),
+                Path::new("Cargo.toml")
+            ],
+            shown
+        );
+        assert_eq!(2, filetree.filter_extensions("*.md").unwrap());
+        assert_eq!(
+            vec!["md".to_owned(), "rs, toml".to_owned()],
+            filetree.recent_extensions
+        );
+        assert!(filetree.filter_extensions(" , ").is_err());
     }
 
     #[test]
-    fn can_send_toggle_preview_cmd() {
-        let temp = temp_files!();
-        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
+    fn marks_empty_and_unreadable_dirs() {
+        let temp = temp_files!("a.txt", "full/b.txt");
+        std::fs::create_dir(temp.path().join("empty")).unwrap();
+        let filetree =
+            Filetree::from_dir_with_config(temp.path(), Queue::new(), Rc::default(), Rc::default())
+                .unwrap();
+        let marker = |name: &str| {
+            filetree
+                .dir_marker(&Item::File(temp.path().join(name)))
+                .map(|(marker, _)| marker)
+        };
+        assert_eq!(Some(" (empty)"), marker("empty"));
+        assert_eq!(None, marker("a.txt"));
+        assert_eq!(
+            None,
+            filetree.dir_marker(&Item::Dir(temp.path().join("full")))
+        );
 
-        let slash = input_event!(KeyCode::Char('t'));
         filetree
-            .handle_event(&slash)
-            .expect("should be able to handle event");
-        assert!(filetree.queue.contains(&AppEvent::TogglePreviewMode));
+            .unreadable
+            .borrow_mut()
+            .insert(temp.path().join("empty"));
+        assert_eq!(Some(" (unreadable)"), marker("empty"));
     }
 
     #[test]
-    fn partial_refresh_delete_goes_to_same_item() {
-        let temp = temp_files!("test/test.txt", "test/test2.txt");
-        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
-        filetree.listing.unfold_all();
-        filetree.listing.select(1);
-        filetree
-            .partial_refresh(&RefreshData::Delete(
-                filetree
-                    .listing
-                    .selected_item()
-                    .unwrap()
-                    .path()
-                    .to_path_buf(),
-            ))
-            .unwrap();
-        assert_eq!(1, filetree.listing.selected().unwrap());
+    fn max_depth_stops_the_walk() {
+        let temp = temp_files!("a.txt", "dir/b.txt", "dir/sub/c.txt");
+        let mut config = Config::default();
+        config.filetree.max_depth = 2;
+        let filetree = Filetree::from_dir_with_config(
+            temp.path(),
+            Queue::new(),
+            Rc::new(config),
+            Rc::default(),
+        )
+        .unwrap();
+        let paths = filetree
+            .listing
+            .all_items()
+            .iter()
+            .map(|item| item.path().to_path_buf())
+            .collect_vec();
+        assert!(paths.contains(&temp.path().join("dir/b.txt")));
+        assert!(!paths.contains(&temp.path().join("dir/sub/c.txt")));
+        assert_eq!(
+            Some(" (too deep)"),
+            filetree
+                .dir_marker(&Item::File(temp.path().join("dir/sub")))
+                .map(|(marker, _)| marker)
+        );
+    }
+
+    #[test]
+    fn extra_ignore_files_are_read_until_toggled_off() {
+        use assert_fs::prelude::*;
+
+        let temp = temp_files!("a.txt", "built.txt", "secret.txt");
+        temp.child(".ignore").write_str("built.txt").unwrap();
+        temp.child(".rgignore").write_str("secret.txt").unwrap();
+        let mut config = Config::default();
+        config.filetree.ignore_files = vec![".rgignore".to_owned()];
+        let mut filetree = Filetree::from_dir_with_config(
+            temp.path(),
+            Queue::new(),
+            Rc::new(config),
+            Rc::default(),
+        )
+        .unwrap();
+        let paths = |filetree: &Filetree| {
+            filetree
+                .listing
+                .all_items()
+                .iter()
+                .map(|item| item.path().strip_prefix(temp.path()).unwrap().to_owned())
+                .collect_vec()
+        };
+        assert_eq!(vec![PathBuf::from("a.txt")], paths(&filetree));
+
+        filetree.toggle_ignored().unwrap();
+        assert_eq!(
+            vec![
+                PathBuf::from("a.txt"),
+                PathBuf::from("built.txt"),
+                PathBuf::from("secret.txt")
+            ],
+            paths(&filetree)
+        );
+        filetree.toggle_ignored().unwrap();
+        assert_eq!(vec![PathBuf::from("a.txt")], paths(&filetree));
+    }
+
+    #[test]
+    fn modified_times_can_be_relative() {
+        let temp = temp_files!("a.txt", "dir/b.txt");
+        let mut config = Config::default();
+        config.filetree.show_modified = true;
+        config.filetree.modified_format = TimeFormat::Relative;
+        let filetree = Filetree::from_dir_with_config(
+            temp.path(),
+            Queue::new(),
+            Rc::new(config),
+            Rc::default(),
+        )
+        .unwrap();
+        let file = temp.join("a.txt");
+        let modified = fs::metadata(&file).unwrap().modified().unwrap();
+
+        let label = |now| filetree.modified_label(&file, now);
+        assert_eq!(Some(" just now".to_owned()), label(modified));
+        let later = modified + Duration::from_secs(3 * 60 * 60);
+        assert_eq!(Some(" 3h ago".to_owned()), label(later));
+        // Ages count from the time of drawing, not of the last refresh
+        assert_eq!(
+            Some(" yesterday".to_owned()),
+            label(later + Duration::from_secs(24 * 60 * 60))
+        );
+        assert_eq!(
+            Some(" just now".to_owned()),
+            label(modified - Duration::from_secs(60))
+        );
+        assert_eq!(None, filetree.modified_label(&temp.join("dir"), later));
+    }
+
+    #[test]
+    fn sizes_take_the_style_of_their_band() {
+        use assert_fs::prelude::*;
+
+        let temp = temp_files!("dir/empty.txt");
+        temp.child("small.txt").write_str("12345").unwrap();
+        temp.child("big.txt").write_str(&"a".repeat(2048)).unwrap();
+        let mut config = Config::default();
+        config.filetree.show_sizes = true;
+        config.filetree.size_bands.truncate(1);
+        config.filetree.size_bands[0].below = Some(1024);
+        let filetree = Filetree::from_dir_with_config(
+            temp.path(),
+            Queue::new(),
+            Rc::new(config),
+            Rc::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some((
+                " 5 B".to_owned(),
+                Style::from(crate::config::Style::color(crate::config::Color::Green))
+            )),
+            filetree.size_label(&temp.join("small.txt"))
+        );
+        // Past every band
+        assert_eq!(
+            Some((" 2.0 KiB".to_owned(), Style::default())),
+            filetree.size_label(&temp.join("big.txt"))
+        );
+        assert_eq!(None, filetree.size_label(&temp.join("dir")));
     }
 
     #[test]
-    fn partial_refresh_goes_to_parent_if_only_child() {
-        let temp = temp_files!("test/test.txt");
+    fn finds_path_of_walk_errors() {
+        let err = ignore::Error::WithDepth {
+            depth: 1,
+            err: Box::new(ignore::Error::WithPath {
+                path: "/locked".into(),
+                err: Box::new(ignore::Error::Io(
+                    std::io::ErrorKind::PermissionDenied.into(),
+                )),
+            }),
+        };
+        assert_eq!(Some(Path::new("/locked")), error_path(&err));
+        assert_eq!(
+            None,
+            error_path(&ignore::Error::Io(std::io::ErrorKind::Other.into()))
+        );
+    }
+
+    #[test]
+    fn clear_closes_expanded_dirs_only() {
+        let temp = temp_files!("a/src/main.rs", "c/file.txt");
         let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
-        filetree.listing.unfold_all();
-        filetree.listing.select(1);
+        filetree.open_path(temp.join("c/file.txt")).unwrap();
+        filetree.expand_matching("src").unwrap();
+
         filetree
-            .partial_refresh(&RefreshData::Delete(
-                filetree
-                    .listing
-                    .selected_item()
-                    .unwrap()
-                    .path()
-                    .to_path_buf(),
-            ))
+            .handle_event(&input_event!(KeyCode::Char('\\')))
             .unwrap();
-        assert_eq!(0, filetree.listing.selected().unwrap());
+        assert_eq!(Some(true), filetree.listing.is_folded(temp.join("a")));
+        assert_eq!(Some(false), filetree.listing.is_folded(temp.join("c")));
+        assert_eq!(
+            temp.join("c/file.txt"),
+            filetree.get_selected().unwrap().path()
+        );
     }
 
     #[test]
-    fn can_open_all() {
-        let temp = temp_files!("test.txt", "test/test2.txt", "test2/test4/test.txt");
+    fn multi_selection_falls_back_to_selected() {
+        let temp = temp_files!("a.txt", "b.txt", "c.txt");
         let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
-        filetree.open_all();
-        assert_eq!(6, filetree.listing.len());
+        let items = filetree
+            .listing
+            .items()
+            .iter()
+            .map(|item| item.path().to_path_buf())
+            .collect_vec();
+        filetree.listing.select_first();
+        assert_eq!(vec![items[0].clone()], filetree.multi_selection());
+
+        // Selecting moves down, so this picks the first two items
+        let space = input_event!(KeyCode::Char(' '));
+        filetree.handle_event(&space).unwrap();
+        filetree.handle_event(&space).unwrap();
+        assert_eq!(
+            items[..2].iter().cloned().sorted().collect_vec(),
+            filetree.multi_selection()
+        );
+
+        // Clearing empties the multi-selection before anything else
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('\\')))
+            .unwrap();
+        assert_eq!(vec![items[2].clone()], filetree.multi_selection());
     }
 
     #[test]
-    fn can_mark_selected() {
-        let temp = temp_files!("test.txt");
+    fn toggling_twice_unselects() {
+        let temp = temp_files!("a.txt", "b.txt");
         let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
-        let path = temp.path().to_path_buf();
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
-        let event = input_event!(KeyCode::Char('m'));
-        assert!(filetree.handle_event(&event).is_ok());
-        assert!(filetree
-            .queue
-            .contains(&AppEvent::Mark(path.join("test.txt"))));
+        filetree.open_path(temp.join("b.txt")).unwrap();
+        filetree.toggle_multi_select();
+        filetree.toggle_multi_select();
+        assert!(filetree.multi_selection.is_empty());
     }
 
     #[test]
-    fn swallow_invalid_delete_external_events() {
-        let temp = temp_files!("test.txt");
+    fn sibling_dir_jumps_skip_files_and_wrap() {
+        let temp = temp_files!("a/file.txt", "b/file.txt", "top.txt");
         let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
-        let event = ExternalEvent::PartialRefresh(smallvec![RefreshData::Delete(
-            "does_not_exist.txt".into()
-        )]);
-        assert!(filetree.handle_event(&event).is_ok());
+        filetree.open_path(temp.join("a")).unwrap();
+
+        filetree.select_sibling_dir(true);
+        assert_eq!(temp.join("b"), filetree.get_selected().unwrap().path());
+        filetree.select_sibling_dir(true);
+        assert_eq!(temp.join("a"), filetree.get_selected().unwrap().path());
+        filetree.select_sibling_dir(false);
+        assert_eq!(temp.join("b"), filetree.get_selected().unwrap().path());
     }
 
     #[test]
-    fn opening_path_adds_preview_event_to_queue() {
-        let temp = temp_files!("test.txt");
-        let path = temp.path().to_path_buf();
+    fn sibling_dir_jumps_from_file_use_parent() {
+        let temp = temp_files!("a/file.txt", "b/file.txt", "c/file.txt");
         let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
-        filetree.open_path(path.join("test.txt")).unwrap();
+        filetree.open_path(temp.join("b/file.txt")).unwrap();
 
-        assert!(filetree
-            .queue
-            .contains(&AppEvent::PreviewFile(path.join("test.txt"))));
+        filetree.select_sibling_dir(true);
+        assert_eq!(temp.join("c"), filetree.get_selected().unwrap().path());
+        filetree.open_path(temp.join("b/file.txt")).unwrap();
+        filetree.select_sibling_dir(false);
+        assert_eq!(temp.join("a"), filetree.get_selected().unwrap().path());
     }
 
     #[test]
-    fn can_ignore() {
-        let temp = temp_files!("test.txt", "test2.txt");
-        let config = Config {
-            filetree: FiletreeConfig {
-                ignore: vec!["test2.txt".to_owned()],
-                ..Default::default()
-            },
-            ..Default::default()
-        };
-        let filetree = Filetree::from_dir_with_config(
-            temp.path(),
-            Queue::new(),
-            Rc::new(config),
-            Rc::new(RefCell::new(Marks::default())),
-        )
-        .unwrap();
-        scopeguard::guard(temp, |temp| temp.close().unwrap());
+    fn quick_filter_narrows_tree_to_matches_and_ancestors() {
+        let temp = temp_files!("dir/match.txt", "dir/other.txt", "top.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
 
-        assert_eq!(1, filetree.listing.items().len());
+        for event in input_events!(
+            KeyCode::Char('f'),
+            KeyCode::Char('M'),
+            KeyCode::Char('a'),
+            KeyCode::Char('t')
+        ) {
+            filetree.handle_event(&event).unwrap();
+        }
+        assert!(filetree.is_filtering());
         assert_eq!(
-            Path::new("test.txt"),
-            filetree.listing.items()[0].path().file_name().unwrap()
+            vec![
+                &Item::Dir(temp.join("dir")),
+                &Item::File(temp.join("dir/match.txt"))
+            ],
+            filetree.listing.items()
+        );
+        // The first match is selected, not its parent
+        assert_eq!(
+            &Item::File(temp.join("dir/match.txt")),
+            filetree.get_selected().unwrap()
         );
     }
 
     #[test]
-    fn can_full_refresh_to_account_for_new_files() {
-        let temp = temp_files!("test.txt", "test2.txt");
+    fn partial_refresh_keeps_quick_filter() {
+        let temp = temp_files!("dir/match.txt", "top.txt");
         let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
-        std::fs::File::create(temp.path().join("new.txt")).unwrap();
+        filetree.start_quick_filter();
+        for event in input_events!(KeyCode::Char('m'), KeyCode::Char('a')) {
+            filetree.handle_event(&event).unwrap();
+        }
 
-        assert_eq!(2, filetree.listing.len());
-        assert!(filetree.refresh().is_ok());
+        std::fs::File::create(temp.join("dir/match2.txt")).unwrap();
+        std::fs::File::create(temp.join("other.txt")).unwrap();
+        filetree
+            .partial_refresh(&RefreshData::Add(temp.join("dir/match2.txt")))
+            .unwrap();
+        filetree
+            .partial_refresh(&RefreshData::Add(temp.join("other.txt")))
+            .unwrap();
+        assert!(filetree.is_filtering());
         assert_eq!(3, filetree.listing.len());
+        assert!(filetree
+            .listing
+            .items()
+            .contains(&&Item::File(temp.join("dir/match2.txt"))));
+
+        // The refreshed files are in the tree after the filter ends
+        filetree.end_quick_filter(false).unwrap();
+        assert!(filetree
+            .listing
+            .all_items()
+            .iter()
+            .any(|item| item.path() == temp.join("other.txt")));
     }
 
     #[test]
-    fn can_partial_refresh_to_add_files() {
-        let temp = temp_files!("test.txt", "test2.txt");
+    fn quick_filter_moves_between_matches() {
+        let temp = temp_files!("dir/a.txt", "dir/b.txt", "c.rs");
         let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
-        std::fs::File::create(temp.path().join("new.txt")).unwrap();
 
-        assert_eq!(2, filetree.listing.len());
-        assert!(filetree
-            .partial_refresh(&RefreshData::Add(temp.join("new.txt")))
-            .is_ok());
+        for event in input_events!(KeyCode::Char('f'), KeyCode::Char('t')) {
+            filetree.handle_event(&event).unwrap();
+        }
+        let first = filetree.get_selected().unwrap().clone();
+        assert!(first.is_file());
+        filetree.handle_event(&input_event!(KeyCode::Down)).unwrap();
+        let second = filetree.get_selected().unwrap().clone();
+        assert!(second.is_file());
+        assert_ne!(first, second);
+        // Stays on the last match instead of moving past it
+        filetree.handle_event(&input_event!(KeyCode::Down)).unwrap();
+        assert_eq!(&second, filetree.get_selected().unwrap());
+        filetree.handle_event(&input_event!(KeyCode::Up)).unwrap();
+        assert_eq!(&first, filetree.get_selected().unwrap());
+    }
+
+    #[test]
+    fn escape_restores_tree_and_enter_keeps_selection() {
+        let temp = temp_files!("dir/a.txt", "b.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        let full = filetree.listing.len();
+
+        for event in input_events!(KeyCode::Char('f'), KeyCode::Char('a'), KeyCode::Esc) {
+            filetree.handle_event(&event).unwrap();
+        }
+        assert!(!filetree.is_filtering());
+        assert_eq!(full, filetree.listing.len());
+
+        for event in input_events!(KeyCode::Char('f'), KeyCode::Char('a'), KeyCode::Enter) {
+            filetree.handle_event(&event).unwrap();
+        }
+        assert!(!filetree.is_filtering());
         assert_eq!(
-            Path::new("new.txt"),
-            filetree
-                .listing
-                .items()
-                .get(0)
-                .unwrap()
-                .path()
-                .file_name()
-                .unwrap()
+            temp.join("dir/a.txt"),
+            filetree.get_selected().unwrap().path()
         );
     }
 
     #[test]
-    fn can_filter_include_certain_files() {
-        let temp = temp_files!("test.txt", "test2.txt");
+    fn quick_filter_match_ignores_case() {
+        assert_eq!(
+            Some(2..5),
+            quick_filter_match(Path::new("/dir/myFile.txt"), "fil")
+        );
+        assert_eq!(None, quick_filter_match(Path::new("/dir/file.txt"), "dir"));
+    }
+
+    #[test]
+    fn searchable_files_follow_dotfile_visibility() {
+        let temp = temp_files!("dir/test.txt", ".hidden.txt");
         let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
 
-        assert!(filetree
-            .filter_include(&[temp.path().join("test.txt")])
-            .is_ok());
         assert_eq!(
-            vec![&Item::File(temp.path().join("test.txt"))],
-            filetree.listing.items()
+            vec![temp.path().join("dir/test.txt")],
+            filetree.searchable_files().unwrap()
+        );
+        filetree.toggle_dotfiles().unwrap();
+        let mut files = filetree.searchable_files().unwrap();
+        files.sort();
+        assert_eq!(
+            vec![
+                temp.path().join(".hidden.txt"),
+                temp.path().join("dir/test.txt")
+            ],
+            files
         );
     }
 
@@ -991,7 +3386,7 @@ mod tests {
     fn arbitrary_keys_are_fed_into_custom_commands() {
         let temp = temp_files!("test.txt", "test2.txt");
         let config = Config {
-            commands: collect![HashMap<_, _>: (crate::config::Key::normal('z'), "testing".to_owned())],
+            commands: collect![HashMap<_, _>: (crate::config::Key::normal('z'), "testing".to_owned().into())],
             ..Default::default()
         };
         let mut filetree = Filetree::from_dir_with_config(
@@ -1010,13 +3405,253 @@ mod tests {
             .contains(&AppEvent::RunCommand("testing".to_owned())));
     }
 
+    #[test]
+    fn scrolls_selection_to_center_top_and_bottom() {
+        let temp = temp_files!("a.txt", "b.txt", "c.txt", "d.txt", "e.txt", "f.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.height.set(4);
+        filetree.listing.select(4);
+        assert_eq!(1, filetree.visible_offset(6));
+
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('Z'); KeyModifiers::SHIFT))
+            .unwrap();
+        assert_eq!(2, filetree.visible_offset(6));
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('k'); KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(4, filetree.visible_offset(6));
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('j'); KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(1, filetree.visible_offset(6));
+
+        // Moving out of view scrolls just enough to keep the selection visible
+        filetree.listing.select(0);
+        assert_eq!(0, filetree.visible_offset(6));
+    }
+
+    #[test]
+    fn scroll_margin_keeps_rows_around_selection() {
+        let temp = temp_files!("a.txt", "b.txt", "c.txt", "d.txt", "e.txt", "f.txt", "g.txt");
+        let mut config = Config::default();
+        config.filetree.scroll_margin = 1;
+        let mut filetree = Filetree {
+            config: Rc::new(config),
+            ..Filetree::from_dir(temp.path(), Queue::new()).unwrap()
+        };
+        filetree.height.set(4);
+        let mut offsets = Vec::new();
+        for selected in 0..7 {
+            filetree.listing.select(selected);
+            let offset = filetree.visible_offset(7);
+            filetree.offset.set(offset);
+            offsets.push(offset);
+        }
+        // Scrolls one row early, but not past the end
+        assert_eq!(vec![0, 0, 0, 1, 2, 3, 3], offsets);
+
+        filetree.listing.select(3);
+        assert_eq!(2, filetree.visible_offset(7));
+        filetree.offset.set(2);
+        filetree.listing.select(2);
+        assert_eq!(1, filetree.visible_offset(7));
+
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('k'); KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(1, filetree.visible_offset(7));
+    }
+
+    #[test]
+    fn clicks_select_toggle_and_open() {
+        let temp = temp_files!("b.txt", "src/a.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.area.set(Rect::new(0, 0, 20, 10));
+        let click = |row| {
+            ExternalEvent::Crossterm(Event::Mouse(crossterm::event::MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Left),
+                column: 2,
+                row,
+                modifiers: KeyModifiers::NONE,
+            }))
+        };
+
+        filetree.handle_event(&click(1)).unwrap();
+        assert_eq!(
+            temp.path().join("src"),
+            filetree.get_selected().unwrap().path()
+        );
+        assert_eq!(3, filetree.listing.len());
+
+        // Below the items and on the border
+        filetree.handle_event(&click(5)).unwrap();
+        filetree.handle_event(&click(0)).unwrap();
+        assert_eq!(
+            temp.path().join("src"),
+            filetree.get_selected().unwrap().path()
+        );
+
+        filetree.handle_event(&click(3)).unwrap();
+        assert!(!filetree
+            .queue
+            .contains(&AppEvent::OpenFile(temp.path().join("b.txt"))));
+        filetree.handle_event(&click(3)).unwrap();
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::OpenFile(temp.path().join("b.txt"))));
+    }
+
+    #[test]
+    fn name_area_is_after_the_indent_of_the_selected_row() {
+        let temp = temp_files!("b.txt", "src/a.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.area.set(Rect::new(0, 0, 20, 10));
+        filetree.height.set(8);
+        filetree.open_path(temp.path().join("src/a.txt")).unwrap();
+        assert_eq!(
+            Some(Rect::new(7, 2, 12, 1)),
+            filetree.name_area(&temp.path().join("src/a.txt"))
+        );
+        assert_eq!(None, filetree.name_area(&temp.path().join("b.txt")));
+
+        filetree.offset.set(2);
+        assert_eq!(None, filetree.name_area(&temp.path().join("src/a.txt")));
+    }
+
+    #[test]
+    fn toggling_dirs_first_keeps_selection() {
+        let temp = temp_files!("a.txt", "b/c.txt", "d.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        let names = |filetree: &Filetree| {
+            filetree
+                .listing
+                .items()
+                .iter()
+                .map(|item| item.path().strip_prefix(temp.path()).unwrap().to_owned())
+                .collect_vec()
+        };
+        filetree
+            .listing
+            .select(temp.path().join("b/c.txt").as_path());
+        assert_eq!(
+            vec![
+                Path::new("b"),
+                Path::new("b/c.txt"),
+                Path::new("a.txt"),
+                Path::new("d.txt")
+            ],
+            names(&filetree)
+        );
+
+        filetree.toggle_dirs_first();
+        assert_eq!(
+            vec![
+                Path::new("a.txt"),
+                Path::new("b"),
+                Path::new("b/c.txt"),
+                Path::new("d.txt")
+            ],
+            names(&filetree)
+        );
+        assert_eq!(
+            temp.path().join("b/c.txt"),
+            filetree.get_selected().unwrap().path()
+        );
+    }
+
+    #[test]
+    fn reversing_keeps_selection() {
+        let temp = temp_files!("a.txt", "b.txt", "c.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.listing.select(temp.path().join("c.txt").as_path());
+        filetree.toggle_reverse();
+        assert_eq!(
+            temp.path().join("c.txt"),
+            filetree.listing.items()[0].path()
+        );
+        assert_eq!(0, filetree.listing.selected().unwrap());
+        filetree.toggle_reverse();
+        assert_eq!(2, filetree.listing.selected().unwrap());
+    }
+
+    #[test]
+    fn wheel_over_tree_moves_selection() {
+        let temp = temp_files!("a.txt", "b.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        filetree.area.set(Rect::new(0, 0, 20, 10));
+        let scroll = |kind, column| {
+            ExternalEvent::Crossterm(Event::Mouse(crossterm::event::MouseEvent {
+                kind,
+                column,
+                row: 3,
+                modifiers: KeyModifiers::NONE,
+            }))
+        };
+
+        for _ in 0..3 {
+            filetree
+                .handle_event(&scroll(MouseEventKind::ScrollDown, 2))
+                .unwrap();
+        }
+        assert_eq!(
+            temp.path().join("b.txt"),
+            filetree.get_selected().unwrap().path()
+        );
+        // Over the preview
+        filetree
+            .handle_event(&scroll(MouseEventKind::ScrollUp, 30))
+            .unwrap();
+        assert_eq!(
+            temp.path().join("b.txt"),
+            filetree.get_selected().unwrap().path()
+        );
+        filetree
+            .handle_event(&scroll(MouseEventKind::ScrollUp, 2))
+            .unwrap();
+        assert_eq!(
+            temp.path().join("a.txt"),
+            filetree.get_selected().unwrap().path()
+        );
+    }
+
+    #[test]
+    fn tmux_commands_keep_their_options() {
+        let temp = temp_files!("test.txt");
+        let command = TmuxCommand {
+            size: Some(30),
+            ..TmuxCommand::new("vim {}".to_owned(), TmuxOpts::HorizontalSplit)
+        };
+        let config = Config {
+            tmux_commands: collect![HashMap<_, _>: (crate::config::Key::normal('z'), command.clone())],
+            ..Default::default()
+        };
+        let mut filetree = Filetree::from_dir_with_config(
+            temp.path(),
+            Queue::new(),
+            Rc::new(config),
+            Default::default(),
+        )
+        .unwrap();
+
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('z')))
+            .unwrap();
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::RunTmuxCommand(TmuxCommand {
+                cmd: format!("vim {}", command::quote(temp.join("test.txt"))),
+                ..command
+            })));
+    }
+
     #[test]
     fn custom_commands_are_performed_with_substitutions() {
         let temp = temp_files!("test.txt");
         let config = Config {
             commands: collect![HashMap<_, _>:
-                (crate::config::Key::normal('z'), "vim {}".to_owned()),
-                (crate::config::Key::normal('x'), "nvim {...}".to_owned())],
+                (crate::config::Key::normal('z'), "vim {}".to_owned().into()),
+                (crate::config::Key::normal('x'), "nvim {...}".to_owned().into())],
             ..Default::default()
         };
         let mut filetree = Filetree::from_dir_with_config(
@@ -1032,7 +3667,7 @@ mod tests {
             .is_ok());
         assert!(filetree.queue.contains(&AppEvent::RunCommand(format!(
             "vim {}",
-            temp.join("test.txt").display()
+            command::quote(temp.join("test.txt"))
         ))));
         assert!(filetree
             .handle_event(&input_event!(KeyCode::Char('x')))
@@ -1040,7 +3675,8 @@ mod tests {
         assert!(filetree
             .queue
             .contains(&AppEvent::OpenInput(InputOperation::SpecialCommand(
-                "nvim {...}".to_owned()
+                "nvim {...}".to_owned(),
+                RunOptions::default()
             ))));
     }
 }
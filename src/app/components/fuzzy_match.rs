@@ -1,14 +1,30 @@
 use crate::{
-    app::component::{Component, Drawable},
-    config::Config,
-    external_event::ExternalEvent,
+    app::{
+        component::{Component, Drawable},
+        PendingOperation,
+    },
+    command::{CommandDir, RunOptions},
+    config::{Config, FuzzyPaths},
+    external_event::{ExternalEvent, FileSearch, WalkFilters},
     queue::{AppEvent, Queue},
     ui,
 };
 use anyhow::Result;
+use crossterm::event::Event;
+use easy_switch::switch;
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher as Matcher};
 use itertools::Itertools;
-use std::{cell::Cell, path::PathBuf, rc::Rc};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    mem::{self, Discriminant},
+    path::{PathBuf, MAIN_SEPARATOR},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 use tui::{
     backend::Backend,
     layout::{Constraint, Corner, Direction, Layout, Rect},
@@ -19,18 +35,41 @@ use tui::{
 };
 use tui_textarea::{Input, Key, TextArea};
 
+/// Files found past this are left out, so matching stays quick on huge trees
+const MAX_CANDIDATES: usize = 200_000;
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum FuzzyOperation {
     OpenFile,
     MoveFile(PathBuf),
+    /// Move the multi-selection into the chosen directory
+    MoveFiles(Vec<PathBuf>),
+    /// Run the chosen command at the root
+    RunCommand,
     None,
 }
 
 pub struct FuzzyMatcher {
     input: Vec<String>,
+    /// Byte where the shown part of each input starts, when paths are shortened
+    starts: Vec<usize>,
+    /// Indices of the inputs with each file name, to shorten them until they are told apart
+    by_name: HashMap<String, Vec<usize>>,
     area: TextArea<'static>,
     operation: FuzzyOperation,
     state: Cell<ListState>,
+    /// The id and cancel flag of the walk still finding files
+    finding: Option<(usize, Arc<AtomicBool>)>,
+    last_id: usize,
+    /// Whether files were left out for going over `MAX_CANDIDATES`
+    capped: bool,
+    /// Candidates are relative to this
+    root: PathBuf,
+    /// Whether the highlighted result is shown in the preview
+    previewing: bool,
+    previewed: Option<String>,
+    /// The query and list state each kind of operation was closed with
+    remembered: HashMap<Discriminant<FuzzyOperation>, (String, ListState)>,
     config: Rc<Config>,
     queue: Queue,
 }
@@ -41,9 +80,18 @@ impl FuzzyMatcher {
         textarea.set_block(Block::default().borders(Borders::ALL));
         Self {
             input: Vec::new(),
+            starts: Vec::new(),
+            by_name: HashMap::new(),
             area: textarea,
             operation: FuzzyOperation::None,
             state: ListState::default().into(),
+            finding: None,
+            last_id: 0,
+            capped: false,
+            root: PathBuf::new(),
+            previewing: false,
+            previewed: None,
+            remembered: HashMap::new(),
             config: Rc::new(Config::default()),
             queue,
         }
@@ -57,52 +105,198 @@ impl FuzzyMatcher {
     }
 
     pub fn start(&mut self, items: Vec<String>, operation: FuzzyOperation) {
+        let remembered = self
+            .remembered
+            .get(&mem::discriminant(&operation))
+            .filter(|_| self.config.remember_fuzzy);
+        if let Some((query, state)) = remembered {
+            self.area.insert_str(query);
+            self.state.set(state.clone());
+        } else {
+            self.state.get_mut().select(Some(0));
+        }
         self.operation = operation;
         self.input = items;
-        self.state.get_mut().select(Some(0));
+        self.starts.clear();
+        self.by_name.clear();
+        self.shorten_from(0);
+    }
+
+    /// Shorten the paths of the inputs from `first` on, along with the ones sharing their names
+    fn shorten_from(&mut self, first: usize) {
+        if self.config.fuzzy_paths != FuzzyPaths::Short
+            || self.operation != FuzzyOperation::OpenFile
+        {
+            return;
+        }
+        self.starts.resize(self.input.len(), 0);
+        let mut touched = Vec::new();
+        for (index, path) in self.input.iter().enumerate().skip(first) {
+            let name = path[suffix_start(path, 1)..].to_owned();
+            self.by_name.entry(name.clone()).or_default().push(index);
+            // The others with the name may need more of their paths now too
+            touched.push(name);
+        }
+        for name in touched.into_iter().unique() {
+            let indices = &self.by_name[&name];
+            let paths = indices
+                .iter()
+                .map(|&index| self.input[index].as_str())
+                .collect_vec();
+            for (&index, start) in indices.iter().zip(unique_suffix_starts(&paths)) {
+                self.starts[index] = start;
+            }
+        }
     }
 
     pub fn open_path(&mut self, items: Vec<String>) {
         self.start(items, FuzzyOperation::OpenFile);
     }
 
+    /// Open with no files, which are added by the returned walk as it finds them
+    pub fn find_files(&mut self, root: PathBuf, filters: WalkFilters) -> FileSearch {
+        self.reset();
+        self.start(Vec::new(), FuzzyOperation::OpenFile);
+        self.last_id += 1;
+        self.root = root.clone();
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.finding = Some((self.last_id, Arc::clone(&cancel)));
+        FileSearch {
+            id: self.last_id,
+            root,
+            filters,
+            cancel,
+        }
+    }
+
+    /// Add files found by the walk with `id`, ignoring ones from walks that were closed
+    pub fn add_found(&mut self, id: usize, paths: &[String], done: bool) {
+        let Some((finding, cancel)) = &self.finding else {
+            return;
+        };
+        if *finding != id {
+            return;
+        }
+        let room = MAX_CANDIDATES.saturating_sub(self.input.len());
+        let first = self.input.len();
+        self.input.extend(paths.iter().take(room).cloned());
+        if paths.len() > room {
+            self.capped = true;
+            cancel.store(true, Ordering::Release);
+            self.finding = None;
+        } else if done {
+            self.finding = None;
+        }
+        self.shorten_from(first);
+    }
+
+    pub fn is_finding(&self) -> bool {
+        self.finding.is_some()
+    }
+
+    pub fn is_previewing(&self) -> bool {
+        self.previewing
+    }
+
+    /// Preview the highlighted result if it is not already
+    fn preview_selected(&mut self) {
+        let matches = self.compute_best_matches();
+        let Some(selected) = self
+            .selected_match(matches.len())
+            .map(|index| matches[index].0.to_owned())
+        else {
+            return;
+        };
+        if self.previewed.as_ref() != Some(&selected) {
+            self.queue
+                .add(AppEvent::PreviewFile(self.root.join(&selected)));
+            self.previewed = Some(selected);
+        }
+    }
+
     pub fn compute_best_matches(&self) -> Vec<(&str, Vec<usize>)> {
+        self.best_matches()
+            .into_iter()
+            .map(|(index, indices)| (self.input[index].as_str(), indices))
+            .collect()
+    }
+
+    /// Index and matched characters of each input matching the query, best first. Files named
+    /// exactly like the query come before the rest.
+    fn best_matches(&self) -> Vec<(usize, Vec<usize>)> {
         let match_against = &self.area.lines()[0];
         let matcher = SkimMatcherV2::default();
         self.input
             .iter()
-            .filter_map(|option| {
+            .enumerate()
+            .filter_map(|(index, option)| {
                 matcher
                     .fuzzy_indices(option, match_against)
-                    .map(|m| (option.as_str(), m))
+                    .map(|(score, indices)| {
+                        (index, is_named(option, match_against), score, indices)
+                    })
             })
-            .sorted_by(|a, b| a.1 .0.cmp(&b.1 .0))
-            .map(|(option, (_, indices))| (option, indices))
+            .sorted_by_key(|&(_, named, score, _)| (named, score))
+            .map(|(index, _, _, indices)| (index, indices))
             .rev()
             .collect()
     }
 
+    /// The part of the input at `index` that is shown, and the characters left out before it
+    fn label(&self, index: usize) -> (&str, usize) {
+        let option = &self.input[index];
+        let start = self.starts.get(index).copied().unwrap_or(0);
+        (&option[start..], option[..start].chars().count())
+    }
+
     pub fn reset(&mut self) {
+        if self.operation != FuzzyOperation::None && self.config.remember_fuzzy {
+            self.remembered.insert(
+                mem::discriminant(&self.operation),
+                (self.area.lines()[0].clone(), self.state.take()),
+            );
+        }
         self.area = TextArea::default();
         self.area.set_block(Block::default().borders(Borders::ALL));
         self.operation = FuzzyOperation::None;
         self.input = Vec::new();
+        self.starts.clear();
+        self.by_name.clear();
         self.state = ListState::default().into();
+        if let Some((_, cancel)) = self.finding.take() {
+            cancel.store(true, Ordering::Release);
+        }
+        self.capped = false;
+        self.root = PathBuf::new();
+        self.previewing = false;
+        self.previewed = None;
     }
 
     pub fn submit(&mut self) {
-        let Some(sel) = self.selected() else { return; };
-        let selected = if let Some(sel) = self.compute_best_matches().get(sel) {
-            sel.0.to_owned()
-        } else {
+        let matches = self.compute_best_matches();
+        let Some(sel) = self.selected_match(matches.len()) else {
             return;
         };
+        let selected = matches[sel].0.to_owned();
         match self.operation {
             FuzzyOperation::OpenFile => self.queue.add(AppEvent::GotoFile(selected.into())),
             FuzzyOperation::MoveFile(ref mut path) => {
                 let path = std::mem::take(path);
                 self.queue.add(AppEvent::MoveFile(path, selected.into()));
             }
+            FuzzyOperation::MoveFiles(ref mut paths) => {
+                let paths = std::mem::take(paths);
+                self.queue
+                    .add(if paths.len() > self.config.confirm_threshold {
+                        AppEvent::OpenPopup(PendingOperation::MoveFiles(paths, selected.into()))
+                    } else {
+                        AppEvent::MoveFiles(paths, selected.into())
+                    });
+            }
+            FuzzyOperation::RunCommand => self.queue.add(AppEvent::RunCommandWith(
+                selected,
+                RunOptions::in_dir(CommandDir::Root),
+            )),
             FuzzyOperation::None => panic!("should not submit with no operation"),
         }
         self.reset();
@@ -112,7 +306,7 @@ impl FuzzyMatcher {
         let old = self.state.get_mut().selected().unwrap_or_default();
         self.state
             .get_mut()
-            .select(Some(Ord::min(old + 1, self.input.len() - 1)));
+            .select(Some(Ord::min(old + 1, self.input.len().saturating_sub(1))));
     }
 
     pub fn select_prev(&mut self) {
@@ -126,6 +320,13 @@ impl FuzzyMatcher {
         self.state.set(state);
         selected
     }
+
+    /// The selection kept within `matches`, which can be fewer than when it was remembered
+    fn selected_match(&self, matches: usize) -> Option<usize> {
+        self.selected()
+            .filter(|_| matches > 0)
+            .map(|selected| selected.min(matches - 1))
+    }
 }
 
 impl Component for FuzzyMatcher {
@@ -139,49 +340,45 @@ impl Component for FuzzyMatcher {
         }
 
         if let ExternalEvent::Crossterm(ev) = ev {
-            let input_event: Input = ev.clone().into();
-            match input_event {
-                Input {
-                    key: Key::Esc,
-                    ctrl: false,
-                    alt: false,
-                } => {
-                    self.reset();
-                    return Ok(());
-                }
-                Input {
-                    key: Key::Enter,
-                    ctrl: false,
-                    alt: false,
-                } => {
-                    self.submit();
-                    return Ok(());
-                }
-                Input {
-                    key: Key::Char('n'),
-                    alt: false,
-                    ctrl: true,
-                } => {
-                    self.select_prev();
-                }
-                Input {
-                    key: Key::Char('p'),
-                    alt: false,
-                    ctrl: true,
-                } => {
-                    self.select_next();
+            if let Event::Key(key) = ev {
+                switch! { key;
+                    self.config.fuzzy_preview => {
+                        self.previewing = !self.previewing;
+                        self.previewed = None;
+                        if self.previewing {
+                            self.preview_selected();
+                        }
+                        return Ok(());
+                    },
+                    self.config.popup.cancel => {
+                        self.reset();
+                        return Ok(());
+                    },
+                    self.config.popup.confirm => {
+                        self.submit();
+                        return Ok(());
+                    },
+                    // Results are listed from the bottom up
+                    self.config.popup.down => self.select_prev(),
+                    self.config.popup.up => self.select_next(),
                 }
+            }
+            let input_event: Input = ev.clone().into();
+            if matches!(
+                input_event,
                 Input {
                     key: Key::Char('u'),
                     ctrl: true,
                     alt: false,
-                } => {
-                    self.area = TextArea::default();
-                    self.area.set_block(Block::default().borders(Borders::ALL));
                 }
-                _ => {}
+            ) {
+                self.area = TextArea::default();
+                self.area.set_block(Block::default().borders(Borders::ALL));
             }
             self.area.input(input_event);
+            if self.previewing {
+                self.preview_selected();
+            }
         }
 
         Ok(())
@@ -199,27 +396,29 @@ impl Drawable for FuzzyMatcher {
         let [options_area, prompt_area] = Layout::default()
             .direction(Direction::Vertical)
             .constraints([Constraint::Min(1), Constraint::Length(3)].as_ref())
-            .split(area)[..] else {
-                unreachable!("should always have 2 sections");
-            };
+            .split(area)[..]
+        else {
+            unreachable!("should always have 2 sections");
+        };
+        let matches = self.best_matches();
+        let selected = self.selected_match(matches.len());
         let options = List::new(
-            self.compute_best_matches()
+            matches
                 .into_iter()
                 .enumerate()
-                .map(|(index, item)| {
+                .map(|(index, (input, indices))| {
+                    let (label, skipped) = self.label(input);
                     ListItem::new(Spans::from(
-                        item.0
+                        label
                             .chars()
                             .enumerate()
                             .map(|(c_idx, c)| {
                                 Span::styled(
                                     c.to_string(),
-                                    if item.1.contains(&c_idx) {
+                                    if indices.contains(&(c_idx + skipped)) {
                                         Style::default().fg(Color::Blue)
-                                    } else if self.selected().is_some()
-                                        && self.selected().unwrap() == index
-                                    {
-                                        Style::default().fg(Color::Black)
+                                    } else if selected == Some(index) {
+                                        self.config.selected.into()
                                     } else {
                                         Style::default()
                                     },
@@ -230,21 +429,86 @@ impl Drawable for FuzzyMatcher {
                 })
                 .collect_vec(),
         )
-        .block(Block::default().borders(Borders::ALL))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(if self.is_finding() {
+                    format!("Finding files... {}", self.input.len())
+                } else if self.capped {
+                    format!("{} files, the rest were left out", self.input.len())
+                } else {
+                    String::new()
+                }),
+        )
         .start_corner(Corner::BottomLeft)
-        .highlight_style(self.config.selected.into());
-        let mut state = self.state.take();
+        .highlight_style(self.config.selected.into())
+        .highlight_symbol(&self.config.selected_symbol);
+        let state = self.state.take();
         f.render_widget(self.area.widget(), prompt_area);
-        f.render_stateful_widget(options, options_area, &mut state);
-        self.state.set(state);
+        if selected == state.selected() {
+            let mut state = state;
+            f.render_stateful_widget(options, options_area, &mut state);
+            self.state.set(state);
+        } else {
+            // Keep the remembered selection, more matches may still come in
+            let mut clamped = state.clone();
+            clamped.select(selected);
+            f.render_stateful_widget(options, options_area, &mut clamped);
+            self.state.set(state);
+        }
 
         Ok(())
     }
 }
 
+fn is_separator(c: char) -> bool {
+    c == '/' || c == MAIN_SEPARATOR
+}
+
+/// Byte where the last `components` components of `path` start, 0 if it has no more than that
+fn suffix_start(path: &str, components: usize) -> usize {
+    path.rmatch_indices(is_separator)
+        .nth(components - 1)
+        .map_or(0, |(index, separator)| index + separator.len())
+}
+
+/// Whether the file name of `path`, with or without its extension, is `query`
+fn is_named(path: &str, query: &str) -> bool {
+    let name = &path[suffix_start(path, 1)..];
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    !query.is_empty() && (name.eq_ignore_ascii_case(query) || stem.eq_ignore_ascii_case(query))
+}
+
+/// For each path, the byte where the fewest trailing components telling it apart from the others
+/// start
+fn unique_suffix_starts(paths: &[&str]) -> Vec<usize> {
+    let mut starts = vec![None; paths.len()];
+    let mut components = 1;
+    while starts.iter().any(Option::is_none) {
+        let suffixes = paths
+            .iter()
+            .map(|path| suffix_start(path, components))
+            .collect_vec();
+        let counts = paths
+            .iter()
+            .zip(&suffixes)
+            .map(|(path, &start)| &path[start..])
+            .counts();
+        for ((start, path), &suffix) in starts.iter_mut().zip(paths).zip(&suffixes) {
+            // A path that is whole already cannot grow any more
+            if start.is_none() && (counts[&path[suffix..]] == 1 || suffix == 0) {
+                *start = Some(suffix);
+            }
+        }
+        components += 1;
+    }
+    starts.into_iter().flatten().collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{app::components::testing::*, config::KeyBind};
 
     #[test]
     fn compute_best_matches_gives_sorted_list_of_matches_with_indices() {
@@ -268,6 +532,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn short_paths_keep_the_directories_telling_names_apart() {
+        let config = Config {
+            fuzzy_paths: FuzzyPaths::Short,
+            ..Default::default()
+        };
+        let mut matcher = FuzzyMatcher::new_with_config(Queue::new(), Rc::new(config));
+        let paths = ["src/app/mod.rs", "src/ui/mod.rs", "src/model.rs", "mod.rs"]
+            .map(|path| path.replace('/', std::path::MAIN_SEPARATOR_STR));
+        let search = matcher.find_files(PathBuf::from("root"), WalkFilters::default());
+        matcher.add_found(search.id, &paths[..2], false);
+        matcher.add_found(search.id, &paths[2..], true);
+        let labels = (0..4)
+            .map(|index| matcher.label(index).0.replace(MAIN_SEPARATOR, "/"))
+            .collect_vec();
+        assert_eq!(
+            vec!["app/mod.rs", "ui/mod.rs", "model.rs", "mod.rs"],
+            labels
+        );
+
+        // Files named like the query come first
+        matcher.area.insert_str("mod");
+        let best = matcher
+            .best_matches()
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect_vec();
+        assert_eq!(4, best.len());
+        assert_eq!(2, best[3]);
+        matcher.state.get_mut().select(Some(3));
+        matcher.submit();
+        assert!(matcher
+            .queue
+            .contains(&AppEvent::GotoFile(PathBuf::from(&paths[2]))));
+    }
+
+    #[test]
+    fn unique_suffixes_grow_until_told_apart() {
+        assert_eq!(
+            vec![2, 0, 0],
+            unique_suffix_starts(&["a/b/c/mod.rs", "x/c/mod.rs", "mod.rs"])
+        );
+        assert_eq!(vec![0, 0], unique_suffix_starts(&["a/mod.rs", "a/mod.rs"]));
+    }
+
     #[test]
     fn submit_resets_everything() {
         let mut matcher = FuzzyMatcher::new(Queue::new());
@@ -328,4 +637,167 @@ mod tests {
             .queue
             .contains(&AppEvent::GotoFile("item2".to_owned().into())));
     }
+
+    #[test]
+    fn moving_many_items_asks_first() {
+        let mut matcher = FuzzyMatcher::new(Queue::new());
+        let paths: Vec<PathBuf> = (0..=matcher.config.confirm_threshold)
+            .map(|n| format!("file{n}").into())
+            .collect();
+        matcher.operation = FuzzyOperation::MoveFiles(paths.clone());
+        matcher.input = vec!["dest".to_owned()];
+        matcher.area.insert_str("dest");
+        matcher.select_prev();
+        matcher.submit();
+        assert!(matcher
+            .queue
+            .contains(&AppEvent::OpenPopup(PendingOperation::MoveFiles(
+                paths,
+                "dest".into()
+            ))));
+    }
+
+    #[test]
+    fn found_files_are_added_until_done() {
+        let mut matcher = FuzzyMatcher::new(Queue::new());
+        let search = matcher.find_files(PathBuf::from("root"), WalkFilters::default());
+        matcher.add_found(search.id, &["a.txt".to_owned()], false);
+        assert!(matcher.is_finding());
+        // Batches of a closed finder are ignored
+        matcher.add_found(search.id + 1, &["stale.txt".to_owned()], false);
+        matcher.add_found(search.id, &["b.txt".to_owned()], true);
+        assert!(!matcher.is_finding());
+        assert_eq!(vec!["a.txt".to_owned(), "b.txt".to_owned()], matcher.input);
+    }
+
+    #[test]
+    fn closing_cancels_the_walk() {
+        let mut matcher = FuzzyMatcher::new(Queue::new());
+        let search = matcher.find_files(PathBuf::from("root"), WalkFilters::default());
+        matcher.reset();
+        assert!(search.cancel.load(Ordering::Acquire));
+        matcher.add_found(search.id, &["a.txt".to_owned()], true);
+        assert!(matcher.input.is_empty());
+    }
+
+    #[test]
+    fn stops_at_max_candidates() {
+        let mut matcher = FuzzyMatcher::new(Queue::new());
+        let search = matcher.find_files(PathBuf::from("root"), WalkFilters::default());
+        let paths = vec![String::new(); MAX_CANDIDATES + 1];
+        matcher.add_found(search.id, &paths, false);
+        assert_eq!(MAX_CANDIDATES, matcher.input.len());
+        assert!(search.cancel.load(Ordering::Acquire));
+        assert!(!matcher.is_finding());
+    }
+
+    #[test]
+    fn reopening_restores_query_and_selection_per_operation() {
+        let mut matcher = FuzzyMatcher::new(Queue::new());
+        let items = || vec!["a".to_owned(), "ab".to_owned(), "abc".to_owned()];
+        matcher.open_path(items());
+        matcher.area.insert_str("a");
+        matcher.select_next();
+        matcher.select_next();
+        matcher.reset();
+
+        matcher.start(items(), FuzzyOperation::MoveFile("a".into()));
+        assert_eq!("", matcher.area.lines()[0]);
+        assert_eq!(Some(0), matcher.selected());
+        matcher.reset();
+
+        matcher.open_path(items());
+        assert_eq!("a", matcher.area.lines()[0]);
+        assert_eq!(Some(2), matcher.selected());
+    }
+
+    #[test]
+    fn remembered_selection_is_kept_within_matches() {
+        let mut matcher = FuzzyMatcher::new(Queue::new());
+        matcher.open_path(vec!["item".to_owned(), "item2".to_owned()]);
+        matcher.select_next();
+        matcher.reset();
+
+        matcher.open_path(vec!["item".to_owned()]);
+        matcher.submit();
+        assert!(matcher
+            .queue
+            .contains(&AppEvent::GotoFile("item".to_owned().into())));
+    }
+
+    #[test]
+    fn remembering_can_be_turned_off() {
+        let mut matcher = FuzzyMatcher::new_with_config(
+            Queue::new(),
+            Rc::new(Config {
+                remember_fuzzy: false,
+                ..Config::default()
+            }),
+        );
+        matcher.open_path(vec!["a".to_owned(), "b".to_owned()]);
+        matcher.area.insert_str("a");
+        matcher.select_next();
+        matcher.reset();
+        matcher.open_path(vec!["a".to_owned(), "b".to_owned()]);
+        assert_eq!("", matcher.area.lines()[0]);
+        assert_eq!(Some(0), matcher.selected());
+    }
+
+    #[test]
+    fn previewing_follows_the_highlight() {
+        let mut matcher = FuzzyMatcher::new(Queue::new());
+        let search = matcher.find_files(PathBuf::from("root"), WalkFilters::default());
+        matcher.add_found(search.id, &["a".to_owned(), "b".to_owned()], true);
+        matcher
+            .handle_event(&input_event!(KeyCode::Char('v'); KeyModifiers::CONTROL))
+            .unwrap();
+        assert!(matcher.is_previewing());
+        assert!(matcher.visible());
+        assert!(matcher
+            .queue
+            .contains(&AppEvent::PreviewFile(PathBuf::from("root").join("b"))));
+
+        matcher
+            .handle_event(&input_event!(KeyCode::Char('p'); KeyModifiers::CONTROL))
+            .unwrap();
+        assert!(matcher
+            .queue
+            .contains(&AppEvent::PreviewFile(PathBuf::from("root").join("a"))));
+    }
+
+    #[test]
+    fn popup_keys_can_be_rebound() {
+        let mut config = Config::default();
+        config.popup.up = KeyBind::key("ctrl-k".parse().unwrap());
+        config.popup.cancel = KeyBind::key("ctrl-c".parse().unwrap());
+        let mut matcher = FuzzyMatcher::new_with_config(Queue::new(), Rc::new(config));
+        matcher.start(
+            vec!["a".to_owned(), "b".to_owned()],
+            FuzzyOperation::OpenFile,
+        );
+        matcher
+            .handle_event(&input_event!(KeyCode::Char('k'); KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(Some(1), matcher.selected());
+        matcher.handle_event(&input_event!(KeyCode::Esc)).unwrap();
+        assert!(matcher.visible());
+        matcher
+            .handle_event(&input_event!(KeyCode::Char('c'); KeyModifiers::CONTROL))
+            .unwrap();
+        assert!(!matcher.visible());
+    }
+
+    #[test]
+    fn chosen_commands_run_at_the_root() {
+        let mut matcher = FuzzyMatcher::new(Queue::new());
+        matcher.start(
+            vec!["cargo run --bin app".to_owned()],
+            FuzzyOperation::RunCommand,
+        );
+        matcher.submit();
+        assert!(matcher.queue.contains(&AppEvent::RunCommandWith(
+            "cargo run --bin app".to_owned(),
+            RunOptions::in_dir(CommandDir::Root)
+        )));
+    }
 }
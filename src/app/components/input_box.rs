@@ -1,11 +1,20 @@
+use super::Jump;
 use crate::{
     app::component::{Component, Drawable},
+    command::{self, RunOptions},
+    config::Config,
     external_event::ExternalEvent,
     queue::{AppEvent, Queue, TmuxOpts},
     ui,
 };
 use anyhow::Result;
-use std::path::{PathBuf, MAIN_SEPARATOR};
+use crossterm::event::Event;
+use itertools::Itertools;
+use std::{
+    fs,
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+    rc::Rc,
+};
 use tui::{
     backend::Backend,
     layout::{Alignment, Rect},
@@ -29,7 +38,32 @@ pub enum InputOperation {
     Rename {
         to: PathBuf,
     },
-    SpecialCommand(String),
+    /// A command with `{...}` to fill in, and how its binding runs it
+    SpecialCommand(String, RunOptions),
+    SearchContents,
+    ExpandMatching,
+    /// A command to run for each file listed in the tree
+    BatchCommand,
+    /// Extensions to filter the tree by, with `recent` ones newest first
+    FilterExtensions {
+        recent: Vec<String>,
+    },
+    /// A command whose output is shown in the preview, with `recent` ones newest first
+    ScratchCommand {
+        recent: Vec<String>,
+    },
+    ExportLog,
+    SearchPreview,
+    /// Line or percentage to scroll the preview to
+    PreviewJump,
+    /// Jump to a path, relative to `root` unless absolute
+    Goto {
+        root: PathBuf,
+    },
+    /// Ref to preview `path` at
+    ShowAtRef {
+        path: PathBuf,
+    },
     #[default]
     NoOperations,
 }
@@ -38,9 +72,12 @@ pub enum InputOperation {
 pub struct InputBox {
     pub operation: InputOperation,
     queue: Queue,
+    config: Rc<Config>,
     text: String,
     /// Offset from back of `text`
     cursor_offset: u32,
+    /// Shown in place of the title until the next key
+    error: Option<String>,
 }
 
 impl InputBox {
@@ -50,9 +87,115 @@ impl InputBox {
             queue,
             operation: Default::default(),
             cursor_offset: 0,
+            error: None,
+            config: Rc::default(),
+        }
+    }
+
+    pub fn new_with_config(queue: Queue, config: Rc<Config>) -> Self {
+        Self {
+            config,
+            ..Self::new(queue)
         }
     }
 
+    /// Start on `operation`, with a rename starting out as the current name
+    pub fn open(&mut self, operation: InputOperation) {
+        self.text = match &operation {
+            InputOperation::Rename { to } => to
+                .file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().into_owned()),
+            _ => String::new(),
+        };
+        self.cursor_offset = 0;
+        self.error = None;
+        self.operation = operation;
+    }
+
+    /// Send off the input for the operation, or show why it cannot be used
+    fn submit(&mut self) {
+        match &self.operation {
+            InputOperation::NewFile { at } => {
+                self.queue
+                    .add(AppEvent::NewFile(at.join(self.text.as_str())));
+            }
+            InputOperation::NewDir { at } => self
+                .queue
+                .add(AppEvent::NewDir(at.join(self.text.as_str()))),
+            InputOperation::Command { to } => {
+                // Perform string substitution for path
+                let cmd = command::fill_path(&self.text, to);
+                self.queue.add(AppEvent::RunCommand(cmd));
+            }
+            InputOperation::SpecialCommand(cmd, options) => {
+                let full_cmd = cmd.replace("{...}", self.text.as_str());
+                self.queue.add(if *options == RunOptions::default() {
+                    AppEvent::RunCommand(full_cmd)
+                } else {
+                    AppEvent::RunCommandWith(full_cmd, *options)
+                });
+            }
+            InputOperation::SearchContents => {
+                self.queue.add(AppEvent::SearchContents(self.text.clone()));
+            }
+            InputOperation::ExpandMatching => {
+                self.queue.add(AppEvent::ExpandMatching(self.text.clone()));
+            }
+            InputOperation::BatchCommand => {
+                self.queue.add(AppEvent::RunBatch(self.text.clone()));
+            }
+            InputOperation::FilterExtensions { .. } => {
+                self.queue
+                    .add(AppEvent::FilterExtensions(self.text.clone()));
+            }
+            InputOperation::ScratchCommand { .. } => {
+                self.queue.add(AppEvent::RunScratch(self.text.clone()));
+            }
+            InputOperation::Goto { root } => {
+                let path = root.join(&self.text);
+                if !path.starts_with(root) {
+                    self.error = Some("not in the project".to_owned());
+                    return;
+                }
+                if !path.exists() {
+                    self.error = Some(format!("\"{}\" does not exist", self.text));
+                    return;
+                }
+                self.queue.add(AppEvent::GotoFile(path));
+            }
+            InputOperation::SearchPreview => {
+                self.queue.add(AppEvent::SearchPreview(self.text.clone()));
+            }
+            InputOperation::PreviewJump => {
+                let Some(jump) = Jump::parse(&self.text) else {
+                    self.error = Some(format!("\"{}\" is not a line or percentage", self.text));
+                    return;
+                };
+                self.queue.add(AppEvent::PreviewJump(jump));
+            }
+            InputOperation::ExportLog => {
+                let to = (!self.text.is_empty()).then(|| PathBuf::from(&self.text));
+                self.queue.add(AppEvent::ExportLog(to));
+            }
+            InputOperation::Rename { to } => {
+                // Keeping the name is the same as cancelling
+                if to.file_name() != Some(self.text.as_ref()) {
+                    let new_path = to.parent().unwrap().join(&self.text);
+                    self.queue.add(AppEvent::RenameFile(to.clone(), new_path));
+                }
+            }
+            InputOperation::ShowAtRef { path } => {
+                let reference = self.text.trim();
+                self.queue.add(AppEvent::ShowAtRef(
+                    path.clone(),
+                    (!reference.is_empty()).then(|| reference.to_owned()),
+                ));
+            }
+            InputOperation::NoOperations => unreachable!("checked before submitting"),
+        };
+        self.reset();
+    }
+
     fn has_work(&self) -> bool {
         self.operation != InputOperation::NoOperations
     }
@@ -60,6 +203,7 @@ impl InputBox {
     fn reset(&mut self) {
         self.text = String::new();
         self.operation = InputOperation::NoOperations;
+        self.error = None;
     }
 
     fn cursor_left(&mut self) {
@@ -83,7 +227,8 @@ impl InputBox {
 
     fn has_valid_input(&self) -> Option<bool> {
         if self.text.is_empty() {
-            return Some(false);
+            // Exports to the default file
+            return Some(self.operation == InputOperation::ExportLog);
         }
         match self.operation {
             InputOperation::NewFile { .. } | InputOperation::NewDir { .. } => {
@@ -121,9 +266,44 @@ impl Component for InputBox {
             return Ok(());
         }
         if let ExternalEvent::Crossterm(ev) = ev {
+            self.error = None;
+            if let Event::Key(key) = ev {
+                if self.config.popup.cancel == key {
+                    self.reset();
+                    return Ok(());
+                }
+                if self.config.popup.confirm == key {
+                    if self
+                        .has_valid_input()
+                        .expect("should not be called with no work")
+                    {
+                        self.submit();
+                    }
+                    return Ok(());
+                }
+            }
             let input_event: Input = ev.clone().into();
             match input_event {
-                Input { key: Key::Esc, .. } => self.reset(),
+                Input { key: Key::Tab, .. } => match &self.operation {
+                    InputOperation::Goto { root } => {
+                        if let Some(completed) = complete_path(root, &self.text) {
+                            self.text = completed;
+                            self.cursor_offset = 0;
+                        }
+                    }
+                    InputOperation::FilterExtensions { recent }
+                    | InputOperation::ScratchCommand { recent }
+                        if !recent.is_empty() =>
+                    {
+                        let next = recent
+                            .iter()
+                            .position(|extensions| *extensions == self.text)
+                            .map_or(0, |index| (index + 1) % recent.len());
+                        self.text = recent[next].clone();
+                        self.cursor_offset = 0;
+                    }
+                    _ => {}
+                },
                 Input {
                     key: Key::Right, ..
                 } => self.cursor_right(),
@@ -135,7 +315,7 @@ impl Component for InputBox {
                     ctrl: true,
                 } if matches!(key, 'v' | 'x' | 'n' | 'f') => {
                     if let InputOperation::Command { to } = &self.operation {
-                        let cmd = self.text.replace("{}", &to.display().to_string());
+                        let cmd = command::fill_path(&self.text, to);
                         self.queue.add(AppEvent::RunCommandWithTmux(
                             cmd,
                             match key {
@@ -149,39 +329,6 @@ impl Component for InputBox {
                         self.reset();
                     }
                 }
-                Input {
-                    key: Key::Enter,
-                    alt: false,
-                    ctrl: false,
-                } if self
-                    .has_valid_input()
-                    .expect("should not be called with no work") =>
-                {
-                    match &self.operation {
-                        InputOperation::NewFile { at } => {
-                            self.queue
-                                .add(AppEvent::NewFile(at.join(self.text.as_str())));
-                        }
-                        InputOperation::NewDir { at } => self
-                            .queue
-                            .add(AppEvent::NewDir(at.join(self.text.as_str()))),
-                        InputOperation::Command { to } => {
-                            // Perform string substitution for path
-                            let cmd = self.text.replace("{}", &to.display().to_string());
-                            self.queue.add(AppEvent::RunCommand(cmd));
-                        }
-                        InputOperation::SpecialCommand(cmd) => {
-                            let full_cmd = cmd.replace("{...}", self.text.as_str());
-                            self.queue.add(AppEvent::RunCommand(full_cmd));
-                        }
-                        InputOperation::Rename { to } => {
-                            let new_path = to.parent().unwrap().join(&self.text);
-                            self.queue.add(AppEvent::RenameFile(to.clone(), new_path));
-                        }
-                        InputOperation::NoOperations => unreachable!("checked in match guard"),
-                    };
-                    self.reset();
-                }
                 Input {
                     key: Key::Char('u'),
                     ctrl: true,
@@ -211,42 +358,147 @@ impl Drawable for InputBox {
             return Ok(());
         }
         let area = ui::centered_rect_absolute(50, 3, area);
-        let title = match self.operation {
+        let title = match &self.operation {
             InputOperation::Command { .. } => "Run Command",
-            InputOperation::SpecialCommand(_) => "Command Input",
+            InputOperation::SpecialCommand(..) => "Command Input",
             InputOperation::NewDir { .. } => "New Directory",
             InputOperation::NewFile { .. } => "New File",
             InputOperation::Rename { .. } => "Rename file",
+            InputOperation::SearchContents => "Search Contents",
+            InputOperation::ExpandMatching => "Expand Matching",
+            InputOperation::BatchCommand => "Batch Command (like \"optipng {path}\")",
+            InputOperation::FilterExtensions { recent } if recent.is_empty() => {
+                "Filter Extensions (like \"rs, toml\")"
+            }
+            InputOperation::FilterExtensions { .. } => "Filter Extensions (tab for recent)",
+            InputOperation::ScratchCommand { recent } if recent.is_empty() => "Scratch Command",
+            InputOperation::ScratchCommand { .. } => "Scratch Command (tab for recent)",
+            InputOperation::ExportLog => "Export Log (empty for default file)",
+            InputOperation::Goto { .. } => "Go To (tab completes)",
+            InputOperation::ShowAtRef { .. } => "Show at Ref (empty for the working copy)",
+            InputOperation::SearchPreview => "Search Preview",
+            InputOperation::PreviewJump => "Go To Line (like 120 or 50%)",
             InputOperation::NoOperations => unreachable!("checked at top of method"),
         };
-        let mut textarea = TextArea::default();
-        textarea.insert_str(&self.text);
+        let title = self.error.as_deref().unwrap_or(title);
+        let mut textarea = self.textarea();
         textarea.set_block(
             Block::default()
                 .borders(Borders::ALL)
                 .title(title)
                 .title_alignment(Alignment::Center)
-                .border_style(if self.has_valid_input().expect("should have operation") {
-                    Style::default().fg(Color::LightGreen)
-                } else {
-                    Style::default().fg(Color::Red)
-                }),
+                .border_style(
+                    if self.error.is_none()
+                        && self.has_valid_input().expect("should have operation")
+                    {
+                        Style::default().fg(Color::LightGreen)
+                    } else {
+                        Style::default().fg(Color::Red)
+                    },
+                ),
         );
-        for _ in 0..self.cursor_offset {
-            textarea.move_cursor(CursorMove::Back);
-        }
         f.render_widget(Clear, area);
         f.render_widget(textarea.widget(), area);
         Ok(())
     }
 }
 
+impl InputBox {
+    /// Draw just the text over `area`, a single row, in place of a popup
+    pub fn draw_inline<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let mut textarea = self.textarea();
+        textarea.set_style(if self.has_valid_input().expect("should have operation") {
+            self.config.selected.into()
+        } else {
+            Style::default().fg(Color::Red)
+        });
+        f.render_widget(Clear, area);
+        f.render_widget(textarea.widget(), area);
+    }
+
+    fn textarea(&self) -> TextArea<'_> {
+        let mut textarea = TextArea::default();
+        textarea.insert_str(&self.text);
+        for _ in 0..self.cursor_offset {
+            textarea.move_cursor(CursorMove::Back);
+        }
+        textarea
+    }
+}
+
+/// Complete the last component of `text`, a path relative to `root`, as far as the entries
+/// starting with it agree. Directories get a trailing separator once complete.
+fn complete_path(root: &Path, text: &str) -> Option<String> {
+    let (dir, prefix) = text
+        .rfind(['/', MAIN_SEPARATOR])
+        .map_or(("", text), |idx| text.split_at(idx + 1));
+    let mut matches = fs::read_dir(root.join(dir))
+        .ok()?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden entries only when asked for
+            (name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.')))
+                .then(|| (name, entry.path().is_dir()))
+        })
+        .collect_vec();
+    let completed = match matches.len() {
+        0 => return None,
+        1 => {
+            let (mut name, is_dir) = matches.pop().expect("has one match");
+            if is_dir {
+                name.push(MAIN_SEPARATOR);
+            }
+            name
+        }
+        _ => matches
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .reduce(|common, name| {
+                let len = common
+                    .char_indices()
+                    .zip(name.chars())
+                    .find(|((_, a), b)| a != b)
+                    .map_or(common.len().min(name.len()), |((idx, _), _)| idx);
+                &common[..len]
+            })
+            .expect("has many matches")
+            .to_owned(),
+    };
+    Some(format!("{dir}{completed}"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::{super::testing::*, *};
+    use assert_fs::{prelude::*, TempDir};
     use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers};
     use test_log::test;
 
+    #[test]
+    fn renaming_starts_from_the_current_name() {
+        let mut input_box = InputBox::new(Queue::new());
+        input_box.open(InputOperation::Rename {
+            to: "/dir/old.txt".into(),
+        });
+        assert_eq!("old.txt", input_box.text);
+        input_box.submit();
+        assert!(input_box.queue.pop().is_none());
+
+        input_box.open(InputOperation::Rename {
+            to: "/dir/old.txt".into(),
+        });
+        input_box.text = "new.txt".to_owned();
+        input_box.submit();
+        assert_eq!(
+            Some(AppEvent::RenameFile(
+                "/dir/old.txt".into(),
+                "/dir/new.txt".into()
+            )),
+            input_box.queue.pop()
+        );
+    }
+
     #[test]
     fn giving_operation_gives_work() {
         let mut input_box = InputBox::default();
@@ -451,7 +703,7 @@ mod tests {
         input_box.handle_event(&enter).unwrap();
 
         assert_eq!(
-            AppEvent::RunCommand("testing /".to_owned()),
+            AppEvent::RunCommand(format!("testing {}", command::quote("/"))),
             input_box.queue.pop().unwrap()
         );
     }
@@ -513,4 +765,73 @@ mod tests {
             TmuxOpts::VerticalSplit
         )));
     }
+
+    #[test]
+    fn completes_paths_as_far_as_they_agree() {
+        let temp = TempDir::new().unwrap();
+        temp.child("src/main.rs").touch().unwrap();
+        temp.child("src/mod_a.rs").touch().unwrap();
+        temp.child("src/mod_b.rs").touch().unwrap();
+        temp.child(".hidden").touch().unwrap();
+
+        let sep = MAIN_SEPARATOR;
+        assert_eq!(Some(format!("src{sep}")), complete_path(temp.path(), "s"));
+        assert_eq!(
+            Some("src/mod_".to_owned()),
+            complete_path(temp.path(), "src/mo")
+        );
+        assert_eq!(
+            Some("src/main.rs".to_owned()),
+            complete_path(temp.path(), "src/ma")
+        );
+        assert_eq!(None, complete_path(temp.path(), "nothing"));
+        assert_eq!(Some(format!("src{sep}")), complete_path(temp.path(), ""));
+    }
+
+    #[test]
+    fn tab_goes_through_recent_extensions() {
+        let mut input_box = InputBox {
+            operation: InputOperation::FilterExtensions {
+                recent: vec!["rs".to_owned(), "md, txt".to_owned()],
+            },
+            ..Default::default()
+        };
+        for expected in ["rs", "md, txt", "rs"] {
+            input_box.handle_event(&input_event!(KeyCode::Tab)).unwrap();
+            assert_eq!(expected, input_box.text);
+        }
+        input_box
+            .handle_event(&input_event!(KeyCode::Enter))
+            .unwrap();
+        assert_eq!(
+            Some(AppEvent::FilterExtensions("rs".to_owned())),
+            input_box.queue.pop()
+        );
+    }
+
+    #[test]
+    fn goto_missing_path_shows_error() {
+        let temp = TempDir::new().unwrap();
+        let mut input_box = InputBox {
+            operation: InputOperation::Goto {
+                root: temp.path().to_path_buf(),
+            },
+            text: "missing".to_owned(),
+            ..Default::default()
+        };
+        input_box
+            .handle_event(&input_event!(KeyCode::Enter))
+            .unwrap();
+        assert!(input_box.error.is_some());
+        assert!(input_box.queue.pop().is_none());
+
+        temp.child("missing").touch().unwrap();
+        input_box
+            .handle_event(&input_event!(KeyCode::Enter))
+            .unwrap();
+        assert_eq!(
+            Some(AppEvent::GotoFile(temp.path().join("missing"))),
+            input_box.queue.pop()
+        );
+    }
 }
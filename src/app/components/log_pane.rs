@@ -0,0 +1,182 @@
+use crate::{
+    app::component::{Component, Drawable},
+    config::{Config, Key},
+    external_event::ExternalEvent,
+    ui,
+};
+use anyhow::Result;
+use crossterm::event::Event;
+use easy_switch::switch;
+use std::{cell::Cell, rc::Rc};
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    widgets::{Block, Borders},
+    Frame,
+};
+use tui_logger::{
+    TuiLoggerLevelOutput as LoggerLevel, TuiLoggerWidget as Logger, TuiWidgetEvent, TuiWidgetState,
+};
+
+/// The log of the session. Once focused it can be scrolled back through, while it otherwise
+/// follows the newest messages.
+pub struct LogPane {
+    state: TuiWidgetState,
+    focused: bool,
+    /// Whether the newest messages may be out of view
+    scrolled: bool,
+    /// Drawn with a thick border, as the pane keys go to
+    pub highlight: Cell<bool>,
+    config: Rc<Config>,
+}
+
+impl Default for LogPane {
+    fn default() -> Self {
+        Self::new(Rc::new(Config::default()))
+    }
+}
+
+impl LogPane {
+    pub fn new(config: Rc<Config>) -> Self {
+        Self {
+            state: TuiWidgetState::new(),
+            focused: false,
+            scrolled: false,
+            highlight: Cell::new(false),
+            config,
+        }
+    }
+
+    pub fn is_scrolled(&self) -> bool {
+        self.scrolled
+    }
+
+    fn scroll_back(&mut self) {
+        self.state.transition(&TuiWidgetEvent::PrevPageKey);
+        self.scrolled = true;
+    }
+
+    fn scroll_forward(&mut self) {
+        // Paging past the newest message follows new ones again, which cannot be told from here
+        self.state.transition(&TuiWidgetEvent::NextPageKey);
+    }
+
+    /// Jump to the newest messages and keep following them
+    fn jump_to_latest(&mut self) {
+        self.state.transition(&TuiWidgetEvent::EscapeKey);
+        self.scrolled = false;
+    }
+}
+
+impl Drawable for LogPane {
+    fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Result<()> {
+        let title = match (self.focused, self.scrolled) {
+            (true, true) => "Log (scrolled back)",
+            (true, false) => "Log (scrolling)",
+            (false, true) => "Log (not following)",
+            (false, false) => "Log",
+        };
+        let logger = Logger::default()
+            .style_error(self.config.log.error.into())
+            .style_debug(self.config.log.debug.into())
+            .style_warn(self.config.log.warn.into())
+            .style_trace(self.config.log.trace.into())
+            .style_info(self.config.log.info.into())
+            .output_level(Some(LoggerLevel::Long))
+            .output_target(false)
+            .output_file(false)
+            .output_line(false)
+            .output_level(None)
+            .output_timestamp(None)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(title)
+                    .border_style(self.config.log.border_color.into())
+                    .border_type(ui::pane_border(self.highlight.get())),
+            )
+            .state(&self.state);
+        f.render_widget(logger, area);
+
+        Ok(())
+    }
+}
+
+impl Component for LogPane {
+    fn visible(&self) -> bool {
+        true
+    }
+
+    fn focus(&mut self, focus: bool) {
+        self.focused = focus;
+    }
+
+    fn focused(&self) -> bool {
+        self.focused
+    }
+
+    fn handle_event(&mut self, ev: &ExternalEvent) -> Result<()> {
+        if !self.focused {
+            return Ok(());
+        }
+
+        if let ExternalEvent::Crossterm(Event::Key(key)) = ev {
+            // The logger only scrolls by pages
+            switch! { key;
+                self.config.up => self.scroll_back(),
+                self.config.page_up => self.scroll_back(),
+                self.config.down => self.scroll_forward(),
+                self.config.page_down => self.scroll_forward(),
+                self.config.all_down => self.jump_to_latest(),
+                self.config.log.focus => self.focused = false,
+                self.config.quit => self.focused = false,
+                Key::esc() => self.focused = false,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::components::testing::*;
+    use test_log::test;
+
+    fn focused_pane() -> LogPane {
+        let mut pane = LogPane::default();
+        pane.focus(true);
+        pane
+    }
+
+    #[test]
+    fn jumping_to_latest_follows_again() {
+        let mut pane = focused_pane();
+        pane.handle_event(&input_event!(KeyCode::Char('k')))
+            .unwrap();
+        assert!(pane.is_scrolled());
+        pane.handle_event(&input_event!(KeyCode::Char('G'); KeyModifiers::SHIFT))
+            .unwrap();
+        assert!(!pane.is_scrolled());
+    }
+
+    #[test]
+    fn esc_gives_focus_back() {
+        let mut pane = focused_pane();
+        pane.handle_event(&input_event!(KeyCode::Char('k')))
+            .unwrap();
+        pane.handle_event(&input_event!(KeyCode::Esc)).unwrap();
+        assert!(!pane.focused());
+        // Stays scrolled back without focus
+        assert!(pane.is_scrolled());
+    }
+
+    #[test]
+    fn ignores_keys_without_focus() {
+        let mut pane = LogPane::default();
+        pane.handle_event(&input_event!(KeyCode::Char('k')))
+            .unwrap();
+        assert!(!pane.is_scrolled());
+    }
+}
@@ -12,7 +12,7 @@ use easy_switch::switch;
 use itertools::Itertools;
 use std::{
     cell::{Cell, RefCell},
-    path::PathBuf,
+    path::{Path, PathBuf},
     rc::Rc,
 };
 use tui::{
@@ -30,6 +30,8 @@ pub struct MarksPopup {
     config: Rc<Config>,
     state: Cell<ListState>,
     root: PathBuf,
+    /// Marks of the other projects and their roots, while those are shown too
+    others: Option<Vec<(PathBuf, PathBuf)>>,
 }
 
 impl Default for MarksPopup {
@@ -54,6 +56,7 @@ impl MarksPopup {
             root,
             state: state.into(),
             open: false,
+            others: None,
         }
     }
 
@@ -64,6 +67,43 @@ impl MarksPopup {
     pub fn close(&mut self) {
         self.state.get_mut().select(Some(0));
         self.open = false;
+        self.others = None;
+    }
+
+    /// Show or hide the marks of every other project after those of this one
+    pub fn toggle_all(&mut self) -> Result<()> {
+        if self.others.take().is_none() {
+            let others = self.marks.borrow().other_projects()?;
+            self.others = Some(
+                others
+                    .into_iter()
+                    .flat_map(|(project, marks)| {
+                        marks.into_iter().map(move |mark| (project.clone(), mark))
+                    })
+                    .collect(),
+            );
+        }
+        if self.selected() >= self.len() {
+            self.select_first();
+        }
+        Ok(())
+    }
+
+    /// Save the marks of this project, then load those of `root` in their place
+    pub fn switch_project(&mut self, root: PathBuf) -> Result<()> {
+        self.marks.borrow().write()?;
+        *self.marks.borrow_mut() = Marks::from_marks_file(&root)?;
+        self.root = root;
+        Ok(())
+    }
+
+    /// Marks shared with the tree
+    pub fn marks(&self) -> Rc<RefCell<Marks>> {
+        Rc::clone(&self.marks)
+    }
+
+    fn len(&self) -> usize {
+        self.marks.borrow().marks.len() + self.others.as_ref().map_or(0, Vec::len)
     }
 
     pub fn add_mark(&mut self, path: PathBuf) {
@@ -74,10 +114,22 @@ impl MarksPopup {
         self.marks.borrow_mut().marks.push(path);
     }
 
+    pub fn remove_mark(&mut self, path: &Path) {
+        self.marks.borrow_mut().marks.retain(|mark| mark != path);
+        if self.selected() >= self.len() {
+            self.select_first();
+        }
+    }
+
+    /// Write the marks right away, instead of when quitting
+    pub fn save(&self) -> Result<()> {
+        self.marks.borrow().write()
+    }
+
     pub fn delete_selected(&mut self) {
         self.marks.borrow_mut().marks.remove(self.selected());
         if let Some(selected) = self.state.get_mut().selected() {
-            if selected >= self.marks.borrow().marks.len() {
+            if selected >= self.len() {
                 self.select_first();
             }
         } else {
@@ -94,7 +146,7 @@ impl MarksPopup {
 
     fn select_next(&mut self) {
         let current = self.selected();
-        if self.marks.borrow().marks.is_empty() || current == self.marks.borrow().marks.len() - 1 {
+        if current + 1 >= self.len() {
             return;
         }
         self.state.get_mut().select(Some(current + 1));
@@ -113,12 +165,19 @@ impl MarksPopup {
     }
 
     fn select_last(&mut self) {
-        if self.marks.borrow().marks.is_empty() {
+        let len = self.len();
+        if len == 0 {
             return;
         }
-        self.state
-            .get_mut()
-            .select(Some(self.marks.borrow().marks.len() - 1));
+        self.state.get_mut().select(Some(len - 1));
+    }
+
+    /// The selected mark of another project, and that project's root
+    fn selected_other(&self) -> Option<(PathBuf, PathBuf)> {
+        let index = self
+            .selected()
+            .checked_sub(self.marks.borrow().marks.len())?;
+        self.others.as_ref()?.get(index).cloned()
     }
 }
 
@@ -143,14 +202,32 @@ impl Drawable for MarksPopup {
                 })
                 .style(self.config.marks.mark_style.into())
             })
+            .chain(self.others.iter().flatten().map(|(project, mark)| {
+                let name = project
+                    .file_name()
+                    .unwrap_or(project.as_os_str())
+                    .to_string_lossy();
+                let mark = if self.config.marks.relative {
+                    mark.strip_prefix(project).unwrap_or(mark)
+                } else {
+                    mark
+                };
+                ListItem::new(format!("{name}: {}", mark.display()))
+                    .style(self.config.marks.mark_style.into())
+            }))
             .collect_vec();
         let list = List::new(marks)
             .highlight_style(self.config.selected.into())
+            .highlight_symbol(&self.config.selected_symbol)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
                     .border_style(self.config.popup_border_style.into())
-                    .title("Marks"),
+                    .title(if self.others.is_some() {
+                        "Marks (all projects)"
+                    } else {
+                        "Marks"
+                    }),
             );
         let area = ui::centered_rect_absolute(50, 15, area);
         f.render_widget(Clear, area);
@@ -189,6 +266,17 @@ impl Component for MarksPopup {
                     if let Some(selected) = selected {
                         self.queue.add(AppEvent::GotoFile(selected));
                         self.close();
+                    } else if let Some((project, mark)) = self.selected_other() {
+                        self.queue.add(AppEvent::Reroot(project, mark));
+                        self.close();
+                    }
+                },
+                self.config.marks.show_all => self.toggle_all()?,
+                self.config.marks.open_all => {
+                    let marks = self.marks.borrow().marks.clone();
+                    if !marks.is_empty() {
+                        self.queue.add(AppEvent::OpenFiles(marks));
+                        self.close();
                     }
                 },
                 self.config.marks.delete => {
@@ -213,6 +301,10 @@ impl Component for MarksPopup {
 mod tests {
     use super::*;
     use crate::app::components::testing::*;
+    use assert_fs::{prelude::*, TempDir};
+    use scopeguard::defer;
+    use serial_test::serial;
+    use std::env;
     use test_log::test;
 
     fn test_popup() -> MarksPopup {
@@ -311,4 +403,51 @@ mod tests {
         popup.close();
         assert_eq!(0, popup.selected());
     }
+
+    #[test]
+    fn open_all_sends_every_mark() {
+        let mut popup = test_popup();
+        let o = input_event!(KeyCode::Char('o'));
+        popup.handle_event(&o).unwrap();
+        assert_eq!(
+            Some(AppEvent::OpenFiles(vec![".".into(), "/".into()])),
+            popup.queue.pop()
+        );
+        assert!(!popup.visible());
+    }
+
+    #[test]
+    #[serial]
+    fn showing_all_lists_other_projects_and_reroots_to_them() {
+        let temp = TempDir::new().unwrap();
+        temp.child("marks.json")
+            .write_str("{\"/other\": [\"/other/a.txt\"]}")
+            .unwrap();
+        env::set_var("PROJECTABLE_DATA_DIR", temp.path());
+        defer! {
+            env::remove_var("PROJECTABLE_DATA_DIR");
+        }
+
+        let mut popup = test_popup();
+        popup
+            .handle_event(&input_event!(KeyCode::Char('a')))
+            .unwrap();
+        assert_eq!(3, popup.len());
+        popup.select_last();
+        popup.handle_event(&input_event!(KeyCode::Enter)).unwrap();
+        assert_eq!(
+            Some(AppEvent::Reroot("/other".into(), "/other/a.txt".into())),
+            popup.queue.pop()
+        );
+
+        popup.open();
+        assert_eq!(2, popup.len());
+        popup
+            .handle_event(&input_event!(KeyCode::Char('a')))
+            .unwrap();
+        popup
+            .handle_event(&input_event!(KeyCode::Char('a')))
+            .unwrap();
+        assert_eq!(2, popup.len());
+    }
 }
@@ -1,18 +1,28 @@
+mod commands_popup;
+mod error_popup;
 mod file_cmd_popup;
 mod filetree;
 mod fuzzy_match;
 mod input_box;
+mod log_pane;
 mod marks_popup;
 mod pending_popup;
 mod popup;
 mod preview_file;
+mod status_bar;
 mod testing;
+mod toast;
 
+pub use commands_popup::*;
+pub use error_popup::*;
 pub use file_cmd_popup::*;
 pub use filetree::*;
 pub use fuzzy_match::*;
 pub use input_box::*;
+pub use log_pane::*;
 pub use marks_popup::*;
 pub use pending_popup::*;
 pub use popup::*;
 pub use preview_file::*;
+pub use status_bar::*;
+pub use toast::*;
@@ -1,5 +1,5 @@
 use crate::app::component::{Component, Drawable};
-use crate::config::{Config, Key};
+use crate::config::Config;
 use crate::{
     external_event::ExternalEvent,
     queue::{AppEvent, Queue},
@@ -8,6 +8,7 @@ use crate::{
 use anyhow::Result;
 use crossterm::event::Event;
 use easy_switch::switch;
+use itertools::Itertools;
 use std::rc::Rc;
 use std::{cell::Cell, path::PathBuf};
 use tui::{
@@ -17,9 +18,26 @@ use tui::{
     Frame,
 };
 
+/// Items named in the message, the rest are only counted
+const SAMPLE_SIZE: usize = 5;
+
 #[derive(Debug, PartialEq, Eq, Default, Clone)]
 pub enum PendingOperation {
     DeleteFile(PathBuf),
+    OpenFiles(Vec<PathBuf>),
+    /// Move the items into the directory
+    MoveFiles(Vec<PathBuf>, PathBuf),
+    /// Write the default config to the path, as there is no config there yet
+    WriteConfig(PathBuf),
+    /// Run the command for each of the files
+    RunBatch(String, Vec<PathBuf>),
+    /// Make the directory the root to go to a path outside the current one, at a line if given
+    Reroot {
+        root: PathBuf,
+        path: PathBuf,
+        line: Option<usize>,
+        column: Option<usize>,
+    },
     #[default]
     NoPending,
 }
@@ -82,6 +100,90 @@ impl PendingPopup {
         self.state.get_mut().select(Some(current - 1));
     }
 
+    /// Do the pending operation if it was confirmed, then close
+    fn confirm(&mut self) {
+        // The first option denies
+        if self.selected() != 0 {
+            match &self.operation {
+                PendingOperation::DeleteFile(path) => {
+                    self.queue.add(AppEvent::DeleteFile(path.clone()));
+                }
+                PendingOperation::OpenFiles(paths) => {
+                    self.queue.add(AppEvent::EditFiles(paths.clone()));
+                }
+                PendingOperation::MoveFiles(paths, dest) => {
+                    self.queue
+                        .add(AppEvent::MoveFiles(paths.clone(), dest.clone()));
+                }
+                PendingOperation::WriteConfig(path) => {
+                    self.queue.add(AppEvent::WriteConfig(path.clone()));
+                }
+                PendingOperation::RunBatch(command, paths) => {
+                    self.queue
+                        .add(AppEvent::RunBatchOn(command.clone(), paths.clone()));
+                }
+                PendingOperation::Reroot {
+                    root,
+                    path,
+                    line,
+                    column,
+                } => {
+                    self.queue.add(AppEvent::Reroot(root.clone(), path.clone()));
+                    if let Some(line) = line {
+                        self.queue
+                            .add(AppEvent::OpenFileAt(path.clone(), *line, *column));
+                    }
+                }
+                PendingOperation::NoPending => {
+                    unreachable!("only handles events with work")
+                }
+            }
+        }
+        self.reset_work();
+    }
+
+    fn message(&self) -> String {
+        match &self.operation {
+            PendingOperation::DeleteFile(_) => {
+                "Are you sure you want to delete this file/directory?".to_owned()
+            }
+            PendingOperation::OpenFiles(paths) => {
+                format!("Open all {} files?\n\n{}", paths.len(), sample(paths))
+            }
+            PendingOperation::MoveFiles(paths, dest) => format!(
+                "Move all {} items into \"{}\"?\n\n{}",
+                paths.len(),
+                dest.display(),
+                sample(paths)
+            ),
+            PendingOperation::WriteConfig(path) => format!(
+                "There is no config at \"{}\" yet, write one with the defaults?",
+                path.display()
+            ),
+            PendingOperation::RunBatch(command, paths) => format!(
+                "Run \"{command}\" for all {} files?\n\n{}",
+                paths.len(),
+                sample(paths)
+            ),
+            PendingOperation::Reroot { root, path, .. } => format!(
+                "\"{}\" is outside of the project, move the root to \"{}\"?",
+                path.display(),
+                root.display()
+            ),
+            PendingOperation::NoPending => String::new(),
+        }
+    }
+
+    /// Whether the message lists some of the items, needing more room
+    fn lists_items(&self) -> bool {
+        matches!(
+            self.operation,
+            PendingOperation::OpenFiles(_)
+                | PendingOperation::MoveFiles(..)
+                | PendingOperation::RunBatch(..)
+        )
+    }
+
     fn selected(&self) -> usize {
         let state = self.state.take();
         let selected = state.selected().expect("should have selected something");
@@ -103,29 +205,13 @@ impl Component for PendingPopup {
         if let ExternalEvent::Crossterm(Event::Key(key)) = ev {
             switch! { key;
                 self.config.down => self.select_next(),
+                self.config.popup.down => self.select_next(),
                 self.config.up => self.select_prev(),
+                self.config.popup.up => self.select_prev(),
                 self.config.quit => self.reset_work(),
-                Key::esc() => self.reset_work(),
-                self.config.open => {
-                    let selected = self
-                        .state
-                        .get_mut()
-                        .selected()
-                        .expect("should always have something selected");
-                    // Delete option
-                    if selected == 0 {
-                        self.reset_work();
-                        return Ok(());
-                    }
-                    let event = match &self.operation {
-                        PendingOperation::DeleteFile(path) => AppEvent::DeleteFile(path.clone()),
-                        PendingOperation::NoPending => {
-                            unreachable!("has work, checked at top of method")
-                        }
-                    };
-                    self.queue.add(event);
-                    self.reset_work();
-                },
+                self.config.popup.cancel => self.reset_work(),
+                self.config.open => self.confirm(),
+                self.config.popup.confirm => self.confirm(),
             }
         }
         Ok(())
@@ -138,11 +224,17 @@ impl Drawable for PendingPopup {
             return Ok(());
         }
         let items = [ListItem::new("Deny"), ListItem::new("Confirm")];
-        let list = List::new(items).highlight_style(self.config.selected.into());
-        let area = ui::centered_rect(30, 20, area);
+        let list = List::new(items)
+            .highlight_style(self.config.selected.into())
+            .highlight_symbol(&self.config.selected_symbol);
+        let area = if self.lists_items() {
+            ui::centered_rect(50, 50, area)
+        } else {
+            ui::centered_rect(30, 20, area)
+        };
         let layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Length(2), Constraint::Percentage(50)].as_ref())
+            .constraints([Constraint::Min(2), Constraint::Length(2)].as_ref())
             .horizontal_margin(2)
             .vertical_margin(2)
             .split(area);
@@ -156,7 +248,7 @@ impl Drawable for PendingPopup {
             area,
         );
         f.render_widget(
-            Paragraph::new("Are you sure you want to delete this file/directory?")
+            Paragraph::new(self.message())
                 .alignment(Alignment::Center)
                 .wrap(Wrap { trim: true }),
             layout[0],
@@ -168,6 +260,24 @@ impl Drawable for PendingPopup {
     }
 }
 
+/// The names of the first few of `paths`, and how many more there are
+fn sample(paths: &[PathBuf]) -> String {
+    let mut lines = paths
+        .iter()
+        .take(SAMPLE_SIZE)
+        .map(|path| {
+            path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            )
+        })
+        .collect_vec();
+    if paths.len() > SAMPLE_SIZE {
+        lines.push(format!("and {} more", paths.len() - SAMPLE_SIZE));
+    }
+    lines.join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -242,4 +352,34 @@ mod tests {
         }
         assert!(popup.queue.pop().is_some());
     }
+
+    #[test]
+    fn confirming_open_files_opens_each_file() {
+        let events = input_events!(KeyCode::Char('j'), KeyCode::Enter);
+        let mut popup = PendingPopup {
+            operation: PendingOperation::OpenFiles(vec!["/a".into(), "/b".into()]),
+            ..Default::default()
+        };
+        for event in events {
+            popup.handle_event(&event).expect("should handle input");
+        }
+        assert_eq!(
+            Some(AppEvent::EditFiles(vec!["/a".into(), "/b".into()])),
+            popup.queue.pop()
+        );
+        assert!(!popup.visible());
+    }
+
+    #[test]
+    fn message_names_a_sample_of_the_items() {
+        let paths: Vec<PathBuf> = (0..8).map(|n| format!("/dir/{n}.txt").into()).collect();
+        let popup = PendingPopup {
+            operation: PendingOperation::MoveFiles(paths, "/dest".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            "Move all 8 items into \"/dest\"?\n\n0.txt\n1.txt\n2.txt\n3.txt\n4.txt\nand 3 more",
+            popup.message()
+        );
+    }
 }
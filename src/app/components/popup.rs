@@ -5,10 +5,12 @@ use crate::{
     ui,
 };
 use anyhow::Result;
-use crossterm::event::Event;
+use crossterm::event::{Event, KeyCode, KeyModifiers};
 use easy_switch::switch;
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher as Matcher};
 use itertools::Itertools;
 use std::{cell::Cell, rc::Rc};
+use strum::Display;
 use tui::{
     backend::Backend,
     layout::Rect,
@@ -21,14 +23,272 @@ use tui::{
 pub enum Preset {
     Help,
     RunningCommand,
+    /// What the destructive actions skipped by dry run would have done
+    DryRun(Vec<String>),
+    /// Items moved so far out of all of them
+    Moving {
+        done: usize,
+        total: usize,
+    },
+    /// Jobs of a batch command ended so far out of all of them
+    Batch {
+        done: usize,
+        total: usize,
+    },
+    /// How each job of a batch command ended
+    BatchResults(Vec<String>),
     #[default]
     Nothing,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Display)]
+pub enum Category {
+    General,
+    Navigation,
+    #[strum(serialize = "File operations")]
+    FileOperations,
+    #[strum(serialize = "Search and filters")]
+    Search,
+    Git,
+    Preview,
+    Marks,
+    Commands,
+}
+
+/// Every keybind with its description, grouped by category
+pub fn keymap(config: &Config) -> Vec<(Category, Vec<(String, String)>)> {
+    fn entries(binds: &[(&crate::config::KeyBind, &str)]) -> Vec<(String, String)> {
+        binds
+            .iter()
+            .map(|(key, description)| (key.to_string(), (*description).to_owned()))
+            .collect()
+    }
+
+    vec![
+        (
+            Category::General,
+            entries(&[
+                (&config.quit, "Quit"),
+                (&config.help, "Open help window"),
+                (&config.kill_processes, "Kill running commands"),
+                (
+                    &config.dry_run,
+                    "Toggle dry run, only logging destructive actions",
+                ),
+                (&config.copy_error, "Copy error (in error window)"),
+                (
+                    &config.cycle_focus,
+                    "Focus the next pane (tree, preview or log)",
+                ),
+                (&config.open_config, "Open the config in the editor"),
+                (&config.running_commands, "List running commands"),
+                (&config.cargo_targets, "Run or test a Cargo target"),
+                (
+                    &config.scratch_command,
+                    "Run a command and show its output in the preview",
+                ),
+                (&config.log.focus, "Focus the log to scroll it"),
+                (&config.log.export, "Export the log to a file"),
+                (
+                    &config.kill_command,
+                    "Kill selected command (in running commands window)",
+                ),
+            ]),
+        ),
+        (
+            Category::Navigation,
+            entries(&[
+                (&config.open, "Open file/toggle opened"),
+                (&config.down, "Move down"),
+                (&config.up, "Move up"),
+                (&config.all_up, "Go to top"),
+                (&config.all_down, "Go to bottom"),
+                (&config.page_down, "Page down (in special commands window)"),
+                (&config.page_up, "Page up (in special commands window)"),
+                (
+                    &config.fuzzy_preview,
+                    "Preview highlighted result (in fuzzy finder)",
+                ),
+                (&config.popup.confirm, "Confirm (in popups)"),
+                (&config.popup.cancel, "Cancel (in popups)"),
+                (&config.popup.down, "Move down (in popups)"),
+                (&config.popup.up, "Move up (in popups)"),
+                (&config.filetree.down_three, "Move down by 3"),
+                (&config.filetree.up_three, "Move up by 3"),
+                (&config.filetree.open_all, "Open all directories"),
+                (&config.filetree.close_all, "Close all directories"),
+                (&config.filetree.open_under, "Open all under directory"),
+                (&config.filetree.close_under, "Close all under directory"),
+                (
+                    &config.filetree.center_selected,
+                    "Scroll selection to center",
+                ),
+                (&config.filetree.selected_to_top, "Scroll selection to top"),
+                (
+                    &config.filetree.selected_to_bottom,
+                    "Scroll selection to bottom",
+                ),
+                (
+                    &config.filetree.expand_matching,
+                    "Open directories matching a glob",
+                ),
+                (&config.filetree.goto, "Go to a typed path"),
+                (
+                    &config.filetree.recent_dirs,
+                    "Go to a recently visited directory",
+                ),
+                (
+                    &config.filetree.toggle_dirs_first,
+                    "Toggle directories before files",
+                ),
+                (&config.filetree.toggle_reverse, "Reverse the order"),
+                (
+                    &config.filetree.extension_filter,
+                    "Only show files with some extensions",
+                ),
+                (
+                    &config.filetree.flat_mode,
+                    "Toggle a flat list of all files",
+                ),
+                (&config.filetree.next_dir, "Next sibling directory"),
+                (&config.filetree.prev_dir, "Previous sibling directory"),
+            ]),
+        ),
+        (
+            Category::FileOperations,
+            entries(&[
+                (&config.filetree.new_file, "Create new file"),
+                (&config.filetree.new_dir, "Create new directory"),
+                (&config.filetree.delete, "Delete file"),
+                (&config.filetree.rename, "Rename file"),
+                (
+                    &config.filetree.move_path,
+                    "Move file, or the multi-selection, into a directory",
+                ),
+                (&config.filetree.exec_cmd, "Execute command"),
+                (
+                    &config.filetree.batch_cmd,
+                    "Execute command for each listed file",
+                ),
+                (&config.filetree.special_command, "Execute special command"),
+                (
+                    &config.filetree.run_last_special_command,
+                    "Rerun last special command",
+                ),
+                (&config.filetree.copy_contents, "Copy file contents"),
+                (
+                    &config.filetree.copy_paths,
+                    "Copy paths of the selected files",
+                ),
+                (&config.filetree.copy_name, "Copy name of the selection"),
+                (
+                    &config.filetree.copy_stem,
+                    "Copy name without extension of the selection",
+                ),
+                (
+                    &config.filetree.copy_extension,
+                    "Copy extension of the selection",
+                ),
+                (&config.filetree.paste_path, "Go to the copied path"),
+                (
+                    &config.filetree.multi_select,
+                    "Toggle item in multi-selection",
+                ),
+            ]),
+        ),
+        (
+            Category::Search,
+            entries(&[
+                (&config.filetree.search, "Search"),
+                (&config.filetree.content_search, "Search file contents"),
+                (&config.filetree.quick_filter, "Filter the tree"),
+                (&config.filetree.clear, "Clear search and filters"),
+                (&config.filetree.show_dotfiles, "Show dotfiles"),
+                (
+                    &config.filetree.show_ignored,
+                    "Show files left out by ignore files",
+                ),
+                (&config.filetree.full_refresh, "Reload the tree from disk"),
+            ]),
+        ),
+        (
+            Category::Git,
+            entries(&[
+                (&config.filetree.diff_mode, "Toggle diff view"),
+                (&config.filetree.staged_diff_mode, "Toggle staged diff view"),
+                (&config.filetree.blame_mode, "Toggle blame view"),
+                (&config.filetree.show_at_ref, "Show the file on another ref"),
+                (&config.filetree.stage, "Stage selected files"),
+                (&config.filetree.unstage, "Unstage selected files"),
+                (&config.filetree.stage_all, "Stage everything"),
+                (
+                    &config.filetree.git_filter,
+                    "Filter for files with new git changes",
+                ),
+            ]),
+        ),
+        (
+            Category::Preview,
+            entries(&[
+                (&config.preview.down_key, "Preview down"),
+                (&config.preview.up_key, "Preview up"),
+                (&config.preview.pin, "Pin the preview"),
+                (&config.preview.copy_visible, "Copy the visible preview"),
+                (&config.preview.raw, "Show the raw contents of the file"),
+                (&config.preview.search, "Search the preview"),
+                (
+                    &config.preview.next_match,
+                    "Next match (while searching the preview)",
+                ),
+                (
+                    &config.preview.prev_match,
+                    "Previous match (while searching the preview)",
+                ),
+                (
+                    &config.preview.jump,
+                    "Go to a line or percentage of the preview",
+                ),
+                (
+                    &config.preview.open_at_line,
+                    "Open the file at the current match or line",
+                ),
+            ]),
+        ),
+        (
+            Category::Marks,
+            entries(&[
+                (&config.filetree.mark_selected, "Mark selected file"),
+                (&config.filetree.toggle_mark, "Mark or unmark selected file"),
+                (&config.marks.open, "Open marks window"),
+                (&config.marks.open_all, "Open all marks (in marks window)"),
+                (
+                    &config.marks.show_all,
+                    "Show marks of every project (in marks window)",
+                ),
+            ]),
+        ),
+        (
+            Category::Commands,
+            config
+                .commands
+                .iter()
+                .map(|(key, command)| (key.to_string(), command.cmd().to_owned()))
+                .sorted()
+                .collect(),
+        ),
+    ]
+}
+
 #[derive(Default)]
 pub struct Popup {
     pub preset: Preset,
     scroll_y: Cell<u16>,
+    /// Fuzzy filter for the help window
+    filter: String,
+    typing: bool,
+    /// Index into the visible help sections
+    section: usize,
+    collapsed: Vec<Category>,
     config: Rc<Config>,
 }
 
@@ -37,9 +297,69 @@ impl Popup {
         Self {
             preset: Preset::default(),
             scroll_y: 0.into(),
+            filter: String::new(),
+            typing: false,
+            section: 0,
+            collapsed: Vec::new(),
             config,
         }
     }
+
+    fn close(&mut self) {
+        self.preset = Preset::Nothing;
+        self.filter.clear();
+        self.typing = false;
+        self.section = 0;
+        self.scroll_y.set(0);
+    }
+
+    /// Help sections that have at least one entry matching the filter
+    fn help_sections(&self) -> Vec<(Category, Vec<(String, String)>)> {
+        let matcher = SkimMatcherV2::default();
+        keymap(&self.config)
+            .into_iter()
+            .map(|(category, entries)| {
+                if self.filter.is_empty() {
+                    return (category, entries);
+                }
+                let entries = entries
+                    .into_iter()
+                    .filter(|(key, description)| {
+                        matcher
+                            .fuzzy_match(&format!("{key} {description}"), &self.filter)
+                            .is_some()
+                    })
+                    .collect();
+                (category, entries)
+            })
+            .filter(|(_, entries)| !entries.is_empty())
+            .collect()
+    }
+
+    fn toggle_section(&mut self) {
+        let Some((category, _)) = self.help_sections().into_iter().nth(self.section) else {
+            return;
+        };
+        if let Some(pos) = self.collapsed.iter().position(|other| *other == category) {
+            self.collapsed.remove(pos);
+        } else {
+            self.collapsed.push(category);
+        }
+    }
+
+    fn handle_typing(&mut self, code: KeyCode, mods: KeyModifiers) {
+        match code {
+            KeyCode::Char(c) if !mods.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                self.filter.push(c);
+                self.section = 0;
+                self.scroll_y.set(0);
+            }
+            KeyCode::Backspace => drop(self.filter.pop()),
+            KeyCode::Enter => self.typing = false,
+            KeyCode::Esc => self.close(),
+            _ => {}
+        }
+    }
 }
 
 impl Component for Popup {
@@ -53,6 +373,12 @@ impl Component for Popup {
         }
 
         if let ExternalEvent::Crossterm(Event::Key(key)) = ev {
+            if self.typing {
+                self.handle_typing(key.code, key.modifiers);
+                return Ok(());
+            }
+
+            let is_help = self.preset == Preset::Help;
             switch! { key;
                 self.config.down => *self.scroll_y.get_mut() += 1,
                 self.config.up => {
@@ -62,8 +388,18 @@ impl Component for Popup {
                 },
                 self.config.all_up => *self.scroll_y.get_mut() = 0,
                 self.config.all_down => *self.scroll_y.get_mut() = u16::MAX,
-                self.config.quit => self.preset = Preset::Nothing,
-                Key::esc() => self.preset = Preset::Nothing,
+                self.config.quit => self.close(),
+                Key::esc() => self.close(),
+                self.config.filetree.search, is_help => self.typing = true,
+                self.config.open, is_help => self.toggle_section(),
+                Key::key_code(KeyCode::Tab), is_help => {
+                    let sections = self.help_sections().len();
+                    self.section = (self.section + 1) % sections.max(1);
+                },
+                Key::key_code(KeyCode::BackTab), is_help => {
+                    let sections = self.help_sections().len().max(1);
+                    self.section = (self.section + sections - 1) % sections;
+                },
             }
         }
 
@@ -77,107 +413,93 @@ impl Drawable for Popup {
             return Ok(());
         }
 
-        let (text, title, height) = match self.preset {
+        let help_title;
+        let (text, title, height) = match &self.preset {
             Preset::Help => {
-                let mut keybinds = vec![
-                    (self.config.open.to_string(), "Open file/toggle opened"),
-                    (self.config.down.to_string(), "Move down"),
-                    (self.config.up.to_string(), "Move up"),
-                    (self.config.all_up.to_string(), "Go to bottom"),
-                    (self.config.all_down.to_string(), "Go to top"),
-                    (
-                        self.config.filetree.down_three.to_string(),
-                        "Move down by 3",
-                    ),
-                    (self.config.filetree.up_three.to_string(), "Move up by 3"),
-                    (self.config.filetree.delete.to_string(), "Delete file"),
-                    (self.config.filetree.new_file.to_string(), "Create new file"),
-                    (
-                        self.config.filetree.new_dir.to_string(),
-                        "Create new directory",
-                    ),
-                    (self.config.filetree.search.to_string(), "Search"),
-                    (self.config.filetree.clear.to_string(), "Clear search"),
-                    (self.config.preview.up_key.to_string(), "Preview down"),
-                    (self.config.preview.down_key.to_string(), "Preview up"),
-                    (
-                        self.config.filetree.diff_mode.to_string(),
-                        "Toggle diff view",
-                    ),
-                    (
-                        self.config.filetree.git_filter.to_string(),
-                        "Filter for files with new git changes",
-                    ),
-                    (
-                        self.config.filetree.show_dotfiles.to_string(),
-                        "Show dotfiles",
-                    ),
-                    (self.config.filetree.exec_cmd.to_string(), "Execute command"),
-                    (
-                        self.config.filetree.special_command.to_string(),
-                        "Execute special command",
-                    ),
-                    (
-                        self.config.filetree.open_all.to_string(),
-                        "Open all directories",
-                    ),
-                    (
-                        self.config.filetree.close_all.to_string(),
-                        "Close all directories",
-                    ),
-                    (
-                        self.config.filetree.close_under.to_string(),
-                        "Close all under directory",
-                    ),
-                    (
-                        self.config.filetree.open_under.to_string(),
-                        "Open all under directory",
-                    ),
-                    (
-                        self.config.filetree.mark_selected.to_string(),
-                        "Mark selected file",
-                    ),
-                    (self.config.filetree.rename.to_string(), "Rename/move file"),
-                    (self.config.marks.open.to_string(), "Open marks window"),
-                    (self.config.quit.to_string(), "Quit"),
-                    (self.config.help.to_string(), "Open help window"),
-                ];
-                keybinds.extend(
-                    self.config
-                        .commands
-                        .iter()
-                        .map(|(key, action)| (key.to_string(), action.as_str())),
-                );
-                let longest_key_len = keybinds
+                let sections = self.help_sections();
+                let longest_key_len = sections
                     .iter()
-                    .map(|(key, _)| key.len())
+                    .flat_map(|(_, entries)| entries.iter().map(|(key, _)| key.len()))
                     .max()
-                    .expect("should not be empty");
-                (
-                    keybinds
-                        .into_iter()
-                        .map(|(key, description)| {
-                            Spans::from(vec![
-                                Span::styled(
-                                    // Pad based on longest key length
-                                    format!("{:width$}", key, width = longest_key_len + 1),
-                                    self.config.help_key_style.into(),
-                                ),
-                                Span::raw(description),
-                            ])
-                        })
-                        .collect_vec(),
-                    "Help",
-                    35,
-                )
+                    .unwrap_or_default();
+                let mut text = Vec::new();
+                for (i, (category, entries)) in sections.into_iter().enumerate() {
+                    // Sections are always expanded while filtering
+                    let collapsed = self.filter.is_empty() && self.collapsed.contains(&category);
+                    let header = format!("{} {category}", if collapsed { '▸' } else { '▾' });
+                    text.push(Spans::from(Span::styled(
+                        header,
+                        if i == self.section {
+                            self.config.selected.into()
+                        } else {
+                            self.config.help_key_style.into()
+                        },
+                    )));
+                    if collapsed {
+                        continue;
+                    }
+                    text.extend(entries.into_iter().map(|(key, description)| {
+                        Spans::from(vec![
+                            Span::styled(
+                                // Pad based on longest key length
+                                format!("  {:width$}", key, width = longest_key_len + 1),
+                                self.config.help_key_style.into(),
+                            ),
+                            Span::raw(description),
+                        ])
+                    }));
+                }
+                help_title = if self.typing || !self.filter.is_empty() {
+                    format!(
+                        "Help /{}{}",
+                        self.filter,
+                        if self.typing { "_" } else { "" }
+                    )
+                } else {
+                    format!("Help ({} to filter)", self.config.filetree.search)
+                };
+                (text, help_title.as_str(), 35)
             }
             Preset::RunningCommand => {
                 let message = format!(
-                    "Command in-progress. Press {} to quit",
-                    self.config.kill_processes
+                    "Command in-progress. Press {} to quit, {} to list commands",
+                    self.config.kill_processes, self.config.running_commands
                 );
                 (vec![Spans::from(vec![Span::raw(message)])], "Command", 3)
             }
+            Preset::Moving { done, total } => {
+                let message = format!("Moved {done} of {total} items");
+                (vec![Spans::from(vec![Span::raw(message)])], "Moving", 3)
+            }
+            Preset::Batch { done, total } => {
+                let message = format!(
+                    "Ran {done} of {total} commands. Press {} to stop",
+                    self.config.kill_processes
+                );
+                (vec![Spans::from(vec![Span::raw(message)])], "Batch", 3)
+            }
+            Preset::BatchResults(results) => {
+                let text = results
+                    .iter()
+                    .map(|line| Spans::from(line.as_str()))
+                    .collect_vec();
+                let height = u16::try_from(results.len())
+                    .unwrap_or(u16::MAX)
+                    .saturating_add(2)
+                    .min(20);
+                (text, "Batch results", height)
+            }
+            Preset::DryRun(actions) => {
+                let text = actions
+                    .iter()
+                    .map(|action| Spans::from(format!("Would {action}")))
+                    .collect_vec();
+                let height = u16::try_from(actions.len())
+                    .unwrap_or(u16::MAX)
+                    .saturating_add(2)
+                    .min(20);
+                (text, "Dry run", height)
+            }
             Preset::Nothing => unreachable!("checked at top of method"),
         };
 
@@ -222,6 +544,17 @@ mod tests {
         assert!(popup.visible());
     }
 
+    #[test]
+    fn dry_run_closes_like_other_presets() {
+        let mut popup = Popup {
+            preset: Preset::DryRun(vec!["delete \"a.txt\"".to_owned()]),
+            ..Default::default()
+        };
+        assert!(popup.visible());
+        popup.handle_event(&input_event!(KeyCode::Esc)).unwrap();
+        assert_eq!(Preset::Nothing, popup.preset);
+    }
+
     #[test]
     fn q_resets() {
         let mut popup = Popup {
@@ -260,4 +593,89 @@ mod tests {
         popup.handle_event(&all_up).unwrap();
         assert_eq!(0, popup.scroll_y.get());
     }
+
+    fn help_popup() -> Popup {
+        Popup {
+            preset: Preset::Help,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn slash_starts_filtering_help() {
+        let mut popup = help_popup();
+        let [slash, g, i, t] = input_events!(
+            KeyCode::Char('/'),
+            KeyCode::Char('g'),
+            KeyCode::Char('i'),
+            KeyCode::Char('t')
+        );
+        for ev in [slash, g, i, t] {
+            popup.handle_event(&ev).unwrap();
+        }
+        assert_eq!("git", popup.filter);
+        assert_eq!(Preset::Help, popup.preset);
+    }
+
+    #[test]
+    fn filter_narrows_sections() {
+        let mut popup = help_popup();
+        popup.filter = "diff view".to_owned();
+        let sections = popup.help_sections();
+        assert_eq!(1, sections.len());
+        assert_eq!(Category::Git, sections[0].0);
+        assert_eq!(
+            vec![
+                ("t".to_owned(), "Toggle diff view".to_owned()),
+                ("alt-t".to_owned(), "Toggle staged diff view".to_owned())
+            ],
+            sections[0].1
+        );
+    }
+
+    #[test]
+    fn enter_stops_typing_but_keeps_filter() {
+        let mut popup = help_popup();
+        let [slash, q, enter] =
+            input_events!(KeyCode::Char('/'), KeyCode::Char('q'), KeyCode::Enter);
+        for ev in [slash, q, enter] {
+            popup.handle_event(&ev).unwrap();
+        }
+        assert!(!popup.typing);
+        assert_eq!("q", popup.filter);
+        assert_eq!(Preset::Help, popup.preset);
+    }
+
+    #[test]
+    fn esc_closes_while_typing_and_clears_filter() {
+        let mut popup = help_popup();
+        let [slash, q, esc] = input_events!(KeyCode::Char('/'), KeyCode::Char('q'), KeyCode::Esc);
+        for ev in [slash, q, esc] {
+            popup.handle_event(&ev).unwrap();
+        }
+        assert_eq!(Preset::Nothing, popup.preset);
+        assert!(popup.filter.is_empty());
+    }
+
+    #[test]
+    fn enter_toggles_selected_section() {
+        let mut popup = help_popup();
+        let [tab, enter] = input_events!(KeyCode::Tab, KeyCode::Enter);
+        popup.handle_event(&tab).unwrap();
+        popup.handle_event(&enter).unwrap();
+        assert_eq!(vec![Category::Navigation], popup.collapsed);
+        popup.handle_event(&enter).unwrap();
+        assert!(popup.collapsed.is_empty());
+    }
+
+    #[test]
+    fn keymap_reads_current_bindings() {
+        let config = Config {
+            quit: crate::config::KeyBind::key(Key::normal('x')),
+            ..Default::default()
+        };
+        let (category, entries) = &keymap(&config)[0];
+        assert_eq!(Category::General, *category);
+        assert_eq!(("x".to_owned(), "Quit".to_owned()), entries[0]);
+    }
 }
@@ -1,15 +1,19 @@
 use crate::{
     app::component::{Component, Drawable},
-    config::Config,
+    archive, blame, command,
+    config::{Config, DirPreview, Key},
+    encoding,
     external_event::ExternalEvent,
-    ui::{ParagraphState, ScrollParagraph},
+    search::ContentMatch,
+    ui::{self, ParagraphState, ScrollParagraph},
 };
 use ansi_to_tui::IntoText;
 use anyhow::{bail, Context, Result};
-use crossterm::event::{Event, MouseEventKind};
+use crossterm::event::{Event, KeyEvent, MouseEventKind};
 #[cfg(not(target_os = "windows"))]
 use duct::cmd;
 use easy_switch::switch;
+use itertools::Itertools;
 use log::trace;
 #[cfg(not(target_os = "windows"))]
 use std::env;
@@ -17,11 +21,24 @@ use std::env;
 use std::os::windows::process::CommandExt;
 #[cfg(target_os = "windows")]
 use std::process::Command;
-use std::{cell::Cell, collections::VecDeque, path::Path, rc::Rc};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::{self, Display},
+    fs::{self, File},
+    io::Read,
+    mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
 use tui::{
     backend::Backend,
     layout::Rect,
-    widgets::{Block, Borders},
+    style::Style as TuiStyle,
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Paragraph},
     Frame,
 };
 
@@ -43,15 +60,445 @@ enum Mode {
     #[default]
     Preview,
     Diff,
+    /// Diff of changes in the index
+    StagedDiff,
+    /// Who last changed each line
+    Blame,
+}
+
+const DIFF_CMD: &str = "git diff --color=always -- {}";
+
+/// Most links followed in a row before giving up, as the OS does
+const MAX_LINK_DEPTH: usize = 40;
+
+/// Where a symlink leads
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LinkTarget {
+    /// The end of the chain of links, which exists
+    Found(PathBuf),
+    /// The end of the chain of links, which does not exist
+    Broken(PathBuf),
+    /// The links lead back to one of themselves
+    Cycle,
+}
+
+/// Follow `file` through every link until reaching something that is not one, or `None` if
+/// `file` is not a link
+fn follow_link(file: &Path) -> Option<LinkTarget> {
+    if !file.symlink_metadata().ok()?.file_type().is_symlink() {
+        return None;
+    }
+    let mut seen = HashSet::new();
+    let mut current = file.to_path_buf();
+    while let Ok(target) = fs::read_link(&current) {
+        if seen.len() >= MAX_LINK_DEPTH || !seen.insert(current.clone()) {
+            return Some(LinkTarget::Cycle);
+        }
+        // Relative targets start from the directory of the link
+        current = current.parent().unwrap_or(Path::new("")).join(target);
+    }
+    Some(if current.symlink_metadata().is_ok() {
+        LinkTarget::Found(current)
+    } else {
+        LinkTarget::Broken(current)
+    })
+}
+
+/// `file` as it is on `reference`, or why it cannot be shown
+fn show_at(file: &Path, reference: &str) -> Result<String> {
+    let name = file
+        .file_name()
+        .context("file should have a name")?
+        .to_string_lossy();
+    let dir = file.parent();
+    // `./` makes the path relative to the directory git runs in
+    let spec = command::quote(format!("{reference}:./{name}"));
+    let (success, out) = run_with_status(&format!("git show {spec}"), dir)?;
+    if success {
+        return Ok(out);
+    }
+    let (in_repo, _) = run_with_status("git rev-parse --git-dir", dir)?;
+    let (known, _) = run_with_status(
+        &format!(
+            "git rev-parse --verify --quiet {}",
+            command::quote(format!("{reference}^{{commit}}"))
+        ),
+        dir,
+    )?;
+    Ok(if !in_repo {
+        "Not in a git repository".to_owned()
+    } else if known {
+        format!("\"{name}\" does not exist on \"{reference}\"")
+    } else {
+        format!("Unknown ref \"{reference}\"")
+    })
+}
+
+/// Shown instead of the contents of links to something that cannot be previewed as a file
+fn link_note(link: &LinkTarget) -> Option<String> {
+    match link {
+        LinkTarget::Found(target) if target.is_dir() => {
+            Some(format!("Link to the directory \"{}\"", target.display()))
+        }
+        LinkTarget::Found(_) => None,
+        LinkTarget::Broken(target) => Some(format!(
+            "Broken link, \"{}\" does not exist",
+            target.display()
+        )),
+        LinkTarget::Cycle => Some("The link leads back to itself".to_owned()),
+    }
+}
+
+/// Most bytes of previews kept in memory, as estimated by `Rendered::size`. The ones used
+/// longest ago are dropped first.
+const CACHE_BYTES: usize = 8 * 1024 * 1024;
+
+/// A file as it was when previewed, so its preview is only reused until it changes. Diffs and
+/// blames are not cached, as they change with git too.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: PathBuf,
+    modified: SystemTime,
+    len: u64,
+    raw: bool,
+}
+
+/// A preview ready to be drawn
+#[derive(Debug, Clone)]
+struct Rendered {
+    contents: String,
+    text: Text<'static>,
+    header: Option<Header>,
+}
+
+impl Rendered {
+    /// Rough bytes held by the preview: its contents, and the styled lines and spans they were
+    /// turned into
+    fn size(&self) -> usize {
+        let text: usize = self
+            .text
+            .lines
+            .iter()
+            .map(|line| {
+                mem::size_of::<Spans>()
+                    + line
+                        .0
+                        .iter()
+                        .map(|span| mem::size_of::<Span>() + span.content.len())
+                        .sum::<usize>()
+            })
+            .sum();
+        self.contents.len() + text
+    }
+}
+
+/// Previews of files selected lately, the most recently used last
+#[derive(Debug, Default)]
+struct PreviewCache {
+    entries: VecDeque<(CacheKey, Rendered)>,
+    bytes: usize,
+}
+
+impl PreviewCache {
+    fn get(&mut self, key: &CacheKey) -> Option<Rendered> {
+        let index = self.entries.iter().position(|(cached, _)| cached == key)?;
+        let entry = self.entries.remove(index)?;
+        let rendered = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(rendered)
+    }
+
+    fn insert(&mut self, key: CacheKey, rendered: Rendered) {
+        // Older versions of the file will not be asked for again
+        self.forget(|cached| cached.path == key.path && cached.raw == key.raw);
+        let size = rendered.size();
+        if size > CACHE_BYTES {
+            return;
+        }
+        self.bytes += size;
+        self.entries.push_back((key, rendered));
+        while self.bytes > CACHE_BYTES {
+            let Some((_, dropped)) = self.entries.pop_front() else {
+                break;
+            };
+            self.bytes -= dropped.size();
+        }
+    }
+
+    fn forget(&mut self, stale: impl Fn(&CacheKey) -> bool) {
+        let bytes = &mut self.bytes;
+        self.entries.retain(|(key, rendered)| {
+            let keep = !stale(key);
+            if !keep {
+                *bytes -= rendered.size();
+            }
+            keep
+        });
+    }
+}
+
+/// The text of a preview with its colors, or as it is if they cannot be read
+fn render(contents: &str) -> Text<'static> {
+    contents
+        .into_text()
+        .unwrap_or_else(|_| Text::raw(contents.to_owned()))
+}
+
+/// Facts about the previewed file, shown above it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Header {
+    name: String,
+    /// Ref the file is shown at instead of the working copy
+    at_ref: Option<String>,
+    /// Where the file leads, if it is a link
+    link: Option<LinkTarget>,
+    /// Bytes in the file, `None` for directories and links leading nowhere
+    size: Option<u64>,
+    lines: usize,
+    /// Words and bytes of the text read, if counting them is on
+    counts: Option<(usize, usize)>,
+    language: Option<&'static str>,
+    encoding: Option<&'static str>,
+    /// Shown as is, instead of as its type would be
+    raw: bool,
+    truncated: bool,
+}
+
+impl Header {
+    fn new(file: &Path, link: Option<LinkTarget>, contents: &str, truncated: bool) -> Self {
+        let source = match &link {
+            Some(LinkTarget::Found(target)) => target.as_path(),
+            _ => file,
+        };
+        let metadata = fs::metadata(source).ok();
+        let is_file = metadata.as_ref().is_some_and(fs::Metadata::is_file);
+        let language = language(source);
+        let encoding = is_file.then(|| encoding::sniff(source).ok()).flatten();
+        Self {
+            name: file
+                .file_name()
+                .unwrap_or(file.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+            at_ref: None,
+            link,
+            size: metadata.filter(|_| is_file).map(|metadata| metadata.len()),
+            lines: contents.lines().count(),
+            counts: None,
+            language,
+            encoding,
+            raw: false,
+            truncated,
+        }
+    }
+}
+
+impl Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(reference) = &self.at_ref {
+            write!(f, " @ {reference}")?;
+        }
+        match &self.link {
+            Some(LinkTarget::Found(target)) => write!(f, " → {}", target.display())?,
+            Some(LinkTarget::Broken(target)) => write!(f, " → {} (broken)", target.display())?,
+            Some(LinkTarget::Cycle) => write!(f, " → cycle")?,
+            None => {}
+        }
+        match (self.size, &self.link) {
+            (Some(size), _) => write!(f, " · {}", ui::format_bytes(size))?,
+            (None, None | Some(LinkTarget::Found(_))) => write!(f, " · directory")?,
+            (None, _) => {}
+        }
+        match self.lines {
+            1 => write!(f, " · 1 line")?,
+            lines => write!(f, " · {lines} lines")?,
+        }
+        if let Some((words, bytes)) = self.counts {
+            match words {
+                1 => write!(f, ", 1 word")?,
+                words => write!(f, ", {words} words")?,
+            }
+            match bytes {
+                1 => write!(f, ", 1 byte")?,
+                bytes => write!(f, ", {bytes} bytes")?,
+            }
+            // Only what was read is counted
+            if self.truncated {
+                write!(f, " (partial)")?;
+            }
+        }
+        for part in [self.language, self.encoding].into_iter().flatten() {
+            write!(f, " · {part}")?;
+        }
+        if self.raw {
+            write!(f, " · raw")?;
+        }
+        if self.truncated {
+            write!(f, " · truncated")?;
+        }
+        Ok(())
+    }
+}
+
+/// A scratch command whose output is shown in place of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Output {
+    command: String,
+    /// Exit code, `None` if killed by a signal
+    status: Option<i32>,
+}
+
+impl Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "$ {}", self.command)?;
+        match self.status {
+            Some(0) => Ok(()),
+            Some(code) => write!(f, " · exit status {code}"),
+            None => write!(f, " · killed by a signal"),
+        }
+    }
+}
+
+/// A search through the text of the preview
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Search {
+    query: String,
+    /// Line and byte range of every match, in order
+    matches: Vec<(usize, Range<usize>)>,
+    /// Index of the match jumped to last
+    current: usize,
+}
+
+/// Restyle the parts of `line` in `ranges`, byte ranges of the line's text in order
+fn highlight(line: Spans<'_>, ranges: &[(Range<usize>, TuiStyle)]) -> Spans<'static> {
+    let mut spans = Vec::with_capacity(line.0.len() + ranges.len() * 2);
+    let mut offset = 0;
+    for span in line.0 {
+        let text = span.content.as_ref();
+        let (start, end) = (offset, offset + text.len());
+        offset = end;
+        let mut pos = 0;
+        for (range, style) in ranges {
+            let from = range.start.clamp(start, end) - start;
+            let to = range.end.clamp(start, end) - start;
+            if from >= to {
+                continue;
+            }
+            if from > pos {
+                spans.push(Span::styled(text[pos..from].to_owned(), span.style));
+            }
+            spans.push(Span::styled(
+                text[from..to].to_owned(),
+                span.style.patch(*style),
+            ));
+            pos = to;
+        }
+        if pos < text.len() {
+            spans.push(Span::styled(text[pos..].to_owned(), span.style));
+        }
+    }
+    Spans::from(spans)
+}
+
+/// Language of a file, by its name
+fn language(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?;
+    match name {
+        "Makefile" | "makefile" | "GNUmakefile" => return Some("Makefile"),
+        "Dockerfile" => return Some("Dockerfile"),
+        "CMakeLists.txt" => return Some("CMake"),
+        _ => {}
+    }
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "rs" => "Rust",
+        "py" => "Python",
+        "js" | "mjs" | "cjs" => "JavaScript",
+        "ts" => "TypeScript",
+        "jsx" | "tsx" => "JSX",
+        "go" => "Go",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "C++",
+        "cs" => "C#",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "swift" => "Swift",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "lua" => "Lua",
+        "zig" => "Zig",
+        "hs" => "Haskell",
+        "ex" | "exs" => "Elixir",
+        "sh" | "bash" | "zsh" => "Shell",
+        "fish" => "Fish",
+        "ps1" => "PowerShell",
+        "nix" => "Nix",
+        "sql" => "SQL",
+        "html" | "htm" => "HTML",
+        "css" => "CSS",
+        "scss" => "SCSS",
+        "json" => "JSON",
+        "toml" => "TOML",
+        "yaml" | "yml" => "YAML",
+        "xml" => "XML",
+        "md" | "markdown" => "Markdown",
+        "tex" => "LaTeX",
+        "vim" => "Vim script",
+        _ => return None,
+    })
+}
+
+/// Where to scroll the preview to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jump {
+    /// A 1-based line
+    Line(usize),
+    Percent(usize),
+}
+
+impl Jump {
+    /// Read a line like `120` or a percentage like `50%`
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        text.strip_suffix('%').map_or_else(
+            || text.parse().ok().map(Self::Line),
+            |percent| percent.trim().parse().ok().map(Self::Percent),
+        )
+    }
 }
 
 pub struct PreviewFile {
     git_cmd: String,
     mode: Mode,
     contents: String,
+    /// `contents` with its colors, as drawn
+    text: Text<'static>,
+    /// Shown above the contents, if enabled
+    header: Option<Header>,
+    search: Option<Search>,
+    /// The file being previewed
+    path: Option<PathBuf>,
+    /// Ref to show the file at instead of the working copy, dropped when previewing another file
+    at_ref: Option<String>,
+    /// Show the file's plain contents in any mode, until another file is previewed
+    raw: bool,
+    /// Set while showing the output of a scratch command instead of a file
+    output: Option<Output>,
+    /// Ignore requests to preview other files
+    pinned: bool,
+    /// Files found by the content search, previewed as the lines around their matches
+    matches: HashMap<PathBuf, ContentMatch>,
+    /// Set while showing the matches of a file instead of its contents
+    excerpt: bool,
+    cache: PreviewCache,
     focused: bool,
     config: Rc<Config>,
     state: Cell<ParagraphState>,
+    /// Lines of text that fit in the last drawn preview
+    height: Cell<u16>,
+    /// Drawn with a thick border, as the pane keys go to
+    pub highlight: Cell<bool>,
     scrolls: Cell<VecDeque<Scroll>>,
 }
 
@@ -59,11 +506,24 @@ impl Default for PreviewFile {
     fn default() -> Self {
         Self {
             contents: String::new(),
+            text: Text::default(),
+            header: None,
+            search: None,
+            path: None,
+            at_ref: None,
+            raw: false,
+            output: None,
+            pinned: false,
+            matches: HashMap::new(),
+            excerpt: false,
+            cache: PreviewCache::default(),
             focused: true,
             mode: Mode::default(),
             config: Rc::new(Config::default()),
-            git_cmd: "git diff {}".to_owned(),
+            git_cmd: DIFF_CMD.to_owned(),
             state: ParagraphState::default().into(),
+            height: Cell::new(0),
+            highlight: Cell::new(false),
             scrolls: VecDeque::new().into(),
         }
     }
@@ -73,11 +533,24 @@ impl PreviewFile {
     pub fn new() -> Self {
         Self {
             contents: String::new(),
+            text: Text::default(),
+            header: None,
+            search: None,
+            path: None,
+            at_ref: None,
+            raw: false,
+            output: None,
+            pinned: false,
+            matches: HashMap::new(),
+            excerpt: false,
+            cache: PreviewCache::default(),
             focused: true,
             mode: Mode::default(),
             config: Rc::new(Config::default()),
-            git_cmd: "git diff {}".to_owned(),
+            git_cmd: DIFF_CMD.to_owned(),
             state: ParagraphState::default().into(),
+            height: Cell::new(0),
+            highlight: Cell::new(false),
             scrolls: VecDeque::new().into(),
         }
     }
@@ -89,63 +562,510 @@ impl PreviewFile {
                 .preview
                 .git_pager
                 .as_ref()
-                .map_or("git diff {}".to_owned(), |cmd| {
-                    format!("git diff {{}} | {}", cmd)
+                .map_or(DIFF_CMD.to_owned(), |cmd| {
+                    format!("git diff -- {{}} | {}", cmd)
                 }),
             ..Self::new()
         }
     }
 
+    /// Preview `file`, unless the preview is pinned to another file
     pub fn preview_file(&mut self, file: impl AsRef<Path>) -> Result<()> {
+        if self.pinned {
+            return Ok(());
+        }
+        self.load(file)
+    }
+
+    /// Preview `file` as it is on `reference` until another file is previewed, or as it is on
+    /// disk again without one
+    pub fn show_at_ref(&mut self, file: impl AsRef<Path>, reference: Option<String>) -> Result<()> {
+        self.path = Some(file.as_ref().to_path_buf());
+        self.at_ref = reference;
+        self.load(file)
+    }
+
+    fn load(&mut self, file: impl AsRef<Path>) -> Result<()> {
         if self.config.preview.preview_cmd.is_empty() || self.git_cmd.is_empty() {
             bail!("should have command");
         }
+        if self.path.as_deref() != Some(file.as_ref()) {
+            self.at_ref = None;
+            self.raw = false;
+        }
         self.state.get_mut().reset();
-        let replaced = {
-            #[cfg(target_os = "windows")]
-            let replacement = format!("\"{}\"", file.as_ref().display());
-            #[cfg(not(target_os = "windows"))]
-            let replacement = format!("'{}'", file.as_ref().display());
-
-            if self.mode == Mode::Preview {
-                self.config.preview.preview_cmd.replace("{}", &replacement)
-            } else {
-                self.git_cmd.replace("{}", &replacement)
+        self.search = None;
+        self.output = None;
+        let quoted = command::quote(file.as_ref());
+        // Previews read what links lead to, while git still looks at the link itself
+        let link = follow_link(file.as_ref());
+        let mut note = link.as_ref().and_then(link_note);
+        let source = match &link {
+            Some(LinkTarget::Found(target)) => target.as_path(),
+            _ => file.as_ref(),
+        };
+        // A directory shows its README instead, if it has one
+        let readme = (self.mode == Mode::Preview
+            && self.at_ref.is_none()
+            && !self.raw
+            && self.config.preview.dir_preview == DirPreview::Readme
+            && source.is_dir())
+        .then(|| find_readme(source))
+        .flatten();
+        let source = readme.as_deref().unwrap_or(source);
+        let quoted_source = command::quote(source);
+
+        let mut truncated = false;
+        self.excerpt = !self.raw
+            && self.mode == Mode::Preview
+            && self.at_ref.is_none()
+            && self.matches.contains_key(file.as_ref());
+        let key = self.cache_key(file.as_ref(), source);
+        if let Some(rendered) = key.as_ref().and_then(|key| self.cache.get(key)) {
+            trace!("previewing \"{}\" from the cache", file.as_ref().display());
+            self.contents = rendered.contents;
+            self.text = rendered.text;
+            self.header = rendered.header;
+            self.path = Some(file.as_ref().to_path_buf());
+            return Ok(());
+        }
+        self.contents = match (&self.mode, &self.at_ref) {
+            _ if self.excerpt => self.matches[file.as_ref()].excerpt(),
+            _ if self.raw && note.is_some() => note.take().unwrap_or_default(),
+            _ if self.raw => {
+                let text;
+                (text, truncated) = raw_preview(source, self.config.preview.max_size)?;
+                text
+            }
+            (Mode::Preview, Some(reference)) => show_at(file.as_ref(), reference)?,
+            (Mode::Preview, None) if note.is_some() => note.take().unwrap_or_default(),
+            (Mode::Preview, None) if is_pdf(source) && !self.config.preview.pdf_cmd.is_empty() => {
+                let text;
+                (text, truncated) = self.pdf_preview(source, &quoted_source)?;
+                text
+            }
+            (Mode::Preview, None) if archive::is_archive(source) => {
+                let text;
+                (text, truncated) = archive_preview(source, self.config.preview.max_size)?;
+                text
+            }
+            (Mode::Preview, None) if source.is_dir() => match self.config.preview.dir_preview {
+                DirPreview::None => String::new(),
+                DirPreview::Readme | DirPreview::Listing => {
+                    let text;
+                    (text, truncated) = dir_listing(source)?;
+                    text
+                }
+            },
+            (Mode::Preview, None) => run(
+                &self
+                    .config
+                    .preview
+                    .preview_cmd
+                    .replace("{}", &quoted_source),
+                None,
+            )?,
+            (Mode::Diff | Mode::StagedDiff, reference) => {
+                let mut git_diff = "git diff".to_owned();
+                if self.mode == Mode::StagedDiff {
+                    git_diff.push_str(" --cached");
+                }
+                // Compare against the ref instead of the index or the last commit
+                if let Some(reference) = reference {
+                    git_diff = format!("{git_diff} {}", command::quote(reference));
+                }
+                let git_cmd = self.git_cmd.replacen("git diff", &git_diff, 1);
+                // Run from the file's directory, so git finds the repo it belongs to
+                let dir = file.as_ref().parent();
+                let out = run(&git_cmd.replace("{}", &quoted), dir)?;
+                match reference {
+                    _ if !out.trim().is_empty() => out,
+                    Some(reference) => format!("No changes since \"{reference}\""),
+                    None => self.diff_note(&quoted, dir)?,
+                }
+            }
+            (Mode::Blame, _) => {
+                let text;
+                (text, truncated) = self.blame(file.as_ref(), &quoted)?;
+                text
             }
         };
+        self.header = self.config.preview.header.then(|| {
+            let header = Header::new(file.as_ref(), link, &self.contents, truncated);
+            let is_text = header.encoding.is_some_and(|encoding| encoding != "binary");
+            Header {
+                at_ref: self.at_ref.clone(),
+                raw: self.raw,
+                counts: (self.config.preview.counts && self.mode == Mode::Preview && is_text).then(
+                    || {
+                        (
+                            self.contents.split_whitespace().count(),
+                            self.contents.len(),
+                        )
+                    },
+                ),
+                ..header
+            }
+        });
+        self.text = render(&self.contents);
+        if let Some(key) = key {
+            self.cache.insert(
+                key,
+                Rendered {
+                    contents: self.contents.clone(),
+                    text: self.text.clone(),
+                    header: self.header.clone(),
+                },
+            );
+        }
+        self.path = Some(file.as_ref().to_path_buf());
+        Ok(())
+    }
 
-        #[cfg(target_os = "windows")]
-        let out = {
-            let out = Command::new("cmd.exe")
-                // See https://github.com/rust-lang/rust/issues/92939
-                .raw_arg(&format!("/C {replaced}"))
-                .output()
-                .with_context(|| format!("problem running preview command with {replaced}"))?;
-            String::from_utf8_lossy(&out.stdout).to_string()
+    /// What the preview of `file`, read from `source`, is cached by, unless it cannot be
+    fn cache_key(&self, file: &Path, source: &Path) -> Option<CacheKey> {
+        if !self.config.preview.cache
+            || self.mode != Mode::Preview
+            || self.excerpt
+            || self.at_ref.is_some()
+        {
+            return None;
+        }
+        let metadata = fs::metadata(source).ok()?;
+        Some(CacheKey {
+            path: file.to_path_buf(),
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+            raw: self.raw,
+        })
+    }
+
+    /// Stop reusing the previews of `paths`, and of anything in them
+    pub fn forget_cached(&mut self, paths: &[&Path]) {
+        self.cache
+            .forget(|key| paths.iter().any(|path| key.path.starts_with(path)));
+    }
+
+    pub fn clear_cache(&mut self) {
+        self.cache = PreviewCache::default();
+    }
+
+    /// Show what a scratch command printed until the next file is previewed. Unpins the preview,
+    /// since there is no file left to pin it to.
+    pub fn show_output(&mut self, command: &str, output: &str, status: Option<i32>) {
+        self.state.get_mut().reset();
+        self.search = None;
+        self.header = None;
+        self.path = None;
+        self.pinned = false;
+        self.excerpt = false;
+        self.contents = if output.trim().is_empty() {
+            "No output".to_owned()
+        } else {
+            output.to_owned()
         };
-        #[cfg(not(target_os = "windows"))]
-        let out = cmd!(
-            env::var("SHELL").unwrap_or("sh".to_owned()),
-            "-c",
-            &replaced
-        )
-        .unchecked()
-        .stderr_to_stdout()
-        .read()
-        .with_context(|| format!("problem running preview command with {replaced}"))?;
+        self.text = render(&self.contents);
+        self.output = Some(Output {
+            command: command.to_owned(),
+            status,
+        });
+    }
 
-        trace!("ran preview command: \"{replaced}\"");
-        self.contents = out;
-        Ok(())
+    /// Text of the first page of a PDF, or its size if no text could be extracted. Also returns
+    /// whether the text was cut off.
+    fn pdf_preview(&self, file: &Path, quoted: &str) -> Result<(String, bool)> {
+        let pdf_cmd = &self.config.preview.pdf_cmd;
+        let (success, mut text) = run_with_status(&pdf_cmd.replace("{}", quoted), None)?;
+        if !success || text.trim().is_empty() {
+            let size = fs::metadata(file)
+                .with_context(|| format!("failed to read metadata of \"{}\"", file.display()))?
+                .len();
+            return Ok((
+                format!("PDF, {size} bytes\n\nNo text could be extracted with \"{pdf_cmd}\""),
+                false,
+            ));
+        }
+        let max_size = self.config.preview.max_size;
+        let truncated = text.len() > max_size;
+        if truncated {
+            let end = (0..=max_size)
+                .rev()
+                .find(|&index| text.is_char_boundary(index))
+                .unwrap_or_default();
+            text.truncate(end);
+        }
+        Ok((text, truncated))
+    }
+
+    /// Explains why a file has no diff to show
+    fn diff_note(&self, quoted: &str, dir: Option<&Path>) -> Result<String> {
+        let status = run(&format!("git status --porcelain -- {quoted}"), dir)?;
+        let note = if status.starts_with("??") {
+            "Untracked file, there is nothing to compare it to"
+        } else if self.mode == Mode::StagedDiff {
+            "No staged changes"
+        } else if !status.trim().is_empty() {
+            "No unstaged changes, only staged ones"
+        } else {
+            "No changes since the last commit"
+        };
+        Ok(note.to_owned())
+    }
+
+    /// `git blame` of the file, capped to the lines in the first `max_size` bytes, or a note
+    /// when it has no history. Also returns whether lines were left out.
+    fn blame(&self, file: &Path, quoted: &str) -> Result<(String, bool)> {
+        if !file.is_file() {
+            return Ok(("Only files can be blamed".to_owned(), false));
+        }
+        let max_size = self.config.preview.max_size;
+        let mut start = Vec::with_capacity(max_size.min(1 << 20));
+        File::open(file)
+            .and_then(|opened| opened.take(max_size as u64 + 1).read_to_end(&mut start))
+            .with_context(|| format!("failed to read \"{}\"", file.display()))?;
+        let truncated = start.len() > max_size;
+        let range = if truncated {
+            let lines = start[..max_size]
+                .iter()
+                .filter(|&&byte| byte == b'\n')
+                .count();
+            format!("-L 1,{} ", lines.max(1))
+        } else {
+            String::new()
+        };
+        let dir = file.parent();
+        let (success, out) = run_with_status(
+            &format!("git blame --line-porcelain {range}-- {quoted}"),
+            dir,
+        )?;
+        if success {
+            return Ok((blame::format(&out), truncated));
+        }
+        let (in_repo, status) =
+            run_with_status(&format!("git status --porcelain -- {quoted}"), dir)?;
+        let note = if !in_repo {
+            "Not in a git repository"
+        } else if status.starts_with("??") {
+            "Untracked file, it has no history to blame"
+        } else {
+            out.trim()
+        };
+        Ok((note.to_owned(), false))
+    }
+
+    pub fn toggle_mode(&mut self) -> Result<()> {
+        self.switch_mode(Mode::Diff)
+    }
+
+    pub fn toggle_staged_diff(&mut self) -> Result<()> {
+        self.switch_mode(Mode::StagedDiff)
+    }
+
+    pub fn toggle_blame(&mut self) -> Result<()> {
+        self.switch_mode(Mode::Blame)
+    }
+
+    /// Show the plain contents of the current file, or go back to its usual preview
+    pub fn toggle_raw(&mut self) -> Result<()> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        self.raw = !self.raw;
+        self.load(path)
+    }
+
+    /// Go to `mode`, or back to previewing if already in it
+    fn switch_mode(&mut self, mode: Mode) -> Result<()> {
+        self.mode = if self.mode == mode {
+            Mode::Preview
+        } else {
+            mode
+        };
+        // The selection will not update a pinned preview, so reload it here
+        match self.path.clone() {
+            Some(path) if self.pinned => self.load(path),
+            _ => Ok(()),
+        }
+    }
+
+    /// The lines of the preview currently on screen, without colors
+    pub fn visible_text(&self) -> Result<String> {
+        let text = self.contents.into_text()?;
+        let offset = self.state.take();
+        let lines = text
+            .lines
+            .iter()
+            .skip(offset.offset_top.into())
+            .take(self.height.get().into())
+            .map(|line| {
+                line.0
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        self.state.set(offset);
+        Ok(lines.join("\n"))
+    }
+
+    /// Find `query` in the text of the preview, ignoring ASCII case, and jump to the first match
+    /// from the top of the view. Returns the amount of matches.
+    pub fn search(&mut self, query: &str) -> Result<usize> {
+        let text = self.contents.into_text()?;
+        let needle = query.to_ascii_lowercase();
+        let matches = text
+            .lines
+            .iter()
+            .enumerate()
+            .flat_map(|(line, spans)| {
+                let plain = spans
+                    .0
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect::<String>()
+                    .to_ascii_lowercase();
+                plain
+                    .match_indices(&needle)
+                    .map(|(start, _)| (line, start..start + needle.len()))
+                    .collect_vec()
+            })
+            .collect_vec();
+        let top = usize::from(self.state.get_mut().offset_top);
+        let current = matches
+            .iter()
+            .position(|(line, _)| *line >= top)
+            .unwrap_or_default();
+        let count = matches.len();
+        self.search = Some(Search {
+            query: query.to_owned(),
+            matches,
+            current,
+        });
+        self.scroll_to_match();
+        Ok(count)
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    /// Handle a key of an ongoing search, returning false if it is not one
+    pub fn handle_search_key(&mut self, key: &KeyEvent) -> bool {
+        let Some(search) = &mut self.search else {
+            return false;
+        };
+        let len = search.matches.len().max(1);
+        switch! { key;
+            self.config.preview.next_match => search.current = (search.current + 1) % len,
+            self.config.preview.prev_match => search.current = (search.current + len - 1) % len,
+            Key::esc() => {
+                self.search = None;
+                return true;
+            },
+            _ => return false,
+        }
+        self.scroll_to_match();
+        true
     }
 
-    pub fn toggle_mode(&mut self) {
-        if self.mode == Mode::Preview {
-            self.mode = Mode::Diff;
+    /// The previewed file and the 1-based line of the current match, or of the top of the view.
+    /// `None` unless the preview shows the lines of a file as they are.
+    pub fn current_line(&self) -> Option<(PathBuf, usize)> {
+        let path = self.path.as_ref().filter(|path| path.is_file())?;
+        if self.mode != Mode::Preview || is_pdf(path) || archive::is_archive(path) {
+            return None;
+        }
+        let line = self
+            .search
+            .as_ref()
+            .and_then(|search| search.matches.get(search.current))
+            .map_or_else(
+                || {
+                    let state = self.state.take();
+                    let top = state.offset_top.into();
+                    self.state.set(state);
+                    top
+                },
+                |(line, _)| *line,
+            );
+        if self.excerpt {
+            return Some((path.clone(), self.matches.get(path)?.line_at(line)?));
+        }
+        Some((path.clone(), line + 1))
+    }
+
+    /// Scroll the current match to the middle of the view
+    fn scroll_to_match(&mut self) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        let Some((line, _)) = search.matches.get(search.current) else {
+            return;
+        };
+        let top = line.saturating_sub(usize::from(self.height.get() / 2));
+        self.state.get_mut().offset_top = u16::try_from(top).unwrap_or(u16::MAX);
+    }
+
+    /// Scroll with the keys that otherwise move through the tree, returning whether `key` was one
+    /// of them
+    pub fn scroll_key(&mut self, key: &KeyEvent) -> bool {
+        let lines = u16::try_from(self.contents.lines().count()).unwrap_or(u16::MAX);
+        let bottom = lines.saturating_sub(self.height.get());
+        let state = self.state.get_mut();
+        if self.config.down == key {
+            state.offset_top = state.offset_top.saturating_add(1).min(bottom);
+        } else if self.config.up == key {
+            state.up();
+        } else if self.config.all_down == key {
+            state.offset_top = bottom;
+        } else if self.config.all_up == key {
+            state.reset();
         } else {
-            self.mode = Mode::Preview;
+            return false;
+        }
+        true
+    }
+
+    /// Scroll the line of `jump` to the top of the view, staying within the preview
+    pub fn jump(&mut self, jump: Jump) {
+        let lines = self.contents.lines().count();
+        let line = match jump {
+            Jump::Line(line) => line.saturating_sub(1),
+            Jump::Percent(percent) => lines * percent.min(100) / 100,
+        };
+        let top = line.min(lines.saturating_sub(1));
+        self.state.get_mut().offset_top = u16::try_from(top).unwrap_or(u16::MAX);
+    }
+
+    /// Preview the files of `matches` as their matching lines from now on
+    pub fn add_matches(&mut self, matches: &[ContentMatch]) {
+        self.matches.extend(
+            matches
+                .iter()
+                .map(|found| (found.path.clone(), found.clone())),
+        );
+    }
+
+    /// Go back to previewing the contents of the files found by the content search
+    pub fn clear_matches(&mut self) -> Result<()> {
+        self.matches.clear();
+        match self.path.clone() {
+            Some(path) if self.excerpt => self.load(path),
+            _ => Ok(()),
         }
     }
+
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    /// Pin the preview to the current file, or unpin it
+    pub fn toggle_pin(&mut self) {
+        if self.path.is_none() {
+            return;
+        }
+        self.pinned = !self.pinned;
+    }
 }
 
 impl Component for PreviewFile {
@@ -200,14 +1120,85 @@ impl Component for PreviewFile {
 
 impl Drawable for PreviewFile {
     fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Result<()> {
-        let text = self.contents.into_text()?;
+        let mut text = self.text.clone();
+        if let Some(search) = &self.search {
+            let styles = (
+                TuiStyle::from(self.config.preview.match_style),
+                TuiStyle::from(self.config.preview.current_match_style),
+            );
+            for (line, matches) in &search
+                .matches
+                .iter()
+                .enumerate()
+                .group_by(|(_, (line, _))| *line)
+            {
+                let ranges = matches
+                    .map(|(index, (_, range))| {
+                        let style = if index == search.current {
+                            styles.1
+                        } else {
+                            styles.0
+                        };
+                        (range.clone(), style)
+                    })
+                    .collect_vec();
+                if let Some(spans) = text.lines.get_mut(line) {
+                    *spans = highlight(mem::take(spans), &ranges);
+                }
+            }
+        }
+        let name = match self.mode {
+            Mode::Preview => "Preview",
+            Mode::Diff => "Diff",
+            Mode::StagedDiff => "Staged Diff",
+            Mode::Blame => "Blame",
+        };
+        let name = if self.output.is_some() {
+            "Output"
+        } else if self.excerpt {
+            "Matches"
+        } else {
+            name
+        };
+        let title = match &self.path {
+            Some(path) if self.pinned => format!(
+                "{name} (pinned: {})",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            _ => name.to_owned(),
+        };
+        let title = match &self.search {
+            Some(search) if search.matches.is_empty() => {
+                format!("{title} [{}: no matches]", search.query)
+            }
+            Some(search) => format!(
+                "{title} [{}: {}/{}]",
+                search.query,
+                search.current + 1,
+                search.matches.len()
+            ),
+            None => title,
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(self.config.preview.border_color.into())
+            .border_type(ui::pane_border(self.highlight.get()));
+        let mut inner = block.inner(area);
+        f.render_widget(block, area);
+        // Shown even without headers, it is the only place the exit status appears
+        let header = self
+            .output
+            .as_ref()
+            .map(Output::to_string)
+            .or_else(|| self.header.as_ref().map(Header::to_string));
+        if let Some(header) = header.filter(|_| inner.height > 1) {
+            let line = Paragraph::new(header).style(self.config.preview.header_style.into());
+            f.render_widget(line, Rect { height: 1, ..inner });
+            inner.y += 1;
+            inner.height -= 1;
+        }
         let paragraph = ScrollParagraph::new(text)
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .title("Preview")
-                    .border_style(self.config.preview.border_color.into()),
-            )
             .bar_style(self.config.preview.scroll_bar_color.into())
             .unreached_bar_style(self.config.preview.unreached_bar_color.into());
         let mut state = self.state.take();
@@ -221,19 +1212,223 @@ impl Drawable for PreviewFile {
                 }
             }
         }
-        f.render_stateful_widget(paragraph, area, &mut state);
+        f.render_stateful_widget(paragraph, inner, &mut state);
         self.state.set(state);
+        self.height.set(inner.height);
 
         Ok(())
     }
 }
 
+/// Most bytes of a binary file shown in a raw preview, as each takes up several characters
+const MAX_HEX_DUMP: usize = 64 * 1024;
+
+/// The text of `file` with control characters made visible, or a hex dump of its bytes if it is
+/// binary. Also returns whether it was cut off.
+fn raw_preview(file: &Path, max_size: usize) -> Result<(String, bool)> {
+    if file.is_dir() {
+        return Ok(("Directories have no raw contents".to_owned(), false));
+    }
+    let mut bytes = Vec::new();
+    File::open(file)
+        .and_then(|opened| opened.take(max_size as u64 + 1).read_to_end(&mut bytes))
+        .with_context(|| format!("failed to read \"{}\"", file.display()))?;
+    let mut truncated = bytes.len() > max_size;
+    bytes.truncate(max_size);
+    if let Some(text) = encoding::decode(&bytes) {
+        // Escape sequences would otherwise be drawn as colors
+        let text = text
+            .chars()
+            .map(|c| match c {
+                '\n' | '\t' => c,
+                '\x7f' => '\u{2421}',
+                c if c < ' ' => char::from_u32(0x2400 + u32::from(c)).unwrap_or(c),
+                c => c,
+            })
+            .collect();
+        return Ok((text, truncated));
+    }
+    truncated |= bytes.len() > MAX_HEX_DUMP;
+    bytes.truncate(MAX_HEX_DUMP);
+    Ok((hex_dump(&bytes), truncated))
+}
+
+/// Offset, hex and printable characters of every 16 bytes, like `xxd`
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex = chunk.iter().map(|byte| format!("{byte:02x}")).join(" ");
+            let ascii: String = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        char::from(byte)
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x}  {hex:<47}  {ascii}", line * 16)
+        })
+        .join("\n")
+}
+
+/// Most entries listed when previewing an archive
+const MAX_ARCHIVE_ENTRIES: usize = 500;
+
+/// Sizes and names of the entries of an archive, and whether some were left out
+fn archive_preview(file: &Path, max_size: usize) -> Result<(String, bool)> {
+    let listing = archive::list(file, MAX_ARCHIVE_ENTRIES, max_size as u64)?;
+    let entries = listing
+        .entries
+        .iter()
+        .map(|entry| (entry.name.as_str(), (!entry.is_dir).then_some(entry.size)))
+        .collect_vec();
+    Ok((
+        list_entries(&entries, listing.truncated, MAX_ARCHIVE_ENTRIES),
+        listing.truncated,
+    ))
+}
+
+/// Most entries listed when previewing a directory
+const MAX_DIR_ENTRIES: usize = 500;
+
+/// Sizes and names of what is in `dir`, directories first, and whether some were left out
+fn dir_listing(dir: &Path) -> Result<(String, bool)> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("failed to read \"{}\"", dir.display()))?
+        .filter_map(Result::ok)
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.path().is_dir() {
+                (format!("{name}/"), None)
+            } else {
+                (name, entry.metadata().ok().map(|metadata| metadata.len()))
+            }
+        })
+        .collect_vec();
+    entries.sort_by(|(a, a_size), (b, b_size)| {
+        a_size
+            .is_some()
+            .cmp(&b_size.is_some())
+            .then_with(|| a.to_lowercase().cmp(&b.to_lowercase()))
+    });
+    let truncated = entries.len() > MAX_DIR_ENTRIES;
+    entries.truncate(MAX_DIR_ENTRIES);
+    let entries = entries
+        .iter()
+        .map(|(name, size)| (name.as_str(), *size))
+        .collect_vec();
+    Ok((
+        list_entries(&entries, truncated, MAX_DIR_ENTRIES),
+        truncated,
+    ))
+}
+
+/// `entries` under a line counting them, each with its size unless it is a directory
+fn list_entries(entries: &[(&str, Option<u64>)], truncated: bool, max: usize) -> String {
+    let sizes = entries
+        .iter()
+        .map(|(_, size)| size.map(ui::format_bytes).unwrap_or_default())
+        .collect_vec();
+    let width = sizes.iter().map(String::len).max().unwrap_or_default();
+    let mut preview = match entries.len() {
+        _ if truncated => format!("First {max} entries\n\n"),
+        1 => "1 entry\n\n".to_owned(),
+        count => format!("{count} entries\n\n"),
+    };
+    preview.extend(
+        entries
+            .iter()
+            .zip(sizes)
+            .map(|((name, _), size)| format!("{size:>width$}  {name}\n")),
+    );
+    if truncated {
+        preview.push_str("...");
+    }
+    preview.trim_end().to_owned()
+}
+
+/// Extensions of the files taken for a README, besides none at all, the ones first preferred
+const README_EXTENSIONS: [&str; 7] = ["md", "markdown", "rst", "adoc", "org", "txt", "textile"];
+
+/// The README of `dir`, whatever the case of its name
+fn find_readme(dir: &Path) -> Option<PathBuf> {
+    let rank = |path: &Path| {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        README_EXTENSIONS
+            .iter()
+            .position(|readme| *readme == extension)
+    };
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_stem()
+                .is_some_and(|stem| stem.eq_ignore_ascii_case("readme"))
+                && (path.extension().is_none() || rank(path).is_some())
+                && path.is_file()
+        })
+        .min_by_key(|path| (rank(path).unwrap_or(README_EXTENSIONS.len()), path.clone()))
+}
+
+fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"))
+}
+
+/// Run `command` in the shell, optionally from `dir`, returning its output
+fn run(command: &str, dir: Option<&Path>) -> Result<String> {
+    run_with_status(command, dir).map(|(_, out)| out)
+}
+
+/// Output of `command`, along with whether it exited successfully
+fn run_with_status(command: &str, dir: Option<&Path>) -> Result<(bool, String)> {
+    #[cfg(target_os = "windows")]
+    let out = {
+        let mut process = Command::new("cmd.exe");
+        // See https://github.com/rust-lang/rust/issues/92939
+        process.raw_arg(&format!("/C {command}"));
+        if let Some(dir) = dir {
+            process.current_dir(dir);
+        }
+        process
+            .output()
+            .with_context(|| format!("problem running preview command with {command}"))?
+    };
+    #[cfg(not(target_os = "windows"))]
+    let out = {
+        let expr = cmd!(env::var("SHELL").unwrap_or("sh".to_owned()), "-c", command);
+        match dir {
+            Some(dir) => expr.dir(dir),
+            None => expr,
+        }
+    }
+    .unchecked()
+    .stderr_to_stdout()
+    .stdout_capture()
+    .run()
+    .with_context(|| format!("problem running preview command with {command}"))?;
+
+    trace!("ran preview command: \"{command}\"");
+    let text = String::from_utf8_lossy(&out.stdout);
+    // Like duct's `read`, drop the trailing newline
+    #[cfg(not(target_os = "windows"))]
+    let text = text.strip_suffix('\n').map_or(text.as_ref(), |text| {
+        text.strip_suffix('\r').unwrap_or(text)
+    });
+    Ok((out.status.success(), text.to_owned()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use assert_fs::{prelude::*, TempDir};
     use collect_all::collect;
-    use crossterm::event::{KeyModifiers, MouseEvent};
+    use crossterm::event::{KeyCode, KeyModifiers, MouseEvent};
     use test_log::test;
 
     fn preview_default() -> String {
@@ -260,6 +1455,72 @@ mod tests {
         assert_eq!("should be previewed".to_owned(), previewer.contents);
     }
 
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn previews_are_reused_until_the_file_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir.child("a.txt").write_str("first").unwrap();
+        temp_dir.child("b.txt").write_str("second").unwrap();
+        let runs = temp_dir.join("runs");
+        let mut config = Config::default();
+        config.preview.preview_cmd = format!("echo >> {}; cat {{}}", command::quote(&runs));
+        let mut previewer = PreviewFile::with_config(Rc::new(config));
+        let count_runs = || fs::read_to_string(&runs).unwrap().lines().count();
+
+        for file in ["a.txt", "b.txt", "a.txt"] {
+            previewer.preview_file(temp_dir.join(file)).unwrap();
+        }
+        assert_eq!("first", previewer.contents);
+        assert_eq!(2, count_runs());
+
+        temp_dir.child("a.txt").write_str("changed").unwrap();
+        previewer.preview_file(temp_dir.join("a.txt")).unwrap();
+        assert_eq!("changed", previewer.contents);
+        assert_eq!(3, count_runs());
+        assert_eq!(2, previewer.cache.entries.len());
+
+        previewer.forget_cached(&[temp_dir.path()]);
+        previewer.preview_file(temp_dir.join("b.txt")).unwrap();
+        assert_eq!(4, count_runs());
+    }
+
+    #[test]
+    fn cache_drops_the_least_recently_used_first() {
+        let rendered = |contents: String| Rendered {
+            text: Text::raw(contents.clone()),
+            contents,
+            header: None,
+        };
+        let key = |name: &str| CacheKey {
+            path: name.into(),
+            modified: SystemTime::UNIX_EPOCH,
+            len: 0,
+            raw: false,
+        };
+        // The styled text counts too, so each of these takes up over a fourth of the cache
+        let quarter = || rendered("x".repeat(CACHE_BYTES / 7));
+        assert!(quarter().size() > CACHE_BYTES / 4);
+        let mut cache = PreviewCache::default();
+        for name in ["a", "b", "c"] {
+            cache.insert(key(name), quarter());
+        }
+        assert!(cache.get(&key("a")).is_some());
+        cache.insert(key("d"), quarter());
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("a")).is_some());
+        cache.insert(key("big"), rendered("x".repeat(CACHE_BYTES / 2 + 1)));
+        assert!(cache.get(&key("big")).is_none());
+        assert!(cache.bytes <= CACHE_BYTES);
+        assert_eq!(
+            cache
+                .entries
+                .iter()
+                .map(|(_, cached)| cached.size())
+                .sum::<usize>(),
+            cache.bytes
+        );
+    }
+
     #[test]
     fn does_not_work_with_zero_args() {
         let temp_dir = TempDir::new().expect("should be able to make temp dir");
@@ -306,6 +1567,492 @@ mod tests {
         assert_eq!("should be previewed", previewer.contents);
     }
 
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn raw_shows_the_plain_contents_until_another_file() {
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir.child("doc.pdf").write_str("%PDF\x1b[31m").unwrap();
+        temp_dir
+            .child("data.bin")
+            .write_binary(b"\x7fELF\0")
+            .unwrap();
+        let mut config = Config::default();
+        config.preview.pdf_cmd = "echo first page".to_owned();
+        let mut previewer = PreviewFile::with_config(Rc::new(config));
+
+        previewer.preview_file(temp_dir.join("doc.pdf")).unwrap();
+        assert_eq!("first page", previewer.contents);
+        previewer.toggle_raw().unwrap();
+        assert_eq!("%PDF\u{241b}[31m", previewer.contents);
+        assert!(previewer
+            .header
+            .as_ref()
+            .unwrap()
+            .to_string()
+            .ends_with(" · raw"));
+
+        previewer.preview_file(temp_dir.join("data.bin")).unwrap();
+        assert!(!previewer.raw);
+        previewer.toggle_raw().unwrap();
+        assert_eq!(
+            format!("00000000  7f 45 4c 46 00{}  .ELF.", " ".repeat(33)),
+            previewer.contents
+        );
+        previewer.toggle_raw().unwrap();
+        assert!(!previewer.raw);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn pdfs_use_pdf_command() {
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir.child("doc.PDF").write_str("%PDF-1.4").unwrap();
+        let mut config = Config::default();
+        config.preview.pdf_cmd = "echo first page of {}".to_owned();
+        config.preview.max_size = 10;
+        let mut previewer = PreviewFile::with_config(Rc::new(config));
+
+        previewer.preview_file(temp_dir.join("doc.PDF")).unwrap();
+        assert_eq!("first page", previewer.contents);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn pdfs_fall_back_to_size() {
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir.child("doc.pdf").write_str("%PDF-1.4").unwrap();
+        let mut config = Config::default();
+        config.preview.pdf_cmd = "false {}".to_owned();
+        let mut previewer = PreviewFile::with_config(Rc::new(config));
+
+        previewer.preview_file(temp_dir.join("doc.pdf")).unwrap();
+        assert!(previewer.contents.starts_with("PDF, 8 bytes"));
+    }
+
+    #[test]
+    fn archives_list_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut tar = vec![0; 512];
+        tar[..5].copy_from_slice(b"a.txt");
+        tar[124..135].copy_from_slice(b"00000002000");
+        tar[156] = b'0';
+        tar.extend([0; 1024 + 1024]);
+        temp_dir.child("test.tar").write_binary(&tar).unwrap();
+        let mut previewer = PreviewFile::new();
+
+        previewer.preview_file(temp_dir.join("test.tar")).unwrap();
+        assert_eq!("1 entry\n\n1.0 KiB  a.txt", previewer.contents);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn directories_show_their_readme_or_listing() {
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir
+            .child("docs/ReadMe.MD")
+            .write_str("# Docs")
+            .unwrap();
+        temp_dir
+            .child("docs/readme.txt")
+            .write_str("plain")
+            .unwrap();
+        temp_dir
+            .child("src/main.rs")
+            .write_str("fn main() {}")
+            .unwrap();
+        temp_dir.child("src/bin").create_dir_all().unwrap();
+        let mut previewer = PreviewFile::new();
+
+        previewer.preview_file(temp_dir.join("docs")).unwrap();
+        assert_eq!("# Docs", previewer.contents);
+        previewer.preview_file(temp_dir.join("src")).unwrap();
+        assert_eq!("2 entries\n\n      bin/\n12 B  main.rs", previewer.contents);
+
+        let mut config = Config::default();
+        config.preview.dir_preview = DirPreview::Listing;
+        let mut previewer = PreviewFile::with_config(Rc::new(config));
+        previewer.preview_file(temp_dir.join("docs")).unwrap();
+        assert!(previewer.contents.starts_with("2 entries"));
+    }
+
+    #[test]
+    fn header_describes_the_file() {
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir.child("main.rs").write_str("fn\nmain").unwrap();
+        let mut previewer = PreviewFile::new();
+        previewer.preview_file(temp_dir.join("main.rs")).unwrap();
+        assert_eq!(
+            "main.rs · 7 B · 2 lines · Rust · UTF-8",
+            previewer.header.unwrap().to_string()
+        );
+
+        let mut config = Config::default();
+        config.preview.header = false;
+        let mut previewer = PreviewFile::with_config(Rc::new(config));
+        previewer.preview_file(temp_dir.join("main.rs")).unwrap();
+        assert_eq!(None, previewer.header);
+    }
+
+    #[test]
+    fn counts_are_partial_for_truncated_text() {
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir
+            .child("notes.txt")
+            .write_str("one two\nthree")
+            .unwrap();
+        let mut config = Config::default();
+        config.preview.counts = true;
+        config.preview.max_size = 9;
+        let mut previewer = PreviewFile::with_config(Rc::new(config));
+        previewer.preview_file(temp_dir.join("notes.txt")).unwrap();
+        assert_eq!(
+            "notes.txt · 13 B · 2 lines, 3 words, 13 bytes · UTF-8",
+            previewer.header.as_ref().unwrap().to_string()
+        );
+
+        // Raw previews stop at `max_size`
+        previewer.toggle_raw().unwrap();
+        assert_eq!(
+            "notes.txt · 13 B · 2 lines, 3 words, 9 bytes (partial) · UTF-8 · raw · truncated",
+            previewer.header.as_ref().unwrap().to_string()
+        );
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn links_are_followed_to_their_target() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir
+            .child("dir/main.rs")
+            .write_str("fn main() {}")
+            .unwrap();
+        symlink("dir/main.rs", temp_dir.join("link.rs")).unwrap();
+        symlink("link.rs", temp_dir.join("chained")).unwrap();
+        symlink("missing.txt", temp_dir.join("broken")).unwrap();
+        symlink("loop_b", temp_dir.join("loop_a")).unwrap();
+        symlink("loop_a", temp_dir.join("loop_b")).unwrap();
+        let mut previewer = PreviewFile::new();
+
+        previewer.preview_file(temp_dir.join("chained")).unwrap();
+        assert_eq!("fn main() {}", previewer.contents);
+        assert_eq!(
+            format!(
+                "chained → {} · 12 B · 1 line · Rust · UTF-8",
+                temp_dir.join("dir/main.rs").display()
+            ),
+            previewer.header.as_ref().unwrap().to_string()
+        );
+
+        previewer.preview_file(temp_dir.join("broken")).unwrap();
+        assert_eq!(
+            format!(
+                "Broken link, \"{}\" does not exist",
+                temp_dir.join("missing.txt").display()
+            ),
+            previewer.contents
+        );
+
+        previewer.preview_file(temp_dir.join("loop_a")).unwrap();
+        assert_eq!("The link leads back to itself", previewer.contents);
+        assert_eq!(
+            "loop_a → cycle · 1 line",
+            previewer.header.as_ref().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn scratch_output_replaces_file_until_next_preview() {
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir.child("main.rs").write_str("fn main() {}").unwrap();
+        let mut previewer = PreviewFile::new();
+        previewer.preview_file(temp_dir.join("main.rs")).unwrap();
+        previewer.toggle_pin();
+
+        previewer.show_output("cargo check", "error[E0425]\n", Some(101));
+        assert!(!previewer.is_pinned());
+        assert_eq!("error[E0425]\n", previewer.contents);
+        assert_eq!(
+            "$ cargo check · exit status 101",
+            previewer.output.as_ref().unwrap().to_string()
+        );
+        assert_eq!(None, previewer.current_line());
+
+        previewer.show_output("true", "", Some(0));
+        assert_eq!("No output", previewer.contents);
+        assert_eq!("$ true", previewer.output.as_ref().unwrap().to_string());
+
+        previewer.preview_file(temp_dir.join("main.rs")).unwrap();
+        assert_eq!(None, previewer.output);
+    }
+
+    #[test]
+    fn pinned_preview_ignores_other_files() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        temp_dir.child("pinned.txt").write_str("pinned").unwrap();
+        temp_dir.child("other.txt").write_str("other").unwrap();
+
+        let mut previewer = PreviewFile::default();
+        previewer.preview_file(temp_dir.join("pinned.txt")).unwrap();
+        previewer.toggle_pin();
+        assert!(previewer.is_pinned());
+        previewer.preview_file(temp_dir.join("other.txt")).unwrap();
+        assert_eq!("pinned", previewer.contents);
+
+        previewer.toggle_pin();
+        previewer.preview_file(temp_dir.join("other.txt")).unwrap();
+        assert_eq!("other", previewer.contents);
+    }
+
+    /// Run git in `dir` with a throwaway identity
+    #[cfg(not(target_os = "windows"))]
+    fn git(dir: &Path, args: &[&str]) {
+        let mut full_args = vec!["-c", "user.name=test", "-c", "user.email=test@test"];
+        full_args.extend(args);
+        cmd("git", full_args).dir(dir).stdout_null().run().unwrap();
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn diff_explains_files_without_changes() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        temp_dir.child("committed.txt").write_str("old\n").unwrap();
+        git(temp_dir.path(), &["init", "-q"]);
+        git(temp_dir.path(), &["add", "committed.txt"]);
+        git(temp_dir.path(), &["commit", "-q", "-m", "init"]);
+        temp_dir.child("untracked.txt").write_str("new").unwrap();
+
+        let mut previewer = PreviewFile::default();
+        previewer.toggle_mode().unwrap();
+        previewer
+            .preview_file(temp_dir.join("committed.txt"))
+            .unwrap();
+        assert_eq!("No changes since the last commit", previewer.contents);
+        previewer
+            .preview_file(temp_dir.join("untracked.txt"))
+            .unwrap();
+        assert_eq!(
+            "Untracked file, there is nothing to compare it to",
+            previewer.contents
+        );
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn staged_diff_shows_cached_changes() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        temp_dir.child("file.txt").write_str("old\n").unwrap();
+        git(temp_dir.path(), &["init", "-q"]);
+        git(temp_dir.path(), &["add", "file.txt"]);
+        git(temp_dir.path(), &["commit", "-q", "-m", "init"]);
+        temp_dir.child("file.txt").write_str("staged\n").unwrap();
+        git(temp_dir.path(), &["add", "file.txt"]);
+
+        let mut previewer = PreviewFile::default();
+        previewer.toggle_mode().unwrap();
+        previewer.preview_file(temp_dir.join("file.txt")).unwrap();
+        assert_eq!("No unstaged changes, only staged ones", previewer.contents);
+        previewer.toggle_staged_diff().unwrap();
+        previewer.preview_file(temp_dir.join("file.txt")).unwrap();
+        assert!(previewer.contents.contains("staged"));
+        assert!(previewer.contents.contains("old"));
+
+        previewer.toggle_staged_diff().unwrap();
+        previewer.preview_file(temp_dir.join("file.txt")).unwrap();
+        assert_eq!("staged", previewer.contents);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn blame_shows_commits_or_a_note() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        temp_dir.child("file.txt").write_str("one\ntwo\n").unwrap();
+        let mut previewer = PreviewFile::default();
+        previewer.toggle_blame().unwrap();
+        previewer.preview_file(temp_dir.join("file.txt")).unwrap();
+        assert_eq!("Not in a git repository", previewer.contents);
+
+        git(temp_dir.path(), &["init", "-q"]);
+        previewer.preview_file(temp_dir.join("file.txt")).unwrap();
+        assert_eq!(
+            "Untracked file, it has no history to blame",
+            previewer.contents
+        );
+
+        git(temp_dir.path(), &["add", "file.txt"]);
+        git(temp_dir.path(), &["commit", "-q", "-m", "init"]);
+        previewer.preview_file(temp_dir.join("file.txt")).unwrap();
+        let lines: Vec<_> = previewer.contents.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].contains("test") && lines[0].ends_with("│ one"));
+        assert!(lines[1].ends_with("│ two"));
+
+        let mut config = Config::default();
+        config.preview.max_size = 5;
+        let mut previewer = PreviewFile::with_config(Rc::new(config));
+        previewer.toggle_blame().unwrap();
+        previewer.preview_file(temp_dir.join("file.txt")).unwrap();
+        assert_eq!(1, previewer.contents.lines().count());
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn shows_files_at_another_ref() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        temp_dir.child("file.txt").write_str("old\n").unwrap();
+        git(temp_dir.path(), &["init", "-q", "-b", "main"]);
+        git(temp_dir.path(), &["add", "file.txt"]);
+        git(temp_dir.path(), &["commit", "-q", "-m", "init"]);
+        temp_dir.child("file.txt").write_str("new\n").unwrap();
+        temp_dir.child("added.txt").write_str("added\n").unwrap();
+
+        let mut previewer = PreviewFile::default();
+        let file = temp_dir.join("file.txt");
+        previewer
+            .show_at_ref(&file, Some("main".to_owned()))
+            .unwrap();
+        assert_eq!("old", previewer.contents);
+        assert!(previewer
+            .header
+            .as_ref()
+            .unwrap()
+            .to_string()
+            .starts_with("file.txt @ main"));
+        previewer.toggle_mode().unwrap();
+        previewer.preview_file(&file).unwrap();
+        assert!(previewer.contents.contains("-old") && previewer.contents.contains("new"));
+        previewer.toggle_mode().unwrap();
+
+        previewer
+            .show_at_ref(temp_dir.join("added.txt"), Some("main".to_owned()))
+            .unwrap();
+        assert_eq!(
+            "\"added.txt\" does not exist on \"main\"",
+            previewer.contents
+        );
+        previewer
+            .show_at_ref(&file, Some("nope".to_owned()))
+            .unwrap();
+        assert_eq!("Unknown ref \"nope\"", previewer.contents);
+
+        // Another file, or no ref, shows the working copy again
+        previewer.preview_file(temp_dir.join("added.txt")).unwrap();
+        assert_eq!("added", previewer.contents);
+        previewer.show_at_ref(&file, None).unwrap();
+        assert_eq!("new", previewer.contents);
+    }
+
+    #[test]
+    fn visible_text_follows_scroll() {
+        let mut previewer = PreviewFile {
+            contents: "\x1b[31mone\x1b[0m\ntwo\nthree\nfour".to_owned(),
+            ..Default::default()
+        };
+        previewer.height.set(2);
+        assert_eq!("one\ntwo", previewer.visible_text().unwrap());
+        previewer.state.get_mut().down();
+        assert_eq!("two\nthree", previewer.visible_text().unwrap());
+    }
+
+    #[test]
+    fn search_jumps_between_matches() {
+        let mut previewer = PreviewFile {
+            contents: "Foo\nbar\nfoo foo\nbaz\nfoo".to_owned(),
+            ..Default::default()
+        };
+        previewer.height.set(2);
+        assert_eq!(4, previewer.search("foo").unwrap());
+        assert_eq!(0, previewer.state.get_mut().offset_top);
+        let (n, shift_n) = (
+            KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Char('N'), KeyModifiers::SHIFT),
+        );
+        assert!(previewer.handle_search_key(&n));
+        assert!(previewer.handle_search_key(&n));
+        assert!(previewer.handle_search_key(&n));
+        assert_eq!(3, previewer.state.get_mut().offset_top);
+        // Wraps around both ways
+        assert!(previewer.handle_search_key(&n));
+        assert_eq!(0, previewer.state.get_mut().offset_top);
+        assert!(previewer.handle_search_key(&shift_n));
+        assert_eq!(3, previewer.state.get_mut().offset_top);
+        assert!(
+            !previewer.handle_search_key(&KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE))
+        );
+        assert!(previewer.handle_search_key(&KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)));
+        assert!(!previewer.is_searching());
+        assert!(!previewer.handle_search_key(&n));
+    }
+
+    #[test]
+    fn jumps_stay_within_the_preview() {
+        let mut previewer = PreviewFile {
+            contents: (1..=200).join("\n"),
+            ..Default::default()
+        };
+        for (text, top) in [
+            ("120", 119),
+            ("0", 0),
+            ("999", 199),
+            ("50%", 100),
+            (" 25 % ", 50),
+            ("150%", 199),
+        ] {
+            previewer.jump(Jump::parse(text).unwrap());
+            assert_eq!(top, previewer.state.get_mut().offset_top, "{text}");
+        }
+        for text in ["", "-1", "half", "%"] {
+            assert_eq!(None, Jump::parse(text), "{text}");
+        }
+    }
+
+    #[test]
+    fn current_line_follows_match_or_scroll() {
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir
+            .child("test.txt")
+            .write_str("one\ntwo\nthree\nfour")
+            .unwrap();
+        let path = temp_dir.join("test.txt");
+        let mut previewer = PreviewFile::default();
+        previewer.height.set(2);
+        assert_eq!(None, previewer.current_line());
+
+        previewer.preview_file(&path).unwrap();
+        assert_eq!(Some((path.clone(), 1)), previewer.current_line());
+        previewer.state.get_mut().down();
+        assert_eq!(Some((path.clone(), 2)), previewer.current_line());
+        previewer.search("four").unwrap();
+        assert_eq!(Some((path, 4)), previewer.current_line());
+
+        previewer.preview_file(temp_dir.path()).ok();
+        assert_eq!(None, previewer.current_line());
+    }
+
+    #[test]
+    fn highlight_splits_spans() {
+        let line = Spans::from(vec![Span::raw("ab"), Span::raw("cd")]);
+        let style = TuiStyle::default().fg(tui::style::Color::Red);
+        let highlighted = highlight(line, &[(1..3, style)]);
+        assert_eq!(
+            Spans::from(vec![
+                Span::raw("a"),
+                Span::styled("b", style),
+                Span::styled("c", style),
+                Span::raw("d"),
+            ]),
+            highlighted
+        );
+    }
+
+    #[test]
+    fn cannot_pin_without_file() {
+        let mut previewer = PreviewFile::default();
+        previewer.toggle_pin();
+        assert!(!previewer.is_pinned());
+    }
+
     #[test]
     fn mouse_inputs_are_stored_in_queue() {
         let mut previewer = PreviewFile::default();
@@ -0,0 +1,338 @@
+use crate::{app::component::Drawable, config::Config};
+use anyhow::Result;
+use git2::{Branch, ErrorCode, Repository};
+use std::{
+    cell::{Cell, RefCell},
+    fmt::{self, Display},
+    rc::Rc,
+};
+use tui::{
+    backend::Backend,
+    layout::{Alignment, Rect},
+    text::{Span, Spans},
+    widgets::Paragraph,
+    Frame,
+};
+
+/// The component that currently receives key input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Focus {
+    #[default]
+    Filetree,
+    Help,
+    RunningCommand,
+    DryRun,
+    Error,
+    Pending,
+    Input,
+    FileCmd,
+    Marks,
+    Commands,
+    Fuzzy,
+    QuickFilter,
+    Log,
+    PreviewSearch,
+    /// The preview pane, scrolled with the keys that move through the tree
+    Preview,
+}
+
+/// Where the root repo's HEAD is, and how far it is from its upstream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitBranch {
+    /// Name of the branch, or the short commit when HEAD is detached
+    pub head: String,
+    /// Commits ahead of and behind the upstream, if there is one
+    pub ahead_behind: Option<(usize, usize)>,
+}
+
+impl GitBranch {
+    pub fn read(repo: &Repository) -> Option<Self> {
+        let head = match repo.head() {
+            Ok(head) => head,
+            // Nothing committed yet, but HEAD still names the branch
+            Err(err) if err.code() == ErrorCode::UnbornBranch => {
+                let head = repo.find_reference("HEAD").ok()?;
+                let name = head.symbolic_target()?;
+                return Some(Self {
+                    head: name.strip_prefix("refs/heads/").unwrap_or(name).to_owned(),
+                    ahead_behind: None,
+                });
+            }
+            Err(_) => return None,
+        };
+        if !head.is_branch() {
+            let commit = head.peel_to_commit().ok()?;
+            let short = commit.as_object().short_id().ok()?;
+            return Some(Self {
+                head: short.as_str()?.to_owned(),
+                ahead_behind: None,
+            });
+        }
+        let name = head.shorthand()?.to_owned();
+        let local = head.target();
+        let upstream = Branch::wrap(head)
+            .upstream()
+            .ok()
+            .and_then(|upstream| upstream.get().target());
+        Some(Self {
+            head: name,
+            ahead_behind: local
+                .zip(upstream)
+                .and_then(|(local, upstream)| repo.graph_ahead_behind(local, upstream).ok()),
+        })
+    }
+}
+
+impl Display for GitBranch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.head)?;
+        match self.ahead_behind {
+            Some((0, 0)) | None => Ok(()),
+            Some((ahead, 0)) => write!(f, " ↑{ahead}"),
+            Some((0, behind)) => write!(f, " ↓{behind}"),
+            Some((ahead, behind)) => write!(f, " ↑{ahead} ↓{behind}"),
+        }
+    }
+}
+
+/// Single line at the bottom of the screen showing the most relevant keybinds
+pub struct StatusBar {
+    pub focus: Cell<Focus>,
+    /// Shows a banner so dry run is not forgotten
+    pub dry_run: Cell<bool>,
+    /// Shown on the right, outside a repo there is none
+    pub branch: RefCell<Option<GitBranch>>,
+    /// Spinner and names of the tasks running in the background, if there are any
+    pub activity: RefCell<Option<String>>,
+    /// Keys finishing the sequence being typed and their actions, shown instead of the hints
+    pub sequence: RefCell<Vec<(String, String)>>,
+    config: Rc<Config>,
+}
+
+impl Default for StatusBar {
+    fn default() -> Self {
+        Self::new(Rc::new(Config::default()))
+    }
+}
+
+impl StatusBar {
+    pub fn new(config: Rc<Config>) -> Self {
+        Self {
+            focus: Cell::default(),
+            dry_run: Cell::new(false),
+            branch: RefCell::default(),
+            activity: RefCell::default(),
+            sequence: RefCell::default(),
+            config,
+        }
+    }
+
+    /// Keybinds and short descriptions for the focused component
+    pub fn hints(&self) -> Vec<(String, &'static str)> {
+        let config = &self.config;
+        match self.focus.get() {
+            Focus::Filetree => vec![
+                (config.open.to_string(), "open"),
+                (config.filetree.search.to_string(), "search"),
+                (config.filetree.quick_filter.to_string(), "filter"),
+                (config.filetree.new_file.to_string(), "new file"),
+                (config.filetree.delete.to_string(), "delete"),
+                (config.filetree.exec_cmd.to_string(), "command"),
+                (config.filetree.mark_selected.to_string(), "mark"),
+                (config.help.to_string(), "help"),
+                (config.quit.to_string(), "quit"),
+            ],
+            Focus::Help => vec![
+                (config.filetree.search.to_string(), "filter"),
+                ("tab".to_owned(), "next section"),
+                (config.open.to_string(), "toggle section"),
+                ("esc".to_owned(), "close"),
+            ],
+            Focus::RunningCommand => vec![
+                (config.kill_processes.to_string(), "kill"),
+                (config.running_commands.to_string(), "list"),
+                ("esc".to_owned(), "hide"),
+            ],
+            Focus::Commands => vec![
+                (format!("{}/{}", config.down, config.up), "move"),
+                (config.kill_command.to_string(), "kill"),
+                ("esc".to_owned(), "close"),
+            ],
+            Focus::PreviewSearch => vec![
+                (
+                    format!(
+                        "{}/{}",
+                        config.preview.next_match, config.preview.prev_match
+                    ),
+                    "next/previous match",
+                ),
+                (config.preview.search.to_string(), "new search"),
+                (config.preview.open_at_line.to_string(), "open at match"),
+                ("esc".to_owned(), "end search"),
+            ],
+            Focus::Preview => vec![
+                (format!("{}/{}", config.down, config.up), "scroll"),
+                (
+                    format!("{}/{}", config.all_up, config.all_down),
+                    "top/bottom",
+                ),
+                (config.preview.search.to_string(), "search"),
+                (config.preview.jump.to_string(), "go to line"),
+                (config.cycle_focus.to_string(), "next pane"),
+            ],
+            Focus::Log => vec![
+                (format!("{}/{}", config.up, config.down), "page"),
+                (config.all_down.to_string(), "latest"),
+                ("esc".to_owned(), "back"),
+            ],
+            Focus::DryRun => vec![
+                (format!("{}/{}", config.down, config.up), "scroll"),
+                ("esc".to_owned(), "close"),
+            ],
+            Focus::Error => vec![
+                (format!("{}/{}", config.down, config.up), "scroll"),
+                (config.copy_error.to_string(), "copy"),
+                ("esc".to_owned(), "next"),
+            ],
+            Focus::Pending => vec![
+                (format!("{}/{}", config.down, config.up), "move"),
+                (config.open.to_string(), "choose"),
+                (config.popup.cancel.to_string(), "cancel"),
+            ],
+            Focus::Input => vec![
+                (config.popup.confirm.to_string(), "submit"),
+                (config.popup.cancel.to_string(), "cancel"),
+                ("ctrl-v/ctrl-x".to_owned(), "tmux split"),
+            ],
+            Focus::FileCmd => vec![
+                (format!("{}/{}", config.down, config.up), "move"),
+                (config.open.to_string(), "run"),
+                (format!("{}/{}", config.page_down, config.page_up), "page"),
+                ("esc".to_owned(), "close"),
+            ],
+            Focus::Marks => vec![
+                (config.open.to_string(), "go to"),
+                (config.marks.delete.to_string(), "delete"),
+                (config.marks.open_all.to_string(), "open all"),
+                (config.marks.show_all.to_string(), "all projects"),
+                ("esc".to_owned(), "close"),
+            ],
+            Focus::QuickFilter => vec![
+                ("up/down".to_owned(), "move between matches"),
+                ("enter".to_owned(), "keep selection"),
+                ("esc".to_owned(), "show all"),
+            ],
+            Focus::Fuzzy => vec![
+                (format!("{}/{}", config.popup.down, config.popup.up), "move"),
+                (config.fuzzy_preview.to_string(), "preview"),
+                (config.popup.confirm.to_string(), "select"),
+                (config.popup.cancel.to_string(), "close"),
+            ],
+        }
+    }
+}
+
+impl Drawable for StatusBar {
+    fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Result<()> {
+        let mut spans = Vec::new();
+        if self.dry_run.get() {
+            spans.push(Span::styled(" DRY RUN ", self.config.dry_run_style.into()));
+        }
+        if let Some(activity) = self.activity.borrow().as_ref() {
+            if !spans.is_empty() {
+                spans.push(Span::raw(" "));
+            }
+            spans.push(Span::raw(format!("{activity} ")));
+        }
+        let sequence = self.sequence.borrow();
+        let hints = if !sequence.is_empty() {
+            sequence.clone()
+        } else if self.config.show_hints {
+            self.hints()
+                .into_iter()
+                .map(|(key, description)| (key, description.to_owned()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        for (key, description) in hints {
+            if !spans.is_empty() {
+                spans.push(Span::raw("  "));
+            }
+            spans.push(Span::styled(key, self.config.help_key_style.into()));
+            spans.push(Span::raw(format!(" {description}")));
+        }
+        f.render_widget(Paragraph::new(Spans::from(spans)), area);
+        if let Some(branch) = self.branch.borrow().as_ref() {
+            // Drawn over the end of the hints, which matter less than where the repo is
+            let text = format!(" {branch} ");
+            let width = (text.chars().count() as u16).min(area.width);
+            let area = Rect {
+                x: area.right() - width,
+                width,
+                ..area
+            };
+            f.render_widget(Paragraph::new(text).alignment(Alignment::Right), area);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Key, KeyBind};
+    use assert_fs::{prelude::*, TempDir};
+    use duct::cmd;
+    use test_log::test;
+
+    #[test]
+    fn hints_follow_focus() {
+        let status_bar = StatusBar::default();
+        assert!(status_bar.hints().contains(&("/".to_owned(), "search")));
+        status_bar.focus.set(Focus::Marks);
+        assert!(status_bar.hints().contains(&("d".to_owned(), "delete")));
+    }
+
+    #[test]
+    fn hints_use_configured_keys() {
+        let mut config = Config::default();
+        config.filetree.search = KeyBind::key(Key::normal('s'));
+        let status_bar = StatusBar::new(Rc::new(config));
+        assert!(status_bar.hints().contains(&("s".to_owned(), "search")));
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn branch_shows_upstream_distance_or_commit() {
+        let temp = TempDir::new().unwrap();
+        let git = |args: &[&str]| {
+            let mut full_args = vec!["-c", "user.name=test", "-c", "user.email=test@test"];
+            full_args.extend(args);
+            cmd("git", full_args)
+                .dir(temp.path())
+                .stdout_null()
+                .stderr_null()
+                .run()
+                .unwrap();
+        };
+        git(&["init", "-q", "-b", "main"]);
+        let repo = Repository::open(temp.path()).unwrap();
+        assert_eq!("main", GitBranch::read(&repo).unwrap().to_string());
+
+        temp.child("file.txt").write_str("one").unwrap();
+        git(&["add", "file.txt"]);
+        git(&["commit", "-q", "-m", "one"]);
+        git(&["branch", "up"]);
+        git(&["branch", "-q", "--set-upstream-to", "up"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "two"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "three"]);
+        assert_eq!("main ↑2", GitBranch::read(&repo).unwrap().to_string());
+
+        git(&["checkout", "-q", "--detach", "up"]);
+        let branch = GitBranch::read(&repo).unwrap();
+        assert_eq!(None, branch.ahead_behind);
+        assert!(branch.head.len() >= 7 && !branch.head.contains("up"));
+    }
+}
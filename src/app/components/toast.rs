@@ -0,0 +1,208 @@
+use crate::{
+    app::component::{Component, Drawable},
+    config::Config,
+    external_event::ExternalEvent,
+};
+use anyhow::Result;
+use std::{
+    collections::VecDeque,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+/// Most toasts that can be shown at once, older ones are dropped first
+const MAX_TOASTS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToastLevel {
+    Info,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Toast {
+    message: String,
+    level: ToastLevel,
+    expires: Instant,
+}
+
+/// Transient notifications drawn in the bottom right corner
+pub struct Toasts {
+    toasts: VecDeque<Toast>,
+    config: Rc<Config>,
+}
+
+impl Default for Toasts {
+    fn default() -> Self {
+        Self::new(Rc::new(Config::default()))
+    }
+}
+
+impl Toasts {
+    pub fn new(config: Rc<Config>) -> Self {
+        Self {
+            toasts: VecDeque::with_capacity(MAX_TOASTS),
+            config,
+        }
+    }
+
+    pub fn info(&mut self, message: impl Into<String>) {
+        self.push(message.into(), ToastLevel::Info);
+    }
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.push(message.into(), ToastLevel::Error);
+    }
+
+    fn push(&mut self, message: String, level: ToastLevel) {
+        let timeout = match level {
+            ToastLevel::Info => self.config.toast.timeout,
+            ToastLevel::Error => self.config.toast.error_timeout,
+        };
+        // A timeout of zero disables toasts for that level
+        if timeout == 0 {
+            return;
+        }
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back(Toast {
+            message,
+            level,
+            expires: Instant::now() + Duration::from_millis(timeout),
+        });
+    }
+
+    /// Remove every toast that has expired by `now`
+    pub fn expire(&mut self, now: Instant) {
+        self.toasts.retain(|toast| toast.expires > now);
+    }
+
+    /// Time until the next toast should disappear
+    pub fn next_timeout(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.toasts
+            .iter()
+            .map(|toast| toast.expires.saturating_duration_since(now))
+            .min()
+    }
+}
+
+impl Component for Toasts {
+    fn visible(&self) -> bool {
+        !self.toasts.is_empty()
+    }
+
+    fn handle_event(&mut self, ev: &ExternalEvent) -> Result<()> {
+        if matches!(ev, ExternalEvent::Tick) {
+            self.expire(Instant::now());
+        }
+        Ok(())
+    }
+}
+
+impl Drawable for Toasts {
+    fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Result<()> {
+        if !self.visible() {
+            return Ok(());
+        }
+
+        let max_width = (area.width / 3).max(20).min(area.width);
+        let mut bottom = area.bottom();
+        // Newest toasts go on the bottom, closest to the corner
+        for toast in self.toasts.iter().rev() {
+            if bottom < area.top() + 3 {
+                break;
+            }
+            let width = u16::try_from(toast.message.chars().count())
+                .unwrap_or(u16::MAX)
+                .saturating_add(2)
+                .min(max_width);
+            let toast_area = Rect::new(area.right() - width, bottom - 3, width, 3);
+            bottom -= 3;
+
+            let style = match toast.level {
+                ToastLevel::Info => self.config.toast.info_style,
+                ToastLevel::Error => self.config.toast.error_style,
+            };
+            let paragraph = Paragraph::new(toast.message.as_str()).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(style.into()),
+            );
+            f.render_widget(Clear, toast_area);
+            f.render_widget(paragraph, toast_area);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn visible_with_toasts() {
+        let mut toasts = Toasts::default();
+        assert!(!toasts.visible());
+        toasts.info("created file");
+        assert!(toasts.visible());
+    }
+
+    #[test]
+    fn toasts_expire_after_timeout() {
+        let mut toasts = Toasts::default();
+        toasts.info("created file");
+        toasts.expire(Instant::now());
+        assert!(toasts.visible());
+        toasts.expire(Instant::now() + Duration::from_millis(toasts.config.toast.timeout));
+        assert!(!toasts.visible());
+    }
+
+    #[test]
+    fn errors_outlast_info_toasts() {
+        let mut toasts = Toasts::default();
+        toasts.info("created file");
+        toasts.error("failed to create file");
+        toasts.expire(Instant::now() + Duration::from_millis(toasts.config.toast.timeout));
+        assert_eq!(1, toasts.toasts.len());
+        assert_eq!(ToastLevel::Error, toasts.toasts[0].level);
+    }
+
+    #[test]
+    fn zero_timeout_disables_toasts() {
+        let mut config = Config::default();
+        config.toast.timeout = 0;
+        let mut toasts = Toasts::new(Rc::new(config));
+        toasts.info("created file");
+        assert!(!toasts.visible());
+        toasts.error("failed to create file");
+        assert!(toasts.visible());
+    }
+
+    #[test]
+    fn drops_oldest_toast_when_full() {
+        let mut toasts = Toasts::default();
+        for i in 0..=MAX_TOASTS {
+            toasts.info(i.to_string());
+        }
+        assert_eq!(MAX_TOASTS, toasts.toasts.len());
+        assert_eq!("1", toasts.toasts[0].message);
+    }
+
+    #[test]
+    fn next_timeout_is_none_without_toasts() {
+        let mut toasts = Toasts::default();
+        assert_eq!(None, toasts.next_timeout());
+        toasts.info("marked");
+        assert!(toasts.next_timeout().is_some());
+    }
+}
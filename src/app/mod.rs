@@ -4,15 +4,25 @@ mod components;
 use self::component::{Component, Drawable};
 pub use self::components::*;
 use crate::{
-    config::{Config, Key},
-    external_event::{ExternalEvent, RefreshData},
+    activity::{Activity, Task},
+    cargo, clipboard,
+    command::{self, CommandDir, Placeholders, RunOptions},
+    config::{self, Config, Key},
+    debounce::Debounce,
+    editor, encoding,
+    external_event::{Batch, ContentSearch, ExternalEvent, FileSearch, RefreshData},
+    last_commands::LastCommands,
     marks::Marks,
-    queue::{AppEvent, Queue, TmuxOpts},
+    queue::{AppEvent, Queue, TmuxCommand, TmuxOpts},
+    sequence::{Press, Sequence},
+    session_log,
 };
-use anyhow::{Context, Result};
-use crossterm::event::Event;
+use anyhow::{anyhow, bail, Context, Result};
+use crossterm::event::{Event, KeyEvent};
 use duct::{cmd, Expression};
 use easy_switch::switch;
+use globset::{Glob, GlobMatcher};
+use itertools::Itertools;
 use log::{error, info, warn};
 use std::env;
 #[cfg(target_os = "windows")]
@@ -21,25 +31,71 @@ use std::os::windows::process::CommandExt;
 use std::process::Command;
 use std::{
     cell::RefCell,
+    ffi::OsStr,
     fs::{self, File},
     path::{Path, PathBuf},
     rc::Rc,
+    slice,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
-    widgets::{Block, Borders},
+    widgets::{Paragraph, Wrap},
     Frame,
 };
-use tui_logger::{TuiLoggerLevelOutput as LoggerLevel, TuiLoggerWidget as Logger};
+
+/// Moving more items than this shows their progress
+const MOVE_PROGRESS_THRESHOLD: usize = 20;
+
+/// Scratch commands kept to go back to
+const SCRATCH_HISTORY: usize = 20;
 
 /// Event that is sent back up to main.rs
 #[derive(Debug)]
 pub enum TerminalEvent {
-    OpenFile(PathBuf),
-    RunCommandThreaded(Expression),
+    /// Open a file, optionally at a line and then a column
+    OpenFile(PathBuf, Option<usize>, Option<usize>),
+    /// Open every file in one run of the editor
+    OpenFiles(Vec<PathBuf>),
+    RunCommandThreaded {
+        id: usize,
+        name: String,
+        expr: Expression,
+        timeout: Option<Duration>,
+    },
     RunCommand(Expression),
+    /// Run a command whose output goes to the preview
+    RunScratch {
+        command: String,
+        expr: Expression,
+        timeout: Option<Duration>,
+    },
+    StopCommand(usize),
     StopAllCommands,
+    SearchContents(ContentSearch),
+    /// Move every path into `dest`
+    MoveFiles {
+        paths: Vec<PathBuf>,
+        dest: PathBuf,
+    },
+    FindFiles(FileSearch),
+    RunBatch(Batch),
+    /// The project root changed, so everything watching the old one should follow
+    Reroot(PathBuf),
+}
+
+/// The panes that keys can go to when no popup is open, cycled with `cycle_focus`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Pane {
+    #[default]
+    Tree,
+    Preview,
+    Log,
 }
 
 pub struct App {
@@ -53,7 +109,41 @@ pub struct App {
     text_popup: Popup,
     file_cmd_popup: FileCmdPopup,
     marks_popup: MarksPopup,
+    commands_popup: CommandsPopup,
+    /// Commands for the Cargo targets, until a manifest or source file changes
+    cargo_targets: Option<Vec<String>>,
+    /// Scratch commands run before, newest first
+    scratch_history: Vec<String>,
+    error_popup: ErrorPopup,
+    log_pane: LogPane,
+    /// Id given to the next background command
+    next_command_id: usize,
+    /// Destructive actions skipped so far, while dry run is on
+    dry_run: Option<Vec<String>>,
     fuzzy_matcher: FuzzyMatcher,
+    toasts: Toasts,
+    status_bar: StatusBar,
+    /// Files to preview once the selection stops moving
+    preview_debounce: Debounce<PathBuf>,
+    /// When the tree should next be reread from disk, if periodic refreshes are enabled
+    next_full_refresh: Option<Instant>,
+    /// Keys typed so far of a sequence from the config
+    sequence: Sequence,
+    /// Commands from `open_with`, the longest pattern first
+    open_with: Vec<(GlobMatcher, String)>,
+    /// When the session should be saved, pushed back by every input
+    next_autosave: Option<Instant>,
+    /// Background work shown with a spinner in the status bar
+    activity: Activity,
+    /// Pane getting the keys, once popups are closed
+    pane: Pane,
+    /// The id and cancel flag of the content search still running
+    content_search: Option<(usize, Arc<AtomicBool>)>,
+    last_search_id: usize,
+    /// Files found so far by the last content search
+    content_results: Vec<PathBuf>,
+    /// The cancel flag of the batch command still running
+    batch: Option<Arc<AtomicBool>>,
     config: Rc<Config>,
 }
 
@@ -77,13 +167,35 @@ impl App {
             tree,
             should_quit: false,
             pending: PendingPopup::new(queue.clone(), Rc::clone(&config)),
-            input_box: InputBox::new(queue.clone()),
+            input_box: InputBox::new_with_config(queue.clone(), Rc::clone(&config)),
             previewer: PreviewFile::with_config(Rc::clone(&config)),
             text_popup: Popup::new(Rc::clone(&config)),
             config: Rc::clone(&config),
             marks_popup: MarksPopup::new(marks, queue.clone(), Rc::clone(&config), path),
-            file_cmd_popup: FileCmdPopup::new(queue.clone(), Rc::clone(&config)),
+            file_cmd_popup: FileCmdPopup::new(queue.clone(), Rc::clone(&config))
+                .with_last_commands(LastCommands::from_data_file()),
+            commands_popup: CommandsPopup::new(queue.clone(), Rc::clone(&config)),
+            cargo_targets: None,
+            scratch_history: Vec::new(),
+            error_popup: ErrorPopup::new(queue.clone(), Rc::clone(&config)),
+            log_pane: LogPane::new(Rc::clone(&config)),
+            next_command_id: 0,
+            dry_run: None,
             fuzzy_matcher: FuzzyMatcher::new_with_config(queue.clone(), Rc::clone(&config)),
+            toasts: Toasts::new(Rc::clone(&config)),
+            status_bar: StatusBar::new(Rc::clone(&config)),
+            preview_debounce: Debounce::new(Duration::from_millis(config.preview.debounce)),
+            sequence: Sequence::default(),
+            open_with: open_with_commands(&config),
+            next_full_refresh: full_refresh_interval(&config)
+                .map(|interval| Instant::now() + interval),
+            next_autosave: None,
+            activity: Activity::default(),
+            pane: Pane::default(),
+            content_search: None,
+            last_search_id: 0,
+            content_results: Vec::new(),
+            batch: None,
             queue,
         })
     }
@@ -93,16 +205,30 @@ impl App {
         while let Some(app_event) = self.queue.pop() {
             // Handle events from queue
             match app_event {
+                AppEvent::OpenPopup(PendingOperation::DeleteFile(path))
+                    if self.refuse_protected("delete", &path) => {}
+                AppEvent::OpenPopup(PendingOperation::MoveFiles(paths, dest)) => {
+                    let paths = self.unprotected("move", paths);
+                    if !paths.is_empty() {
+                        self.pending.operation = PendingOperation::MoveFiles(paths, dest);
+                    }
+                }
                 AppEvent::OpenPopup(operation) => self.pending.operation = operation,
                 AppEvent::DeleteFile(path) => {
+                    if self.refuse_protected("delete", &path) {
+                        continue;
+                    }
+                    if self.skip_for_dry_run(format!("delete \"{}\"", path.display())) {
+                        continue;
+                    }
                     if path.is_file() {
                         fs::remove_file(&path)
                             .context("failed to remove file while resolving event queue")?;
-                        info!("deleted file \"{}\"", path.display());
+                        self.notify(format!("deleted file \"{}\"", path.display()));
                     } else {
                         fs::remove_dir_all(&path)
                             .context("failed to remove dir while resolving event queue")?;
-                        info!("deleted directory \"{}\"", path.display());
+                        self.notify(format!("deleted directory \"{}\"", path.display()));
                     }
                     self.tree.partial_refresh(&RefreshData::Delete(path))?;
                     if let Some(item) = self.tree.get_selected() {
@@ -110,108 +236,287 @@ impl App {
                     }
                 }
                 AppEvent::OpenFile(path) => {
+                    if let Some((_, cmd)) =
+                        self.open_with.iter().find(|(glob, _)| glob.is_match(&path))
+                    {
+                        info!("opening file \"{}\" with \"{cmd}\"", path.display());
+                        let cmd = command::fill_path(cmd, &path);
+                        return Ok(Some(self.run_command(&cmd, RunOptions::default())));
+                    }
                     info!("opening file \"{}\"", path.display());
-                    return Ok(Some(TerminalEvent::OpenFile(path)));
+                    return Ok(Some(TerminalEvent::OpenFile(path, None, None)));
+                }
+                AppEvent::OpenFileAt(path, line, column) => {
+                    info!("opening file \"{}\" at line {line}", path.display());
+                    return Ok(Some(TerminalEvent::OpenFile(path, Some(line), column)));
+                }
+                AppEvent::OpenFiles(paths) => {
+                    let (paths, missing): (Vec<_>, Vec<_>) =
+                        paths.into_iter().partition(|path| path.exists());
+                    for path in missing {
+                        warn!("skipping \"{}\", it no longer exists", path.display());
+                    }
+                    if paths.len() > self.config.confirm_threshold {
+                        self.pending.operation = PendingOperation::OpenFiles(paths);
+                    } else {
+                        self.queue.add(AppEvent::EditFiles(paths));
+                    }
+                }
+                AppEvent::EditFiles(paths) => {
+                    // Directories and files with a command in `open_with` still open one by one
+                    let (edited, alone): (Vec<_>, Vec<_>) =
+                        paths.iter().cloned().partition(|path| {
+                            path.is_file()
+                                && !self.open_with.iter().any(|(glob, _)| glob.is_match(path))
+                        });
+                    if edited.len() < 2 || !editor::opens_many(&self.config) {
+                        for path in paths {
+                            self.queue.add(AppEvent::OpenFile(path));
+                        }
+                    } else {
+                        for path in alone {
+                            self.queue.add(AppEvent::OpenFile(path));
+                        }
+                        info!("opening {} files in the editor", edited.len());
+                        return Ok(Some(TerminalEvent::OpenFiles(edited)));
+                    }
+                }
+                AppEvent::OpenInput(op) => self.input_box.open(op),
+                AppEvent::PastePath => {
+                    let text = clipboard::paste()?;
+                    self.goto_reference(&text)?;
+                }
+                AppEvent::WriteConfig(path) => {
+                    fs::create_dir_all(path.parent().context("config file has no parent")?)
+                        .context("failed to create config dir")?;
+                    fs::write(&path, config::DEFAULT_CONFIG).with_context(|| {
+                        format!("failed to write config \"{}\"", path.display())
+                    })?;
+                    self.notify(format!("wrote default config to \"{}\"", path.display()));
+                    self.open_config()?;
                 }
-                AppEvent::OpenInput(op) => self.input_box.operation = op,
                 AppEvent::NewFile(path) => {
                     File::create(&path)
                         .context("failed to create file while resolving event queue")?;
-                    info!("created file \"{}\"", path.display());
+                    self.notify(format!("created file \"{}\"", path.display()));
                     self.tree.partial_refresh(&RefreshData::Add(path))?;
                 }
                 AppEvent::NewDir(path) => {
                     fs::create_dir(&path)
                         .context("failed to create dir while resolving event queue")?;
-                    info!("created directory \"{}\"", path.display());
+                    self.notify(format!("created directory \"{}\"", path.display()));
                     self.tree.partial_refresh(&RefreshData::Add(path))?;
                 }
                 AppEvent::RenameFile(old, new) => {
+                    if self.refuse_protected("rename", &old) {
+                        continue;
+                    }
                     let new = old
                         .parent()
                         .context("file to rename has no parent")?
                         .join(new);
+                    if self.skip_for_dry_run(format!(
+                        "rename \"{}\" to \"{}\"",
+                        old.display(),
+                        new.display()
+                    )) {
+                        continue;
+                    }
                     cmd!("mv", &old, &new).stderr_capture().run()?;
-                    info!("renamed file to {}", new.display());
+                    self.notify(format!("renamed file to {}", new.display()));
                     self.tree.rename(old, new)?;
                 }
                 AppEvent::MoveFile(from, to) => {
+                    if self.refuse_protected("move", &from) {
+                        continue;
+                    }
+                    if self.skip_for_dry_run(format!(
+                        "move \"{}\" to \"{}\"",
+                        from.display(),
+                        to.display()
+                    )) {
+                        continue;
+                    }
                     cmd!("mv", &from, &to).stderr_capture().run()?;
+                    self.notify(format!("moved file to {}", to.display()));
                     self.tree.move_item(from, to)?;
                 }
-                AppEvent::PreviewFile(path) => self
-                    .previewer
-                    .preview_file(path)
-                    .context("failed to preview while resolving event queue")?,
-                AppEvent::TogglePreviewMode => self.previewer.toggle_mode(),
-                AppEvent::RunCommand(cmd) => {
-                    // Strip !!, and if it exists, run in foreground, not background
-                    let (threaded, cmd) = cmd
-                        .strip_prefix("!!")
-                        .map_or((true, cmd.as_str()), |s| (false, s));
-
-                    #[cfg(not(target_os = "windows"))]
-                    let cmd = cmd!(
-                        env::var("SHELL").unwrap_or_else(|_| "sh".to_owned()),
-                        "-c",
-                        cmd
-                    );
-                    #[cfg(target_os = "windows")]
-                    let cmd = cmd!("cmd.exe", "/C", cmd);
-
-                    if threaded {
-                        self.text_popup.preset = Preset::RunningCommand;
-                        return Ok(Some(TerminalEvent::RunCommandThreaded(
-                            cmd.stdout_capture()
-                                .stderr_capture()
-                                .stderr_to_stdout()
-                                .stdin_null()
-                                .unchecked(),
-                        )));
-                    } else {
-                        return Ok(Some(TerminalEvent::RunCommand(cmd)));
+                AppEvent::MoveFiles(paths, dest) => {
+                    let paths = self.unprotected("move", paths);
+                    if paths.is_empty() {
+                        continue;
+                    }
+                    if self.skip_for_dry_run(format!(
+                        "move {} items to \"{}\"",
+                        paths.len(),
+                        dest.display()
+                    )) {
+                        continue;
+                    }
+                    info!("moving {} items to \"{}\"", paths.len(), dest.display());
+                    if paths.len() > MOVE_PROGRESS_THRESHOLD {
+                        self.text_popup.preset = Preset::Moving {
+                            done: 0,
+                            total: paths.len(),
+                        };
+                    }
+                    self.activity.start(Task::Move, Instant::now());
+                    return Ok(Some(TerminalEvent::MoveFiles { paths, dest }));
+                }
+                AppEvent::CopyContents(path) => {
+                    let Some(contents) = encoding::read_text(&path, clipboard::MAX_SIZE)? else {
+                        warn!("refusing to copy binary file \"{}\"", path.display());
+                        continue;
                     };
+                    clipboard::copy(&contents.text)?;
+                    self.notify(if contents.truncated {
+                        format!(
+                            "copied first {} bytes of \"{}\"",
+                            clipboard::MAX_SIZE,
+                            path.display()
+                        )
+                    } else {
+                        format!("copied contents of \"{}\"", path.display())
+                    });
                 }
-                AppEvent::RunCommandWithTmux(cmd, opts) => {
-                    if env::var("TMUX").is_err() {
-                        error!("not in tmux session");
+                AppEvent::CopyPaths(paths) => {
+                    let text = paths
+                        .iter()
+                        .map(|path| {
+                            if self.config.filetree.copy_paths_relative {
+                                path.strip_prefix(&self.path).unwrap_or(path)
+                            } else {
+                                path
+                            }
+                            .display()
+                        })
+                        .join("\n");
+                    clipboard::copy(&text)?;
+                    info!("copied {} paths", paths.len());
+                    self.notify(if paths.len() == 1 {
+                        format!("copied \"{text}\"")
+                    } else {
+                        format!("copied {} paths", paths.len())
+                    });
+                }
+                AppEvent::CopyNamePart(path, part) => {
+                    let Some(text) = part.of(&path) else {
+                        warn!("\"{}\" has no {part} to copy", path.display());
                         continue;
-                    }
-
-                    let cmd_expr = match opts {
-                        TmuxOpts::NewWindow => cmd!("command", "tmux", "new-window", cmd),
-                        TmuxOpts::VerticalSplit => {
-                            cmd!("command", "tmux", "split-window", "-h", cmd)
-                        }
-                        TmuxOpts::HorizontalSplit => {
-                            cmd!("command", "tmux", "split-window", "-v", cmd)
-                        }
-                        TmuxOpts::FloatingWindow => {
-                            cmd!("command", "tmux", "display-popup", "-E", cmd)
-                        }
                     };
-                    let out = cmd_expr.stderr_to_stdout().unchecked().read()?;
-                    if out.is_empty() {
+                    clipboard::copy(&text)?;
+                    info!("copied the {part} of \"{}\"", path.display());
+                    self.notify(format!("copied {part} \"{text}\""));
+                }
+                AppEvent::CopyText(text) => {
+                    clipboard::copy(&text)?;
+                    self.notify(format!("copied {} lines", text.lines().count()));
+                }
+                AppEvent::PreviewFile(path) => self.preview_debounce.push(path, Instant::now()),
+                AppEvent::TogglePreviewMode => self.previewer.toggle_mode()?,
+                AppEvent::ToggleStagedDiff => self.previewer.toggle_staged_diff()?,
+                AppEvent::ToggleBlame => self.previewer.toggle_blame()?,
+                AppEvent::ShowAtRef(path, reference) => {
+                    self.previewer.show_at_ref(path, reference)?;
+                }
+                AppEvent::RunScratch(command) => {
+                    self.scratch_history.retain(|recent| *recent != command);
+                    self.scratch_history.insert(0, command.clone());
+                    self.scratch_history.truncate(SCRATCH_HISTORY);
+                    let expr = self
+                        .shell_command(&self.substitute(&command), CommandDir::Root)
+                        .stderr_to_stdout()
+                        .stdin_null()
+                        .unchecked();
+                    self.activity.start(Task::Scratch, Instant::now());
+                    return Ok(Some(TerminalEvent::RunScratch {
+                        command,
+                        expr,
+                        timeout: self.timeout(None),
+                    }));
+                }
+                AppEvent::RunBatch(command) => {
+                    let files = self.tree.listed_files();
+                    if files.is_empty() {
+                        warn!("no files listed to run \"{command}\" for");
+                    } else if files.len() > self.config.confirm_threshold {
+                        self.pending.operation = PendingOperation::RunBatch(command, files);
+                    } else {
+                        self.queue.add(AppEvent::RunBatchOn(command, files));
+                    }
+                }
+                AppEvent::RunBatchOn(command, files) => {
+                    if self.batch.is_some() {
+                        warn!("a batch command is still running");
                         continue;
                     }
-
-                    info!("opening tmux window");
-                    warn!("{out}");
-                }
-                AppEvent::SearchFiles(files) => {
-                    self.fuzzy_matcher.open_path(
-                        files
-                            .into_iter()
-                            .map(|path| {
-                                path.strip_prefix(self.path())
-                                    .expect("path should start with root")
-                                    .display()
-                                    .to_string()
-                            })
-                            .collect(),
-                    );
+                    let jobs = files
+                        .into_iter()
+                        .map(|file| {
+                            let cmd = command::substitute(
+                                &command,
+                                &Placeholders {
+                                    root: self.path(),
+                                    selected: Some(&file),
+                                    paths: slice::from_ref(&file),
+                                    file: Some(&file),
+                                },
+                            );
+                            let cmd =
+                                self.shell_command_for(&cmd, self.config.command_dir, Some(&file));
+                            (file, cmd)
+                        })
+                        .collect_vec();
+                    info!("running \"{command}\" for {} files", jobs.len());
+                    let cancel = Arc::new(AtomicBool::new(false));
+                    self.batch = Some(Arc::clone(&cancel));
+                    self.text_popup.preset = Preset::Batch {
+                        done: 0,
+                        total: jobs.len(),
+                    };
+                    self.activity.start(Task::Batch, Instant::now());
+                    return Ok(Some(TerminalEvent::RunBatch(Batch {
+                        command,
+                        jobs,
+                        parallel: self.config.batch_jobs,
+                        timeout: self.timeout(None),
+                        cancel,
+                    })));
+                }
+                AppEvent::Stage(paths) => {
+                    let done = format!("staged {} items", paths.len());
+                    self.run_git(&["add"], &paths, done)?;
+                }
+                AppEvent::Unstage(paths) => {
+                    let done = format!("unstaged {} items", paths.len());
+                    self.run_git(&["reset", "-q"], &paths, done)?;
+                }
+                AppEvent::StageAll => {
+                    self.run_git(&["add", "-A"], &[], "staged everything".to_owned())?;
+                }
+                AppEvent::RunCommand(cmd) => {
+                    return Ok(Some(self.run_command(&cmd, RunOptions::default())));
+                }
+                AppEvent::RunCommandWith(cmd, options) => {
+                    return Ok(Some(self.run_command(&cmd, options)));
+                }
+                AppEvent::RunCommandWithTmux(cmd, opts) => {
+                    self.run_in_tmux(&TmuxCommand::new(cmd, opts))?;
+                }
+                AppEvent::RunTmuxCommand(command) => self.run_in_tmux(&command)?,
+                AppEvent::SearchFiles => {
+                    let filters = self.tree.walk_filters()?;
+                    let search = self
+                        .fuzzy_matcher
+                        .find_files(self.path().to_path_buf(), filters);
+                    self.activity
+                        .start(Task::FindFiles(search.id), Instant::now());
+                    return Ok(Some(TerminalEvent::FindFiles(search)));
                 }
                 AppEvent::SpecialCommand(path) => drop(self.file_cmd_popup.open_for(path)),
+                AppEvent::RunLastSpecialCommand(path) => {
+                    self.file_cmd_popup.run_last_for(path)?;
+                }
                 AppEvent::GotoFile(path) => {
                     let path = if path.is_relative() {
                         self.path().join(path)
@@ -220,65 +525,868 @@ impl App {
                     };
                     self.tree.open_path(path)?;
                 }
+                AppEvent::Reroot(root, path) => {
+                    if !root.is_dir() {
+                        warn!("\"{}\" no longer exists", root.display());
+                        continue;
+                    }
+                    self.marks_popup.switch_project(root.clone())?;
+                    let mut tree = Filetree::from_dir_with_config(
+                        &root,
+                        self.queue.clone(),
+                        Rc::clone(&self.config),
+                        self.marks_popup.marks(),
+                    )?;
+                    tree.open_path(&path)?;
+                    self.end_content_search()?;
+                    self.tree = tree;
+                    self.path = root.clone();
+                    self.cargo_targets = None;
+                    self.notify(format!("moved to \"{}\"", root.display()));
+                    return Ok(Some(TerminalEvent::Reroot(root)));
+                }
                 AppEvent::Mark(path) => {
-                    info!("marked: \"{}\"", path.display());
+                    self.notify(format!("marked: \"{}\"", path.display()));
                     self.marks_popup.add_mark(path);
+                    self.marks_popup.save()?;
+                }
+                AppEvent::Unmark(path) => {
+                    self.notify(format!("unmarked: \"{}\"", path.display()));
+                    self.marks_popup.remove_mark(&path);
+                    self.marks_popup.save()?;
                 }
                 AppEvent::OpenFuzzy(items, operation) => self.fuzzy_matcher.start(items, operation),
                 AppEvent::FilterFor(items) => self.tree.filter_include(&items)?,
+                AppEvent::ExpandMatching(pattern) => {
+                    let opened = self.tree.expand_matching(&pattern)?;
+                    self.notify(format!(
+                        "opened {opened} directories matching \"{pattern}\""
+                    ));
+                }
+                AppEvent::FilterExtensions(extensions) => {
+                    let shown = self.tree.filter_extensions(&extensions)?;
+                    self.notify(format!("showing {shown} files with extension {extensions}"));
+                }
+                AppEvent::ExportLog(to) => self.export_log(to)?,
+                AppEvent::SearchPreview(query) => {
+                    if self.previewer.search(&query)? == 0 {
+                        warn!("no matches for \"{query}\" in the preview");
+                    }
+                }
+                AppEvent::PreviewJump(jump) => self.previewer.jump(jump),
+                AppEvent::SearchContents(query) => {
+                    info!("searching for \"{query}\"");
+                    self.end_content_search()?;
+                    self.last_search_id += 1;
+                    let cancel = Arc::new(AtomicBool::new(false));
+                    self.content_search = Some((self.last_search_id, Arc::clone(&cancel)));
+                    self.activity.start(Task::ContentSearch, Instant::now());
+                    return Ok(Some(TerminalEvent::SearchContents(ContentSearch {
+                        id: self.last_search_id,
+                        paths: self.tree.searchable_files()?,
+                        query,
+                        max_size: self.config.preview.max_size,
+                        context: self.config.preview.search_context,
+                        cancel,
+                    })));
+                }
+                AppEvent::StopCommand(id) => return Ok(Some(TerminalEvent::StopCommand(id))),
                 AppEvent::StopAllCommands => {
+                    self.cancel_content_search();
+                    if let Some(cancel) = &self.batch {
+                        cancel.store(true, Ordering::Release);
+                    }
                     self.text_popup.preset = Preset::Nothing;
                     return Ok(Some(TerminalEvent::StopAllCommands));
                 }
             }
         }
+        self.flush_preview()?;
 
         Ok(None)
     }
 
+    /// Preview the last selected file, once it has been selected for long enough
+    fn flush_preview(&mut self) -> Result<()> {
+        if let Some(path) = self.preview_debounce.take_ready(Instant::now()) {
+            self.previewer
+                .preview_file(path)
+                .context("failed to preview while resolving event queue")?;
+        }
+        Ok(())
+    }
+
     pub fn handle_event(&mut self, ev: &ExternalEvent) -> Result<()> {
+        if self.config.autosave_interval > 0
+            && matches!(
+                ev,
+                ExternalEvent::Crossterm(Event::Key(_) | Event::Mouse(_))
+            )
+        {
+            self.next_autosave =
+                Some(Instant::now() + Duration::from_secs(self.config.autosave_interval));
+        }
+        // Errors are drawn on top of everything, so they take the keys first
+        if self.error_popup.visible() && matches!(ev, ExternalEvent::Crossterm(Event::Key(_))) {
+            return self.error_popup.handle_event(ev);
+        }
         let popup_open = self.pending.visible()
             || self.input_box.visible()
             || self.text_popup.visible()
             || self.file_cmd_popup.visible()
             || self.marks_popup.visible()
+            || self.commands_popup.visible()
             || self.fuzzy_matcher.visible();
+        let running_command = self.focus() == Focus::RunningCommand;
+        // Checked before the tree handles the event, so the esc ending the filter will not quit
+        let filtering = self.tree.is_filtering();
+        let log_focused = self.log_pane.focused();
         // Do not give the Filetree or previewer focus if there are any popups open
-        self.tree.focus(!popup_open);
-        self.previewer.focus(!popup_open);
+        self.tree.focus(!popup_open && !log_focused);
+        self.previewer.focus(!popup_open && !log_focused);
+        if let ExternalEvent::Crossterm(Event::Key(key)) = ev {
+            if !popup_open && !filtering && self.config.cycle_focus == key {
+                self.cycle_pane();
+                return Ok(());
+            }
+        }
+        // A finished sequence is handled as the key of its action
+        let sequence_event;
+        let ev = match ev {
+            ExternalEvent::Crossterm(Event::Key(key))
+                if !popup_open && !log_focused && !filtering =>
+            {
+                let Some(key) = self.sequence_key(key) else {
+                    return Ok(());
+                };
+                sequence_event = ExternalEvent::Crossterm(Event::Key(key));
+                &sequence_event
+            }
+            _ => ev,
+        };
+        // A search in the preview takes its keys before the tree does
+        if let ExternalEvent::Crossterm(Event::Key(key)) = ev {
+            if !popup_open && !log_focused && !filtering && self.previewer.handle_search_key(key) {
+                return Ok(());
+            }
+            if !popup_open && self.pane == Pane::Preview && self.previewer.scroll_key(key) {
+                return Ok(());
+            }
+        }
 
         self.pending.handle_event(ev)?;
         self.input_box.handle_event(ev)?;
+        let peeking = self.fuzzy_matcher.is_previewing();
         self.fuzzy_matcher.handle_event(ev)?;
-        self.tree.handle_event(ev)?;
+        if peeking && !self.fuzzy_matcher.visible() {
+            // Show the tree's selection again instead of the last result peeked at
+            if let Some(item) = self.tree.get_selected() {
+                self.queue
+                    .add(AppEvent::PreviewFile(item.path().to_path_buf()));
+            }
+        }
+        // Esc clears the tree before it can quit
+        let clearing = self.pane != Pane::Preview
+            && self.tree.has_something_to_clear()
+            && matches!(ev, ExternalEvent::Crossterm(Event::Key(key)) if Key::esc() == *key);
+        // Keys typed with the preview focused are not meant for the tree
+        if self.pane != Pane::Preview || !matches!(ev, ExternalEvent::Crossterm(Event::Key(_))) {
+            self.tree.handle_event(ev)?;
+        }
+        // Leaving the results of a content search behind stops it too
+        if !self.content_results.is_empty() && !self.tree.is_showing_search_results() {
+            self.end_content_search()?;
+        }
         self.previewer.handle_event(ev)?;
         self.text_popup.handle_event(ev)?;
         self.file_cmd_popup.handle_event(ev)?;
         self.marks_popup.handle_event(ev)?;
+        self.commands_popup.handle_event(ev)?;
+        self.toasts.handle_event(ev)?;
+        if !popup_open {
+            self.log_pane.handle_event(ev)?;
+            if self.pane == Pane::Log && !self.log_pane.focused() {
+                self.pane = Pane::Tree;
+            }
+        }
+        if matches!(ev, ExternalEvent::Tick) {
+            self.flush_preview()?;
+            self.periodic_refresh()?;
+            self.sequence.expire(Instant::now());
+        }
 
         match ev {
             ExternalEvent::Crossterm(Event::Key(key)) => {
-                if popup_open {
+                if running_command {
+                    switch! { key;
+                        self.config.kill_processes => self.queue.add(AppEvent::StopAllCommands),
+                        self.config.running_commands => {
+                            self.text_popup.preset = Preset::Nothing;
+                            self.commands_popup.open();
+                        },
+                    };
+                }
+                // The filter takes every key typed, `q` included
+                if popup_open || filtering || log_focused {
                     return Ok(());
                 }
                 switch! { key;
                     self.config.quit => self.should_quit = true,
                     self.config.help => self.text_popup.preset = Preset::Help,
                     self.config.marks.open => self.marks_popup.open(),
-                    Key::esc(), self.config.esc_to_close => self.should_quit = true,
+                    self.config.log.focus => self.focus_pane(Pane::Log),
+                    self.config.log.export => self.input_box.operation = InputOperation::ExportLog,
+                    self.config.preview.search => self.input_box.operation = InputOperation::SearchPreview,
+                    self.config.preview.jump => self.input_box.operation = InputOperation::PreviewJump,
+                    self.config.running_commands => self.commands_popup.open(),
+                    self.config.cargo_targets => self.open_cargo_targets()?,
+                    self.config.scratch_command => {
+                        self.input_box.operation = InputOperation::ScratchCommand {
+                            recent: self.scratch_history.clone(),
+                        };
+                    },
+                    self.config.dry_run => self.toggle_dry_run(),
+                    self.config.open_config => self.open_config()?,
+                    self.config.preview.pin => {
+                        self.previewer.toggle_pin();
+                        // Catch up with the selection after unpinning
+                        if !self.previewer.is_pinned() {
+                            if let Some(item) = self.tree.get_selected() {
+                                self.previewer.preview_file(item.path())?;
+                            }
+                        }
+                    },
+                    self.config.preview.raw => self.previewer.toggle_raw()?,
+                    self.config.preview.copy_visible => {
+                        let text = self.previewer.visible_text()?;
+                        clipboard::copy(&text)?;
+                        self.notify(format!("copied {} lines of the preview", text.lines().count()));
+                    },
+                    self.config.preview.open_at_line => {
+                        if let Some((path, line)) = self.previewer.current_line() {
+                            self.queue.add(AppEvent::OpenFileAt(path, line, None));
+                        }
+                    },
+                    self.config.filetree.clear => self.cancel_content_search(),
+                    Key::esc(), self.config.esc_to_close && !clearing => self.should_quit = true,
                     self.config.kill_processes => self.queue.add(AppEvent::StopAllCommands),
                 };
             }
             ExternalEvent::CommandOutput(out) => {
-                self.text_popup.preset = Preset::Nothing;
                 info!("output:");
                 info!("{}", if out.is_empty() { " " } else { out });
             }
+            ExternalEvent::CommandTimedOut { id, after } => {
+                let name = self.commands_popup.name(*id).unwrap_or("command");
+                self.report_error(&anyhow!(
+                    "\"{name}\" timed out after {after:?} and was killed"
+                ));
+            }
+            ExternalEvent::CommandFinished(id) => {
+                self.activity.finish(Task::Command(*id));
+                self.commands_popup.remove(*id);
+                if self.commands_popup.is_empty()
+                    && self.text_popup.preset == Preset::RunningCommand
+                {
+                    self.text_popup.preset = Preset::Nothing;
+                }
+            }
+            ExternalEvent::ScratchOutput {
+                command,
+                output,
+                status,
+            } => {
+                self.activity.finish(Task::Scratch);
+                self.previewer.show_output(command, output, *status);
+            }
+            ExternalEvent::Error(err) => self.report_error(err),
+            ExternalEvent::RefreshFiletree => {
+                self.cargo_targets = None;
+                self.previewer.clear_cache();
+            }
+            ExternalEvent::PartialRefresh(changes) => {
+                let paths = changes
+                    .iter()
+                    .map(|change| match change {
+                        RefreshData::Add(path) | RefreshData::Delete(path) => path.as_path(),
+                    })
+                    .collect_vec();
+                if paths.iter().any(|path| cargo::affects_targets(path)) {
+                    self.cargo_targets = None;
+                }
+                self.previewer.forget_cached(&paths);
+            }
+            ExternalEvent::MoveProgress { done, total } => {
+                if matches!(self.text_popup.preset, Preset::Moving { .. }) {
+                    self.text_popup.preset = Preset::Moving {
+                        done: *done,
+                        total: *total,
+                    };
+                }
+            }
+            ExternalEvent::FilesMoved {
+                dest,
+                moved,
+                skipped,
+            } => {
+                self.activity.finish(Task::Move);
+                if matches!(self.text_popup.preset, Preset::Moving { .. }) {
+                    self.text_popup.preset = Preset::Nothing;
+                }
+                for (path, reason) in skipped {
+                    warn!("skipped moving \"{}\": {reason}", path.display());
+                }
+                self.notify(format!(
+                    "moved {} items to \"{}\", skipped {}",
+                    moved.len(),
+                    dest.display(),
+                    skipped.len()
+                ));
+                self.tree.clear_multi_selection();
+                // Both where the items were and where they went changed, which the tree reads
+                // again in one go
+                self.tree.full_refresh()?;
+                if let Some((_, to)) = moved.first() {
+                    self.tree.open_path(to)?;
+                }
+            }
+            ExternalEvent::BatchProgress { done, total } => {
+                if matches!(self.text_popup.preset, Preset::Batch { .. }) {
+                    self.text_popup.preset = Preset::Batch {
+                        done: *done,
+                        total: *total,
+                    };
+                }
+            }
+            ExternalEvent::BatchFinished { command, results } => {
+                self.batch = None;
+                self.activity.finish(Task::Batch);
+                let failed = results
+                    .iter()
+                    .filter(|(_, status)| !status.succeeded())
+                    .count();
+                for (path, status) in results.iter().filter(|(_, status)| !status.succeeded()) {
+                    warn!("\"{command}\" for \"{}\": {status}", path.display());
+                }
+                self.notify(format!(
+                    "ran \"{command}\" for {} files, {failed} failed",
+                    results.len()
+                ));
+                if matches!(
+                    self.text_popup.preset,
+                    Preset::Batch { .. } | Preset::Nothing
+                ) {
+                    self.text_popup.preset = Preset::BatchResults(
+                        results
+                            .iter()
+                            .map(|(path, status)| {
+                                let path = path.strip_prefix(&self.path).unwrap_or(path);
+                                format!("{status}: {}", path.display())
+                            })
+                            .collect(),
+                    );
+                }
+            }
+            ExternalEvent::FoundFiles { id, paths, done } => {
+                if *done {
+                    self.activity.finish(Task::FindFiles(*id));
+                }
+                self.fuzzy_matcher.add_found(*id, paths, *done);
+            }
+            ExternalEvent::SearchResults {
+                id,
+                query,
+                matches,
+                done,
+            } => {
+                if *done {
+                    self.activity.finish(Task::ContentSearch);
+                }
+                if self.content_search.as_ref().map(|(running, _)| running) != Some(id) {
+                    return Ok(());
+                }
+                if !matches.is_empty() {
+                    let first = self.content_results.is_empty();
+                    let found = matches.iter().map(|found| found.path.clone()).collect_vec();
+                    self.previewer.add_matches(matches);
+                    if first {
+                        self.tree.show_search_results(query, &found)?;
+                    } else {
+                        self.tree.add_search_results(&found);
+                    }
+                    self.content_results.extend(found);
+                    self.tree.count_matches(matches);
+                    if first {
+                        if let Some(item) = self.tree.get_selected() {
+                            self.previewer.preview_file(item.path())?;
+                        }
+                    }
+                }
+                if *done {
+                    self.content_search = None;
+                    if self.content_results.is_empty() {
+                        warn!("no files contain \"{query}\"");
+                    } else {
+                        self.notify(format!(
+                            "{} files contain \"{query}\"",
+                            self.content_results.len()
+                        ));
+                    }
+                }
+            }
             _ => (),
         }
         Ok(())
     }
 
+    /// Reread the tree from disk if the refresh interval has passed
+    fn periodic_refresh(&mut self) -> Result<()> {
+        let (Some(next), Some(interval)) =
+            (self.next_full_refresh, full_refresh_interval(&self.config))
+        else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        if next <= now {
+            self.next_full_refresh = Some(now + interval);
+            self.tree.full_refresh()?;
+        }
+        Ok(())
+    }
+
+    /// Run git at the root on `paths`, then show the new statuses in the tree
+    fn run_git(&mut self, args: &[&str], paths: &[PathBuf], done: String) -> Result<()> {
+        if !self.tree.in_repo() {
+            info!("not in a git repository, nothing to {}", args[0]);
+            return Ok(());
+        }
+        git(self.path(), args, paths)?;
+        self.tree.refresh_git_status();
+        self.notify(done);
+        Ok(())
+    }
+
+    /// Pick a Cargo target to run, reading the manifests again only after they might have changed
+    fn open_cargo_targets(&mut self) -> Result<()> {
+        if self.cargo_targets.is_none() {
+            self.cargo_targets = Some(cargo::target_commands(self.path())?);
+        }
+        let targets = self.cargo_targets.clone().unwrap_or_default();
+        if targets.is_empty() {
+            self.notify("no Cargo targets at the root".to_owned());
+            return Ok(());
+        }
+        self.fuzzy_matcher
+            .start(targets, FuzzyOperation::RunCommand);
+        Ok(())
+    }
+
+    /// Build a shell command to run as `options` say, in the background unless it starts with `!!`
+    fn run_command(&mut self, cmd: &str, options: RunOptions) -> TerminalEvent {
+        if cmd.contains("{paths}") {
+            let count = self.tree.multi_selection().len();
+            self.notify(format!("running \"{cmd}\" on {count} items"));
+        }
+        let cmd = self.substitute(cmd);
+        // Strip !!, and if it exists, run in foreground, not background
+        let (threaded, cmd) = cmd
+            .strip_prefix("!!")
+            .map_or((true, cmd.as_str()), |s| (false, s));
+        let name = cmd;
+        let cmd = self.shell_command(cmd, options.dir.unwrap_or(self.config.command_dir));
+
+        if threaded {
+            self.text_popup.preset = Preset::RunningCommand;
+            let id = self.next_command_id;
+            self.next_command_id += 1;
+            self.activity.start(Task::Command(id), Instant::now());
+            TerminalEvent::RunCommandThreaded {
+                id,
+                name: name.to_owned(),
+                expr: cmd.stderr_to_stdout().stdin_null().unchecked(),
+                timeout: self.timeout(options.timeout),
+            }
+        } else {
+            TerminalEvent::RunCommand(cmd)
+        }
+    }
+
+    /// How long a command may run for, given the seconds its binding allows
+    fn timeout(&self, seconds: Option<u64>) -> Option<Duration> {
+        Some(seconds.unwrap_or(self.config.command_timeout))
+            .filter(|seconds| *seconds > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// `cmd` run by the shell in `dir`, with the selection in its environment
+    fn shell_command(&self, cmd: &str, dir: CommandDir) -> Expression {
+        let selected = self.tree.get_selected().map(|item| item.path());
+        self.shell_command_for(cmd, dir, selected)
+    }
+
+    /// `cmd` run by the shell in `dir`, with `selected` in its environment
+    fn shell_command_for(&self, cmd: &str, dir: CommandDir, selected: Option<&Path>) -> Expression {
+        #[cfg(not(target_os = "windows"))]
+        let cmd = cmd!(
+            env::var("SHELL").unwrap_or_else(|_| "sh".to_owned()),
+            "-c",
+            cmd
+        );
+        #[cfg(target_os = "windows")]
+        let cmd = cmd!("cmd.exe", "/C", cmd);
+        let cmd = command_env(self.path(), selected)
+            .into_iter()
+            .fold(cmd, |cmd, (var, value)| {
+                value.map_or_else(|| cmd.env_remove(var), |value| cmd.env(var, value))
+            });
+        match dir.resolve(self.path(), selected) {
+            Some(dir) => cmd.dir(dir),
+            None => cmd,
+        }
+    }
+
+    /// Fill in the placeholders of a command with the current selection
+    fn substitute(&self, cmd: &str) -> String {
+        let selected = self.tree.get_selected().map(|item| item.path());
+        let paths = self.tree.multi_selection();
+        command::substitute(
+            cmd,
+            &Placeholders {
+                root: self.path(),
+                selected,
+                paths: &paths,
+                file: None,
+            },
+        )
+    }
+
+    fn toggle_dry_run(&mut self) {
+        if self.dry_run.take().is_some() {
+            self.notify("dry run off".to_owned());
+        } else {
+            self.dry_run = Some(Vec::new());
+            self.notify("dry run on, destructive actions are only logged".to_owned());
+        }
+    }
+
+    /// Warn that `path` cannot be deleted or moved if it is or holds the root or the working
+    /// directory, returning whether it does
+    fn refuse_protected(&self, action: &str, path: &Path) -> bool {
+        let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let path = canonical(path);
+        let protected = [
+            (canonical(&self.path), "the project root"),
+            (
+                env::current_dir().map_or_else(|_| PathBuf::new(), |dir| canonical(&dir)),
+                "the working directory",
+            ),
+        ];
+        let Some((dir, name)) = protected
+            .iter()
+            .find(|(dir, _)| !dir.as_os_str().is_empty() && dir.starts_with(&path))
+        else {
+            return false;
+        };
+        let relation = if *dir == path { "is" } else { "holds" };
+        warn!(
+            "refusing to {action} \"{}\", it {relation} {name}",
+            path.display()
+        );
+        true
+    }
+
+    /// `paths` without the ones that cannot be moved or deleted
+    fn unprotected(&self, action: &str, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+        paths
+            .into_iter()
+            .filter(|path| !self.refuse_protected(action, path))
+            .collect()
+    }
+
+    /// Stop the content search still running, keeping what it found so far
+    fn cancel_content_search(&mut self) {
+        if let Some((_, cancel)) = self.content_search.take() {
+            cancel.store(true, Ordering::Release);
+            info!("stopped the content search");
+        }
+    }
+
+    /// Stop the content search and forget its results
+    fn end_content_search(&mut self) -> Result<()> {
+        self.cancel_content_search();
+        self.content_results.clear();
+        self.tree.clear_match_counts();
+        self.previewer.clear_matches()
+    }
+
+    /// While dry run is on, log and show `action` instead of doing it. Returns whether it was
+    /// skipped.
+    fn skip_for_dry_run(&mut self, action: String) -> bool {
+        let Some(actions) = &mut self.dry_run else {
+            return false;
+        };
+        info!("dry run: would {action}");
+        actions.push(action);
+        self.text_popup.preset = Preset::DryRun(actions.clone());
+        true
+    }
+
+    /// Track a background command started by main
+    pub fn command_started(&mut self, command: RunningCommand) {
+        info!("started \"{}\"", command.name);
+        self.commands_popup.add(command);
+    }
+
+    fn run_in_tmux(&mut self, command: &TmuxCommand) -> Result<()> {
+        if env::var("TMUX").is_err() {
+            error!("not in tmux session");
+            return Ok(());
+        }
+        // Keys sent to a pane go to the shell already in it, wherever that is
+        let dir = match command.dir {
+            Some(dir) => Some(dir),
+            None if command.window == TmuxOpts::SendKeys => None,
+            None => Some(self.config.command_dir),
+        }
+        .and_then(|dir| {
+            dir.resolve(
+                self.path(),
+                self.tree.get_selected().map(|item| item.path()),
+            )
+        });
+        let args = match command.args(&self.substitute(&command.cmd), dir.as_deref()) {
+            Ok(args) => args,
+            Err(err) => {
+                self.report_error(&err.context("invalid tmux command"));
+                return Ok(());
+            }
+        };
+
+        let out = duct::cmd("command", ["tmux".to_owned()].into_iter().chain(args))
+            .stderr_to_stdout()
+            .unchecked()
+            .read()?;
+        if out.is_empty() {
+            return Ok(());
+        }
+
+        info!("opening tmux window");
+        warn!("{out}");
+        Ok(())
+    }
+
+    fn export_log(&mut self, to: Option<PathBuf>) -> Result<()> {
+        let to = match to {
+            Some(to) if to.is_relative() => self.path().join(to),
+            Some(to) => to,
+            None => {
+                session_log::default_export_path().context("no config directory to export to")?
+            }
+        };
+        let messages = session_log::export(&session_log::session_file(), &to)?;
+        self.notify(format!(
+            "exported {messages} log messages to \"{}\"",
+            to.display()
+        ));
+        Ok(())
+    }
+
+    /// Log a successful action and show it as a toast
+    fn notify(&mut self, message: String) {
+        info!("{message}");
+        self.toasts.info(message);
+    }
+
+    pub fn report_error(&mut self, err: &anyhow::Error) {
+        error!("{err:#}");
+        if self.config.error_popup {
+            self.error_popup.push(format!("{err:?}"));
+        } else {
+            self.toasts.error(format!("{err:#}"));
+        }
+    }
+
+    /// How long the event loop may wait before sending an `ExternalEvent::Tick`
+    pub fn next_tick(&self) -> Option<Duration> {
+        [
+            self.toasts.next_timeout(),
+            self.preview_debounce.next_timeout(Instant::now()),
+            self.next_full_refresh
+                .map(|next| next.saturating_duration_since(Instant::now())),
+            self.sequence.next_timeout(Instant::now()),
+            self.next_autosave
+                .map(|next| next.saturating_duration_since(Instant::now())),
+            self.activity.next_timeout(Instant::now()),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Directories navigated into, newest first
+    pub fn recent_dirs(&self) -> &[PathBuf] {
+        self.tree.recent_dirs()
+    }
+
+    pub fn set_recent_dirs(&mut self, dirs: Vec<PathBuf>) {
+        self.tree.set_recent_dirs(dirs);
+    }
+
+    /// Whether input has been idle long enough to save the session, which is then no longer due
+    /// until the next input
+    pub fn autosave_due(&mut self) -> bool {
+        let due = self
+            .next_autosave
+            .is_some_and(|next| next <= Instant::now());
+        if due {
+            self.next_autosave = None;
+        }
+        due
+    }
+
+    /// Directories opened in the tree and the selected item, to restore next launch
+    pub fn tree_state(&self) -> (Vec<PathBuf>, Option<PathBuf>) {
+        (
+            self.tree.open_dirs(),
+            self.tree
+                .get_selected()
+                .map(|item| item.path().to_path_buf()),
+        )
+    }
+
+    pub fn restore_tree(&mut self, dirs: &[PathBuf], selected: Option<&Path>) {
+        self.tree.restore(dirs, selected);
+    }
+
+    /// Give the key to the sequence being typed, returning the key to handle in its place.
+    /// `None` when the sequence took it.
+    fn sequence_key(&mut self, key: &KeyEvent) -> Option<KeyEvent> {
+        match self.sequence.press(*key, &self.config, Instant::now()) {
+            Press::Ignored => Some(*key),
+            Press::Pending => None,
+            Press::Cancelled => {
+                info!("no sequence continues with {:?}", key.code);
+                None
+            }
+            Press::Done(action) => {
+                let key = self.config.action_key(&action).map(Key::event);
+                if key.is_none() {
+                    warn!("sequence runs unknown action \"{action}\"");
+                }
+                key
+            }
+        }
+    }
+
+    /// Go to a path like `src/main.rs:12:5`, opening the file at the line and column if there are
+    /// any. Relative paths are from the root, and paths outside of it ask to move the root first.
+    fn goto_reference(&mut self, text: &str) -> Result<()> {
+        let (path, line, column) = clipboard::path_reference(text)
+            .with_context(|| format!("no path in \"{}\"", text.trim()))?;
+        let path = self.path.join(path);
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("cannot go to \"{}\"", path.display()))?;
+        let line = line.filter(|_| path.is_file());
+        let root = self
+            .path
+            .canonicalize()
+            .unwrap_or_else(|_| self.path.clone());
+        let Ok(relative) = path.strip_prefix(&root) else {
+            let root = self
+                .config
+                .project_roots
+                .find_root(&path)
+                .unwrap_or_else(|| {
+                    if path.is_dir() {
+                        path.clone()
+                    } else {
+                        path.parent().unwrap_or(&path).to_path_buf()
+                    }
+                });
+            self.pending.operation = PendingOperation::Reroot {
+                root,
+                path,
+                line,
+                column,
+            };
+            return Ok(());
+        };
+        let path = self.path.join(relative);
+        self.queue.add(AppEvent::GotoFile(path.clone()));
+        if let Some(line) = line {
+            self.queue.add(AppEvent::OpenFileAt(path, line, column));
+        }
+        Ok(())
+    }
+
+    /// Open the global config, then the project config if there is one. Without a global config
+    /// this asks to write the default one first.
+    fn open_config(&mut self) -> Result<()> {
+        let global = config::global_config_file().context("could not find config home")?;
+        if !global.exists() {
+            self.pending.operation = PendingOperation::WriteConfig(global);
+            return Ok(());
+        }
+        info!("changes to the config are used the next time projectable starts");
+        self.queue.add(AppEvent::OpenFile(global));
+        if let Some(local) = config::find_local_config()? {
+            self.queue.add(AppEvent::OpenFile(local));
+        }
+        Ok(())
+    }
+
+    fn focus_pane(&mut self, pane: Pane) {
+        self.pane = pane;
+        self.log_pane.focus(pane == Pane::Log);
+    }
+
+    fn cycle_pane(&mut self) {
+        self.focus_pane(match self.pane {
+            Pane::Tree => Pane::Preview,
+            Pane::Preview => Pane::Log,
+            Pane::Log => Pane::Tree,
+        });
+    }
+
+    /// The topmost component receiving input
+    fn focus(&self) -> Focus {
+        if self.error_popup.visible() {
+            Focus::Error
+        } else if self.fuzzy_matcher.visible() {
+            Focus::Fuzzy
+        } else if self.marks_popup.visible() {
+            Focus::Marks
+        } else if self.commands_popup.visible() {
+            Focus::Commands
+        } else if self.file_cmd_popup.visible() {
+            Focus::FileCmd
+        } else if self.text_popup.preset == Preset::Help {
+            Focus::Help
+        } else if matches!(
+            self.text_popup.preset,
+            Preset::DryRun(_) | Preset::Moving { .. } | Preset::BatchResults(_)
+        ) {
+            Focus::DryRun
+        } else if self.text_popup.visible() {
+            Focus::RunningCommand
+        } else if self.input_box.visible() {
+            Focus::Input
+        } else if self.pending.visible() {
+            Focus::Pending
+        } else if self.log_pane.focused() {
+            Focus::Log
+        } else if self.tree.is_filtering() {
+            Focus::QuickFilter
+        } else if self.previewer.is_searching() {
+            Focus::PreviewSearch
+        } else if self.pane == Pane::Preview {
+            Focus::Preview
+        } else {
+            Focus::Filetree
+        }
+    }
+
     pub fn should_quit(&self) -> bool {
         self.should_quit
     }
@@ -288,47 +1396,710 @@ impl App {
     }
 }
 
+/// Compile the `open_with` patterns, skipping invalid ones. Longer patterns are more specific, so
+/// they are tried first.
+fn open_with_commands(config: &Config) -> Vec<(GlobMatcher, String)> {
+    config
+        .open_with
+        .iter()
+        .sorted_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)))
+        .filter_map(|(pattern, cmd)| {
+            // Prefixed with ** to work with absolute paths, like special commands
+            match Glob::new(&format!("**/{pattern}")) {
+                Ok(glob) => Some((glob.compile_matcher(), cmd.clone())),
+                Err(err) => {
+                    warn!("ignoring `open_with` pattern \"{pattern}\": {err}");
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Smallest terminal that fits the tree next to the preview and log
+const MIN_SPLIT_SIZE: (u16, u16) = (40, 10);
+/// Smallest terminal that fits anything of use, below it there is only a note
+const MIN_SIZE: (u16, u16) = (12, 4);
+
 impl Drawable for App {
     fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Result<()> {
-        let main_layout = Layout::default()
-            .direction(Direction::Horizontal)
-            .horizontal_margin(1)
-            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
-            .split(area);
-        let left_hand_layout = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
-            .split(main_layout[0]);
-
-        let logger = Logger::default()
-            .style_error(self.config.log.error.into())
-            .style_debug(self.config.log.debug.into())
-            .style_warn(self.config.log.warn.into())
-            .style_trace(self.config.log.trace.into())
-            .style_info(self.config.log.info.into())
-            .output_level(Some(LoggerLevel::Long))
-            .output_target(false)
-            .output_file(false)
-            .output_line(false)
-            .output_level(None)
-            .output_timestamp(None)
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .title("Log")
-                    .border_style(self.config.log.border_color.into()),
-            );
-
-        self.tree.draw(f, left_hand_layout[0])?;
-        f.render_widget(logger, left_hand_layout[1]);
-        self.previewer.draw(f, main_layout[1])?;
+        if area.width < MIN_SIZE.0 || area.height < MIN_SIZE.1 {
+            let note = Paragraph::new("terminal too small").wrap(Wrap { trim: true });
+            f.render_widget(note, area);
+            return Ok(());
+        }
+        let focus = self.focus();
+        self.tree.highlight.set(
+            self.pane == Pane::Tree
+                && matches!(
+                    focus,
+                    Focus::Filetree | Focus::QuickFilter | Focus::PreviewSearch
+                ),
+        );
+        self.previewer.highlight.set(
+            self.pane == Pane::Preview && matches!(focus, Focus::Preview | Focus::PreviewSearch),
+        );
+        self.log_pane.highlight.set(focus == Focus::Log);
+        self.status_bar.dry_run.set(self.dry_run.is_some());
+        self.status_bar.branch.replace(self.tree.branch().cloned());
+        self.status_bar
+            .activity
+            .replace(self.activity.label(Instant::now()));
+        self.status_bar
+            .sequence
+            .replace(self.sequence.continuations(&self.config));
+        let area = if (self.config.show_hints || self.dry_run.is_some()) && area.height > 1 {
+            let [area, hints_area] = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+                .split(area)[..]
+            else {
+                unreachable!("should always have 2 sections");
+            };
+            self.status_bar.focus.set(focus);
+            self.status_bar.draw(f, hints_area)?;
+            area
+        } else {
+            area
+        };
+        if area.width < MIN_SPLIT_SIZE.0 || area.height < MIN_SPLIT_SIZE.1 {
+            // Too cramped for the preview and log to show anything useful
+            self.tree.draw(f, area)?;
+        } else {
+            let main_layout = Layout::default()
+                .direction(Direction::Horizontal)
+                .horizontal_margin(1)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                .split(area);
+            let left_hand_layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+                .split(main_layout[0]);
+
+            self.tree.draw(f, left_hand_layout[0])?;
+            self.log_pane.draw(f, left_hand_layout[1])?;
+            self.previewer.draw(f, main_layout[1])?;
+        }
         self.pending.draw(f, area)?;
-        self.input_box.draw(f, area)?;
+        // Renames are typed over the name in the tree, unless it is out of view
+        let rename_row = match &self.input_box.operation {
+            InputOperation::Rename { to } => self.tree.name_area(to),
+            _ => None,
+        };
+        match rename_row {
+            Some(row) => self.input_box.draw_inline(f, row),
+            None => self.input_box.draw(f, area)?,
+        }
         self.text_popup.draw(f, area)?;
         self.file_cmd_popup.draw(f, area)?;
         self.marks_popup.draw(f, area)?;
+        self.commands_popup.draw(f, area)?;
         self.fuzzy_matcher.draw(f, area)?;
+        self.error_popup.draw(f, area)?;
+        self.toasts.draw(f, area)?;
 
         Ok(())
     }
 }
+
+fn full_refresh_interval(config: &Config) -> Option<Duration> {
+    match config.filetree.full_refresh_interval {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
+    }
+}
+
+/// Variables describing the current context, given to every command that is run.
+/// These replace any value already set in projectable's own environment, and the ones that are
+/// `None` are removed from it.
+fn command_env(root: &Path, selected: Option<&Path>) -> Vec<(&'static str, Option<PathBuf>)> {
+    let dir = selected.map(|selected| {
+        if selected.is_dir() {
+            selected
+        } else {
+            selected.parent().unwrap_or(root)
+        }
+    });
+    vec![
+        ("PROJECTABLE_ROOT", Some(root.to_path_buf())),
+        ("PROJECTABLE_SELECTED", selected.map(Path::to_path_buf)),
+        ("PROJECTABLE_SELECTED_DIR", dir.map(Path::to_path_buf)),
+    ]
+}
+
+/// Run git in `dir` with `args` followed by `paths`, failing with what it printed if it fails
+fn git(dir: &Path, args: &[&str], paths: &[PathBuf]) -> Result<()> {
+    let args = args
+        .iter()
+        .map(OsStr::new)
+        .chain([OsStr::new("--")])
+        .chain(paths.iter().map(|path| path.as_os_str()));
+    let out = cmd("git", args)
+        .dir(dir)
+        .stdout_null()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .context("failed to run git")?;
+    if !out.status.success() {
+        bail!(
+            "git failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_event::JobStatus;
+    use assert_fs::{prelude::*, TempDir};
+    use crossterm::event::KeyCode;
+    use scopeguard::defer;
+    use serial_test::serial;
+    use test_log::test;
+    use tui::{backend::TestBackend, Terminal};
+
+    #[test]
+    fn tiny_terminals_draw_without_panicking() {
+        let temp = TempDir::new().unwrap();
+        temp.child("dir/file.txt").write_str("some text").unwrap();
+        let mut app = App::new(
+            temp.to_path_buf(),
+            temp.path(),
+            Rc::new(Config::default()),
+            Rc::default(),
+        )
+        .unwrap();
+        app.update().unwrap();
+        let draw_all = |app: &App| {
+            for (width, height) in [
+                (0, 0),
+                (1, 1),
+                (2, 1),
+                (1, 5),
+                (5, 2),
+                (12, 4),
+                (30, 8),
+                (50, 12),
+                (80, 24),
+            ] {
+                let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+                terminal.draw(|f| app.draw(f, f.size()).unwrap()).unwrap();
+            }
+        };
+        draw_all(&app);
+        let mut terminal = Terminal::new(TestBackend::new(20, 3)).unwrap();
+        let frame = terminal.draw(|f| app.draw(f, f.size()).unwrap()).unwrap();
+        let top: String = frame.buffer.content[..20]
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect();
+        assert_eq!("terminal too small  ", top);
+
+        app.notify("a notification".to_owned());
+        app.report_error(&anyhow::anyhow!("an error"));
+        draw_all(&app);
+        app.error_popup = ErrorPopup::new(app.queue.clone(), Rc::clone(&app.config));
+        app.text_popup.preset = Preset::Help;
+        draw_all(&app);
+        app.text_popup.preset = Preset::Nothing;
+        app.marks_popup.open();
+        draw_all(&app);
+        app.marks_popup.close();
+        app.commands_popup.open();
+        draw_all(&app);
+        app.commands_popup.close();
+        app.input_box.operation = InputOperation::ExportLog;
+        draw_all(&app);
+        app.input_box.operation = InputOperation::NoOperations;
+        app.fuzzy_matcher.open_path(vec!["dir/file.txt".to_owned()]);
+        draw_all(&app);
+    }
+
+    #[test]
+    fn esc_clears_the_tree_before_quitting() {
+        let temp = TempDir::new().unwrap();
+        for file in ["a.txt", "b.txt"] {
+            temp.child(file).touch().unwrap();
+        }
+        let mut app = App::new(
+            temp.to_path_buf(),
+            temp.path(),
+            Rc::default(),
+            Rc::default(),
+        )
+        .unwrap();
+        app.update().unwrap();
+        let esc = ExternalEvent::Crossterm(Event::Key(Key::esc().event()));
+
+        app.tree.show_only(&[temp.join("a.txt")]).unwrap();
+        app.tree.toggle_multi_select();
+        app.handle_event(&esc).unwrap();
+        assert!(!app.should_quit());
+        assert!(app.tree.has_something_to_clear());
+        app.handle_event(&esc).unwrap();
+        assert!(!app.should_quit());
+        assert!(!app.tree.has_something_to_clear());
+        app.handle_event(&esc).unwrap();
+        assert!(app.should_quit());
+
+        let config = Config {
+            esc_to_close: false,
+            ..Default::default()
+        };
+        app.config = Rc::new(config);
+        app.should_quit = false;
+        app.handle_event(&esc).unwrap();
+        assert!(!app.should_quit());
+    }
+
+    #[test]
+    fn open_with_is_used_for_matching_files() {
+        let temp = TempDir::new().unwrap();
+        temp.child("image.png").touch().unwrap();
+        temp.child("notes.txt").touch().unwrap();
+        let config = Config {
+            open_with: collect_all::collect![_: ("*.png".to_owned(), "viewer {}".to_owned())],
+            ..Default::default()
+        };
+        let mut app = App::new(
+            temp.to_path_buf(),
+            temp.path(),
+            Rc::new(config),
+            Rc::default(),
+        )
+        .unwrap();
+        app.update().unwrap();
+
+        app.queue.add(AppEvent::OpenFile(temp.join("image.png")));
+        let expected = format!("viewer {}", command::quote(temp.join("image.png")));
+        assert!(matches!(
+            app.update().unwrap(),
+            Some(TerminalEvent::RunCommandThreaded { name, .. }) if name == expected
+        ));
+        app.queue.add(AppEvent::OpenFile(temp.join("notes.txt")));
+        assert!(matches!(
+            app.update().unwrap(),
+            Some(TerminalEvent::OpenFile(path, None, None)) if path == temp.join("notes.txt")
+        ));
+    }
+
+    #[test]
+    fn files_open_together_in_editors_that_take_several() {
+        let temp = TempDir::new().unwrap();
+        for file in ["a.txt", "b.txt", "image.png"] {
+            temp.child(file).touch().unwrap();
+        }
+        let config = Config {
+            editor: Some("nvim".to_owned()),
+            open_with: collect_all::collect![_: ("*.png".to_owned(), "viewer {}".to_owned())],
+            confirm_threshold: 5,
+            ..Default::default()
+        };
+        let mut app = App::new(
+            temp.to_path_buf(),
+            temp.path(),
+            Rc::new(config),
+            Rc::default(),
+        )
+        .unwrap();
+        app.update().unwrap();
+
+        let files = vec![
+            temp.join("a.txt"),
+            temp.join("image.png"),
+            temp.join("b.txt"),
+        ];
+        app.queue.add(AppEvent::OpenFiles(files.clone()));
+        assert!(matches!(
+            app.update().unwrap(),
+            Some(TerminalEvent::OpenFiles(paths)) if paths == [temp.join("a.txt"), temp.join("b.txt")]
+        ));
+        assert!(matches!(
+            app.update().unwrap(),
+            Some(TerminalEvent::RunCommandThreaded { .. })
+        ));
+
+        let config = Config {
+            editor: Some("gedit".to_owned()),
+            ..Default::default()
+        };
+        app.config = Rc::new(config);
+        app.queue.add(AppEvent::EditFiles(vec![
+            temp.join("a.txt"),
+            temp.join("b.txt"),
+        ]));
+        for file in ["a.txt", "b.txt"] {
+            assert!(matches!(
+                app.update().unwrap(),
+                Some(TerminalEvent::OpenFile(path, None, None)) if path == temp.join(file)
+            ));
+        }
+    }
+
+    #[test]
+    fn batch_runs_for_every_listed_file_and_lists_how_each_ended() {
+        let temp = TempDir::new().unwrap();
+        for file in ["a.txt", "b.txt", "dir/c.txt"] {
+            temp.child(file).touch().unwrap();
+        }
+        let config = Config {
+            confirm_threshold: 2,
+            ..Default::default()
+        };
+        let mut app = App::new(
+            temp.to_path_buf(),
+            temp.path(),
+            Rc::new(config),
+            Rc::default(),
+        )
+        .unwrap();
+        app.update().unwrap();
+
+        app.queue
+            .add(AppEvent::RunBatch("optipng {path}".to_owned()));
+        app.update().unwrap();
+        let PendingOperation::RunBatch(command, mut files) = app.pending.operation.clone() else {
+            panic!("expected confirmation, got {:?}", app.pending.operation);
+        };
+        files.sort();
+        assert_eq!("optipng {path}", command);
+        assert_eq!(
+            vec![
+                temp.join("a.txt"),
+                temp.join("b.txt"),
+                temp.join("dir/c.txt")
+            ],
+            files
+        );
+
+        let files = vec![temp.join("a.txt"), temp.join("b.txt")];
+        app.queue
+            .add(AppEvent::RunBatchOn(command.clone(), files.clone()));
+        assert!(matches!(
+            app.update().unwrap(),
+            Some(TerminalEvent::RunBatch(Batch { jobs, .. })) if jobs.len() == 2
+        ));
+        assert_eq!(Preset::Batch { done: 0, total: 2 }, app.text_popup.preset);
+        assert!(app.activity.is_busy());
+
+        app.handle_event(&ExternalEvent::BatchFinished {
+            command,
+            results: vec![
+                (files[0].clone(), JobStatus::Exited(Some(0))),
+                (files[1].clone(), JobStatus::Exited(Some(2))),
+            ],
+        })
+        .unwrap();
+        assert_eq!(
+            Preset::BatchResults(vec!["ok: a.txt".to_owned(), "exit 2: b.txt".to_owned()]),
+            app.text_popup.preset
+        );
+        assert!(app.batch.is_none());
+        assert!(!app.activity.is_busy());
+
+        // Braces in a name are not filled in again
+        let odd = temp.join("odd{}.txt");
+        app.queue.add(AppEvent::RunBatchOn(
+            "cat {path}".to_owned(),
+            vec![odd.clone()],
+        ));
+        let Some(TerminalEvent::RunBatch(Batch { jobs, .. })) = app.update().unwrap() else {
+            panic!("expected the batch to start");
+        };
+        let job = format!("{:?}", jobs[0].1);
+        assert!(
+            job.contains(&format!("cat {}", command::quote(&odd))),
+            "{job}"
+        );
+    }
+
+    #[test]
+    fn background_work_spins_until_it_reports_back() {
+        let temp = TempDir::new().unwrap();
+        temp.child("notes.txt").touch().unwrap();
+        let mut app = App::new(
+            temp.to_path_buf(),
+            temp.path(),
+            Rc::default(),
+            Rc::default(),
+        )
+        .unwrap();
+        app.update().unwrap();
+
+        app.queue.add(AppEvent::SearchContents("text".to_owned()));
+        assert!(matches!(
+            app.update().unwrap(),
+            Some(TerminalEvent::SearchContents(_))
+        ));
+        assert!(app.activity.is_busy());
+        app.handle_event(&ExternalEvent::SearchResults {
+            id: 1,
+            query: "text".to_owned(),
+            matches: Vec::new(),
+            done: true,
+        })
+        .unwrap();
+        assert!(!app.activity.is_busy());
+    }
+
+    #[test]
+    fn content_results_stream_in_until_cancelled() {
+        let temp = TempDir::new().unwrap();
+        for file in ["a.txt", "b.txt", "c.txt"] {
+            temp.child(file).write_str("one\nneedle\nthree").unwrap();
+        }
+        let mut app = App::new(
+            temp.to_path_buf(),
+            temp.path(),
+            Rc::default(),
+            Rc::default(),
+        )
+        .unwrap();
+        app.update().unwrap();
+        let search = |app: &mut App| {
+            app.queue.add(AppEvent::SearchContents("needle".to_owned()));
+            match app.update().unwrap() {
+                Some(TerminalEvent::SearchContents(search)) => search,
+                _ => panic!("expected a content search"),
+            }
+        };
+        let results = |id: usize, files: &[&str], done: bool| ExternalEvent::SearchResults {
+            id,
+            query: "needle".to_owned(),
+            matches: files
+                .iter()
+                .map(|file| crate::search::match_file(&temp.join(file), "needle", 1024, 1).unwrap())
+                .collect(),
+            done,
+        };
+        let clear = ExternalEvent::Crossterm(Event::Key(Key::normal('\\').event()));
+
+        let first = search(&mut app);
+        app.handle_event(&results(first.id, &["a.txt"], false))
+            .unwrap();
+        assert!(app.tree.is_showing_search_results());
+        assert_eq!(vec![temp.join("a.txt")], app.content_results);
+        assert_eq!(Some((temp.join("a.txt"), 1)), app.previewer.current_line());
+
+        // A new search replaces the first, whose late results are dropped
+        let second = search(&mut app);
+        assert!(first.cancel.load(Ordering::Acquire));
+        app.handle_event(&results(first.id, &["b.txt"], true))
+            .unwrap();
+        assert!(app.content_results.is_empty());
+        app.handle_event(&results(second.id, &["b.txt", "c.txt"], false))
+            .unwrap();
+        assert_eq!(2, app.content_results.len());
+
+        app.handle_event(&clear).unwrap();
+        assert!(second.cancel.load(Ordering::Acquire));
+        app.handle_event(&results(second.id, &["a.txt"], true))
+            .unwrap();
+        assert!(!app.activity.is_busy());
+        while app.tree.is_showing_search_results() {
+            app.handle_event(&clear).unwrap();
+        }
+        assert!(app.content_results.is_empty());
+    }
+
+    #[test]
+    fn tab_moves_the_keys_between_panes() {
+        let temp = TempDir::new().unwrap();
+        for file in ["a.txt", "b.txt"] {
+            temp.child(file).write_str("one\ntwo\nthree").unwrap();
+        }
+        let mut app = App::new(
+            temp.to_path_buf(),
+            temp.path(),
+            Rc::default(),
+            Rc::default(),
+        )
+        .unwrap();
+        app.update().unwrap();
+        let key = |key: Key| ExternalEvent::Crossterm(Event::Key(key.event()));
+        let selected = app.tree.get_selected().unwrap().path().to_path_buf();
+
+        app.handle_event(&key(Key::key_code(KeyCode::Tab))).unwrap();
+        assert_eq!(Focus::Preview, app.focus());
+        app.handle_event(&key(Key::normal('j'))).unwrap();
+        assert_eq!(selected, app.tree.get_selected().unwrap().path());
+
+        // Popups take the keys, and give them back to the same pane
+        app.handle_event(&key(Key::normal('?'))).unwrap();
+        assert_eq!(Focus::Help, app.focus());
+        app.handle_event(&key(Key::esc())).unwrap();
+        assert_eq!(Focus::Preview, app.focus());
+
+        app.handle_event(&key(Key::key_code(KeyCode::Tab))).unwrap();
+        assert_eq!(Focus::Log, app.focus());
+        app.handle_event(&key(Key::key_code(KeyCode::Tab))).unwrap();
+        assert_eq!(Focus::Filetree, app.focus());
+        app.handle_event(&key(Key::normal('j'))).unwrap();
+        assert_ne!(selected, app.tree.get_selected().unwrap().path());
+    }
+
+    #[test]
+    #[serial]
+    fn opening_the_config_offers_to_write_the_defaults() {
+        let temp = TempDir::new().unwrap();
+        env::set_var("PROJECTABLE_CONFIG_DIR", temp.child("config").path());
+        defer! {
+            env::remove_var("PROJECTABLE_CONFIG_DIR");
+        }
+        let mut app = App::new(
+            temp.to_path_buf(),
+            temp.path(),
+            Rc::default(),
+            Rc::default(),
+        )
+        .unwrap();
+        app.update().unwrap();
+        let key = |key: Key| ExternalEvent::Crossterm(Event::Key(key.event()));
+
+        app.handle_event(&key(Key::alt('o'))).unwrap();
+        let file = temp.child("config/config.toml");
+        assert_eq!(
+            PendingOperation::WriteConfig(file.to_path_buf()),
+            app.pending.operation
+        );
+        for confirm in [Key::normal('j'), Key::key_code(KeyCode::Enter)] {
+            app.handle_event(&key(confirm)).unwrap();
+        }
+        assert!(matches!(
+            app.update().unwrap(),
+            Some(TerminalEvent::OpenFile(path, None, None)) if path == file.path()
+        ));
+        assert_eq!(config::DEFAULT_CONFIG, fs::read_to_string(&file).unwrap());
+    }
+
+    #[test]
+    fn references_go_to_the_line_or_offer_to_move_the_root() {
+        let temp = TempDir::new().unwrap();
+        temp.child("project/dir/main.rs")
+            .write_str("fn\nmain")
+            .unwrap();
+        temp.child("elsewhere/.git").create_dir_all().unwrap();
+        temp.child("elsewhere/notes.txt").touch().unwrap();
+        let root = temp.child("project");
+        let mut app = App::new(
+            root.to_path_buf(),
+            root.path(),
+            Rc::default(),
+            Rc::default(),
+        )
+        .unwrap();
+        app.update().unwrap();
+
+        app.goto_reference("dir/main.rs:2:1").unwrap();
+        assert!(matches!(
+            app.update().unwrap(),
+            Some(TerminalEvent::OpenFile(path, Some(2), Some(1))) if path == root.join("dir/main.rs")
+        ));
+        assert_eq!(
+            root.join("dir/main.rs"),
+            app.tree.get_selected().unwrap().path()
+        );
+
+        let outside = temp.join("elsewhere/notes.txt").canonicalize().unwrap();
+        app.goto_reference(&outside.display().to_string()).unwrap();
+        assert_eq!(
+            PendingOperation::Reroot {
+                root: outside.parent().unwrap().to_path_buf(),
+                path: outside.clone(),
+                line: None,
+                column: None
+            },
+            app.pending.operation
+        );
+        assert!(app.goto_reference("missing.txt").is_err());
+    }
+
+    #[test]
+    fn root_and_working_directory_are_never_deleted_or_moved() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.child("project");
+        root.child("file.txt").touch().unwrap();
+        let mut app = App::new(
+            root.to_path_buf(),
+            root.path(),
+            Rc::new(Config::default()),
+            Rc::default(),
+        )
+        .unwrap();
+        app.update().unwrap();
+        // Anything the guard lets through is only recorded
+        app.dry_run = Some(Vec::new());
+        let cwd = env::current_dir().unwrap();
+        for path in [root.to_path_buf(), temp.to_path_buf(), cwd.clone()] {
+            app.queue
+                .add(AppEvent::OpenPopup(PendingOperation::DeleteFile(
+                    path.clone(),
+                )));
+            app.queue.add(AppEvent::DeleteFile(path.clone()));
+            app.queue
+                .add(AppEvent::MoveFile(path.clone(), temp.join("moved")));
+            app.queue.add(AppEvent::RenameFile(path, "renamed".into()));
+        }
+        app.update().unwrap();
+        assert_eq!(PendingOperation::NoPending, app.pending.operation);
+        assert_eq!(Some(Vec::new()), app.dry_run);
+
+        let file = root.join("file.txt");
+        app.queue
+            .add(AppEvent::OpenPopup(PendingOperation::MoveFiles(
+                vec![root.to_path_buf(), file.clone(), cwd],
+                temp.to_path_buf(),
+            )));
+        app.update().unwrap();
+        assert_eq!(
+            PendingOperation::MoveFiles(vec![file], temp.to_path_buf()),
+            app.pending.operation
+        );
+    }
+
+    #[test]
+    fn command_env_describes_selection() {
+        let root = env::temp_dir();
+        let file = root.join("file.txt");
+        assert_eq!(
+            vec![
+                ("PROJECTABLE_ROOT", Some(root.clone())),
+                ("PROJECTABLE_SELECTED", Some(file.clone())),
+                ("PROJECTABLE_SELECTED_DIR", Some(root.clone())),
+            ],
+            command_env(&root, Some(&file))
+        );
+        // Values from a projectable this one was started from do not leak through
+        assert_eq!(
+            vec![
+                ("PROJECTABLE_ROOT", Some(root.clone())),
+                ("PROJECTABLE_SELECTED", None),
+                ("PROJECTABLE_SELECTED_DIR", None),
+            ],
+            command_env(&root, None)
+        );
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn git_stages_and_unstages_paths() {
+        let temp = TempDir::new().unwrap();
+        temp.child("has space.txt").touch().unwrap();
+        git(temp.path(), &["init", "-q"], &[]).unwrap();
+        let status = || {
+            cmd!("git", "status", "--porcelain")
+                .dir(temp.path())
+                .read()
+                .unwrap()
+        };
+
+        git(temp.path(), &["add"], &[temp.join("has space.txt")]).unwrap();
+        assert_eq!("A  \"has space.txt\"", status());
+        git(temp.path(), &["reset", "-q"], &[temp.join("has space.txt")]).unwrap();
+        assert_eq!("?? \"has space.txt\"", status());
+        assert!(git(temp.path(), &["add"], &[temp.join("missing")]).is_err());
+    }
+}
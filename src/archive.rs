@@ -0,0 +1,626 @@
+use anyhow::{bail, Context, Result};
+use miniz_oxide::{
+    inflate::stream::{inflate, InflateState},
+    DataFormat, MZError, MZFlush, MZStatus,
+};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// A file or directory inside an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+/// The first entries of an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    pub entries: Vec<Entry>,
+    /// Whether there were more than the entries read
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Kind {
+    fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") || name.ends_with(".jar") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `path` is an archive that `list` can read
+pub fn is_archive(path: impl AsRef<Path>) -> bool {
+    Kind::of(path.as_ref()).is_some()
+}
+
+/// List up to `limit` entries of the archive at `path`. Only headers are read, nothing is
+/// extracted, though a gzipped tar has to be decompressed to find them. That stops after
+/// `max_decompressed` bytes, listing what was found until then as truncated.
+pub fn list(path: impl AsRef<Path>, limit: usize, max_decompressed: u64) -> Result<Listing> {
+    let path = path.as_ref();
+    let kind = Kind::of(path).context("not a supported archive")?;
+    let file = File::open(path)
+        .with_context(|| format!("failed to open archive \"{}\"", path.display()))?;
+    let listing = match kind {
+        Kind::Zip => list_zip(file, limit),
+        Kind::Tar => list_tar(BufReader::new(file), limit),
+        Kind::TarGz => GzReader::new(BufReader::new(file)).and_then(|reader| {
+            list_tar(
+                Capped {
+                    inner: reader,
+                    left: max_decompressed,
+                },
+                limit,
+            )
+        }),
+    };
+    listing.with_context(|| format!("failed to read archive \"{}\"", path.display()))
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    let mut le = [0; 8];
+    le.copy_from_slice(&bytes[at..at + 8]);
+    u64::from_le_bytes(le)
+}
+
+/// Read the central directory at the end of a zip file
+fn list_zip(mut file: File, limit: usize) -> Result<Listing> {
+    const END_SIGNATURE: u32 = 0x0605_4b50;
+    const ENTRY_SIGNATURE: u32 = 0x0201_4b50;
+    const END_LEN: u64 = 22;
+
+    // The end record is followed by a comment of at most u16::MAX bytes
+    let len = file.seek(SeekFrom::End(0))?;
+    let tail_len = len.min(END_LEN + u64::from(u16::MAX));
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = Vec::new();
+    file.by_ref().take(tail_len).read_to_end(&mut tail)?;
+    let Some(end) = (0..tail.len().saturating_sub(END_LEN as usize - 1))
+        .rev()
+        .find(|&at| u32_at(&tail, at) == END_SIGNATURE)
+    else {
+        bail!("missing end of central directory");
+    };
+    let mut total = u64::from(u16_at(&tail, end + 10));
+    let mut directory_start = u64::from(u32_at(&tail, end + 16));
+    if total == u64::from(u16::MAX) || directory_start == u64::from(u32::MAX) {
+        (total, directory_start) = zip64_directory(&mut file, &tail[..end])?;
+    }
+
+    let mut reader = BufReader::new(file);
+    reader.seek(SeekFrom::Start(directory_start))?;
+    let mut entries = Vec::new();
+    let mut header = [0; 46];
+    for _ in 0..total.min(limit as u64) {
+        reader.read_exact(&mut header)?;
+        if u32_at(&header, 0) != ENTRY_SIGNATURE {
+            bail!("corrupt central directory");
+        }
+        let mut name = vec![0; usize::from(u16_at(&header, 28))];
+        reader.read_exact(&mut name)?;
+        let mut extra = vec![0; usize::from(u16_at(&header, 30))];
+        reader.read_exact(&mut extra)?;
+        reader.seek_relative(i64::from(u16_at(&header, 32)))?;
+        let mut size = u64::from(u32_at(&header, 24));
+        if size == u64::from(u32::MAX) {
+            size = zip64_size(&extra).context("missing zip64 size")?;
+        }
+        let name = String::from_utf8_lossy(&name).into_owned();
+        entries.push(Entry {
+            is_dir: name.ends_with('/'),
+            name,
+            size,
+        });
+    }
+    Ok(Listing {
+        entries,
+        truncated: total > limit as u64,
+    })
+}
+
+/// Entry count and start of the central directory from the zip64 end record, found through the
+/// locator right before the usual end record, `before_end`
+fn zip64_directory(file: &mut File, before_end: &[u8]) -> Result<(u64, u64)> {
+    const LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+    const END_SIGNATURE: u32 = 0x0606_4b50;
+    const LOCATOR_LEN: usize = 20;
+
+    let locator = before_end
+        .len()
+        .checked_sub(LOCATOR_LEN)
+        .map(|at| &before_end[at..])
+        .filter(|locator| u32_at(locator, 0) == LOCATOR_SIGNATURE)
+        .context("missing zip64 end of central directory locator")?;
+    file.seek(SeekFrom::Start(u64_at(locator, 8)))?;
+    let mut end = [0; 56];
+    file.read_exact(&mut end)?;
+    if u32_at(&end, 0) != END_SIGNATURE {
+        bail!("corrupt zip64 end of central directory");
+    }
+    Ok((u64_at(&end, 32), u64_at(&end, 48)))
+}
+
+/// The uncompressed size in the zip64 field of an entry's `extra` fields, which comes first in it
+fn zip64_size(mut extra: &[u8]) -> Option<u64> {
+    const ZIP64_FIELD: u16 = 1;
+
+    while extra.len() >= 4 {
+        let len = usize::from(u16_at(extra, 2));
+        let data = extra.get(4..4 + len)?;
+        if u16_at(extra, 0) == ZIP64_FIELD && data.len() >= 8 {
+            return Some(u64_at(data, 0));
+        }
+        extra = &extra[4 + len..];
+    }
+    None
+}
+
+/// Tar data, read one header at a time
+trait TarStream: Read {
+    /// Move past `len` bytes of contents
+    fn skip(&mut self, len: u64) -> io::Result<()>;
+
+    /// Whether reading stopped at the most bytes allowed, before the end of the archive
+    fn capped(&self) -> bool {
+        false
+    }
+}
+
+impl TarStream for BufReader<File> {
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        let len =
+            i64::try_from(len).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.seek_relative(len)
+    }
+}
+
+/// Stops reading `inner` after `left` more bytes
+struct Capped<R> {
+    inner: R,
+    left: u64,
+}
+
+impl<R: Read> Read for Capped<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf
+            .len()
+            .min(usize::try_from(self.left).unwrap_or(usize::MAX));
+        let read = self.inner.read(&mut buf[..len])?;
+        self.left -= read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read> TarStream for Capped<R> {
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        // Compressed data cannot be skipped, only decompressed and thrown away
+        io::copy(&mut self.by_ref().take(len), &mut io::sink())?;
+        Ok(())
+    }
+
+    fn capped(&self) -> bool {
+        self.left == 0
+    }
+}
+
+/// The size in a tar header, either octal text or, for large sizes, base-256 marked by the
+/// high bit of the first byte
+fn tar_size(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        return field[1..]
+            .iter()
+            .try_fold(u64::from(field[0] & 0x7f), |size, &byte| {
+                size.checked_mul(256)?.checked_add(u64::from(byte))
+            });
+    }
+    let text = std::str::from_utf8(field).ok()?;
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+/// Read tar headers, skipping over the contents of each file
+fn list_tar(mut reader: impl TarStream, limit: usize) -> Result<Listing> {
+    const BLOCK: u64 = 512;
+
+    let mut entries = Vec::new();
+    let mut header = [0; BLOCK as usize];
+    loop {
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            // Some writers leave out the zeroed blocks at the end
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(Listing {
+                    entries,
+                    truncated: reader.capped(),
+                })
+            }
+            Err(err) => return Err(err.into()),
+        }
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        if entries.len() == limit {
+            return Ok(Listing {
+                entries,
+                truncated: true,
+            });
+        }
+        let field = |range: std::ops::Range<usize>| {
+            let bytes = &header[range];
+            let end = bytes
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..end]).into_owned()
+        };
+        // Reading on from a wrong size would take file contents for headers
+        let size = tar_size(&header[124..136]).context("corrupt tar header size")?;
+        let prefix = field(345..500);
+        let name = if prefix.is_empty() {
+            field(0..100)
+        } else {
+            format!("{prefix}/{}", field(0..100))
+        };
+        let is_dir = header[156] == b'5' || name.ends_with('/');
+        entries.push(Entry { name, size, is_dir });
+        // Contents are padded to whole blocks
+        let padded = size
+            .checked_add(BLOCK - 1)
+            .context("corrupt tar header size")?
+            / BLOCK
+            * BLOCK;
+        reader.skip(padded)?;
+    }
+    Ok(Listing {
+        entries,
+        truncated: false,
+    })
+}
+
+/// Decompresses a gzip stream as it is read
+struct GzReader<R> {
+    inner: R,
+    state: Box<InflateState>,
+    done: bool,
+}
+
+impl<R: BufRead> GzReader<R> {
+    fn new(mut inner: R) -> Result<Self> {
+        const EXTRA: u8 = 4;
+        const NAME: u8 = 8;
+        const COMMENT: u8 = 16;
+        const HEADER_CRC: u8 = 2;
+
+        let mut header = [0; 10];
+        inner.read_exact(&mut header)?;
+        if header[..3] != [0x1f, 0x8b, 8] {
+            bail!("not a gzip file");
+        }
+        let flags = header[3];
+        if flags & EXTRA != 0 {
+            let mut len = [0; 2];
+            inner.read_exact(&mut len)?;
+            io::copy(
+                &mut inner.by_ref().take(u64::from(u16::from_le_bytes(len))),
+                &mut io::sink(),
+            )?;
+        }
+        for flag in [NAME, COMMENT] {
+            if flags & flag != 0 {
+                inner.read_until(0, &mut Vec::new())?;
+            }
+        }
+        if flags & HEADER_CRC != 0 {
+            inner.read_exact(&mut [0; 2])?;
+        }
+        Ok(Self {
+            inner,
+            state: InflateState::new_boxed(DataFormat::Raw),
+            done: false,
+        })
+    }
+}
+
+impl<R: BufRead> Read for GzReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            let input = self.inner.fill_buf()?;
+            let eof = input.is_empty();
+            let flush = if eof { MZFlush::Finish } else { MZFlush::None };
+            let result = inflate(&mut self.state, input, buf, flush);
+            self.inner.consume(result.bytes_consumed);
+            match result.status {
+                Ok(MZStatus::StreamEnd) => self.done = true,
+                Ok(_) | Err(MZError::Buf) => {}
+                Err(err) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("corrupt gzip data: {err:?}"),
+                    ))
+                }
+            }
+            if result.bytes_written > 0 || self.done {
+                return Ok(result.bytes_written);
+            }
+            if eof {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use test_log::test;
+
+    const MAX: u64 = 1 << 20;
+
+    fn tar_header(name: &str, size: usize, kind: u8) -> Vec<u8> {
+        let mut header = vec![0; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        let size = format!("{size:011o}");
+        header[124..135].copy_from_slice(size.as_bytes());
+        header[156] = kind;
+        header
+    }
+
+    fn tar(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut tar = tar_header("dir/", 0, b'5');
+        for (name, contents) in files {
+            tar.extend(tar_header(name, contents.len(), b'0'));
+            let mut data = contents.as_bytes().to_vec();
+            data.resize((contents.len() + 511) / 512 * 512, 0);
+            tar.extend(data);
+        }
+        tar.extend([0; 1024]);
+        tar
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut gz = vec![0x1f, 0x8b, 8, 8, 0, 0, 0, 0, 0, 3];
+        gz.extend(b"name.tar\0");
+        gz.extend(miniz_oxide::deflate::compress_to_vec(data, 6));
+        // The checksum and length are not checked
+        gz.extend([0; 8]);
+        gz
+    }
+
+    fn zip(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = Vec::new();
+        let mut directory = Vec::new();
+        for (name, contents) in files {
+            let offset = zip.len() as u32;
+            zip.extend(0x0403_4b50_u32.to_le_bytes());
+            zip.extend([0; 14]);
+            zip.extend((contents.len() as u32).to_le_bytes());
+            zip.extend((contents.len() as u32).to_le_bytes());
+            zip.extend((name.len() as u16).to_le_bytes());
+            zip.extend([0; 2]);
+            zip.extend(name.as_bytes());
+            zip.extend(contents.as_bytes());
+
+            directory.extend(0x0201_4b50_u32.to_le_bytes());
+            directory.extend([0; 16]);
+            directory.extend((contents.len() as u32).to_le_bytes());
+            directory.extend((contents.len() as u32).to_le_bytes());
+            directory.extend((name.len() as u16).to_le_bytes());
+            directory.extend([0; 12]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+        let directory_start = zip.len() as u32;
+        zip.extend(&directory);
+        zip.extend(0x0605_4b50_u32.to_le_bytes());
+        zip.extend([0; 4]);
+        zip.extend((files.len() as u16).to_le_bytes());
+        zip.extend((files.len() as u16).to_le_bytes());
+        zip.extend((directory.len() as u32).to_le_bytes());
+        zip.extend(directory_start.to_le_bytes());
+        zip.extend(b"\x05\0hello");
+        zip
+    }
+
+    fn entry(name: &str, size: u64) -> Entry {
+        Entry {
+            name: name.to_owned(),
+            size,
+            is_dir: name.ends_with('/'),
+        }
+    }
+
+    #[test]
+    fn lists_zip_entries() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.child("test.zip");
+        archive
+            .write_binary(&zip(&[
+                ("a.txt", "hello"),
+                ("src/", ""),
+                ("src/b.rs", "fn"),
+            ]))
+            .unwrap();
+
+        let listing = list(archive.path(), 2, MAX).unwrap();
+        assert_eq!(vec![entry("a.txt", 5), entry("src/", 0)], listing.entries);
+        assert!(listing.truncated);
+    }
+
+    #[test]
+    fn lists_tar_entries() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.child("test.tar");
+        archive
+            .write_binary(&tar(&[("dir/a.txt", "hello"), ("dir/b.txt", "")]))
+            .unwrap();
+
+        let listing = list(archive.path(), 10, MAX).unwrap();
+        assert_eq!(
+            vec![
+                entry("dir/", 0),
+                entry("dir/a.txt", 5),
+                entry("dir/b.txt", 0)
+            ],
+            listing.entries
+        );
+        assert!(!listing.truncated);
+    }
+
+    #[test]
+    fn lists_gzipped_tar_entries() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.child("test.tar.gz");
+        let contents = "a".repeat(2000);
+        archive
+            .write_binary(&gzip(&tar(&[("dir/a.txt", &contents), ("dir/b.txt", "b")])))
+            .unwrap();
+
+        let listing = list(archive.path(), 10, MAX).unwrap();
+        assert_eq!(
+            vec![
+                entry("dir/", 0),
+                entry("dir/a.txt", 2000),
+                entry("dir/b.txt", 1)
+            ],
+            listing.entries
+        );
+    }
+
+    #[test]
+    fn decompresses_only_so_much_of_a_gzipped_tar() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.child("test.tgz");
+        let contents = "a".repeat(2000);
+        archive
+            .write_binary(&gzip(&tar(&[
+                ("dir/a.txt", &contents),
+                ("dir/b.txt", &contents),
+                ("dir/c.txt", &contents),
+            ])))
+            .unwrap();
+
+        // Enough for the directory, the first file and the header of the second
+        let listing = list(archive.path(), 10, 3 * 512 + 2048).unwrap();
+        assert_eq!(
+            vec![
+                entry("dir/", 0),
+                entry("dir/a.txt", 2000),
+                entry("dir/b.txt", 2000)
+            ],
+            listing.entries
+        );
+        assert!(listing.truncated);
+    }
+
+    #[test]
+    fn reads_base_256_tar_sizes() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.child("test.tar");
+        let mut header = tar_header("big.txt", 0, b'0');
+        header[124..136].copy_from_slice(&[0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02, 0x01]);
+        let mut data = header;
+        data.extend([b'a'; 1024]);
+        data.extend(tar_header("after.txt", 0, b'0'));
+        data.extend([0; 1024]);
+        archive.write_binary(&data).unwrap();
+
+        let listing = list(archive.path(), 10, MAX).unwrap();
+        assert_eq!(
+            vec![entry("big.txt", 513), entry("after.txt", 0)],
+            listing.entries
+        );
+        assert_eq!(None, tar_size(&[0xff; 12]));
+    }
+
+    #[test]
+    fn lists_zip64_entries() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.child("test.zip");
+        let name = "huge.bin";
+        let huge: u64 = 5 << 30;
+        let mut directory = Vec::new();
+        directory.extend(0x0201_4b50_u32.to_le_bytes());
+        directory.extend([0; 16]);
+        directory.extend(u32::MAX.to_le_bytes());
+        directory.extend(u32::MAX.to_le_bytes());
+        directory.extend((name.len() as u16).to_le_bytes());
+        directory.extend(24_u16.to_le_bytes());
+        directory.extend([0; 10]);
+        directory.extend(0_u32.to_le_bytes());
+        directory.extend(name.as_bytes());
+        // An unrelated extra field, then the zip64 one with both sizes
+        directory.extend(0x5455_u16.to_le_bytes());
+        directory.extend(0_u16.to_le_bytes());
+        directory.extend(1_u16.to_le_bytes());
+        directory.extend(16_u16.to_le_bytes());
+        directory.extend(huge.to_le_bytes());
+        directory.extend(huge.to_le_bytes());
+
+        let mut zip = directory.clone();
+        let zip64_end = zip.len() as u64;
+        zip.extend(0x0606_4b50_u32.to_le_bytes());
+        zip.extend([0; 28]);
+        zip.extend(1_u64.to_le_bytes());
+        zip.extend((directory.len() as u64).to_le_bytes());
+        zip.extend(0_u64.to_le_bytes());
+        zip.extend(0x0706_4b50_u32.to_le_bytes());
+        zip.extend([0; 4]);
+        zip.extend(zip64_end.to_le_bytes());
+        zip.extend(1_u32.to_le_bytes());
+        zip.extend(0x0605_4b50_u32.to_le_bytes());
+        zip.extend([0; 4]);
+        zip.extend(u16::MAX.to_le_bytes());
+        zip.extend(u16::MAX.to_le_bytes());
+        zip.extend(u32::MAX.to_le_bytes());
+        zip.extend(u32::MAX.to_le_bytes());
+        zip.extend([0; 2]);
+        archive.write_binary(&zip).unwrap();
+
+        let listing = list(archive.path(), 10, MAX).unwrap();
+        assert_eq!(vec![entry(name, huge)], listing.entries);
+        assert!(!listing.truncated);
+    }
+
+    #[test]
+    fn rejects_broken_archives() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.child("broken.zip");
+        archive.write_str("not a zip").unwrap();
+        assert!(list(archive.path(), 10, MAX).is_err());
+        assert!(!is_archive("file.gz"));
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt::Write;
+
+/// Longest author name shown, longer ones are cut off
+const MAX_AUTHOR_WIDTH: usize = 20;
+
+const COMMIT_COLOR: &str = "\x1b[33m";
+const AUTHOR_COLOR: &str = "\x1b[36m";
+const DATE_COLOR: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// Who last changed a line
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Line<'a> {
+    commit: &'a str,
+    author: &'a str,
+    date: String,
+    code: &'a str,
+}
+
+/// Turn the output of `git blame --line-porcelain` into colored columns of the short commit,
+/// author and date, followed by the line itself
+pub fn format(porcelain: &str) -> String {
+    let lines = parse(porcelain);
+    let width = lines
+        .iter()
+        .map(|line| line.author.chars().count())
+        .max()
+        .unwrap_or_default()
+        .min(MAX_AUTHOR_WIDTH);
+    let mut out = String::with_capacity(porcelain.len() / 4);
+    for line in lines {
+        let author: String = line.author.chars().take(width).collect();
+        writeln!(
+            out,
+            "{COMMIT_COLOR}{}{RESET} {AUTHOR_COLOR}{author:<width$}{RESET} {DATE_COLOR}{}{RESET} │ {}",
+            line.commit, line.date, line.code
+        )
+        .expect("string is writable");
+    }
+    out.truncate(out.trim_end().len());
+    out
+}
+
+fn parse(porcelain: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let (mut commit, mut author, mut time, mut tz) = ("", "", 0, "");
+    for line in porcelain.lines() {
+        if let Some(code) = line.strip_prefix('\t') {
+            lines.push(Line {
+                commit,
+                author,
+                date: format_date(time + tz_offset(tz)),
+                code,
+            });
+        } else if let Some(name) = line.strip_prefix("author ") {
+            author = name;
+        } else if let Some(secs) = line.strip_prefix("author-time ") {
+            time = secs.parse().unwrap_or_default();
+        } else if let Some(zone) = line.strip_prefix("author-tz ") {
+            tz = zone;
+        } else if let Some(sha) = line
+            .split(' ')
+            .next()
+            .filter(|sha| sha.len() == 40 && sha.bytes().all(|byte| byte.is_ascii_hexdigit()))
+        {
+            commit = &sha[..8];
+        }
+    }
+    lines
+}
+
+/// Seconds east of UTC of a zone like `+0130`
+fn tz_offset(tz: &str) -> i64 {
+    let (sign, digits) = match tz.split_at(tz.len().min(1)) {
+        ("-", digits) => (-1, digits),
+        (_, digits) => (1, digits),
+    };
+    let Ok(hhmm) = digits.parse::<i64>() else {
+        return 0;
+    };
+    sign * (hhmm / 100 * 3600 + hhmm % 100 * 60)
+}
+
+/// `YYYY-MM-DD` of a Unix timestamp, using Howard Hinnant's `civil_from_days`
+fn format_date(secs: i64) -> String {
+    let days = secs.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn dates_are_in_the_authors_zone() {
+        assert_eq!("1970-01-01", format_date(0));
+        assert_eq!("2000-02-29", format_date(951_782_400));
+        assert_eq!("2023-06-01", format_date(1_685_577_600));
+        assert_eq!(-5400, tz_offset("-0130"));
+        assert_eq!(7200, tz_offset("+0200"));
+    }
+
+    #[test]
+    fn formats_columns() {
+        let porcelain = concat!(
+            "0123456789abcdef0123456789abcdef01234567 1 1 1\n",
+            "author Ann\n",
+            "author-time 0\n",
+            "author-tz +0000\n",
+            "summary first\n",
+            "filename a.txt\n",
+            "\tfn main() {\n",
+            "fedcba9876543210fedcba9876543210fedcba98 2 2 1\n",
+            "author Someone Else\n",
+            "author-time 86400\n",
+            "author-tz -0100\n",
+            "filename a.txt\n",
+            "\t}\n",
+        );
+        assert_eq!(
+            format!(
+                "{COMMIT_COLOR}01234567{RESET} {AUTHOR_COLOR}Ann         {RESET} {DATE_COLOR}1970-01-01{RESET} │ fn main() {{\n\
+                 {COMMIT_COLOR}fedcba98{RESET} {AUTHOR_COLOR}Someone Else{RESET} {DATE_COLOR}1970-01-01{RESET} │ }}"
+            ),
+            format(porcelain)
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use anyhow::{Context, Result};
+use globset::Glob;
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+use toml::{Table, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TargetKind {
+    Bin,
+    Example,
+    Test,
+    Lib,
+}
+
+/// Something `cargo` can run or test
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Target {
+    pub package: String,
+    pub kind: TargetKind,
+    pub name: String,
+}
+
+impl Target {
+    /// The command running the target, naming its package when there are several
+    pub fn command(&self, with_package: bool) -> String {
+        let package = if with_package {
+            format!(" -p {}", self.package)
+        } else {
+            String::new()
+        };
+        match self.kind {
+            TargetKind::Bin => format!("cargo run{package} --bin {}", self.name),
+            TargetKind::Example => format!("cargo run{package} --example {}", self.name),
+            TargetKind::Test => format!("cargo test{package} --test {}", self.name),
+            TargetKind::Lib => format!("cargo test{package} --lib"),
+        }
+    }
+}
+
+/// Commands for the targets of the package or workspace at `root`, empty if there is none
+pub fn target_commands(root: &Path) -> Result<Vec<String>> {
+    let manifest = root.join("Cargo.toml");
+    if !manifest.is_file() {
+        return Ok(Vec::new());
+    }
+    let manifest = read_manifest(&manifest)?;
+    let mut targets = package_targets(root, &manifest);
+    let mut packages = usize::from(manifest.contains_key("package"));
+    for member in workspace_members(root, &manifest) {
+        let path = member.join("Cargo.toml");
+        if member == root || !path.is_file() {
+            continue;
+        }
+        targets.extend(package_targets(&member, &read_manifest(&path)?));
+        packages += 1;
+    }
+    targets.sort();
+    targets.dedup();
+    Ok(targets
+        .iter()
+        .map(|target| target.command(packages > 1))
+        .collect())
+}
+
+fn read_manifest(path: &Path) -> Result<Table> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("could not read \"{}\"", path.display()))?;
+    toml::from_str(&contents).with_context(|| format!("invalid manifest \"{}\"", path.display()))
+}
+
+/// The targets listed in the manifest, along with the ones cargo finds by itself
+fn package_targets(dir: &Path, manifest: &Table) -> Vec<Target> {
+    let Some(package) = manifest.get("package").and_then(Value::as_table) else {
+        return Vec::new();
+    };
+    let Some(name) = package.get("name").and_then(Value::as_str) else {
+        return Vec::new();
+    };
+    let auto = |key: &str| package.get(key).and_then(Value::as_bool).unwrap_or(true);
+    let target = |kind, target_name: String| Target {
+        package: name.to_owned(),
+        kind,
+        name: target_name,
+    };
+
+    let mut targets = Vec::new();
+    for (kind, key, auto_key, auto_dir) in [
+        (TargetKind::Bin, "bin", "autobins", dir.join("src/bin")),
+        (
+            TargetKind::Example,
+            "example",
+            "autoexamples",
+            dir.join("examples"),
+        ),
+        (TargetKind::Test, "test", "autotests", dir.join("tests")),
+    ] {
+        let listed = manifest
+            .get(key)
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|target| target.get("name")?.as_str());
+        targets.extend(listed.map(|listed| target(kind, listed.to_owned())));
+        if auto(auto_key) {
+            targets.extend(
+                found_targets(&auto_dir)
+                    .into_iter()
+                    .map(|found| target(kind, found)),
+            );
+        }
+    }
+    if auto("autobins") && dir.join("src/main.rs").is_file() {
+        targets.push(target(TargetKind::Bin, name.to_owned()));
+    }
+    if manifest.contains_key("lib") || dir.join("src/lib.rs").is_file() {
+        targets.push(target(TargetKind::Lib, name.to_owned()));
+    }
+    targets
+}
+
+/// Names of the `name.rs` files and `name/main.rs` directories in `dir`
+fn found_targets(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let is_target = if path.is_dir() {
+                path.join("main.rs").is_file()
+            } else {
+                path.extension().is_some_and(|ext| ext == "rs")
+            };
+            if !is_target {
+                return None;
+            }
+            path.file_stem()?.to_str().map(str::to_owned)
+        })
+        .collect()
+}
+
+/// Directories of the workspace members, with globs in `members` expanded
+fn workspace_members(root: &Path, manifest: &Table) -> Vec<PathBuf> {
+    let Some(workspace) = manifest.get("workspace").and_then(Value::as_table) else {
+        return Vec::new();
+    };
+    let patterns = |key| {
+        workspace
+            .get(key)
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+    };
+    let excluded: Vec<PathBuf> = patterns("exclude").map(|path| root.join(path)).collect();
+    patterns("members")
+        .flat_map(|pattern| expand(root, Path::new(pattern)))
+        .filter(|member| !excluded.contains(member))
+        .collect()
+}
+
+/// The directories matching `pattern` relative to `dir`, globbing one component at a time
+fn expand(dir: &Path, pattern: &Path) -> Vec<PathBuf> {
+    let mut components = pattern.components();
+    let Some(first) = components.next() else {
+        return vec![dir.to_path_buf()];
+    };
+    let rest = components.as_path();
+    let Component::Normal(first) = first else {
+        return expand(&dir.join(first), rest);
+    };
+    let first = first.to_string_lossy();
+    if !first.contains(['*', '?', '[', '{']) {
+        return expand(&dir.join(&*first), rest);
+    }
+    let (Ok(glob), Ok(entries)) = (Glob::new(&first), fs::read_dir(dir)) else {
+        return Vec::new();
+    };
+    let glob = glob.compile_matcher();
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir() && glob.is_match(entry.file_name()))
+        .flat_map(|entry| expand(&entry.path(), rest))
+        .collect()
+}
+
+/// Whether a change to `path` could change the targets
+pub fn affects_targets(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == "Cargo.toml")
+        || path.extension().is_some_and(|ext| ext == "rs")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use test_log::test;
+
+    #[test]
+    fn finds_targets_of_a_package() {
+        let temp = TempDir::new().unwrap();
+        temp.child("Cargo.toml")
+            .write_str("[package]\nname = \"app\"\n\n[[example]]\nname = \"listed\"\n")
+            .unwrap();
+        for file in [
+            "src/main.rs",
+            "src/lib.rs",
+            "src/bin/tool.rs",
+            "src/bin/other/main.rs",
+            "tests/integration.rs",
+        ] {
+            temp.child(file).touch().unwrap();
+        }
+        assert_eq!(
+            vec![
+                "cargo run --bin app",
+                "cargo run --bin other",
+                "cargo run --bin tool",
+                "cargo run --example listed",
+                "cargo test --test integration",
+                "cargo test --lib",
+            ],
+            target_commands(temp.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn workspace_members_are_named() {
+        let temp = TempDir::new().unwrap();
+        temp.child("Cargo.toml")
+            .write_str("[workspace]\nmembers = [\"crates/*\"]\nexclude = [\"crates/skip\"]\n")
+            .unwrap();
+        for name in ["one", "two", "skip"] {
+            temp.child(format!("crates/{name}/Cargo.toml"))
+                .write_str(&format!("[package]\nname = \"{name}\"\n"))
+                .unwrap();
+            temp.child(format!("crates/{name}/src/main.rs"))
+                .touch()
+                .unwrap();
+        }
+        assert_eq!(
+            vec!["cargo run -p one --bin one", "cargo run -p two --bin two"],
+            target_commands(temp.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn nothing_without_a_manifest() {
+        let temp = TempDir::new().unwrap();
+        assert!(target_commands(temp.path()).unwrap().is_empty());
+    }
+}
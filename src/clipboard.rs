@@ -0,0 +1,134 @@
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use duct::cmd;
+use std::{
+    env,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// Largest amount of bytes that can be copied at once. Many terminals silently drop OSC 52
+/// sequences longer than 100,000 bytes, which this stays under once encoded.
+pub const MAX_SIZE: usize = 74_994;
+
+/// Put `text` on the system clipboard with an OSC 52 escape sequence, which also works over SSH.
+/// Inside tmux, this needs `set-clipboard on`.
+pub fn copy(text: &str) -> Result<()> {
+    if text.len() > MAX_SIZE {
+        bail!(
+            "{} bytes is too much to copy, the limit is {MAX_SIZE}",
+            text.len()
+        );
+    }
+    let mut stdout = io::stdout();
+    write!(stdout, "{}", osc52(text)).context("failed to write to clipboard")?;
+    stdout.flush().context("failed to write to clipboard")
+}
+
+fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+/// Programs that print the clipboard, tried in order
+#[cfg(target_os = "macos")]
+const PASTE_COMMANDS: &[&[&str]] = &[&["pbpaste"]];
+#[cfg(target_os = "windows")]
+const PASTE_COMMANDS: &[&[&str]] = &[&["powershell", "-NoProfile", "-Command", "Get-Clipboard"]];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const PASTE_COMMANDS: &[&[&str]] = &[
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-out"],
+    &["xsel", "--clipboard", "--output"],
+];
+
+/// Read the system clipboard. Terminals rarely let programs read it the way OSC 52 writes it, so
+/// this runs the first clipboard tool that works, falling back to the tmux buffer inside tmux.
+pub fn paste() -> Result<String> {
+    let tmux = env::var_os("TMUX").map(|_| &["tmux", "save-buffer", "-"][..]);
+    for command in PASTE_COMMANDS.iter().copied().chain(tmux) {
+        let output = cmd(command[0], &command[1..])
+            .stdout_capture()
+            .stderr_null()
+            .read();
+        if let Ok(text) = output {
+            if !text.trim().is_empty() {
+                return Ok(text);
+            }
+        }
+    }
+    bail!("could not read the clipboard, nothing on it or no clipboard tool found")
+}
+
+/// The path of a reference like `src/main.rs:12:5`, along with its line and column if it has
+/// them
+pub fn path_reference(text: &str) -> Option<(PathBuf, Option<usize>, Option<usize>)> {
+    let text = text
+        .lines()
+        .next()?
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'')
+        .trim_end_matches(':');
+    let mut path = text.strip_prefix("file://").unwrap_or(text);
+    // Taken from the end, so the column comes first if both are there
+    let mut numbers = Vec::new();
+    for _ in 0..2 {
+        match path.rsplit_once(':') {
+            Some((rest, number))
+                if !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit()) =>
+            {
+                numbers.push(number.parse().ok().filter(|&number: &usize| number > 0));
+                path = rest;
+            }
+            _ => break,
+        }
+    }
+    let (line, column) = match numbers[..] {
+        [column, line] => (line, column.filter(|_| line.is_some())),
+        [line] => (line, None),
+        _ => (None, None),
+    };
+    (!path.is_empty()).then(|| (PathBuf::from(path), line, column))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn osc52_wraps_encoded_text() {
+        assert_eq!("\x1b]52;c;aGk=\x07", osc52("hi"));
+    }
+
+    #[test]
+    fn largest_copy_fits_in_terminal_limit() {
+        assert!(osc52(&"a".repeat(MAX_SIZE)).len() <= 100_000);
+    }
+
+    #[test]
+    fn path_reference_splits_off_line_and_column() {
+        assert_eq!(
+            Some((PathBuf::from("src/main.rs"), Some(12), Some(5))),
+            path_reference("src/main.rs:12:5\n")
+        );
+        assert_eq!(
+            Some((PathBuf::from("/abs/file.txt"), Some(3), None)),
+            path_reference("\"/abs/file.txt:3:\"")
+        );
+        assert_eq!(
+            Some((PathBuf::from("/abs/file.txt"), None, None)),
+            path_reference("file:///abs/file.txt")
+        );
+        assert_eq!(
+            Some((PathBuf::from("dir/"), None, None)),
+            path_reference("  dir/  ")
+        );
+        assert_eq!(None, path_reference(":12"));
+        assert_eq!(None, path_reference(""));
+    }
+
+    #[test]
+    fn refuses_text_over_limit() {
+        assert!(copy(&"a".repeat(MAX_SIZE + 1)).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+use itertools::Itertools;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The directory a command runs in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommandDir {
+    /// Wherever projectable was started
+    #[default]
+    Inherit,
+    /// The root of the project
+    Root,
+    /// The directory the selected item is in
+    #[serde(alias = "selected_dir")]
+    SelectedDir,
+    /// The selected directory, or the parent of a selected file
+    Selected,
+}
+
+impl CommandDir {
+    /// The directory to run in, or `None` to keep projectable's own. Falls back to `root` when
+    /// nothing is selected.
+    pub fn resolve(self, root: &Path, selected: Option<&Path>) -> Option<PathBuf> {
+        let selected = selected.unwrap_or(root);
+        let dir = match self {
+            Self::Inherit => return None,
+            Self::Root => root,
+            Self::SelectedDir if selected == root => root,
+            Self::Selected if selected.is_dir() => selected,
+            Self::SelectedDir | Self::Selected => selected.parent().unwrap_or(root),
+        };
+        Some(dir.to_path_buf())
+    }
+}
+
+/// How a bound command runs, instead of the configured `command_dir` and `command_timeout`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunOptions {
+    pub dir: Option<CommandDir>,
+    /// Seconds it may run for before it is killed, 0 for no limit
+    pub timeout: Option<u64>,
+}
+
+impl RunOptions {
+    pub fn in_dir(dir: CommandDir) -> Self {
+        Self {
+            dir: Some(dir),
+            timeout: None,
+        }
+    }
+}
+
+/// Values filled into the `{placeholders}` of a command
+#[derive(Debug, Clone, Copy)]
+pub struct Placeholders<'a> {
+    pub root: &'a Path,
+    pub selected: Option<&'a Path>,
+    /// Every selected item, for `{paths}`
+    pub paths: &'a [PathBuf],
+    /// Filled into `{}`, which is left alone without it
+    pub file: Option<&'a Path>,
+}
+
+impl Placeholders<'_> {
+    fn value(&self, name: &str) -> Option<String> {
+        let selected = || {
+            if self.selected.is_none() {
+                warn!("nothing is selected to fill in {{{name}}}");
+            }
+            self.selected
+        };
+        let value = match name {
+            "root" => quote(self.root),
+            "path" => quote(selected()?),
+            "name" => quote(selected()?.file_name()?),
+            "dir" => {
+                let selected = selected()?;
+                if selected.is_dir() {
+                    quote(selected)
+                } else {
+                    quote(selected.parent().unwrap_or(self.root))
+                }
+            }
+            "paths" => self.paths.iter().map(quote).join(" "),
+            _ => {
+                warn!("unknown placeholder {{{name}}} left as is");
+                return None;
+            }
+        };
+        Some(value)
+    }
+}
+
+/// Replace the placeholders in `cmd`. `{...}`, shell syntax like `${VAR}` and `{}`, unless there
+/// is a file for it, are left alone
+pub fn substitute(cmd: &str, placeholders: &Placeholders) -> String {
+    let mut out = String::with_capacity(cmd.len());
+    let mut rest = cmd;
+    while let Some(start) = rest.find('{') {
+        let (before, after) = rest.split_at(start);
+        out.push_str(before);
+        if let Some(file) = placeholders.file.filter(|_| after.starts_with("{}")) {
+            out.push_str(&quote(file));
+            rest = &after[2..];
+            continue;
+        }
+        let name = after[1..].find('}').map(|end| &after[1..=end]);
+        match name {
+            Some(name)
+                if !name.is_empty()
+                    && !before.ends_with('$')
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+            {
+                let value = placeholders.value(name);
+                out.push_str(value.as_deref().unwrap_or(&after[..name.len() + 2]));
+                rest = &after[name.len() + 2..];
+            }
+            _ => {
+                out.push('{');
+                rest = &after[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Replace `{}` in `cmd` with the quoted `path`
+pub fn fill_path(cmd: &str, path: impl AsRef<Path>) -> String {
+    cmd.replace("{}", &quote(path))
+}
+
+/// Quote `path` so the shell sees it as a single argument
+pub fn quote(path: impl AsRef<Path>) -> String {
+    let path = path.as_ref().to_string_lossy();
+    #[cfg(not(target_os = "windows"))]
+    return format!("'{}'", path.replace('\'', r"'\''"));
+    #[cfg(target_os = "windows")]
+    return format!("\"{path}\"");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn substitute_with(cmd: &str, selected: &str) -> String {
+        let selected = PathBuf::from(selected);
+        let paths = [selected.clone(), PathBuf::from("/root/b c.txt")];
+        substitute(
+            cmd,
+            &Placeholders {
+                root: Path::new("/root"),
+                selected: Some(&selected),
+                paths: &paths,
+                file: None,
+            },
+        )
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn replaces_every_placeholder() {
+        assert_eq!(
+            "cp '/root/src/a b.rs' '/root/src' 'a b.rs' '/root'",
+            substitute_with("cp {path} {dir} {name} {root}", "/root/src/a b.rs")
+        );
+        assert_eq!(
+            "cat '/root/a.rs' '/root/b c.txt'",
+            substitute_with("cat {paths}", "/root/a.rs")
+        );
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn quotes_single_quotes() {
+        assert_eq!(r"'it'\''s'", quote("it's"));
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn filled_paths_reach_the_shell_unchanged() {
+        for path in [
+            "/root/My Project/a.txt",
+            "/root/it's \"quoted\".txt",
+            "/root/*.rs [a-z]? {x,y}",
+            "/root/$HOME `ls` ; rm -rf ~",
+        ] {
+            let cmd = fill_path("printf %s {}", path);
+            let out = duct::cmd!("sh", "-c", cmd).read().unwrap();
+            assert_eq!(path, out);
+        }
+    }
+
+    #[test]
+    fn fill_path_leaves_prompts_alone() {
+        assert_eq!(
+            format!("cargo add {{...}} --manifest-path {}", quote("Cargo.toml")),
+            fill_path("cargo add {...} --manifest-path {}", "Cargo.toml")
+        );
+    }
+
+    #[test]
+    fn leaves_other_braces_alone() {
+        for cmd in [
+            "echo {}",
+            "cargo add {...}",
+            "echo ${HOME}",
+            "awk '{print $1}'",
+            "echo {unknown}",
+            "echo {",
+            "echo }{",
+        ] {
+            assert_eq!(cmd, substitute_with(cmd, "/root/a.rs"));
+        }
+    }
+
+    #[test]
+    fn leaves_selection_placeholders_without_selection() {
+        let placeholders = Placeholders {
+            root: Path::new("/root"),
+            selected: None,
+            paths: &[],
+            file: None,
+        };
+        assert_eq!("echo {path}", substitute("echo {path}", &placeholders));
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn file_is_filled_in_once() {
+        let file = Path::new("/root/a{}b.txt");
+        let placeholders = Placeholders {
+            root: Path::new("/root"),
+            selected: Some(file),
+            paths: &[],
+            file: Some(file),
+        };
+        assert_eq!(
+            "cp '/root/a{}b.txt' 'a{}b.txt'.bak",
+            substitute("cp {} {name}.bak", &placeholders)
+        );
+    }
+
+    #[test]
+    fn command_dirs_follow_selection() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = temp.path();
+        let sub = root.join("sub");
+        let file = sub.join("file.txt");
+        std::fs::create_dir(&sub).unwrap();
+        let dir = |dir: CommandDir, selected: &Path| dir.resolve(root, Some(selected));
+        assert_eq!(None, dir(CommandDir::Inherit, &file));
+        assert_eq!(Some(root.to_path_buf()), dir(CommandDir::Root, &file));
+        assert_eq!(Some(sub.clone()), dir(CommandDir::SelectedDir, &file));
+        assert_eq!(Some(sub.clone()), dir(CommandDir::Selected, &file));
+        // A selected directory is run in, instead of the one it is in
+        assert_eq!(Some(sub.clone()), dir(CommandDir::Selected, &sub));
+        assert_eq!(Some(root.to_path_buf()), dir(CommandDir::SelectedDir, &sub));
+        assert_eq!(
+            Some(root.to_path_buf()),
+            CommandDir::Selected.resolve(root, None)
+        );
+    }
+}
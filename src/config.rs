@@ -1,4 +1,9 @@
-use anyhow::Error;
+use crate::{
+    command::{CommandDir, RunOptions},
+    filelisting::SortMode,
+    queue::TmuxCommand,
+};
+use anyhow::{anyhow, Context, Error};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use either::Either;
 use globset::{Glob, GlobSet, GlobSetBuilder};
@@ -22,8 +27,10 @@ use std::{
     collections::{hash_map::Entry, HashMap},
     env,
     fmt::{self, Display},
+    fs,
     path::{Path, PathBuf},
     str::FromStr,
+    sync::OnceLock,
 };
 use strum::Display;
 use tui::style::{Color as TuiColor, Modifier as TuiModifier, Style as TuiStyle};
@@ -44,6 +51,75 @@ pub fn get_config_home() -> Option<PathBuf> {
     Some(dir.join("projectable"))
 }
 
+/// The config written by `--write-config`, listing every option with its default
+#[cfg(not(target_os = "windows"))]
+pub const DEFAULT_CONFIG: &str = include_str!("./config_defaults/unix.toml");
+#[cfg(target_os = "windows")]
+pub const DEFAULT_CONFIG: &str = include_str!("./config_defaults/windows.toml");
+
+/// The config every project reads, whether or not it exists
+pub fn global_config_file() -> Option<PathBuf> {
+    get_config_home().map(|dir| dir.join("config.toml"))
+}
+
+/// Gets the local configuration file. Errors if the current directory is invalid, and returns
+/// `None` if there was no `.projectable.toml` found.
+pub fn find_local_config() -> Result<Option<PathBuf>, Error> {
+    let start = env::current_dir()?;
+    Ok(start.ancestors().find_map(|path| {
+        let new_path = path.join(".projectable.toml");
+        if new_path.exists() {
+            Some(new_path)
+        } else {
+            None
+        }
+    }))
+}
+
+/// A config along with the keys in its file that were not recognized
+#[derive(Debug)]
+pub struct LoadedConfig {
+    pub config: Config,
+    pub unknown_keys: Vec<String>,
+}
+
+/// Parse the contents of the config file at `path`. Unknown keys are collected instead of
+/// rejected, so configs written for newer versions still load.
+pub fn parse(contents: &str, path: impl AsRef<Path>) -> Result<LoadedConfig, Error> {
+    let mut unknown_keys = Vec::new();
+    let mut track = serde_path_to_error::Track::new();
+    let deserializer =
+        serde_path_to_error::Deserializer::new(toml::Deserializer::new(contents), &mut track);
+    let config = serde_ignored::deserialize(deserializer, |key| {
+        unknown_keys.push(key.to_string());
+    })
+    .map_err(|err| {
+        let path = path.as_ref().display();
+        let err = err.to_string();
+        match track.path().to_string() {
+            // The path is "." when the error is not inside of any key, like a syntax error
+            key if key == "." => anyhow!("invalid config file \"{path}\"\n{}", err.trim_end()),
+            key => anyhow!(
+                "invalid config file \"{path}\" at key `{key}`\n{}",
+                err.trim_end()
+            ),
+        }
+    })?;
+
+    Ok(LoadedConfig {
+        config,
+        unknown_keys,
+    })
+}
+
+/// Read and parse the config file at `path`
+pub fn load(path: impl AsRef<Path>) -> Result<LoadedConfig, Error> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file \"{}\"", path.display()))?;
+    parse(&contents, path)
+}
+
 pub trait Merge<Other = Self> {
     fn merge(&mut self, other: Other);
 }
@@ -77,6 +153,12 @@ pub enum Action<'a> {
     Help,
     PreviewDown,
     PreviewUp,
+    PreviewPin,
+    PreviewCopyVisible,
+    PreviewRaw,
+    PreviewSearch,
+    PreviewJump,
+    PreviewOpenAtLine,
     Down,
     Up,
     AllUp,
@@ -86,6 +168,7 @@ pub enum Action<'a> {
     FiletreeDownThree,
     FiletreeUpThree,
     FiletreeExecCmd,
+    FiletreeBatchCmd,
     FiletreeDelete,
     FiletreeSearch,
     FiletreeClear,
@@ -93,14 +176,53 @@ pub enum Action<'a> {
     FiletreeNewDir,
     FiletreeGitFilter,
     FiletreeDiffMode,
+    FiletreeStagedDiffMode,
+    FiletreeBlameMode,
+    FiletreeShowAtRef,
+    FiletreeStage,
+    FiletreeUnstage,
+    FiletreeStageAll,
     FiletreeSpecialCommand,
+    FiletreeRunLastSpecialCommand,
+    FiletreeCenterSelected,
+    FiletreeSelectedToTop,
+    FiletreeSelectedToBottom,
     FiletreeMarkSelected,
+    FiletreeToggleMark,
+    LogFocus,
+    LogExport,
     FiletreeCloseUnder,
     FiletreeOpenUnder,
     FiletreeShowDotfiles,
+    FiletreeShowIgnored,
+    FiletreePastePath,
     FiletreeRename,
     FiletreeMove,
+    FiletreeCopyContents,
+    FiletreeCopyPaths,
+    FiletreeCopyName,
+    FiletreeCopyStem,
+    FiletreeCopyExtension,
+    FiletreeContentSearch,
+    FiletreeQuickFilter,
+    FiletreeNextDir,
+    FiletreePrevDir,
+    FiletreeFullRefresh,
+    FiletreeExpandMatching,
+    FiletreeMultiSelect,
+    FiletreeGoto,
+    FiletreeToggleDirsFirst,
+    FiletreeToggleReverse,
+    FiletreeExtensionFilter,
+    FiletreeRecentDirs,
+    FiletreeFlatMode,
     KillProcesses,
+    RunningCommands,
+    CargoTargets,
+    ScratchCommand,
+    DryRun,
+    CycleFocus,
+    OpenConfig,
     Arbitrary(&'a str),
 }
 
@@ -111,6 +233,17 @@ impl GlobList {
     pub fn is_match<P: AsRef<Path>>(&self, path: P) -> bool {
         self.0.is_match(path)
     }
+
+    /// The closest directory at or above `start` with an entry matching a glob
+    pub fn find_root(&self, start: &Path) -> Option<PathBuf> {
+        start.ancestors().find_map(|path| {
+            fs::read_dir(path)
+                .ok()?
+                .filter_map(|entry| entry.ok())
+                .any(|entry| self.is_match(entry.path()))
+                .then(|| path.to_path_buf())
+        })
+    }
 }
 
 impl Default for GlobList {
@@ -167,8 +300,51 @@ impl Serialize for GlobList {
     }
 }
 
+/// A command bound to a key, either on its own or with the directory it runs in and how long
+/// it may run for
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum KeyCommand {
+    Cmd(String),
+    WithDir {
+        cmd: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        dir: Option<CommandDir>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout: Option<u64>,
+    },
+}
+
+impl KeyCommand {
+    pub fn cmd(&self) -> &str {
+        match self {
+            Self::Cmd(cmd) | Self::WithDir { cmd, .. } => cmd,
+        }
+    }
+
+    pub fn dir(&self) -> Option<CommandDir> {
+        self.options().dir
+    }
+
+    pub fn options(&self) -> RunOptions {
+        match self {
+            Self::Cmd(_) => RunOptions::default(),
+            Self::WithDir { dir, timeout, .. } => RunOptions {
+                dir: *dir,
+                timeout: *timeout,
+            },
+        }
+    }
+}
+
+impl From<String> for KeyCommand {
+    fn from(cmd: String) -> Self {
+        Self::Cmd(cmd)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields, default)]
+#[serde(default)]
 pub struct Config {
     pub quit: KeyBind,
     pub help: KeyBind,
@@ -178,24 +354,96 @@ pub struct Config {
     pub all_up: KeyBind,
     pub open: KeyBind,
     pub kill_processes: KeyBind,
+    /// Opens the list of commands running in the background
+    pub running_commands: KeyBind,
+    /// Picks a Cargo target of the project to run or test
+    pub cargo_targets: KeyBind,
+    /// Runs a command at the root and shows its output in the preview
+    pub scratch_command: KeyBind,
+    /// Kills the selected command in the running commands list
+    pub kill_command: KeyBind,
+    /// Toggles only logging destructive actions instead of doing them
+    pub dry_run: KeyBind,
+    /// Moves the keys from the tree to the preview, then the log, then back
+    pub cycle_focus: KeyBind,
+    /// Opens the global config in the editor, then the project config if there is one
+    pub open_config: KeyBind,
+    /// Copies the error shown in the error popup
+    pub copy_error: KeyBind,
+    /// Move a page through long lists in popups
+    pub page_down: KeyBind,
+    pub page_up: KeyBind,
+    /// Previews the highlighted result of the fuzzy finder, following the highlight after
+    pub fuzzy_preview: KeyBind,
     pub special_commands: HashMap<String, Vec<String>>,
-    pub commands: HashMap<Key, String>,
+    /// Commands opening files matching a glob, instead of the editor
+    pub open_with: HashMap<String, String>,
+    pub commands: HashMap<Key, KeyCommand>,
+    /// Commands run in tmux, with a window kind, split size and target pane
+    pub tmux_commands: HashMap<Key, TmuxCommand>,
     pub project_roots: GlobList,
+    /// Quit with esc, once the tree has no multi-selection, filter or search left to clear
     pub esc_to_close: bool,
+    pub show_hints: bool,
+    /// Capture the mouse to click and scroll, at the cost of the terminal's own text selection
+    pub mouse: bool,
+    /// Colors the terminal can show, configured colors are brought down to them
+    pub color_mode: ColorMode,
+    /// Show errors in full in a popup, instead of a toast
+    pub error_popup: bool,
+    /// Overrides `$VISUAL` and `$EDITOR` for opening files
+    pub editor: Option<String>,
+    /// Arguments given to the editor when opening a file at a line
+    pub editor_line_args: String,
+    /// Arguments in place of `editor_line_args` when there is a column too, the column is left
+    /// out when unset
+    pub editor_column_args: Option<String>,
+    /// Whether the editor opens several files in one run, guessed from its name when unset
+    pub editor_multi_file: Option<bool>,
+    /// Most bytes of output kept from a background command, the rest is dropped from the start
+    pub max_command_output: usize,
+    /// Seconds a background or scratch command may run for before it is killed, 0 for no limit
+    pub command_timeout: u64,
+    /// Most commands of a batch running at once, 1 runs them one after another
+    pub batch_jobs: usize,
+    /// Reopen the fuzzy finder with the query and selection it was last closed with
+    pub remember_fuzzy: bool,
+    /// How the fuzzy finder shows the paths of files
+    pub fuzzy_paths: FuzzyPaths,
+    /// Where commands run, unless their binding says otherwise
+    pub command_dir: CommandDir,
+    /// Open the root of the last launch when no directory is given
+    pub reopen_last: bool,
+    /// Operations on more items than this always ask for confirmation first
+    pub confirm_threshold: usize,
+    /// Seconds without input after which the session is saved, 0 only saves on exit
+    pub autosave_interval: u64,
+    /// Key standing in for `<leader>` in sequences
+    pub leader: Option<Key>,
+    /// Milliseconds to type the rest of a sequence before it is dropped
+    pub sequence_timeout: u64,
+    /// Actions run by typing several keys in a row, like `"<leader> f f" = "filetree_search"`
+    pub sequences: HashMap<KeySequence, String>,
 
     pub selected: Style,
+    /// Drawn before the selected row of the tree and of popup lists
+    pub selected_symbol: String,
     pub popup_border_style: Style,
     pub help_key_style: Style,
+    pub dry_run_style: Style,
 
     pub preview: PreviewConfig,
     pub filetree: FiletreeConfig,
     pub log: LogConfig,
     pub marks: MarksConfig,
+    pub toast: ToastConfig,
+    pub popup: PopupConfig,
 }
 
 impl Config {
-    pub fn check_conflicts(&self) -> Vec<KeyConflict> {
-        let keybinds = [
+    /// The actions bound to a single key, everywhere outside of popups
+    fn keybinds(&self) -> Vec<(Action<'_>, &KeyBind)> {
+        vec![
             (Action::Quit, &self.quit),
             (Action::Help, &self.help),
             (Action::Down, &self.down),
@@ -205,9 +453,16 @@ impl Config {
             (Action::AllUp, &self.all_up),
             (Action::PreviewDown, &self.preview.down_key),
             (Action::PreviewUp, &self.preview.up_key),
+            (Action::PreviewPin, &self.preview.pin),
+            (Action::PreviewCopyVisible, &self.preview.copy_visible),
+            (Action::PreviewRaw, &self.preview.raw),
+            (Action::PreviewSearch, &self.preview.search),
+            (Action::PreviewJump, &self.preview.jump),
+            (Action::PreviewOpenAtLine, &self.preview.open_at_line),
             (Action::FiletreeUpThree, &self.filetree.up_three),
             (Action::FiletreeDownThree, &self.filetree.down_three),
             (Action::FiletreeExecCmd, &self.filetree.exec_cmd),
+            (Action::FiletreeBatchCmd, &self.filetree.batch_cmd),
             (Action::FiletreeDelete, &self.filetree.delete),
             (Action::FiletreeSearch, &self.filetree.search),
             (Action::FiletreeClear, &self.filetree.clear),
@@ -215,19 +470,126 @@ impl Config {
             (Action::FiletreeNewDir, &self.filetree.new_dir),
             (Action::FiletreeGitFilter, &self.filetree.git_filter),
             (Action::FiletreeDiffMode, &self.filetree.diff_mode),
+            (
+                Action::FiletreeStagedDiffMode,
+                &self.filetree.staged_diff_mode,
+            ),
+            (Action::FiletreeBlameMode, &self.filetree.blame_mode),
+            (Action::FiletreeShowAtRef, &self.filetree.show_at_ref),
+            (Action::FiletreeStage, &self.filetree.stage),
+            (Action::FiletreeUnstage, &self.filetree.unstage),
+            (Action::FiletreeStageAll, &self.filetree.stage_all),
             (
                 Action::FiletreeSpecialCommand,
                 &self.filetree.special_command,
             ),
+            (
+                Action::FiletreeRunLastSpecialCommand,
+                &self.filetree.run_last_special_command,
+            ),
+            (
+                Action::FiletreeCenterSelected,
+                &self.filetree.center_selected,
+            ),
+            (
+                Action::FiletreeSelectedToTop,
+                &self.filetree.selected_to_top,
+            ),
+            (
+                Action::FiletreeSelectedToBottom,
+                &self.filetree.selected_to_bottom,
+            ),
             (Action::FiletreeMarkSelected, &self.filetree.mark_selected),
+            (Action::FiletreeToggleMark, &self.filetree.toggle_mark),
             (Action::OpenMarks, &self.marks.open),
             (Action::FiletreeOpenUnder, &self.filetree.open_under),
             (Action::FiletreeCloseUnder, &self.filetree.close_under),
             (Action::FiletreeRename, &self.filetree.rename),
             (Action::FiletreeShowDotfiles, &self.filetree.show_dotfiles),
+            (Action::FiletreeShowIgnored, &self.filetree.show_ignored),
             (Action::FiletreeMove, &self.filetree.move_path),
+            (Action::FiletreeCopyContents, &self.filetree.copy_contents),
+            (Action::FiletreeCopyPaths, &self.filetree.copy_paths),
+            (Action::FiletreeCopyName, &self.filetree.copy_name),
+            (Action::FiletreeCopyStem, &self.filetree.copy_stem),
+            (Action::FiletreeCopyExtension, &self.filetree.copy_extension),
+            (Action::FiletreePastePath, &self.filetree.paste_path),
+            (Action::FiletreeContentSearch, &self.filetree.content_search),
+            (Action::FiletreeQuickFilter, &self.filetree.quick_filter),
+            (Action::FiletreeNextDir, &self.filetree.next_dir),
+            (Action::FiletreePrevDir, &self.filetree.prev_dir),
+            (Action::FiletreeFullRefresh, &self.filetree.full_refresh),
+            (
+                Action::FiletreeExpandMatching,
+                &self.filetree.expand_matching,
+            ),
+            (Action::FiletreeMultiSelect, &self.filetree.multi_select),
+            (Action::FiletreeGoto, &self.filetree.goto),
+            (
+                Action::FiletreeToggleDirsFirst,
+                &self.filetree.toggle_dirs_first,
+            ),
+            (Action::FiletreeToggleReverse, &self.filetree.toggle_reverse),
+            (
+                Action::FiletreeExtensionFilter,
+                &self.filetree.extension_filter,
+            ),
+            (Action::FiletreeRecentDirs, &self.filetree.recent_dirs),
+            (Action::FiletreeFlatMode, &self.filetree.flat_mode),
             (Action::KillProcesses, &self.kill_processes),
-        ];
+            (Action::RunningCommands, &self.running_commands),
+            (Action::CargoTargets, &self.cargo_targets),
+            (Action::ScratchCommand, &self.scratch_command),
+            (Action::DryRun, &self.dry_run),
+            (Action::CycleFocus, &self.cycle_focus),
+            (Action::OpenConfig, &self.open_config),
+            (Action::LogFocus, &self.log.focus),
+            (Action::LogExport, &self.log.export),
+        ]
+    }
+
+    /// The first key bound to the action named `name`, like `filetree_search`
+    pub fn action_key(&self, name: &str) -> Option<&Key> {
+        let (_, keybind) = self
+            .keybinds()
+            .into_iter()
+            .find(|(action, _)| action.to_string() == name)?;
+        match keybind.all_bindings() {
+            Either::Left(key) => Some(key),
+            Either::Right(keys) => keys.first(),
+        }
+    }
+
+    /// The sequences with `<leader>` filled in, leaving out the ones that cannot be typed
+    pub fn resolved_sequences(&self) -> impl Iterator<Item = (Vec<Key>, &str)> {
+        self.sequences.iter().filter_map(|(sequence, action)| {
+            Some((sequence.resolve(self.leader.as_ref())?, action.as_str()))
+        })
+    }
+
+    /// Problems with the configured sequences, which are left out when typing
+    pub fn sequence_warnings(&self) -> Vec<String> {
+        self.sequences
+            .iter()
+            .filter_map(|(sequence, action)| {
+                if sequence.resolve(self.leader.as_ref()).is_none() {
+                    Some(format!(
+                        "sequence \"{sequence}\" uses <leader>, but no leader is set"
+                    ))
+                } else if self.action_key(action).is_none() {
+                    Some(format!(
+                        "sequence \"{sequence}\" runs unknown action \"{action}\""
+                    ))
+                } else {
+                    None
+                }
+            })
+            .sorted()
+            .collect()
+    }
+
+    pub fn check_conflicts(&self) -> Vec<KeyConflict<'_>> {
+        let keybinds = self.keybinds();
         let mut keys = Vec::with_capacity(keybinds.len());
         for keybind in keybinds {
             match keybind.1.all_bindings() {
@@ -244,7 +606,12 @@ impl Config {
         keys.extend(
             self.commands
                 .iter()
-                .map(|(key, cmd)| (Action::Arbitrary(cmd), key)),
+                .map(|(key, command)| (Action::Arbitrary(command.cmd()), key)),
+        );
+        keys.extend(
+            self.tmux_commands
+                .iter()
+                .map(|(key, command)| (Action::Arbitrary(&command.cmd), key)),
         );
 
         for (name, key) in keys {
@@ -280,17 +647,54 @@ impl Merge for Config {
             all_up,
             open,
             selected,
+            selected_symbol,
             popup_border_style,
             help_key_style,
+            dry_run_style,
             kill_processes,
+            running_commands,
+            cargo_targets,
+            scratch_command,
+            kill_command,
+            dry_run,
+            cycle_focus,
+            open_config,
+            copy_error,
+            page_down,
+            page_up,
+            fuzzy_preview,
             commands,
-            esc_to_close
+            tmux_commands,
+            esc_to_close,
+            show_hints,
+            mouse,
+            color_mode,
+            error_popup,
+            editor,
+            editor_line_args,
+            editor_column_args,
+            editor_multi_file,
+            max_command_output,
+            command_timeout,
+            batch_jobs,
+            remember_fuzzy,
+            fuzzy_paths,
+            command_dir,
+            reopen_last,
+            confirm_threshold,
+            autosave_interval,
+            leader,
+            sequence_timeout
         );
         self.special_commands.merge(other.special_commands);
+        self.open_with.merge(other.open_with);
+        self.sequences.merge(other.sequences);
         self.preview.merge(other.preview);
         self.filetree.merge(other.filetree);
         self.log.merge(other.log);
         self.marks.merge(other.marks);
+        self.toast.merge(other.toast);
+        self.popup.merge(other.popup);
     }
 }
 
@@ -305,22 +709,63 @@ impl Default for Config {
             all_up: KeyBind::key(Key::normal('g')),
             all_down: KeyBind::key(Key::normal('G')),
             kill_processes: KeyBind::key(Key::ctrl('c')),
+            running_commands: KeyBind::key(Key::normal('C')),
+            cargo_targets: KeyBind::key(Key::alt('c')),
+            scratch_command: KeyBind::key(Key::alt('e')),
+            kill_command: KeyBind::key(Key::normal('d')),
+            dry_run: KeyBind::key(Key::alt('d')),
+            cycle_focus: KeyBind::key(Key::key_code(KeyCode::Tab)),
+            open_config: KeyBind::key(Key::alt('o')),
+            copy_error: KeyBind::key(Key::normal('y')),
+            page_down: KeyBind::key(Key::ctrl('d')),
+            page_up: KeyBind::key(Key::ctrl('u')),
+            fuzzy_preview: KeyBind::key(Key::ctrl('v')),
             special_commands: HashMap::new(),
+            open_with: HashMap::new(),
             selected: Style::bg(Color::Black, Color::Magenta),
+            selected_symbol: String::new(),
             popup_border_style: Style::default(),
             help_key_style: Style {
                 color: Color::LightCyan,
                 bg: Color::Reset,
                 mods: Modifier(TuiModifier::BOLD),
             },
+            dry_run_style: Style {
+                color: Color::Black,
+                bg: Color::Red,
+                mods: Modifier(TuiModifier::BOLD),
+            },
             commands: HashMap::new(),
+            tmux_commands: HashMap::new(),
             project_roots: GlobList::default(),
 
             preview: PreviewConfig::default(),
             filetree: FiletreeConfig::default(),
             log: LogConfig::default(),
             marks: MarksConfig::default(),
+            toast: ToastConfig::default(),
+            popup: PopupConfig::default(),
             esc_to_close: true,
+            show_hints: true,
+            mouse: true,
+            color_mode: ColorMode::default(),
+            error_popup: true,
+            editor: None,
+            editor_line_args: "+{line} {}".to_owned(),
+            editor_column_args: None,
+            editor_multi_file: None,
+            max_command_output: 65536,
+            command_timeout: 0,
+            batch_jobs: 1,
+            remember_fuzzy: true,
+            fuzzy_paths: FuzzyPaths::default(),
+            command_dir: CommandDir::default(),
+            reopen_last: false,
+            confirm_threshold: 10,
+            autosave_interval: 60,
+            leader: None,
+            sequence_timeout: 1000,
+            sequences: HashMap::new(),
         }
     }
 }
@@ -350,22 +795,53 @@ impl KeyConflict<'_> {
         self.on
     }
 
-    pub fn conflictors(&self) -> &[Action] {
+    pub fn conflictors(&self) -> &[Action<'_>] {
         self.conflictors.as_ref()
     }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields, default)]
+#[serde(default)]
 pub struct PreviewConfig {
     pub preview_cmd: String,
+    /// Extracts the text of a PDF's first page, empty previews PDFs like any other file
+    pub pdf_cmd: String,
+    pub dir_preview: DirPreview,
     pub git_pager: Option<String>,
     pub down_key: KeyBind,
     pub up_key: KeyBind,
+    pub pin: KeyBind,
+    pub copy_visible: KeyBind,
+    /// Shows the plain contents of the previewed file, whatever it would be previewed as
+    pub raw: KeyBind,
+    /// Searches the text of the preview
+    pub search: KeyBind,
+    /// Jump between the matches of a search in the preview
+    pub next_match: KeyBind,
+    pub prev_match: KeyBind,
+    /// Scrolls the preview to a line, or a percentage of the way down
+    pub jump: KeyBind,
+    /// Open the previewed file at the current match, or the top line of the preview
+    pub open_at_line: KeyBind,
     pub scroll_amount: u16,
+    /// Largest amount of bytes read from each file when searching contents
+    pub max_size: usize,
+    /// Lines shown around each match when previewing the results of a content search
+    pub search_context: usize,
+    /// Milliseconds the selection has to stay on a file before it is previewed
+    pub debounce: u64,
+    /// Show a line with the name, size, line count, language and encoding of the file
+    pub header: bool,
+    /// Count the words and bytes of text files in the header too, like `wc`
+    pub counts: bool,
+    /// Keep the previews of files selected lately, until they change
+    pub cache: bool,
     pub border_color: Style,
     pub scroll_bar_color: Style,
     pub unreached_bar_color: Style,
+    pub header_style: Style,
+    pub match_style: Style,
+    pub current_match_style: Style,
 }
 
 impl Default for PreviewConfig {
@@ -375,14 +851,45 @@ impl Default for PreviewConfig {
             preview_cmd: "type {}".to_owned(),
             #[cfg(not(target_os = "windows"))]
             preview_cmd: "cat {}".to_owned(),
+            pdf_cmd: "pdftotext -l 1 -layout {} -".to_owned(),
+            dir_preview: DirPreview::default(),
 
             git_pager: None,
             down_key: KeyBind::key(Key::ctrl('d')),
             up_key: KeyBind::key(Key::ctrl('u')),
+            pin: KeyBind::key(Key::normal('p')),
+            copy_visible: KeyBind::key(Key::normal('y')),
+            raw: KeyBind::key(Key::normal('P')),
+            search: KeyBind::key(Key::ctrl('f')),
+            next_match: KeyBind::key(Key::normal('n')),
+            prev_match: KeyBind::key(Key::normal('N')),
+            jump: KeyBind::key(Key::normal(':')),
+            open_at_line: KeyBind::key(Key::ctrl('o')),
             scroll_amount: 10,
+            max_size: 1024 * 1024,
+            search_context: 2,
+            debounce: 100,
+            header: true,
+            counts: false,
+            cache: true,
             border_color: Style::color(Color::Cyan),
             scroll_bar_color: Style::color(Color::Magenta),
             unreached_bar_color: Style::color(Color::Blue),
+            header_style: Style {
+                color: Color::LightCyan,
+                bg: Color::Reset,
+                mods: Modifier(TuiModifier::BOLD),
+            },
+            match_style: Style {
+                color: Color::Black,
+                bg: Color::Yellow,
+                mods: Modifier(TuiModifier::empty()),
+            },
+            current_match_style: Style {
+                color: Color::Black,
+                bg: Color::LightRed,
+                mods: Modifier(TuiModifier::BOLD),
+            },
         }
     }
 }
@@ -392,28 +899,118 @@ impl Merge for PreviewConfig {
         merge!(
             self, other;
             preview_cmd,
+            pdf_cmd,
+            dir_preview,
             git_pager,
             down_key,
             up_key,
+            pin,
+            copy_visible,
+            raw,
+            search,
+            next_match,
+            prev_match,
+            jump,
+            open_at_line,
             scroll_bar_color,
             scroll_amount,
+            max_size,
+            search_context,
+            debounce,
+            header,
+            counts,
+            cache,
             border_color,
             scroll_bar_color,
-            unreached_bar_color
+            unreached_bar_color,
+            header_style,
+            match_style,
+            current_match_style
         );
     }
 }
 
+/// Style of file sizes below `below` bytes, or of every size left without it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SizeBand {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub below: Option<u64>,
+    #[serde(default)]
+    pub style: Style,
+}
+
+/// How the tree shows when files were modified
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeFormat {
+    /// Like `2023-04-01 14:30`, in local time
+    #[default]
+    Absolute,
+    /// Like `3h ago` or `yesterday`
+    Relative,
+}
+
+/// What the preview shows for a directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DirPreview {
+    /// Its README, or its listing if it has none
+    #[default]
+    Readme,
+    /// The names and sizes of what is in it
+    Listing,
+    None,
+}
+
+/// What pressing `open` on an item of the tree does
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EnterAction {
+    /// Open or close a directory, files are opened instead
+    Toggle,
+    /// Open in the editor
+    Open,
+    /// Move the root to the directory, or to the directory of the file
+    Reroot,
+    /// Run a command with `{}` replaced by the path
+    Run(String),
+}
+
 #[derive(Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields, default)]
+#[serde(default)]
 pub struct FiletreeConfig {
     pub use_git: bool,
     pub ignore: Vec<String>,
+    /// Names of extra files read like `.ignore`, such as `.rgignore`
+    pub ignore_files: Vec<String>,
     pub use_gitignore: bool,
     pub refresh_time: u64,
-    // TODO: Actually implement
+    /// Milliseconds between full rereads of the tree, 0 disables them
+    pub full_refresh_interval: u64,
+    /// Group directories before files, otherwise both are ordered by name together
     pub dirs_first: bool,
+    /// How names are ordered
+    pub sort: SortMode,
+    /// Order names backwards, can be toggled at runtime
+    pub reverse: bool,
+    /// Reversing also puts files before directories, instead of only reversing each group
+    pub reverse_groups: bool,
     pub show_hidden_by_default: bool,
+    /// Show the amount of direct children after the name of each directory
+    pub show_dir_counts: bool,
+    /// Show the size of each file after its name, styled by `size_bands`
+    pub show_sizes: bool,
+    /// Each size takes the style of the first band it is below
+    pub size_bands: Vec<SizeBand>,
+    /// Show when each file was modified after its name
+    pub show_modified: bool,
+    pub modified_format: TimeFormat,
+    /// Rows kept in view above and below the selection, like `scrolloff` in vim
+    pub scroll_margin: usize,
+    /// Levels of directories read below the root, 0 reads all of them
+    pub max_depth: usize,
+    pub dir_enter_action: EnterAction,
+    pub file_enter_action: EnterAction,
 
     pub filtered_out_message: Style,
     pub border_color: Style,
@@ -422,11 +1019,24 @@ pub struct FiletreeConfig {
     pub git_modified_style: Style,
     pub marks_style: Style,
     pub dir_style: Style,
-
+    pub filter_match_style: Style,
+    pub multi_select_style: Style,
+    pub empty_dir_style: Style,
+    /// Directories that could not be read
+    pub unreadable_dir_style: Style,
+
+    /// Scroll the tree so the selection is in the center, at the top or at the bottom
+    pub center_selected: KeyBind,
+    pub selected_to_top: KeyBind,
+    pub selected_to_bottom: KeyBind,
     pub special_command: KeyBind,
+    /// Runs the special command last chosen for the selected file
+    pub run_last_special_command: KeyBind,
     pub down_three: KeyBind,
     pub up_three: KeyBind,
     pub exec_cmd: KeyBind,
+    /// Runs a command for each file listed in the tree
+    pub batch_cmd: KeyBind,
     pub delete: KeyBind,
     pub search: KeyBind,
     pub clear: KeyBind,
@@ -434,14 +1044,58 @@ pub struct FiletreeConfig {
     pub new_dir: KeyBind,
     pub git_filter: KeyBind,
     pub diff_mode: KeyBind,
+    pub staged_diff_mode: KeyBind,
+    pub blame_mode: KeyBind,
+    /// Previews the file as it is on a typed branch or other ref
+    pub show_at_ref: KeyBind,
+    pub stage: KeyBind,
+    pub unstage: KeyBind,
+    pub stage_all: KeyBind,
     pub open_all: KeyBind,
     pub close_all: KeyBind,
     pub mark_selected: KeyBind,
+    /// Marks the selection, or unmarks it if it already is
+    pub toggle_mark: KeyBind,
     pub open_under: KeyBind,
     pub close_under: KeyBind,
     pub show_dotfiles: KeyBind,
+    /// Reads the tree again without any ignore files, or with them again
+    pub show_ignored: KeyBind,
     pub rename: KeyBind,
+    /// Moves the selection, or every item of the multi-selection, into a directory
     pub move_path: KeyBind,
+    pub copy_contents: KeyBind,
+    /// Copies the paths of the multi-selection, or the selection, one per line
+    pub copy_paths: KeyBind,
+    /// Copy the name, stem or extension of the selection
+    pub copy_name: KeyBind,
+    pub copy_stem: KeyBind,
+    pub copy_extension: KeyBind,
+    /// Goes to the path on the clipboard, opening it at the line of a `path:line`
+    pub paste_path: KeyBind,
+    pub content_search: KeyBind,
+    pub quick_filter: KeyBind,
+    pub next_dir: KeyBind,
+    pub prev_dir: KeyBind,
+    pub full_refresh: KeyBind,
+    pub expand_matching: KeyBind,
+    pub multi_select: KeyBind,
+    /// Types a path to jump to
+    pub goto: KeyBind,
+    pub toggle_dirs_first: KeyBind,
+    pub toggle_reverse: KeyBind,
+    /// Only shows files with the typed extensions
+    pub extension_filter: KeyBind,
+    /// Picks one of the directories navigated into to go back to
+    pub recent_dirs: KeyBind,
+    /// Lists every file at one level instead of the tree
+    pub flat_mode: KeyBind,
+    /// Keep the recently visited directories for the next launch
+    pub persist_recent_dirs: bool,
+    /// Reopen the directories and selection of the last session in the same root
+    pub restore_tree: bool,
+    /// Copy paths relative to the project root instead of in full
+    pub copy_paths_relative: bool,
 }
 
 impl Default for FiletreeConfig {
@@ -450,12 +1104,38 @@ impl Default for FiletreeConfig {
             use_git: true,
             use_gitignore: true,
             dirs_first: true,
+            sort: SortMode::default(),
+            reverse: false,
+            reverse_groups: false,
             show_hidden_by_default: false,
+            show_dir_counts: false,
+            show_sizes: false,
+            size_bands: vec![
+                SizeBand {
+                    below: Some(1024 * 1024),
+                    style: Style::color(Color::Green),
+                },
+                SizeBand {
+                    below: Some(100 * 1024 * 1024),
+                    style: Style::color(Color::Yellow),
+                },
+                SizeBand {
+                    below: None,
+                    style: Style::color(Color::Red),
+                },
+            ],
+            show_modified: false,
+            modified_format: TimeFormat::default(),
+            scroll_margin: 0,
+            max_depth: 0,
             ignore: Vec::new(),
+            ignore_files: Vec::new(),
             refresh_time: 1000,
+            full_refresh_interval: 0,
             down_three: KeyBind::key(Key::ctrl('n')),
             up_three: KeyBind::key(Key::ctrl('p')),
             exec_cmd: KeyBind::key(Key::normal('e')),
+            batch_cmd: KeyBind::key(Key::alt('x')),
             delete: KeyBind::key(Key::normal('d')),
             search: KeyBind::key(Key::normal('/')),
             clear: KeyBind::key(Key::normal('\\')),
@@ -465,13 +1145,49 @@ impl Default for FiletreeConfig {
             new_dir: KeyBind::key(Key::normal('N')),
             git_filter: KeyBind::key(Key::normal('T')),
             diff_mode: KeyBind::key(Key::normal('t')),
+            staged_diff_mode: KeyBind::key(Key::alt('t')),
+            blame_mode: KeyBind::key(Key::normal('B')),
+            show_at_ref: KeyBind::key(Key::alt('b')),
+            stage: KeyBind::key(Key::normal('s')),
+            unstage: KeyBind::key(Key::normal('u')),
+            stage_all: KeyBind::key(Key::normal('S')),
             special_command: KeyBind::key(Key::normal('v')),
+            center_selected: KeyBind::key(Key::normal('Z')),
+            selected_to_top: KeyBind::key(Key::alt('k')),
+            selected_to_bottom: KeyBind::key(Key::alt('j')),
+            run_last_special_command: KeyBind::key(Key::normal('V')),
             mark_selected: KeyBind::key(Key::normal('m')),
+            toggle_mark: KeyBind::key(Key::alt('m')),
             open_under: KeyBind::key(Key::normal('l')),
             close_under: KeyBind::key(Key::normal('h')),
             show_dotfiles: KeyBind::key(Key::normal('.')),
+            show_ignored: KeyBind::key(Key::normal('I')),
             rename: KeyBind::key(Key::normal('r')),
             move_path: KeyBind::key(Key::normal('R')),
+            copy_contents: KeyBind::key(Key::normal('Y')),
+            copy_paths: KeyBind::key(Key::alt('y')),
+            copy_name: KeyBind::key(Key::ctrl('y')),
+            copy_stem: KeyBind::key(Key::alt('s')),
+            copy_extension: KeyBind::key(Key::alt('.')),
+            paste_path: KeyBind::key(Key::alt('p')),
+            content_search: KeyBind::key(Key::normal('F')),
+            quick_filter: KeyBind::key(Key::normal('f')),
+            next_dir: KeyBind::key(Key::normal(']')),
+            prev_dir: KeyBind::key(Key::normal('[')),
+            full_refresh: KeyBind::key(Key::ctrl('r')),
+            expand_matching: KeyBind::key(Key::normal('E')),
+            multi_select: KeyBind::key(Key::normal(' ')),
+            goto: KeyBind::key(Key::ctrl('g')),
+            toggle_dirs_first: KeyBind::key(Key::alt('g')),
+            toggle_reverse: KeyBind::key(Key::alt('r')),
+            extension_filter: KeyBind::key(Key::normal('X')),
+            recent_dirs: KeyBind::key(Key::normal('H')),
+            flat_mode: KeyBind::key(Key::alt('f')),
+            persist_recent_dirs: false,
+            restore_tree: false,
+            copy_paths_relative: false,
+            dir_enter_action: EnterAction::Toggle,
+            file_enter_action: EnterAction::Open,
 
             filtered_out_message: Style::color(Color::Yellow),
             border_color: Style::color(Color::Magenta),
@@ -484,6 +1200,26 @@ impl Default for FiletreeConfig {
                 bg: Color::Reset,
                 mods: Modifier(TuiModifier::ITALIC),
             },
+            filter_match_style: Style {
+                color: Color::Yellow,
+                bg: Color::Reset,
+                mods: Modifier(TuiModifier::BOLD),
+            },
+            multi_select_style: Style {
+                color: Color::LightGreen,
+                bg: Color::Reset,
+                mods: Modifier(TuiModifier::BOLD),
+            },
+            empty_dir_style: Style {
+                color: Color::Rgb(128, 128, 128),
+                bg: Color::Reset,
+                mods: Modifier(TuiModifier::ITALIC),
+            },
+            unreadable_dir_style: Style {
+                color: Color::LightRed,
+                bg: Color::Reset,
+                mods: Modifier(TuiModifier::ITALIC),
+            },
         }
     }
 }
@@ -491,42 +1227,95 @@ impl Default for FiletreeConfig {
 impl Merge for FiletreeConfig {
     fn merge(&mut self, other: Self) {
         self.ignore.merge(other.ignore);
+        self.ignore_files.merge(other.ignore_files);
         merge!(
             self, other;
             use_git,
             use_gitignore,
             dirs_first,
+            sort,
+            reverse,
+            reverse_groups,
             refresh_time,
+            full_refresh_interval,
             down_three,
             up_three,
             exec_cmd,
+            batch_cmd,
             delete,
             search,
             clear,
             new_dir,
             git_filter,
             diff_mode,
+            staged_diff_mode,
+            blame_mode,
+            show_at_ref,
+            stage,
+            unstage,
+            stage_all,
             filtered_out_message,
             border_color,
             git_added_style,
             git_new_style,
             git_modified_style,
             special_command,
+            run_last_special_command,
+            center_selected,
+            selected_to_top,
+            selected_to_bottom,
             mark_selected,
+            toggle_mark,
             marks_style,
             open_under,
             close_under,
             show_dotfiles,
+            show_ignored,
             show_hidden_by_default,
+            show_dir_counts,
+            show_sizes,
+            size_bands,
+            show_modified,
+            modified_format,
+            scroll_margin,
+            max_depth,
             dir_style,
             rename,
-            move_path
+            move_path,
+            copy_contents,
+            copy_paths,
+            copy_name,
+            copy_stem,
+            copy_extension,
+            paste_path,
+            content_search,
+            quick_filter,
+            filter_match_style,
+            next_dir,
+            prev_dir,
+            full_refresh,
+            expand_matching,
+            multi_select,
+            multi_select_style,
+            empty_dir_style,
+            unreadable_dir_style,
+            goto,
+            toggle_dirs_first,
+            toggle_reverse,
+            extension_filter,
+            recent_dirs,
+            flat_mode,
+            persist_recent_dirs,
+            restore_tree,
+            copy_paths_relative,
+            dir_enter_action,
+            file_enter_action
         );
     }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields, default)]
+#[serde(default)]
 pub struct LogConfig {
     pub error: Style,
     pub debug: Style,
@@ -534,6 +1323,11 @@ pub struct LogConfig {
     pub trace: Style,
     pub info: Style,
     pub border_color: Style,
+
+    /// Focuses the log, to scroll back through it
+    pub focus: KeyBind,
+    /// Writes the log of the session to a file
+    pub export: KeyBind,
 }
 
 impl Default for LogConfig {
@@ -545,24 +1339,28 @@ impl Default for LogConfig {
             trace: Style::color(Color::Magenta),
             info: Style::default(),
             border_color: Style::color(Color::Blue),
+            focus: KeyBind::key(Key::normal('L')),
+            export: KeyBind::key(Key::alt('l')),
         }
     }
 }
 
 impl Merge for LogConfig {
     fn merge(&mut self, other: Self) {
-        merge!(self, other; error, debug, warn, trace, info, border_color);
+        merge!(self, other; error, debug, warn, trace, info, border_color, focus, export);
     }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields, default)]
+#[serde(default)]
 pub struct MarksConfig {
     pub marks_dir: Option<PathBuf>,
     pub relative: bool,
 
     pub open: KeyBind,
     pub delete: KeyBind,
+    pub open_all: KeyBind,
+    pub show_all: KeyBind,
     pub mark_style: Style,
 }
 
@@ -573,6 +1371,8 @@ impl Default for MarksConfig {
             relative: true,
             open: KeyBind::key(Key::normal('M')),
             delete: KeyBind::key(Key::normal('d')),
+            open_all: KeyBind::key(Key::normal('o')),
+            show_all: KeyBind::key(Key::normal('a')),
             mark_style: Style::default(),
         }
     }
@@ -586,11 +1386,68 @@ impl Merge for MarksConfig {
             relative,
             open,
             delete,
+            open_all,
+            show_all,
             mark_style
         );
     }
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ToastConfig {
+    /// Milliseconds before a toast disappears
+    pub timeout: u64,
+    pub error_timeout: u64,
+
+    pub info_style: Style,
+    pub error_style: Style,
+}
+
+impl Default for ToastConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 2000,
+            error_timeout: 5000,
+            info_style: Style::color(Color::Green),
+            error_style: Style::color(Color::Red),
+        }
+    }
+}
+
+impl Merge for ToastConfig {
+    fn merge(&mut self, other: Self) {
+        merge!(self, other; timeout, error_timeout, info_style, error_style);
+    }
+}
+
+/// Keys for the confirmation popup, the input box and the fuzzy finder
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PopupConfig {
+    pub confirm: KeyBind,
+    pub cancel: KeyBind,
+    pub up: KeyBind,
+    pub down: KeyBind,
+}
+
+impl Default for PopupConfig {
+    fn default() -> Self {
+        Self {
+            confirm: KeyBind::key(Key::key_code(KeyCode::Enter)),
+            cancel: KeyBind::key(Key::esc()),
+            up: KeyBind::key(Key::ctrl('p')),
+            down: KeyBind::key(Key::ctrl('n')),
+        }
+    }
+}
+
+impl Merge for PopupConfig {
+    fn merge(&mut self, other: Self) {
+        merge!(self, other; confirm, cancel, up, down);
+    }
+}
+
 struct ColorVisitor;
 
 impl<'de> Visitor<'de> for ColorVisitor {
@@ -647,11 +1504,140 @@ impl From<Color> for TuiColor {
             Color::LightBlue => Self::LightBlue,
             Color::LightMagenta => Self::LightMagenta,
             Color::LightCyan => Self::LightCyan,
-            Color::Rgb(r, g, b) => Self::Rgb(r, g, b),
+            Color::Rgb(r, g, b) => COLOR_MODE
+                .get()
+                .copied()
+                .unwrap_or(ColorMode::TrueColor)
+                .downsample(r, g, b),
         }
     }
 }
 
+/// How much of each path the fuzzy finder shows
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FuzzyPaths {
+    /// The whole path from the root
+    #[default]
+    Full,
+    /// The file name, with only as many parent directories as tell it apart from others
+    Short,
+}
+
+/// The mode every configured color is shown in, set once the config is loaded
+static COLOR_MODE: OnceLock<ColorMode> = OnceLock::new();
+
+/// How many colors the terminal can show
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ColorMode {
+    /// Guessed from `$COLORTERM` and `$TERM`
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "truecolor")]
+    TrueColor,
+    #[serde(rename = "256")]
+    Ansi256,
+    #[serde(rename = "16")]
+    Ansi16,
+}
+
+impl ColorMode {
+    /// Settle `Auto` by the environment of the terminal
+    pub fn detect(self) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+        match env::var("COLORTERM").as_deref() {
+            Ok("truecolor" | "24bit") => Self::TrueColor,
+            _ => match env::var("TERM") {
+                Ok(term) if term.contains("256") => Self::Ansi256,
+                // Windows terminals rarely set either, but show all colors
+                Err(_) if cfg!(windows) => Self::TrueColor,
+                _ => Self::Ansi16,
+            },
+        }
+    }
+
+    /// Show every configured color in this mode from now on
+    pub fn apply(self) {
+        COLOR_MODE.set(self.detect()).ok();
+    }
+
+    /// The nearest color to `rgb(r, g, b)` the mode can show
+    pub fn downsample(self, r: u8, g: u8, b: u8) -> TuiColor {
+        match self.detect() {
+            Self::Auto | Self::TrueColor => TuiColor::Rgb(r, g, b),
+            Self::Ansi256 => TuiColor::Indexed(nearest_256(r, g, b)),
+            Self::Ansi16 => nearest_16(r, g, b),
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    [(r1, r2), (g1, g2), (b1, b2)]
+        .into_iter()
+        .map(|(a, b)| u32::from(a.abs_diff(b)).pow(2))
+        .sum()
+}
+
+/// Index of the nearest color in the 6x6x6 cube or the grayscale ramp of the 256 color palette
+fn nearest_256(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let level = |value: u8| match value {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (value - 35) / 40,
+    };
+    let (cr, cg, cb) = (level(r), level(g), level(b));
+    let cube = (
+        LEVELS[usize::from(cr)],
+        LEVELS[usize::from(cg)],
+        LEVELS[usize::from(cb)],
+    );
+
+    let average = ((u16::from(r) + u16::from(g) + u16::from(b)) / 3) as u8;
+    let gray_index = if average > 238 {
+        23
+    } else {
+        average.saturating_sub(3) / 10
+    };
+    let gray = 8 + 10 * gray_index;
+
+    if distance((r, g, b), (gray, gray, gray)) < distance((r, g, b), cube) {
+        232 + gray_index
+    } else {
+        16 + 36 * cr + 6 * cg + cb
+    }
+}
+
+/// The nearest of the 16 basic colors, by their usual xterm values
+fn nearest_16(r: u8, g: u8, b: u8) -> TuiColor {
+    const PALETTE: [(TuiColor, (u8, u8, u8)); 16] = [
+        (TuiColor::Black, (0, 0, 0)),
+        (TuiColor::Red, (205, 0, 0)),
+        (TuiColor::Green, (0, 205, 0)),
+        (TuiColor::Yellow, (205, 205, 0)),
+        (TuiColor::Blue, (0, 0, 238)),
+        (TuiColor::Magenta, (205, 0, 205)),
+        (TuiColor::Cyan, (0, 205, 205)),
+        (TuiColor::Gray, (229, 229, 229)),
+        (TuiColor::DarkGray, (127, 127, 127)),
+        (TuiColor::LightRed, (255, 0, 0)),
+        (TuiColor::LightGreen, (0, 255, 0)),
+        (TuiColor::LightYellow, (255, 255, 0)),
+        (TuiColor::LightBlue, (92, 92, 255)),
+        (TuiColor::LightMagenta, (255, 0, 255)),
+        (TuiColor::LightCyan, (0, 255, 255)),
+        (TuiColor::White, (255, 255, 255)),
+    ];
+    PALETTE
+        .into_iter()
+        .min_by_key(|(_, rgb)| distance((r, g, b), *rgb))
+        .map(|(color, _)| color)
+        .expect("palette is not empty")
+}
+
 impl FromStr for Color {
     type Err = Error;
 
@@ -698,7 +1684,10 @@ impl FromStr for Color {
                     let (input, _) = delimited(multispace0, tag(")"), multispace0)(input)?;
 
                     let [r, g, b] = digits[..] else {
-                        return Err(nom::Err::Error(nom::error::Error { input, code: nom::error::ErrorKind::SeparatedList }));
+                        return Err(nom::Err::Error(nom::error::Error {
+                            input,
+                            code: nom::error::ErrorKind::SeparatedList,
+                        }));
                     };
 
                     Ok((input, Color::Rgb(r, g, b)))
@@ -749,7 +1738,6 @@ impl Serialize for Color {
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy, Serialize)]
-#[serde(deny_unknown_fields)]
 #[serde(default)]
 pub struct Style {
     pub color: Color,
@@ -884,6 +1872,8 @@ impl FromStr for Key {
                 tag("backspace"),
                 tag("tab"),
                 tag("backtab"),
+                tag("space"),
+                tag("esc"),
                 take(1usize),
             ))(input)?;
 
@@ -896,6 +1886,8 @@ impl FromStr for Key {
                 "backspace" => KeyCode::Backspace,
                 "tab" => KeyCode::Tab,
                 "backtab" => KeyCode::BackTab,
+                "space" => KeyCode::Char(' '),
+                "esc" => KeyCode::Esc,
                 k if k.len() == 1 => {
                     KeyCode::Char(k.chars().next().expect("checked in match guard"))
                 }
@@ -938,6 +1930,7 @@ impl Display for Key {
             key_parts.push_str("alt-");
         }
         match self.code {
+            KeyCode::Char(' ') => key_parts.push_str("space"),
             KeyCode::Char(c) => key_parts.push(c),
             KeyCode::Up => key_parts.push_str("up"),
             KeyCode::Down => key_parts.push_str("down"),
@@ -947,6 +1940,7 @@ impl Display for Key {
             KeyCode::Backspace => key_parts.push_str("backspace"),
             KeyCode::Tab => key_parts.push_str("tab"),
             KeyCode::BackTab => key_parts.push_str("backtab"),
+            KeyCode::Esc => key_parts.push_str("esc"),
             _ => panic!("key conversion not set for: \"{:?}\"", self.code),
         }
 
@@ -969,6 +1963,13 @@ impl Key {
         }
     }
 
+    pub fn alt(c: char) -> Self {
+        Self {
+            code: KeyCode::Char(c),
+            mods: KeyModifiers::ALT,
+        }
+    }
+
     pub fn esc() -> Self {
         Self {
             code: KeyCode::Esc,
@@ -982,6 +1983,15 @@ impl Key {
             mods: KeyModifiers::NONE,
         }
     }
+
+    /// The event sent when the key is pressed
+    pub fn event(&self) -> KeyEvent {
+        let mut mods = self.mods;
+        if matches!(self.code, KeyCode::Char(c) if c.is_uppercase()) {
+            mods |= KeyModifiers::SHIFT;
+        }
+        KeyEvent::new(self.code, mods)
+    }
 }
 
 impl From<&KeyEvent> for Key {
@@ -1065,6 +2075,82 @@ impl KeyBind {
     }
 }
 
+/// One key of a sequence
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum SequenceKey {
+    /// The configured leader key
+    Leader,
+    Key(Key),
+}
+
+/// Keys typed one after another, separated by spaces in the config like `<leader> f f`
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct KeySequence(Vec<SequenceKey>);
+
+impl KeySequence {
+    /// The keys to type, or `None` if it uses `<leader>` without one being set
+    pub fn resolve(&self, leader: Option<&Key>) -> Option<Vec<Key>> {
+        self.0
+            .iter()
+            .map(|key| match key {
+                SequenceKey::Leader => leader.cloned(),
+                SequenceKey::Key(key) => Some(key.clone()),
+            })
+            .collect()
+    }
+}
+
+impl FromStr for KeySequence {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = s
+            .split_whitespace()
+            .map(|key| {
+                if key == "<leader>" {
+                    Ok(SequenceKey::Leader)
+                } else {
+                    key.parse().map(SequenceKey::Key)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if keys.is_empty() {
+            return Err(anyhow!("a sequence needs at least one key"));
+        }
+        Ok(Self(keys))
+    }
+}
+
+impl Display for KeySequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys = self.0.iter().map(|key| match key {
+            SequenceKey::Leader => "<leader>".to_owned(),
+            SequenceKey::Key(key) => key.to_string(),
+        });
+        write!(f, "{}", keys.format(" "))
+    }
+}
+
+impl<'de> Deserialize<'de> for KeySequence {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = Cow::<str>::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|err: Error| de::Error::custom(err.to_string()))
+    }
+}
+
+impl Serialize for KeySequence {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
 impl PartialEq<&KeyEvent> for KeyBind {
     fn eq(&self, other: &&KeyEvent) -> bool {
         self == *other
@@ -1089,6 +2175,94 @@ mod tests {
     use serial_test::serial;
     use test_log::test;
 
+    #[test]
+    fn parse_collects_unknown_keys() {
+        let loaded = parse(
+            "quit = \"z\"\nnot_a_key = 1\n\n[filetree]\nalso_not_a_key = true\n",
+            "config.toml",
+        )
+        .unwrap();
+        assert_eq!(KeyBind::key(Key::normal('z')), loaded.config.quit);
+        assert_eq!(
+            vec!["not_a_key".to_owned(), "filetree.also_not_a_key".to_owned()],
+            loaded.unknown_keys
+        );
+    }
+
+    #[test]
+    fn parse_errors_name_file_key_and_line() {
+        let err = parse("quit = \"q\"\n\n[filetree]\nsearch = 5\n", "config.toml")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("\"config.toml\""));
+        assert!(err.contains("`filetree.search`"));
+        assert!(err.contains("line 4"));
+    }
+
+    #[test]
+    fn parse_errors_on_invalid_syntax() {
+        let err = parse("quit = \n", "config.toml").unwrap_err().to_string();
+        assert!(err.contains("\"config.toml\""));
+        assert!(!err.contains("at key"));
+        assert!(err.contains("line 1"));
+    }
+
+    #[test]
+    fn downsamples_to_nearest_palette_color() {
+        assert_eq!(
+            TuiColor::Rgb(250, 10, 10),
+            ColorMode::TrueColor.downsample(250, 10, 10)
+        );
+        // Pure colors land in the cube, grays on the ramp
+        assert_eq!(
+            TuiColor::Indexed(196),
+            ColorMode::Ansi256.downsample(255, 0, 0)
+        );
+        assert_eq!(
+            TuiColor::Indexed(16),
+            ColorMode::Ansi256.downsample(0, 0, 0)
+        );
+        assert_eq!(
+            TuiColor::Indexed(244),
+            ColorMode::Ansi256.downsample(128, 128, 128)
+        );
+        assert_eq!(
+            TuiColor::LightRed,
+            ColorMode::Ansi16.downsample(250, 10, 10)
+        );
+        assert_eq!(TuiColor::Black, ColorMode::Ansi16.downsample(20, 20, 30));
+        assert_eq!(
+            ColorMode::Ansi256,
+            toml::from_str::<Config>("color_mode = \"256\"")
+                .unwrap()
+                .color_mode
+        );
+    }
+
+    #[test]
+    fn commands_can_have_a_dir() {
+        let config = toml::from_str::<Config>(concat!(
+            "command_dir = \"root\"\n",
+            "[commands]\n",
+            "a = \"make\"\n",
+            "b = { cmd = \"cargo test\", dir = \"selected_dir\" }\n",
+            "c = { cmd = \"cargo bench\", timeout = 600 }\n",
+        ))
+        .unwrap();
+        assert_eq!(CommandDir::Root, config.command_dir);
+        let command = |key| &config.commands[&Key::normal(key)];
+        assert_eq!(KeyCommand::Cmd("make".to_owned()), *command('a'));
+        assert_eq!("cargo test", command('b').cmd());
+        assert_eq!(Some(CommandDir::SelectedDir), command('b').dir());
+        assert_eq!(
+            RunOptions {
+                dir: None,
+                timeout: Some(600)
+            },
+            command('c').options()
+        );
+    }
+
     #[test]
     fn parse_rgb_from_hex_form() {
         let color = "#010203";
@@ -1254,11 +2428,15 @@ mod tests {
         let key = Key::ctrl('j');
         assert_eq!("ctrl-j", &key.to_string());
     }
+    #[test]
+    fn space_displays_as_name() {
+        assert_eq!("space", &Key::normal(' ').to_string());
+    }
 
     #[test]
     fn merges_custom_keybinds() {
         let config = Config {
-            commands: collect![HashMap<_, _>: (Key::normal('v'), "echo testing".to_owned())],
+            commands: collect![HashMap<_, _>: (Key::normal('v'), "echo testing".to_owned().into())],
             ..Default::default()
         };
         assert_eq!(
@@ -1296,6 +2474,8 @@ mod tests {
             ("backtab", KeyCode::BackTab),
             ("enter", KeyCode::Enter),
             ("up", KeyCode::Up),
+            ("space", KeyCode::Char(' ')),
+            ("esc", KeyCode::Esc),
         ];
 
         for (input, expected) in tests {
@@ -1343,4 +2523,30 @@ mod tests {
             assert_eq!(expected, input.parse::<Key>().expect("should parse"));
         }
     }
+
+    #[test]
+    fn parses_sequences_with_leader() {
+        let config = parse(
+            "leader = \"space\"\n[sequences]\n\"<leader> f ctrl-f\" = \"filetree_search\"\n",
+            "config.toml",
+        )
+        .unwrap()
+        .config;
+        let sequence: KeySequence = "<leader> f ctrl-f".parse().unwrap();
+        assert_eq!("<leader> f ctrl-f", sequence.to_string());
+        assert_eq!(
+            Some(vec![Key::normal(' '), Key::normal('f'), Key::ctrl('f')]),
+            sequence.resolve(config.leader.as_ref())
+        );
+        assert_eq!(
+            Some("filetree_search"),
+            config.sequences.get(&sequence).map(String::as_str)
+        );
+        assert_eq!(
+            Some(&Key::normal('/')),
+            config.action_key("filetree_search")
+        );
+        assert!(config.sequence_warnings().is_empty());
+        assert!("".parse::<KeySequence>().is_err());
+    }
 }
@@ -0,0 +1,72 @@
+use std::time::{Duration, Instant};
+
+/// Holds on to the latest value until no new value has come in for `delay`
+#[derive(Debug)]
+pub struct Debounce<T> {
+    delay: Duration,
+    pending: Option<(T, Instant)>,
+}
+
+impl<T> Debounce<T> {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            pending: None,
+        }
+    }
+
+    /// Replace the pending value, restarting the delay
+    pub fn push(&mut self, value: T, now: Instant) {
+        self.pending = Some((value, now + self.delay));
+    }
+
+    /// The pending value, if it has been stable for long enough
+    pub fn take_ready(&mut self, now: Instant) -> Option<T> {
+        match self.pending {
+            Some((_, ready_at)) if ready_at <= now => self.pending.take().map(|(value, _)| value),
+            _ => None,
+        }
+    }
+
+    /// Time left until the pending value is ready
+    pub fn next_timeout(&self, now: Instant) -> Option<Duration> {
+        self.pending
+            .as_ref()
+            .map(|(_, ready_at)| ready_at.saturating_duration_since(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn only_latest_value_is_kept() {
+        let now = Instant::now();
+        let mut debounce = Debounce::new(Duration::from_millis(100));
+        debounce.push(1, now);
+        debounce.push(2, now + Duration::from_millis(50));
+        assert_eq!(None, debounce.take_ready(now + Duration::from_millis(100)));
+        assert_eq!(
+            Some(2),
+            debounce.take_ready(now + Duration::from_millis(150))
+        );
+        assert_eq!(None, debounce.take_ready(now + Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn zero_delay_is_ready_immediately() {
+        let now = Instant::now();
+        let mut debounce = Debounce::new(Duration::ZERO);
+        debounce.push("file", now);
+        assert_eq!(Some(Duration::ZERO), debounce.next_timeout(now));
+        assert_eq!(Some("file"), debounce.take_ready(now));
+    }
+
+    #[test]
+    fn no_timeout_without_value() {
+        let debounce = Debounce::<()>::new(Duration::from_millis(100));
+        assert_eq!(None, debounce.next_timeout(Instant::now()));
+    }
+}
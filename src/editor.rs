@@ -0,0 +1,200 @@
+use crate::config::Config;
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[cfg(not(target_os = "windows"))]
+const DEFAULT_EDITOR: &str = "vi";
+#[cfg(target_os = "windows")]
+const DEFAULT_EDITOR: &str = "notepad";
+
+/// Editors known to open every file they are given at once
+const MULTI_FILE_EDITORS: [&str; 14] = [
+    "vi",
+    "vim",
+    "nvim",
+    "gvim",
+    "code",
+    "codium",
+    "subl",
+    "hx",
+    "kak",
+    "emacs",
+    "emacsclient",
+    "nano",
+    "micro",
+    "zed",
+];
+
+/// The editor to open files with, from the config, then `$VISUAL` and `$EDITOR`
+pub fn editor(config: &Config) -> String {
+    config
+        .editor
+        .clone()
+        .filter(|editor| !editor.trim().is_empty())
+        .or_else(|| env::var("VISUAL").ok().filter(|var| !var.is_empty()))
+        .or_else(|| env::var("EDITOR").ok().filter(|var| !var.is_empty()))
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_owned())
+}
+
+/// Arguments following the editor program. When given a line, they come from the
+/// `editor_line_args` template, where `{}` is the path and `{line}` the line number, or from
+/// `editor_column_args` with `{column}` as well, if it is set and given a column.
+fn args(config: &Config, path: &Path, line: Option<usize>, column: Option<usize>) -> Vec<OsString> {
+    let Some(line) = line else {
+        return vec![path.as_os_str().to_owned()];
+    };
+    let (template, column) = match (&config.editor_column_args, column) {
+        (Some(template), Some(column)) => (template, column.to_string()),
+        _ => (&config.editor_line_args, String::new()),
+    };
+    // Split before substituting, so paths with spaces stay a single argument
+    template
+        .split_whitespace()
+        .map(|arg| {
+            if arg == "{}" {
+                path.as_os_str().to_owned()
+            } else {
+                arg.replace("{line}", &line.to_string())
+                    .replace("{column}", &column)
+                    .replace("{}", &path.display().to_string())
+                    .into()
+            }
+        })
+        .collect()
+}
+
+/// Whether the editor takes several files at once, from `editor_multi_file` or else its name
+pub fn opens_many(config: &Config) -> bool {
+    config.editor_multi_file.unwrap_or_else(|| {
+        let editor = editor(config);
+        let program = editor.split_whitespace().next().unwrap_or(DEFAULT_EDITOR);
+        Path::new(program)
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| MULTI_FILE_EDITORS.contains(&name))
+    })
+}
+
+/// The configured editor with its own flags, like "code --wait"
+fn program(config: &Config) -> Command {
+    let editor = editor(config);
+    let mut words = editor.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or(DEFAULT_EDITOR));
+    command.args(words);
+    command
+}
+
+/// Build the command that opens `path` in the configured editor, optionally at `line` and then
+/// `column`
+pub fn command(
+    config: &Config,
+    path: impl AsRef<Path>,
+    line: Option<usize>,
+    column: Option<usize>,
+) -> Command {
+    let mut command = program(config);
+    command.args(args(config, path.as_ref(), line, column));
+    command
+}
+
+/// Build the command that opens all of `paths` in one run of the configured editor
+pub fn command_for_all(config: &Config, paths: &[PathBuf]) -> Command {
+    let mut command = program(config);
+    command.args(paths);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn args_of(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn config_editor_takes_priority() {
+        let config = Config {
+            editor: Some("hx".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!("hx", editor(&config));
+    }
+
+    #[test]
+    fn editor_can_have_arguments() {
+        let config = Config {
+            editor: Some("code --wait".to_owned()),
+            ..Default::default()
+        };
+        let command = command(&config, "file.txt", None, None);
+        assert_eq!("code", command.get_program());
+        assert_eq!(vec!["--wait", "file.txt"], args_of(&command));
+    }
+
+    #[test]
+    fn several_files_open_in_one_run() {
+        let mut config = Config {
+            editor: Some("/usr/bin/nvim -p".to_owned()),
+            ..Default::default()
+        };
+        assert!(opens_many(&config));
+        let command = command_for_all(&config, &["a.txt".into(), "my file.txt".into()]);
+        assert_eq!("/usr/bin/nvim", command.get_program());
+        assert_eq!(vec!["-p", "a.txt", "my file.txt"], args_of(&command));
+
+        config.editor = Some("gedit".to_owned());
+        assert!(!opens_many(&config));
+        config.editor_multi_file = Some(true);
+        assert!(opens_many(&config));
+    }
+
+    #[test]
+    fn line_uses_template() {
+        let config = Config {
+            editor: Some("vim".to_owned()),
+            ..Default::default()
+        };
+        let command = command(&config, "my file.txt", Some(12), None);
+        assert_eq!(vec!["+12", "my file.txt"], args_of(&command));
+    }
+
+    #[test]
+    fn template_can_join_path_and_line() {
+        let config = Config {
+            editor: Some("code".to_owned()),
+            editor_line_args: "--goto {}:{line}".to_owned(),
+            ..Default::default()
+        };
+        let command = command(&config, "file.txt", Some(3), None);
+        assert_eq!(vec!["--goto", "file.txt:3"], args_of(&command));
+    }
+
+    #[test]
+    fn column_needs_its_own_template() {
+        let mut config = Config {
+            editor: Some("code".to_owned()),
+            editor_line_args: "--goto {}:{line}".to_owned(),
+            ..Default::default()
+        };
+        // Editors without a column template only get the line
+        let args = |config: &Config, column| args_of(&command(config, "file.txt", Some(3), column));
+        assert_eq!(vec!["--goto", "file.txt:3"], args(&config, Some(7)));
+
+        config.editor_column_args = Some("--goto {}:{line}:{column}".to_owned());
+        assert_eq!(vec!["--goto", "file.txt:3:7"], args(&config, Some(7)));
+        assert_eq!(vec!["--goto", "file.txt:3"], args(&config, None));
+        assert_eq!(
+            vec!["file.txt"],
+            args_of(&command(&config, "file.txt", None, Some(7)))
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::{Context, Result};
+use std::{fs::File, io::Read, path::Path};
+
+/// How many bytes are checked for NUL bytes when detecting binary files
+const SNIFF_LEN: usize = 8000;
+
+/// Contents of a file read with a size cap
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FileText {
+    pub text: String,
+    pub truncated: bool,
+}
+
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(SNIFF_LEN)].contains(&0)
+}
+
+/// Name of the encoding the start of a file looks like it is in
+pub fn describe(bytes: &[u8]) -> &'static str {
+    let valid_utf8 = match std::str::from_utf8(&bytes[..bytes.len().min(SNIFF_LEN)]) {
+        Ok(_) => true,
+        // Cut off in the middle of a character
+        Err(err) => err.error_len().is_none(),
+    };
+    if bytes.starts_with(&[0xFF, 0xFE]) || bytes.starts_with(&[0xFE, 0xFF]) {
+        "UTF-16"
+    } else if is_binary(bytes) {
+        "binary"
+    } else if !valid_utf8 {
+        "not UTF-8"
+    } else if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        "UTF-8 with BOM"
+    } else {
+        "UTF-8"
+    }
+}
+
+/// Read the start of a file to describe its encoding
+pub fn sniff(path: impl AsRef<Path>) -> Result<&'static str> {
+    let path = path.as_ref();
+    let file =
+        File::open(path).with_context(|| format!("failed to open \"{}\"", path.display()))?;
+    let mut bytes = Vec::with_capacity(SNIFF_LEN);
+    file.take(SNIFF_LEN as u64)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("failed to read \"{}\"", path.display()))?;
+    Ok(describe(&bytes))
+}
+
+/// Decode bytes as UTF-8, returning `None` for binary data. Invalid sequences are replaced rather
+/// than rejected, and a multibyte character cut off by a size cap is dropped.
+pub fn decode(bytes: &[u8]) -> Option<String> {
+    if is_binary(bytes) {
+        return None;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Some(text.to_owned()),
+        // Only an incomplete character at the very end, most likely from truncation
+        Err(err) if err.error_len().is_none() => {
+            Some(String::from_utf8_lossy(&bytes[..err.valid_up_to()]).into_owned())
+        }
+        Err(_) => Some(String::from_utf8_lossy(bytes).into_owned()),
+    }
+}
+
+/// Read at most `max_size` bytes of a text file. Returns `None` if the file looks binary.
+pub fn read_text(path: impl AsRef<Path>, max_size: usize) -> Result<Option<FileText>> {
+    let path = path.as_ref();
+    let file =
+        File::open(path).with_context(|| format!("failed to open \"{}\"", path.display()))?;
+    let mut bytes = Vec::new();
+    // Read one extra byte to know if anything was cut off
+    file.take(max_size as u64 + 1)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("failed to read \"{}\"", path.display()))?;
+    let truncated = bytes.len() > max_size;
+    bytes.truncate(max_size);
+
+    Ok(decode(&bytes).map(|text| FileText { text, truncated }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use test_log::test;
+
+    #[test]
+    fn describes_encodings() {
+        assert_eq!("UTF-8", describe("héllo".as_bytes()));
+        assert_eq!("UTF-8 with BOM", describe(b"\xEF\xBB\xBFhi"));
+        assert_eq!("UTF-16", describe(b"\xFF\xFEh\0i\0"));
+        assert_eq!("binary", describe(b"\x7FELF\0\0"));
+        assert_eq!("not UTF-8", describe(b"caf\xE9 au lait"));
+    }
+
+    #[test]
+    fn nul_bytes_are_binary() {
+        assert!(is_binary(b"abc\0def"));
+        assert!(!is_binary(b"abcdef"));
+        assert_eq!(None, decode(b"\0\x01\x02"));
+    }
+
+    #[test]
+    fn decode_drops_cut_off_character() {
+        let bytes = "aé".as_bytes();
+        assert_eq!(Some("a".to_owned()), decode(&bytes[..2]));
+    }
+
+    #[test]
+    fn decode_replaces_invalid_sequences() {
+        assert_eq!(Some("a\u{fffd}b".to_owned()), decode(b"a\xffb"));
+    }
+
+    #[test]
+    fn read_text_caps_size() {
+        let temp = TempDir::new().unwrap();
+        temp.child("file.txt").write_str("hello world").unwrap();
+        let text = read_text(temp.join("file.txt"), 5).unwrap().unwrap();
+        assert_eq!(
+            FileText {
+                text: "hello".to_owned(),
+                truncated: true
+            },
+            text
+        );
+        let text = read_text(temp.join("file.txt"), 100).unwrap().unwrap();
+        assert!(!text.truncated);
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::Sender;
+
+use super::ExternalEvent;
+use crate::search;
+
+/// Least time between two batches of results, so a search finding many files does not flood
+/// the app
+const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A search of the contents of files
+#[derive(Debug, Clone)]
+pub struct ContentSearch {
+    /// Tells the batches apart from those of a search that was replaced or stopped since
+    pub id: usize,
+    pub paths: Vec<PathBuf>,
+    pub query: String,
+    pub max_size: usize,
+    /// Lines kept around each match
+    pub context: usize,
+    /// Set once the search should stop early
+    pub cancel: Arc<AtomicBool>,
+}
+
+/// Search the files of `search` on another thread, sending the matches back in batches as
+/// `ExternalEvent::SearchResults` while they are found
+pub fn search_contents(search: ContentSearch, sender: Sender<ExternalEvent>) {
+    thread::spawn(move || {
+        let send = |matches, done| {
+            sender
+                .send(ExternalEvent::SearchResults {
+                    id: search.id,
+                    query: search.query.clone(),
+                    matches,
+                    done,
+                })
+                .expect("sender should not have deallocated");
+        };
+        let cancel = &search.cancel;
+        let paths = search
+            .paths
+            .iter()
+            .cloned()
+            .take_while(|_| !cancel.load(Ordering::Acquire));
+        let mut batch = Vec::new();
+        // The first match is sent right away, so results show up as soon as there are any
+        let mut last_batch = None;
+        for found in search::search_contents(paths, &search.query, search.max_size, search.context)
+        {
+            batch.push(found);
+            if last_batch.map_or(true, |at: Instant| at.elapsed() >= BATCH_INTERVAL) {
+                last_batch = Some(Instant::now());
+                send(std::mem::take(&mut batch), false);
+            }
+        }
+        // Still marked done when cancelled, so the app knows the search is over
+        send(batch, true);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use crossbeam_channel::unbounded;
+    use test_log::test;
+
+    #[test]
+    fn streams_matches_until_done_or_cancelled() {
+        let temp = TempDir::new().unwrap();
+        let paths = (0..20)
+            .map(|n| {
+                let file = temp.child(format!("{n}.txt"));
+                file.write_str("one\ntwo needle\nthree").unwrap();
+                file.to_path_buf()
+            })
+            .collect::<Vec<_>>();
+        let search = ContentSearch {
+            id: 3,
+            paths,
+            query: "needle".to_owned(),
+            max_size: 1024,
+            context: 1,
+            cancel: Arc::default(),
+        };
+        let results = |search: ContentSearch| {
+            let (sender, receiver) = unbounded();
+            search_contents(search, sender);
+            let mut found = Vec::new();
+            for event in receiver.iter() {
+                let ExternalEvent::SearchResults {
+                    id, matches, done, ..
+                } = event
+                else {
+                    panic!("expected search results");
+                };
+                assert_eq!(3, id);
+                found.extend(matches);
+                if done {
+                    break;
+                }
+            }
+            found
+        };
+
+        let found = results(search.clone());
+        assert_eq!(20, found.len());
+        assert_eq!("1-one\n2:two needle\n3-three", found[0].excerpt());
+
+        search.cancel.store(true, Ordering::Release);
+        assert!(results(search).is_empty());
+    }
+}
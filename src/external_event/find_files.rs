@@ -0,0 +1,156 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use crossbeam_channel::Sender;
+use ignore::{overrides::Override, WalkBuilder};
+
+use super::ExternalEvent;
+
+/// How many paths are gathered before they are sent back
+const BATCH_SIZE: usize = 512;
+
+/// What walks of the project leave out, the same for the tree and the walks done elsewhere
+#[derive(Debug, Clone)]
+pub struct WalkFilters {
+    pub overrides: Override,
+    pub show_hidden: bool,
+    /// Names of extra files read like `.ignore`
+    pub ignore_files: Vec<String>,
+    pub use_gitignore: bool,
+    /// Off to read no ignore files at all, showing everything they leave out
+    pub read_ignore_files: bool,
+}
+
+impl Default for WalkFilters {
+    fn default() -> Self {
+        Self {
+            overrides: Override::empty(),
+            show_hidden: false,
+            ignore_files: Vec::new(),
+            use_gitignore: true,
+            read_ignore_files: true,
+        }
+    }
+}
+
+impl WalkFilters {
+    pub fn walker(&self, root: &Path) -> WalkBuilder {
+        let mut builder = WalkBuilder::new(root);
+        let gitignore = self.read_ignore_files && self.use_gitignore;
+        builder
+            .overrides(self.overrides.clone())
+            .hidden(!self.show_hidden)
+            .parents(self.read_ignore_files)
+            .ignore(self.read_ignore_files)
+            .git_ignore(gitignore)
+            .git_global(gitignore)
+            .git_exclude(gitignore);
+        if self.read_ignore_files {
+            for name in &self.ignore_files {
+                builder.add_custom_ignore_filename(name);
+            }
+        }
+        builder
+    }
+}
+
+/// A walk for the candidates of the fuzzy finder
+#[derive(Debug, Clone)]
+pub struct FileSearch {
+    /// Tells the batches apart from those of a finder that was closed since
+    pub id: usize,
+    pub root: PathBuf,
+    pub filters: WalkFilters,
+    /// Set once the walk should stop early
+    pub cancel: Arc<AtomicBool>,
+}
+
+/// Walk the tree under `search.root` on another thread, sending the paths relative to it back in
+/// batches as `ExternalEvent::FoundFiles`
+pub fn find_files(search: FileSearch, sender: Sender<ExternalEvent>) {
+    thread::spawn(move || {
+        let send = |paths, done| {
+            sender
+                .send(ExternalEvent::FoundFiles {
+                    id: search.id,
+                    paths,
+                    done,
+                })
+                .expect("sender should not have deallocated");
+        };
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        for entry in search
+            .filters
+            .walker(&search.root)
+            .build()
+            .filter_map(Result::ok)
+        {
+            if search.cancel.load(Ordering::Acquire) {
+                // Still marked done, so the app knows the walk is over
+                batch.clear();
+                break;
+            }
+            let Ok(path) = entry.path().strip_prefix(&search.root) else {
+                continue;
+            };
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            batch.push(path.display().to_string());
+            if batch.len() == BATCH_SIZE {
+                send(std::mem::take(&mut batch), false);
+            }
+        }
+        send(batch, true);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use crossbeam_channel::unbounded;
+    use test_log::test;
+
+    #[test]
+    fn finds_relative_paths_without_hidden_files() {
+        let temp = TempDir::new().unwrap();
+        temp.child("dir/a.txt").touch().unwrap();
+        temp.child(".hidden.txt").touch().unwrap();
+        let (send, recv) = unbounded();
+        find_files(
+            FileSearch {
+                id: 4,
+                root: temp.path().to_path_buf(),
+                filters: WalkFilters::default(),
+                cancel: Arc::default(),
+            },
+            send,
+        );
+        let mut found = Vec::new();
+        for event in recv.iter() {
+            let ExternalEvent::FoundFiles { id, paths, done } = event else {
+                panic!("expected found files");
+            };
+            assert_eq!(4, id);
+            found.extend(paths);
+            if done {
+                break;
+            }
+        }
+        found.sort();
+        assert_eq!(
+            vec![
+                "dir".to_owned(),
+                PathBuf::from("dir").join("a.txt").display().to_string()
+            ],
+            found
+        );
+    }
+}
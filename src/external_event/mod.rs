@@ -1,14 +1,25 @@
+mod content_search;
 mod crossterm_event;
+mod find_files;
+mod move_files;
 mod refresh;
+mod run_batch;
 mod run_cmd;
+mod signal;
 
+use crate::search::ContentMatch;
 use anyhow::Error;
+pub use content_search::*;
 use crossterm::event::Event;
 pub use crossterm_event::*;
+pub use find_files::*;
+pub use move_files::*;
 pub use refresh::fs_watch;
+pub use run_batch::*;
 pub use run_cmd::*;
+pub use signal::*;
 use smallvec::SmallVec;
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum RefreshData {
@@ -23,5 +34,56 @@ pub enum ExternalEvent {
     /// Wrapper for crossterm events
     Crossterm(Event),
     CommandOutput(String),
+    /// A background command with this id exited or was killed
+    CommandFinished(usize),
+    /// A background command with this id ran for longer than `after` and is being killed
+    CommandTimedOut {
+        id: usize,
+        after: Duration,
+    },
+    /// What a scratch command printed, and its exit code unless it was killed by a signal
+    ScratchOutput {
+        command: String,
+        output: String,
+        status: Option<i32>,
+    },
     Error(Error),
+    /// Files found by a content search since the last batch. `done` is set on the last one.
+    SearchResults {
+        id: usize,
+        query: String,
+        matches: Vec<ContentMatch>,
+        done: bool,
+    },
+    /// Paths found for the fuzzy finder, relative to the root. `done` is set on the last batch.
+    FoundFiles {
+        id: usize,
+        paths: Vec<String>,
+        done: bool,
+    },
+    /// How many of the items being moved have been handled so far
+    MoveProgress {
+        done: usize,
+        total: usize,
+    },
+    /// Items moved into `dest` with where they ended up, and the ones skipped with why
+    FilesMoved {
+        dest: PathBuf,
+        moved: Vec<(PathBuf, PathBuf)>,
+        skipped: Vec<(PathBuf, String)>,
+    },
+    /// How many of the jobs of a batch command have ended so far
+    BatchProgress {
+        done: usize,
+        total: usize,
+    },
+    /// How the command run for each file of a batch ended
+    BatchFinished {
+        command: String,
+        results: Vec<(PathBuf, JobStatus)>,
+    },
+    /// projectable was asked to exit by a signal
+    Terminate,
+    /// Sent when no other event arrived before the deadline asked for by the app
+    Tick,
 }
@@ -0,0 +1,126 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::Sender;
+#[cfg(not(target_os = "windows"))]
+use duct::cmd;
+
+use super::ExternalEvent;
+
+/// Least time between two `ExternalEvent::MoveProgress`, so big moves do not flood the app
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Move each of `paths` into `dest` on another thread, reporting progress along the way and
+/// sending what was moved and skipped as `ExternalEvent::FilesMoved` at the end
+pub fn move_files(paths: Vec<PathBuf>, dest: PathBuf, sender: Sender<ExternalEvent>) {
+    thread::spawn(move || {
+        let total = paths.len();
+        let mut moved = Vec::new();
+        let mut skipped = Vec::new();
+        let mut last_progress = Instant::now();
+        for (done, path) in paths.into_iter().enumerate() {
+            match move_into(&path, &dest) {
+                Ok(to) => moved.push((path, to)),
+                Err(err) => skipped.push((path, format!("{err:#}"))),
+            }
+            if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                last_progress = Instant::now();
+                sender
+                    .send(ExternalEvent::MoveProgress {
+                        done: done + 1,
+                        total,
+                    })
+                    .expect("sender should not have deallocated");
+            }
+        }
+        sender
+            .send(ExternalEvent::FilesMoved {
+                dest,
+                moved,
+                skipped,
+            })
+            .expect("sender should not have deallocated");
+    });
+}
+
+/// Move `path` into the directory `dest`, refusing to replace anything already there
+fn move_into(path: &Path, dest: &Path) -> Result<PathBuf> {
+    let name = path.file_name().context("nothing to move")?;
+    let to = dest.join(name);
+    if path.parent() == Some(dest) {
+        bail!("already in \"{}\"", dest.display());
+    }
+    if dest.starts_with(path) {
+        bail!("cannot move a directory into itself");
+    }
+    if to.symlink_metadata().is_ok() {
+        bail!("\"{}\" already exists", to.display());
+    }
+    if let Err(err) = fs::rename(path, &to) {
+        // Renaming does not work across file systems, which `mv` handles by copying
+        #[cfg(not(target_os = "windows"))]
+        cmd!("mv", path, &to)
+            .stderr_capture()
+            .run()
+            .with_context(|| format!("could not move: {err}"))?;
+        #[cfg(target_os = "windows")]
+        return Err(err).context("could not move");
+    }
+    Ok(to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use crossbeam_channel::unbounded;
+    use test_log::test;
+
+    #[test]
+    fn moves_each_item_and_skips_collisions() {
+        let temp = TempDir::new().unwrap();
+        for file in ["a.txt", "b.txt", "dir/sub/c.txt", "dest/b.txt"] {
+            temp.child(file).touch().unwrap();
+        }
+        let (sender, receiver) = unbounded();
+        move_files(
+            vec![
+                temp.join("a.txt"),
+                temp.join("b.txt"),
+                temp.join("dir/sub"),
+                temp.join("dest"),
+            ],
+            temp.join("dest"),
+            sender,
+        );
+        let (moved, skipped) = loop {
+            match receiver.recv().unwrap() {
+                ExternalEvent::FilesMoved { moved, skipped, .. } => break (moved, skipped),
+                ExternalEvent::MoveProgress { .. } => {}
+                event => panic!("unexpected event {event:?}"),
+            }
+        };
+
+        assert_eq!(
+            vec![
+                (temp.join("a.txt"), temp.join("dest/a.txt")),
+                (temp.join("dir/sub"), temp.join("dest/sub"))
+            ],
+            moved
+        );
+        assert!(temp.join("dest/sub/c.txt").exists());
+        assert!(temp.join("b.txt").exists());
+        assert_eq!(
+            vec![temp.join("b.txt"), temp.join("dest")],
+            skipped
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::Sender;
+use duct::Expression;
+
+use super::ExternalEvent;
+
+/// Least time between two `ExternalEvent::BatchProgress`
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+/// How often a running job is checked for having exited, run out of time or been cancelled
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A command run once for each of a set of files
+#[derive(Debug)]
+pub struct Batch {
+    /// What the commands were filled in from
+    pub command: String,
+    /// Each file with the command filled in for it
+    pub jobs: Vec<(PathBuf, Expression)>,
+    /// Most commands running at once
+    pub parallel: usize,
+    pub timeout: Option<Duration>,
+    pub cancel: Arc<AtomicBool>,
+}
+
+/// How the command for one file of a batch ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobStatus {
+    /// Exit code, unless it was killed by a signal
+    Exited(Option<i32>),
+    TimedOut(Duration),
+    /// Could not be started at all
+    Failed(String),
+    /// Killed or never started, as the batch was cancelled
+    Stopped,
+}
+
+impl JobStatus {
+    pub fn succeeded(&self) -> bool {
+        *self == Self::Exited(Some(0))
+    }
+}
+
+impl Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exited(Some(0)) => write!(f, "ok"),
+            Self::Exited(Some(code)) => write!(f, "exit {code}"),
+            Self::Exited(None) => write!(f, "killed"),
+            Self::TimedOut(after) => write!(f, "timed out after {after:?}"),
+            Self::Failed(err) => write!(f, "failed: {err}"),
+            Self::Stopped => write!(f, "stopped"),
+        }
+    }
+}
+
+/// Run the jobs of `batch` on up to `parallel` threads, reporting progress along the way and
+/// sending how each job ended as `ExternalEvent::BatchFinished`, in the order they were given
+pub fn run_batch(batch: Batch, sender: Sender<ExternalEvent>) {
+    let Batch {
+        command,
+        jobs,
+        parallel,
+        timeout,
+        cancel,
+    } = batch;
+    let total = jobs.len();
+    let (job_send, job_recv) = crossbeam_channel::unbounded();
+    for job in jobs.into_iter().enumerate() {
+        job_send
+            .send(job)
+            .expect("receiver should not have deallocated");
+    }
+    drop(job_send);
+
+    let (done_send, done_recv) = crossbeam_channel::unbounded();
+    for _ in 0..parallel.clamp(1, total.max(1)) {
+        let jobs = job_recv.clone();
+        let done = done_send.clone();
+        let cancel = Arc::clone(&cancel);
+        thread::spawn(move || {
+            for (index, (path, expr)) in jobs {
+                let status = if cancel.load(Ordering::Acquire) {
+                    JobStatus::Stopped
+                } else {
+                    run_job(&expr, timeout, &cancel)
+                };
+                done.send((index, path, status))
+                    .expect("receiver should not have deallocated");
+            }
+        });
+    }
+    drop(done_send);
+
+    // Sending can fail when quitting mid batch, nobody is left to listen then
+    thread::spawn(move || {
+        let mut results = Vec::with_capacity(total);
+        let mut last_progress = Instant::now();
+        for result in done_recv {
+            results.push(result);
+            if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                last_progress = Instant::now();
+                sender
+                    .send(ExternalEvent::BatchProgress {
+                        done: results.len(),
+                        total,
+                    })
+                    .ok();
+            }
+        }
+        results.sort_by_key(|(index, ..)| *index);
+        sender
+            .send(ExternalEvent::BatchFinished {
+                command,
+                results: results
+                    .into_iter()
+                    .map(|(_, path, status)| (path, status))
+                    .collect(),
+            })
+            .ok();
+    });
+}
+
+/// Run one job without its output, killing it once it runs out of time or the batch is cancelled
+fn run_job(expr: &Expression, timeout: Option<Duration>, cancel: &AtomicBool) -> JobStatus {
+    let started = Instant::now();
+    let handle = match expr
+        .stdin_null()
+        .stdout_null()
+        .stderr_null()
+        .unchecked()
+        .start()
+    {
+        Ok(handle) => handle,
+        Err(err) => return JobStatus::Failed(err.to_string()),
+    };
+    loop {
+        match handle.try_wait() {
+            Ok(Some(output)) => return JobStatus::Exited(output.status.code()),
+            Ok(None) => {}
+            Err(err) => return JobStatus::Failed(err.to_string()),
+        }
+        let timed_out = timeout.filter(|timeout| started.elapsed() >= *timeout);
+        if timed_out.is_some() || cancel.load(Ordering::Acquire) {
+            // Fails if it exited in the meantime, which is fine
+            handle.kill().ok();
+            return timed_out.map_or(JobStatus::Stopped, JobStatus::TimedOut);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use duct::cmd;
+    use test_log::test;
+
+    fn finish(batch: Batch) -> Vec<(PathBuf, JobStatus)> {
+        let (sender, receiver) = unbounded();
+        run_batch(batch, sender);
+        loop {
+            match receiver.recv().unwrap() {
+                ExternalEvent::BatchFinished { results, .. } => break results,
+                ExternalEvent::BatchProgress { .. } => {}
+                event => panic!("unexpected event {event:?}"),
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn collects_each_status_in_order() {
+        let jobs = ["exit 0", "sleep 0.1; exit 3", "exit 0", "sleep 5"]
+            .into_iter()
+            .enumerate()
+            .map(|(index, script)| (PathBuf::from(index.to_string()), cmd!("sh", "-c", script)))
+            .collect();
+        let results = finish(Batch {
+            command: "test".to_owned(),
+            jobs,
+            parallel: 2,
+            timeout: Some(Duration::from_millis(300)),
+            cancel: Arc::default(),
+        });
+
+        assert_eq!(
+            vec![
+                (PathBuf::from("0"), JobStatus::Exited(Some(0))),
+                (PathBuf::from("1"), JobStatus::Exited(Some(3))),
+                (PathBuf::from("2"), JobStatus::Exited(Some(0))),
+                (
+                    PathBuf::from("3"),
+                    JobStatus::TimedOut(Duration::from_millis(300))
+                ),
+            ],
+            results
+        );
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn cancelled_batch_stops_every_job() {
+        let results = finish(Batch {
+            command: "true".to_owned(),
+            jobs: vec![(PathBuf::from("a"), cmd!("true"))],
+            parallel: 1,
+            timeout: None,
+            cancel: Arc::new(AtomicBool::new(true)),
+        });
+        assert_eq!(vec![(PathBuf::from("a"), JobStatus::Stopped)], results);
+    }
+}
@@ -1,58 +1,447 @@
 use std::{
+    collections::VecDeque,
+    fmt::Write,
+    io::{self, Read},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
-use crossbeam_channel::Sender;
-use duct::Expression;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use duct::{Expression, ReaderHandle};
 
 use super::ExternalEvent;
 
+/// A command started by `run_cmd`
+pub struct CommandHandle {
+    stop: Arc<AtomicBool>,
+    handle: Arc<ReaderHandle>,
+}
+
+impl CommandHandle {
+    /// Pid of the first process of the command
+    pub fn pid(&self) -> Option<u32> {
+        self.handle.pids().first().copied()
+    }
+
+    /// Stop the command and what it started in the background, without reporting its output
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Release);
+        let handle = Arc::clone(&self.handle);
+        thread::spawn(move || terminate(&handle));
+    }
+
+    /// Stop the command and what it started, waiting for them to exit
+    pub fn kill(&self) {
+        self.stop.store(true, Ordering::Release);
+        terminate(&self.handle);
+    }
+}
+
+/// The end of a command's output, at most `limit` bytes
+#[derive(Debug)]
+struct OutputTail {
+    bytes: VecDeque<u8>,
+    limit: usize,
+    dropped: usize,
+}
+
+impl OutputTail {
+    fn new(limit: usize) -> Self {
+        Self {
+            bytes: VecDeque::new(),
+            limit,
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.bytes.extend(bytes);
+        let excess = self.bytes.len().saturating_sub(self.limit);
+        self.bytes.drain(..excess);
+        self.dropped += excess;
+    }
+
+    fn into_string(self) -> String {
+        let bytes = Vec::from(self.bytes);
+        let text = String::from_utf8_lossy(&bytes);
+        if self.dropped == 0 {
+            return text.into_owned();
+        }
+        // Start at a whole line
+        let text = text.split_once('\n').map_or("", |(_, rest)| rest);
+        format!(
+            "[{} earlier bytes not kept]\n{text}",
+            (self.dropped + bytes.len()).saturating_sub(text.len())
+        )
+    }
+}
+
+/// How long a command has to exit once asked to, before it is killed
+const KILL_GRACE: Duration = Duration::from_millis(500);
+
+/// Start each process of `cmd` in a process group of its own, so stopping it stops everything it
+/// started too
+fn in_own_group(cmd: Expression) -> Expression {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.before_spawn(|command| {
+            command.process_group(0);
+            Ok(())
+        })
+    }
+    #[cfg(not(unix))]
+    cmd
+}
+
+/// Send `signal` to the process group `group` with `kill`, returning whether there was one
+#[cfg(unix)]
+fn signal_group(group: u32, signal: &str) -> bool {
+    std::process::Command::new("kill")
+        .args([signal, "--", &format!("-{group}")])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Ask the process groups of a command started with `in_own_group` to exit with SIGTERM, then
+/// SIGKILL whatever is left of them after `KILL_GRACE`
+#[cfg(unix)]
+fn terminate(handle: &ReaderHandle) {
+    let mut groups = handle.pids();
+    groups.retain(|&group| signal_group(group, "-TERM"));
+    let started = Instant::now();
+    while !groups.is_empty() && started.elapsed() < KILL_GRACE {
+        thread::sleep(Duration::from_millis(50));
+        // Reaps the processes that exited, so they stop counting as alive
+        handle.try_wait().ok();
+        groups.retain(|&group| signal_group(group, "-0"));
+    }
+    for group in groups {
+        signal_group(group, "-KILL");
+    }
+    handle.kill().ok();
+}
+
+#[cfg(not(unix))]
+fn terminate(handle: &ReaderHandle) {
+    handle.kill().ok();
+}
+
+/// Kills a command once its time is up, unless it is dropped first
+struct Deadline {
+    timeout: Option<Duration>,
+    timed_out: Arc<AtomicBool>,
+    /// Dropping it wakes up the thread waiting to kill the command
+    _finished: Sender<()>,
+}
+
+impl Deadline {
+    /// Calls `on_timeout` as soon as the time is up, before the command is stopped
+    fn start(
+        timeout: Option<Duration>,
+        handle: &Arc<ReaderHandle>,
+        on_timeout: impl FnOnce(Duration) + Send + 'static,
+    ) -> Self {
+        let (finished, wait): (_, Receiver<()>) = crossbeam_channel::bounded(0);
+        let timed_out = Arc::new(AtomicBool::new(false));
+        if let Some(timeout) = timeout {
+            let handle = Arc::clone(handle);
+            let timed_out = Arc::clone(&timed_out);
+            thread::spawn(move || {
+                if wait.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                    timed_out.store(true, Ordering::Release);
+                    on_timeout(timeout);
+                    terminate(&handle);
+                }
+            });
+        }
+        Self {
+            timeout,
+            timed_out,
+            _finished: finished,
+        }
+    }
+
+    /// How long the command ran for, if it was killed for running too long
+    fn timed_out(&self) -> Option<Duration> {
+        self.timeout
+            .filter(|_| self.timed_out.load(Ordering::Acquire))
+    }
+}
+
+/// Note that the output was cut short by `timeout`
+fn push_timeout(output: &mut String, timeout: Duration) {
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+    writeln!(output, "[timed out after {timeout:?}]").expect("writing to a string should not fail");
+}
+
+/// Start `cmd` in the background, keeping the last `max_output` bytes it prints. They are sent
+/// once it exits, followed by `ExternalEvent::CommandFinished` with `id`. It is stopped after
+/// `timeout` if it is still running, sending `ExternalEvent::CommandTimedOut` right then.
 pub fn run_cmd(
+    id: usize,
     cmd: Expression,
     sender: Sender<ExternalEvent>,
-    refresh_time: Duration,
-    stop: Arc<AtomicBool>,
-) -> Result<()> {
-    let handle = cmd.start()?;
-    thread::spawn(move || loop {
-        if stop.load(Ordering::Acquire) {
-            if let Err(err) = handle.kill() {
-                sender
-                    .send(ExternalEvent::Error(err.into()))
-                    .expect("sender should not have deallocated");
-            }
-            return;
-        }
-        match handle.try_wait() {
-            Ok(Some(out)) => {
-                sender
-                    .send(ExternalEvent::CommandOutput(
-                        String::from_utf8_lossy(if out.stdout.is_empty() {
-                            &out.stderr
-                        } else {
-                            &out.stdout
-                        })
-                        .to_string(),
-                    ))
-                    .expect("sender should not have deallocated");
-                return;
+    max_output: usize,
+    timeout: Option<Duration>,
+) -> Result<CommandHandle> {
+    let command = CommandHandle {
+        stop: Arc::new(AtomicBool::new(false)),
+        handle: Arc::new(in_own_group(cmd).reader()?),
+    };
+    let stop = Arc::clone(&command.stop);
+    let handle = Arc::clone(&command.handle);
+    let timeout_sender = sender.clone();
+    let deadline = Deadline::start(timeout, &handle, move |after| {
+        timeout_sender
+            .send(ExternalEvent::CommandTimedOut { id, after })
+            .ok();
+    });
+    // Sending can fail when killed while quitting, nobody is left to listen then
+    thread::spawn(move || {
+        let mut output = OutputTail::new(max_output);
+        let mut buf = [0; 8192];
+        loop {
+            match (&*handle).read(&mut buf) {
+                // Reading the end also waits for the command to exit
+                Ok(0) => break,
+                Ok(read) => output.push(&buf[..read]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    if !stop.load(Ordering::Acquire)
+                        && deadline.timed_out().is_none()
+                        && err.kind() != io::ErrorKind::BrokenPipe
+                    {
+                        sender.send(ExternalEvent::Error(err.into())).ok();
+                    }
+                    break;
+                }
             }
-            Ok(None) => {}
-            Err(err) => {
-                sender
-                    .send(ExternalEvent::Error(err.into()))
-                    .expect("sender should not have deallocated");
-                return;
+        }
+        let timed_out = deadline.timed_out();
+        drop(deadline);
+        if !stop.load(Ordering::Acquire) {
+            let mut output = output.into_string();
+            if let Some(after) = timed_out {
+                push_timeout(&mut output, after);
             }
-        };
-        thread::sleep(refresh_time);
+            sender.send(ExternalEvent::CommandOutput(output)).ok();
+        }
+        sender.send(ExternalEvent::CommandFinished(id)).ok();
     });
 
+    Ok(command)
+}
+
+/// Run `cmd` in the background, sending the last `max_output` bytes it printed and how it exited
+/// as `ExternalEvent::ScratchOutput`. It is killed after `timeout` if it is still running.
+pub fn run_scratch(
+    command: String,
+    cmd: Expression,
+    sender: Sender<ExternalEvent>,
+    max_output: usize,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let handle = Arc::new(in_own_group(cmd).reader()?);
+    let deadline = Deadline::start(timeout, &handle, |_| {});
+    thread::spawn(move || {
+        let mut output = OutputTail::new(max_output);
+        let mut buf = [0; 8192];
+        loop {
+            match (&*handle).read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => output.push(&buf[..read]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    if deadline.timed_out().is_none() {
+                        sender.send(ExternalEvent::Error(err.into())).ok();
+                    }
+                    break;
+                }
+            }
+        }
+        let timed_out = deadline.timed_out();
+        drop(deadline);
+        let status = handle
+            .try_wait()
+            .ok()
+            .flatten()
+            .and_then(|out| out.status.code());
+        let mut output = output.into_string();
+        if let Some(after) = timed_out {
+            push_timeout(&mut output, after);
+        }
+        sender
+            .send(ExternalEvent::ScratchOutput {
+                command,
+                output,
+                status,
+            })
+            .ok();
+    });
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use duct::cmd;
+    use test_log::test;
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn kill_ends_command_right_away() {
+        let (send, recv) = unbounded();
+        let command = run_cmd(3, cmd!("sleep", "10"), send, 1024, None).unwrap();
+        assert!(command.pid().is_some());
+        command.kill();
+        let finished = recv
+            .iter()
+            .find(|event| matches!(event, ExternalEvent::CommandFinished(_)));
+        assert!(matches!(finished, Some(ExternalEvent::CommandFinished(3))));
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn stopping_ends_what_the_command_started() {
+        for kill in [true, false] {
+            let (send, recv) = unbounded();
+            let started = Instant::now();
+            let command = run_cmd(
+                6,
+                cmd!("sh", "-c", "sleep 10; echo after"),
+                send,
+                1024,
+                None,
+            )
+            .unwrap();
+            if kill {
+                command.kill();
+            } else {
+                command.stop();
+            }
+            // The pipe only closes once the sleep started by sh is gone too
+            assert!(recv
+                .iter()
+                .any(|event| matches!(event, ExternalEvent::CommandFinished(6))));
+            assert!(started.elapsed() < Duration::from_secs(5));
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn commands_are_killed_once_they_time_out() {
+        let (send, recv) = unbounded();
+        let timeout = Some(Duration::from_millis(100));
+        run_cmd(
+            2,
+            cmd!("sh", "-c", "echo started; sleep 10"),
+            send,
+            1024,
+            timeout,
+        )
+        .unwrap();
+        let events = recv.iter().take(3).collect::<Vec<_>>();
+        assert!(matches!(
+            events[0],
+            ExternalEvent::CommandTimedOut { id: 2, .. }
+        ));
+        assert!(matches!(
+            &events[1],
+            ExternalEvent::CommandOutput(out) if out == "started\n[timed out after 100ms]\n"
+        ));
+        assert!(matches!(events[2], ExternalEvent::CommandFinished(2)));
+
+        // What the command started is stopped with it, instead of keeping its output open
+        let (send, recv) = unbounded();
+        let started = Instant::now();
+        run_cmd(
+            4,
+            cmd!("sh", "-c", "sleep 3; echo after"),
+            send,
+            1024,
+            timeout,
+        )
+        .unwrap();
+        assert!(matches!(
+            recv.recv().unwrap(),
+            ExternalEvent::CommandTimedOut { id: 4, .. }
+        ));
+        assert!(recv
+            .iter()
+            .any(|event| matches!(event, ExternalEvent::CommandFinished(4))));
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // Finishing in time stops the wait to kill it
+        let (send, recv) = unbounded();
+        run_cmd(5, cmd!("true"), send, 1024, Some(Duration::from_secs(10))).unwrap();
+        let events = recv.iter().take(2).collect::<Vec<_>>();
+        assert!(matches!(&events[0], ExternalEvent::CommandOutput(out) if out.is_empty()));
+        assert!(matches!(events[1], ExternalEvent::CommandFinished(5)));
+    }
+
+    #[test]
+    fn keeps_end_of_long_output() {
+        let mut output = OutputTail::new(8);
+        output.push(b"first\nsecond\n");
+        output.push(b"third\n");
+        assert_eq!("[13 earlier bytes not kept]\nthird\n", output.into_string());
+
+        let mut output = OutputTail::new(8);
+        output.push(b"short\n");
+        assert_eq!("short\n", output.into_string());
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn chatty_command_output_is_capped() {
+        let (send, recv) = unbounded();
+        run_cmd(0, cmd!("sh", "-c", "yes | head -n 100000"), send, 100, None).unwrap();
+        let output = recv
+            .iter()
+            .find_map(|event| match event {
+                ExternalEvent::CommandOutput(out) => Some(out),
+                _ => None,
+            })
+            .unwrap();
+        assert!(output.starts_with("[199902 earlier bytes not kept]\n"));
+        assert_eq!(49, output.lines().skip(1).count());
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn scratch_output_comes_with_exit_status() {
+        let (send, recv) = unbounded();
+        let cmd = cmd!("sh", "-c", "echo out; echo err >&2; exit 3")
+            .stderr_to_stdout()
+            .unchecked();
+        run_scratch("failing".to_owned(), cmd, send, 1024, None).unwrap();
+        let event = recv.recv().unwrap();
+        assert!(matches!(
+            event,
+            ExternalEvent::ScratchOutput { command, output, status: Some(3) }
+                if command == "failing" && output == "out\nerr\n"
+        ));
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn closing_stdout_early_is_not_an_error() {
+        let (send, recv) = unbounded();
+        let cmd = cmd!("sh", "-c", "echo done; exec >&-; sleep 0.1").unchecked();
+        run_cmd(1, cmd, send, 1024, None).unwrap();
+        let events = recv.iter().take(2).collect::<Vec<_>>();
+        assert!(matches!(&events[0], ExternalEvent::CommandOutput(out) if out == "done\n"));
+        assert!(matches!(events[1], ExternalEvent::CommandFinished(1)));
+    }
+}
@@ -0,0 +1,29 @@
+use super::ExternalEvent;
+use anyhow::Result;
+use crossbeam_channel::Sender;
+
+/// Send `ExternalEvent::Terminate` when projectable is asked to exit by a signal
+#[cfg(not(target_os = "windows"))]
+pub fn signal_watch(sender: Sender<ExternalEvent>) -> Result<()> {
+    use signal_hook::{
+        consts::{SIGHUP, SIGINT, SIGTERM},
+        iterator::Signals,
+    };
+    use std::thread;
+
+    let mut signals = Signals::new([SIGTERM, SIGINT, SIGHUP])?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            if sender.send(ExternalEvent::Terminate).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Windows has no signals to watch, closing the console ends projectable directly
+#[cfg(target_os = "windows")]
+pub fn signal_watch(_sender: Sender<ExternalEvent>) -> Result<()> {
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use super::{SortMode, SortOrder};
 use anyhow::{bail, Context, Result};
 use bitvec::slice::BitSlice;
 use itertools::Itertools;
@@ -6,7 +7,6 @@ use std::{
     borrow::Cow,
     cmp::Ordering,
     collections::HashMap,
-    iter,
     ops::RangeInclusive,
     path::{Component, Path, PathBuf},
 };
@@ -19,14 +19,7 @@ pub enum Item {
 
 impl Ord for Item {
     fn cmp(&self, other: &Self) -> Ordering {
-        let cow1 = self.path().as_os_str().to_string_lossy();
-        let cow2 = other.path().as_os_str().to_string_lossy();
-        match (cow1, cow2) {
-            (Cow::Borrowed(s1), Cow::Borrowed(s2)) => human_sort::compare(s1, s2),
-            (Cow::Borrowed(s1), Cow::Owned(s2)) => human_sort::compare(s1, &s2),
-            (Cow::Owned(s1), Cow::Borrowed(s2)) => human_sort::compare(&s1, s2),
-            (Cow::Owned(s1), Cow::Owned(s2)) => human_sort::compare(&s1, &s2),
-        }
+        SortMode::default().compare_paths(self.path(), other.path())
     }
 }
 
@@ -94,8 +87,12 @@ pub struct Items {
 
 impl Items {
     pub fn new<T: AsRef<Path>>(files: &[T]) -> Self {
+        Self::with_order(files, SortOrder::default())
+    }
+
+    pub fn with_order<T: AsRef<Path>>(files: &[T], order: SortOrder) -> Self {
         let mut root = files
-            .get(0)
+            .first()
             .map_or(Some(Path::new("")), |path| path.as_ref().parent())
             .unwrap_or(Path::new(""))
             .to_path_buf();
@@ -136,15 +133,49 @@ impl Items {
                 .or_default()
                 .push(Item::File(file.to_path_buf()));
         }
-        // Sort items first by directory name, then flatten into an iterator of `Item`s
-        let items = items
-            .into_iter()
-            .map(|(dir, children)| (Item::Dir(dir), children))
-            .sorted_by(|a, b| Ord::cmp(&a.0, &b.0))
-            .flat_map(|pair| iter::once(pair.0).chain(pair.1))
-            .filter(|item| item.path() != root && item.path().starts_with(&root))
-            .collect();
-        Self { items, root }
+        // Put every directory in its parent next to the files, then flatten from the root down
+        let dirs = items.keys().cloned().collect_vec();
+        for dir in dirs {
+            if let Some(children) = dir.parent().and_then(|parent| items.get_mut(parent)) {
+                children.push(Item::Dir(dir));
+            }
+        }
+        let mut flat = Vec::with_capacity(items.len());
+        Self::flatten(&root, &mut items, order, &mut flat);
+        Self { items: flat, root }
+    }
+
+    /// Only `files`, at one level under `root` instead of nested in their directories
+    pub fn flat<T: AsRef<Path>>(files: &[T], root: &Path, order: SortOrder) -> Self {
+        let mut items = files
+            .iter()
+            .map(|file| Item::File(file.as_ref().to_path_buf()))
+            .collect_vec();
+        items.sort_by(|a, b| order.compare_siblings(a, b));
+        Self {
+            items,
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// Push the children of `dir` to `flat` in order, each directory followed by its own
+    fn flatten(
+        dir: &Path,
+        items: &mut HashMap<PathBuf, Vec<Item>>,
+        order: SortOrder,
+        flat: &mut Vec<Item>,
+    ) {
+        let Some(mut children) = items.remove(dir) else {
+            return;
+        };
+        children.sort_by(|a, b| order.compare_siblings(a, b));
+        for child in children {
+            let sub = (!child.is_file()).then(|| child.path().to_path_buf());
+            flat.push(child);
+            if let Some(sub) = sub {
+                Self::flatten(&sub, items, order, flat);
+            }
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -245,12 +276,17 @@ impl Items {
         T: Into<ItemsIndex<'a>>,
     {
         let index = self.resolve_index(from).context("item not found")?;
-        let item = &mut self.items[index];
-        if to.as_ref().components().count() != item.path().components().count() {
+        let from = self.items[index].path().to_path_buf();
+        if to.as_ref().components().count() != from.components().count() {
             bail!("cannot move item, just rename");
         }
 
-        *item.path_mut() = to.as_ref().to_path_buf();
+        let children = self.get_children(index)?;
+        *self.items[index].path_mut() = to.as_ref().to_path_buf();
+        for item in self.items[children].iter_mut().skip(1) {
+            let new_path = to.as_ref().join(item.path().strip_prefix(&from)?);
+            *item.path_mut() = new_path;
+        }
 
         Ok(())
     }
@@ -308,11 +344,11 @@ impl Items {
         &self.root
     }
 
-    pub fn iter(&self) -> std::slice::Iter<Item> {
+    pub fn iter(&self) -> std::slice::Iter<'_, Item> {
         self.into_iter()
     }
 
-    pub fn iter_mut(&mut self) -> std::slice::IterMut<Item> {
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Item> {
         self.into_iter()
     }
 
@@ -429,9 +465,9 @@ mod tests {
         let items = Items::new(files);
         assert_eq!(
             vec![
-                Item::File("/test2.txt".into()),
                 Item::Dir("/test".into()),
                 Item::File("/test/test.txt".into()),
+                Item::File("/test2.txt".into()),
             ],
             items.items
         );
@@ -443,11 +479,11 @@ mod tests {
         let items = Items::new(files);
         assert_eq!(
             vec![
-                Item::File("/test2.txt".into()),
                 Item::Dir("/test".into()),
                 Item::Dir("/test/test".into()),
                 Item::Dir("/test/test/test".into()),
                 Item::File("/test/test/test/test.txt".into()),
+                Item::File("/test2.txt".into()),
             ],
             items.items
         );
@@ -459,10 +495,10 @@ mod tests {
         let items = Items::new(files);
         assert_eq!(
             vec![
-                Item::File("/test.txt".into()),
                 Item::Dir("/test".into()),
                 Item::File("/test/test.txt".into()),
-                Item::File("/test/test2.txt".into())
+                Item::File("/test/test2.txt".into()),
+                Item::File("/test.txt".into())
             ],
             items.items
         );
@@ -497,11 +533,11 @@ mod tests {
         let items = Items::new(files);
         assert_eq!(
             vec![
-                Item::File("/test.txt".into()),
-                Item::File("/test2.txt".into()),
                 Item::Dir("/test".into()),
                 Item::File("/test/test.txt".into()),
                 Item::File("/test/test2.txt".into()),
+                Item::File("/test.txt".into()),
+                Item::File("/test2.txt".into()),
             ],
             items.items
         );
@@ -513,9 +549,9 @@ mod tests {
         let items = Items::new(files);
         assert_eq!(
             vec![
-                Item::File("/test2.txt".into()),
                 Item::Dir("/test".into()),
                 Item::File("/test/test.txt".into()),
+                Item::File("/test2.txt".into()),
             ],
             items.items
         );
@@ -527,9 +563,9 @@ mod tests {
         let items = Items::new(files);
         assert_eq!(
             vec![
-                Item::File("/test2.txt".into()),
                 Item::Dir("/test".into()),
                 Item::File("/test/test.txt".into()),
+                Item::File("/test2.txt".into()),
             ],
             items.items
         );
@@ -541,11 +577,11 @@ mod tests {
         let items = Items::new(files);
         assert_eq!(
             vec![
-                Item::File("/test2.txt".into()),
                 Item::Dir("/test".into()),
                 Item::Dir("/test/test".into()),
                 Item::Dir("/test/test/test".into()),
-                Item::File("/test/test/test/test.txt".into())
+                Item::File("/test/test/test/test.txt".into()),
+                Item::File("/test2.txt".into())
             ],
             items.items
         );
@@ -557,11 +593,11 @@ mod tests {
         let items = Items::new(files);
         assert_eq!(
             vec![
-                Item::File("/test2.txt".into()),
                 Item::Dir("/test".into()),
                 Item::Dir("/test/test".into()),
                 Item::Dir("/test/test/test".into()),
-                Item::File("/test/test/test/test.txt".into())
+                Item::File("/test/test/test/test.txt".into()),
+                Item::File("/test2.txt".into())
             ],
             items.items
         );
@@ -584,9 +620,9 @@ mod tests {
         let mut items = Items::new(&["/foo", "/bar", "/baz"]);
         assert_eq!(
             vec![
-                &Item::File("/foo".into()),
                 &Item::File("/bar".into()),
                 &Item::File("/baz".into()),
+                &Item::File("/foo".into()),
             ],
             items.iter().collect_vec()
         );
@@ -622,7 +658,7 @@ mod tests {
             "/root/test/test2.txt",
             "/root/test/test3.txt",
         ]);
-        assert_eq!(Some(2..=5), items.remove(2));
+        assert_eq!(Some(0..=3), items.remove(0));
         assert_eq!(
             vec![
                 Item::File("/root/test.txt".into()),
@@ -635,20 +671,26 @@ mod tests {
     #[test]
     fn can_remove_directories_and_removes_until_end_if_children_are_at_end() {
         let mut items = Items::new(&[
-            "/root/test.txt",
+            "/root/a/test.txt",
             "/root/test",
             "/root/test/test.txt",
             "/root/test/test2.txt",
             "/root/test/test3.txt",
         ]);
-        assert_eq!(Some(1..=4), items.remove(1));
-        assert_eq!(vec![Item::File("/root/test.txt".into()),], items.items);
+        assert_eq!(Some(2..=5), items.remove(2));
+        assert_eq!(
+            vec![
+                Item::Dir("/root/a".into()),
+                Item::File("/root/a/test.txt".into())
+            ],
+            items.items
+        );
     }
 
     #[test]
     fn can_remove_single_directory() {
         let mut items = Items::new(&["/root/test.txt", "/root/test", "/root/test/test.txt"]);
-        assert_eq!(Some(1..=2), items.remove(1));
+        assert_eq!(Some(0..=1), items.remove(0));
         assert_eq!(vec![Item::File("/root/test.txt".into()),], items.items);
     }
 
@@ -687,19 +729,19 @@ mod tests {
             "/root/test2/test.txt",
         ]);
         assert_eq!(
-            2,
+            1,
             items
                 .add(Item::File("/root/test/test2.txt".into()))
                 .unwrap()
         );
         assert_eq!(
             vec![
-                Item::File("/root/test.txt".into()),
                 Item::Dir("/root/test".into()),
                 Item::File("/root/test/test2.txt".into()),
                 Item::File("/root/test/test.txt".into()),
                 Item::Dir("/root/test2".into()),
                 Item::File("/root/test2/test.txt".into()),
+                Item::File("/root/test.txt".into()),
             ],
             items.items
         );
@@ -715,9 +757,9 @@ mod tests {
         let items = Items::new(&["relative.txt", "test/relative.txt"]);
         assert_eq!(
             vec![
-                Item::File("relative.txt".into()),
                 Item::Dir("test".into()),
-                Item::File("test/relative.txt".into())
+                Item::File("test/relative.txt".into()),
+                Item::File("relative.txt".into())
             ],
             items.items
         );
@@ -742,7 +784,7 @@ mod tests {
     #[test]
     fn can_move_files() {
         let mut items = Items::new(&["/root/test.txt", "/root/test/test2.txt"]);
-        assert!(items.mv(0, "/root/test").is_ok());
+        assert!(items.mv(2, "/root/test").is_ok());
         assert_eq!(
             vec![
                 Item::Dir("/root/test".into()),
@@ -794,4 +836,20 @@ mod tests {
         assert!(items.rename(0, "/root/test2.txt").is_ok());
         assert_eq!(vec![Item::File("/root/test2.txt".into())], items.items);
     }
+
+    #[test]
+    fn renaming_dir_renames_children() {
+        let mut items = Items::new(&["/root/dir/a.txt", "/root/dir/sub/b.txt", "/root/c.txt"]);
+        items.rename("/root/dir", "/root/new").unwrap();
+        assert_eq!(
+            vec![
+                Item::Dir("/root/new".into()),
+                Item::Dir("/root/new/sub".into()),
+                Item::File("/root/new/sub/b.txt".into()),
+                Item::File("/root/new/a.txt".into()),
+                Item::File("/root/c.txt".into()),
+            ],
+            items.items
+        );
+    }
 }
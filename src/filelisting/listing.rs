@@ -1,8 +1,12 @@
-use super::items::*;
+use super::{items::*, SortOrder};
 use anyhow::{anyhow, Context, Result};
 use bitvec::prelude::*;
 use log::debug;
-use std::path::Path;
+use std::{
+    cell::OnceCell,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, Default)]
 pub struct FileListing {
@@ -13,22 +17,75 @@ pub struct FileListing {
     /// A 1:1 track of folded items. It's length should **always** be the same as `items`
     folded: BitVec,
     cache: Vec<usize>,
+    /// Amount of direct children of each directory, counted when first needed
+    child_counts: OnceCell<HashMap<PathBuf, usize>>,
+    /// Made with `flat`, so reordering keeps it at one level
+    is_flat: bool,
 }
 
 impl FileListing {
     pub fn new<T: AsRef<Path>>(items: &[T]) -> Self {
-        let items = Items::new(items);
+        Self::with_order(items, SortOrder::default())
+    }
+
+    pub fn with_order<T: AsRef<Path>>(items: &[T], order: SortOrder) -> Self {
+        Self::from_items(Items::with_order(items, order), false)
+    }
+
+    /// Every one of `files` at one level under `root`, ordered by their whole path
+    pub fn flat<T: AsRef<Path>>(files: &[T], root: &Path, order: SortOrder) -> Self {
+        Self::from_items(Items::flat(files, root, order), true)
+    }
+
+    fn from_items(items: Items, is_flat: bool) -> Self {
         let len = items.len();
         let mut listing = Self {
             items,
             folded: BitVec::repeat(false, len),
             selected: 0,
             cache: Vec::new(),
+            child_counts: OnceCell::new(),
+            is_flat,
         };
         listing.populate_cache();
         listing
     }
 
+    pub fn is_flat(&self) -> bool {
+        self.is_flat
+    }
+
+    /// Sort the same items again in `order`, keeping what is folded and selected
+    pub fn reorder(&mut self, order: SortOrder) {
+        let selected = self.selected_item().map(|item| item.path().to_path_buf());
+        let folded: HashSet<PathBuf> = self
+            .items
+            .iter()
+            .zip(self.folded.iter())
+            .filter(|(_, folded)| **folded)
+            .map(|(item, _)| item.path().to_path_buf())
+            .collect();
+        let paths: Vec<&Path> = self.items.iter().map(Item::path).collect();
+        let mut listing = if self.is_flat {
+            Self::flat(&paths, self.root(), order)
+        } else {
+            Self::with_order(&paths, order)
+        };
+        for (index, item) in listing.items.items().iter().enumerate() {
+            listing.folded.set(index, folded.contains(item.path()));
+        }
+        listing.populate_cache();
+        if let Some(index) = selected.and_then(|path| {
+            listing
+                .items
+                .iter()
+                .position(|item| item.path() == path.as_path())
+        }) {
+            listing.selected = index;
+        }
+        *self = listing;
+    }
+
     fn populate_cache(&mut self) {
         self.cache = self.iter().map(|(abs_index, _)| abs_index).collect();
     }
@@ -122,9 +179,12 @@ impl FileListing {
     }
 
     pub fn select_next_n(&mut self, n: usize) {
-        let Some(new_selected) = self.iter().skip(self.selected().unwrap_or_default()).nth(n) else {
+        let Some(new_selected) = self.iter().skip(self.selected().unwrap_or_default()).nth(n)
+        else {
             // Set to last if the jump is over the limit
-            self.selected = self.relative_to_absolute(self.len() - 1).unwrap_or_default();
+            self.selected = self
+                .relative_to_absolute(self.len() - 1)
+                .unwrap_or_default();
             return;
         };
         self.selected = new_selected.0;
@@ -190,47 +250,114 @@ impl FileListing {
         self.items.root()
     }
 
+    /// Amount of items directly inside `dir`
+    pub fn child_count(&self, dir: &Path) -> usize {
+        let counts = self.child_counts.get_or_init(|| {
+            let mut counts = HashMap::new();
+            for parent in self.items.iter().filter_map(|item| item.path().parent()) {
+                *counts.entry(parent.to_path_buf()).or_default() += 1;
+            }
+            counts
+        });
+        counts.get(dir).copied().unwrap_or_default()
+    }
+
+    fn selected_path(&self) -> Option<PathBuf> {
+        self.selected_item().map(|item| item.path().to_path_buf())
+    }
+
+    /// Select `path` again after items moved around, returning whether it is still there
+    fn reselect(&mut self, path: Option<&Path>) -> bool {
+        let Some(index) =
+            path.and_then(|path| self.items.iter().position(|item| item.path() == path))
+        else {
+            return false;
+        };
+        self.selected = index;
+        true
+    }
+
     pub fn add(&mut self, item: Item) {
         let is_dir = !item.is_file();
+        let selected = self.selected_path();
+        self.child_counts.take();
         match self.items.add(item) {
             Ok(inserted_at) => self.folded.insert(inserted_at, is_dir),
             Err(err) => debug!("swallowed error: {err}"),
         }
+        self.reselect(selected.as_deref());
         self.populate_cache();
     }
 
+    /// Remove an item and everything under it. If the selection was removed, the next sibling is
+    /// selected, or the item shown before it when there is none.
     pub fn remove<'a, T>(&mut self, index: T) -> Result<()>
     where
         T: Into<ItemsIndex<'a>>,
     {
+        let index = index.into();
+        let selected = self.selected_path();
+        let parent = match &index {
+            ItemsIndex::Number(n) => self.items.get(*n).map(Item::path),
+            ItemsIndex::Path(path) => Some(path.as_ref()),
+        }
+        .and_then(Path::parent)
+        .map(Path::to_path_buf);
         let removed = self
             .items
             .remove(index)
             .ok_or_else(|| anyhow!("invalid remove target"))?;
 
-        self.folded.drain(removed);
-        if self.selected >= self.items.len() {
-            self.selected = self.items.len().saturating_sub(1);
+        self.child_counts.take();
+        self.folded.drain(removed.clone());
+        if !self.reselect(selected.as_deref()) {
+            let start = *removed.start();
+            let next_is_sibling = self
+                .items
+                .get(start)
+                .is_some_and(|item| item.path().parent() == parent.as_deref());
+            self.selected = if next_is_sibling {
+                start
+            } else {
+                // The cache is still from before the removal, so it tells what was shown
+                self.cache
+                    .iter()
+                    .rev()
+                    .find(|&&index| index < start)
+                    .copied()
+                    .unwrap_or_default()
+            };
         }
         self.populate_cache();
 
         Ok(())
     }
 
+    /// Move an item into the directory `new`. A selection under the item moves along with it.
     pub fn mv<'a, T>(&mut self, index: T, new: impl AsRef<Path>) -> Result<()>
     where
         T: Into<ItemsIndex<'a>>,
     {
-        let (moved, idx) = self.items.mv(index, new)?;
-        self.folded.as_mut_bitslice().swap_range(moved, idx);
+        let selected = self.selected_path();
+        let (moved, idx) = self.items.mv(index, &new)?;
+        self.child_counts.take();
+        self.folded.as_mut_bitslice().swap_range(moved.clone(), idx);
+        // The moved items keep their order, starting at `idx`
+        if let Some(offset) = moved.clone().position(|index| index == self.selected) {
+            self.selected = idx + offset;
+        } else {
+            self.reselect(selected.as_deref());
+        }
         self.populate_cache();
         Ok(())
     }
 
+    /// Rename an item, along with the paths of everything under it
     pub fn rename<'a, T>(&mut self, index: T, new: impl AsRef<Path>) -> Result<()>
     where
         T: Into<ItemsIndex<'a>>,
     {
+        self.child_counts.take();
         self.items.rename(index, new)
     }
 
@@ -412,8 +539,8 @@ mod tests {
             "/root/test/test2.txt",
         ]);
 
-        items.fold(1);
-        assert_eq!(bitvec![0, 1, 0, 0], items.folded);
+        items.fold(0);
+        assert_eq!(bitvec![1, 0, 0, 0], items.folded);
     }
 
     #[test]
@@ -424,11 +551,11 @@ mod tests {
             "/root/test/test2.txt",
         ]);
 
-        items.fold(1);
+        items.fold(0);
         assert_eq!(
             vec![
-                &Item::File("/root/test.txt".into()),
-                &Item::Dir("/root/test".into())
+                &Item::Dir("/root/test".into()),
+                &Item::File("/root/test.txt".into())
             ],
             items.items()
         );
@@ -443,11 +570,11 @@ mod tests {
             "/root/test/test/test.txt",
         ]);
 
-        items.fold(1);
+        items.fold(0);
         assert_eq!(
             vec![
-                &Item::File("/root/test.txt".into()),
-                &Item::Dir("/root/test".into())
+                &Item::Dir("/root/test".into()),
+                &Item::File("/root/test.txt".into())
             ],
             items.items()
         );
@@ -488,12 +615,12 @@ mod tests {
             "/root/test/test/test.txt",
         ]);
 
-        items.fold(4);
         items.fold(1);
+        items.fold(0);
         assert_eq!(
             vec![
-                &Item::File("/root/test.txt".into()),
-                &Item::Dir("/root/test".into())
+                &Item::Dir("/root/test".into()),
+                &Item::File("/root/test.txt".into())
             ],
             items.items()
         );
@@ -520,12 +647,12 @@ mod tests {
             "/root/test/test/test.txt",
         ]);
 
-        items.fold(1);
+        items.fold(0);
         let visible = items.iter().collect_vec();
         assert_eq!(
             vec![
-                (0, &Item::File("/root/test.txt".into())),
-                (1, &Item::Dir("/root/test".into()))
+                (0, &Item::Dir("/root/test".into())),
+                (5, &Item::File("/root/test.txt".into()))
             ],
             visible
         );
@@ -541,13 +668,13 @@ mod tests {
             "/root/test2/test.txt",
         ]);
 
-        items.fold(1);
+        items.fold(0);
         assert_eq!(0, items.selected().unwrap());
         items.select_next_n(2);
         assert_eq!(2, items.selected().unwrap());
         items.select_prev_n(2);
         assert_eq!(0, items.selected().unwrap());
-        assert_eq!(&Item::Dir("/root/test2".into()), items.select(2).unwrap());
+        assert_eq!(&Item::Dir("/root/test2".into()), items.select(1).unwrap());
     }
 
     #[test]
@@ -559,13 +686,13 @@ mod tests {
             "/root/test/test/test.txt",
             "/root/test2/test.txt",
         ]);
+        items.fold(0);
         items.fold(1);
-        items.fold(2);
         assert_eq!(
             vec![
-                &Item::File("/root/test.txt".into()),
                 &Item::Dir("/root/test".into()),
-                &Item::Dir("/root/test2".into())
+                &Item::Dir("/root/test2".into()),
+                &Item::File("/root/test.txt".into())
             ],
             items.items()
         );
@@ -579,7 +706,6 @@ mod tests {
             "/root/test/test2.txt",
         ]);
 
-        items.select_next();
         items.toggle_fold();
         assert!(items.is_folded(items.selected().unwrap()).unwrap());
     }
@@ -594,11 +720,10 @@ mod tests {
             "/root/test2/test2.txt",
         ]);
 
-        items.select_next();
         items.toggle_fold();
         items.select_next();
         items.select_next();
-        assert_eq!(5, items.selected);
+        assert_eq!(4, items.selected);
     }
 
     #[test]
@@ -609,10 +734,11 @@ mod tests {
             "/root/test/test2.txt",
         ]);
 
+        items.select(3);
         items.toggle_fold();
-        assert!(!items.is_folded(0).unwrap());
-        items.fold(0);
-        assert!(!items.is_folded(0).unwrap());
+        assert!(!items.is_folded(3).unwrap());
+        items.fold(3);
+        assert!(!items.is_folded(3).unwrap());
     }
 
     #[test]
@@ -625,7 +751,7 @@ mod tests {
         ]);
 
         items.fold_all();
-        assert_eq!(bitvec![0, 1, 0, 0, 1, 0], items.folded);
+        assert_eq!(bitvec![1, 0, 0, 1, 0, 0], items.folded);
     }
 
     #[test]
@@ -652,7 +778,7 @@ mod tests {
 
         items.fold_all();
         items.add(Item::File("/root/test2.txt".into()));
-        assert_eq!(bitvec![0, 0, 1, 0, 0], items.folded);
+        assert_eq!(bitvec![0, 1, 0, 0, 0], items.folded);
     }
 
     #[test]
@@ -679,15 +805,31 @@ mod tests {
         ]);
 
         items.fold_all();
-        assert!(items.remove(1).is_ok());
+        assert!(items.remove(0).is_ok());
         assert_eq!(
             vec![
-                &Item::File("/root/test.txt".into()),
                 &Item::Dir("/root/test2".into()),
+                &Item::File("/root/test.txt".into()),
             ],
             items.items()
         );
-        assert_eq!(bitvec![0, 1, 0], items.folded);
+        assert_eq!(bitvec![1, 0, 0], items.folded);
+    }
+
+    #[test]
+    fn counts_direct_children() {
+        let mut items = FileListing::new(&[
+            "/root/test.txt",
+            "/root/test/test.txt",
+            "/root/test/test2.txt",
+            "/root/test/test/test.txt",
+        ]);
+
+        assert_eq!(3, items.child_count(Path::new("/root/test")));
+        assert_eq!(1, items.child_count(Path::new("/root/test/test")));
+        assert_eq!(0, items.child_count(Path::new("/root/test.txt")));
+        assert!(items.remove("/root/test/test2.txt").is_ok());
+        assert_eq!(2, items.child_count(Path::new("/root/test")));
     }
 
     #[test]
@@ -708,7 +850,7 @@ mod tests {
         let mut items = FileListing::new(&["/root/test/test/test/test.txt", "/root/test.txt"]);
 
         assert!(items.select("/root/test/test/test").is_some());
-        assert_eq!(3, items.selected().unwrap());
+        assert_eq!(2, items.selected().unwrap());
     }
 
     #[test]
@@ -721,7 +863,7 @@ mod tests {
         ]);
 
         items.fold_all();
-        items.unfold(1);
+        items.unfold(0);
         items.select_last();
         items.select_next();
 
@@ -737,8 +879,8 @@ mod tests {
             "/root/test2/test.txt",
         ]);
 
-        assert!(items.fold_under(1).is_ok());
-        assert_eq!(bitvec![0, 1, 1, 0, 1, 0, 0, 0], items.folded);
+        assert!(items.fold_under(0).is_ok());
+        assert_eq!(bitvec![1, 1, 0, 1, 0, 0, 0, 0], items.folded);
     }
 
     #[test]
@@ -751,8 +893,8 @@ mod tests {
         ]);
 
         items.fold_all();
-        assert!(items.unfold_under(1).is_ok());
-        assert_eq!(bitvec![0, 0, 0, 0, 0, 0, 1, 0], items.folded);
+        assert!(items.unfold_under(0).is_ok());
+        assert_eq!(bitvec![0, 0, 0, 0, 0, 1, 0, 0], items.folded);
     }
 
     #[test]
@@ -774,4 +916,68 @@ mod tests {
 
         assert_eq!(bitvec![0, 0, 1, 0, 0], items.folded);
     }
+
+    #[test]
+    fn adding_and_removing_keeps_selected_path() {
+        let mut items = FileListing::new(&["/root/a/a.txt", "/root/b.txt", "/root/c.txt"]);
+        items.select(Path::new("/root/b.txt"));
+        items.add(Item::File("/root/a/new.txt".into()));
+        items.add(Item::File("/root/new.txt".into()));
+        assert_eq!(
+            Path::new("/root/b.txt"),
+            items.selected_item().unwrap().path()
+        );
+
+        items.remove(Path::new("/root/a")).unwrap();
+        assert_eq!(
+            Path::new("/root/b.txt"),
+            items.selected_item().unwrap().path()
+        );
+        // The folds after the removed items stay on the same directories
+        assert_eq!(bitvec![0, 0, 0], items.folded);
+    }
+
+    #[test]
+    fn removing_selected_goes_to_nearest_neighbor() {
+        let mut items = FileListing::new(&[
+            "/root/a/a.txt",
+            "/root/a/b.txt",
+            "/root/c/c.txt",
+            "/root/d.txt",
+        ]);
+        items.select(Path::new("/root/a/a.txt"));
+        items.remove(Path::new("/root/a/a.txt")).unwrap();
+        assert_eq!(
+            Path::new("/root/a/b.txt"),
+            items.selected_item().unwrap().path()
+        );
+
+        // Without a next sibling, the item shown before is selected instead of leaving the dir
+        items.remove(Path::new("/root/a/b.txt")).unwrap();
+        assert_eq!(Path::new("/root/a"), items.selected_item().unwrap().path());
+
+        items.fold(Path::new("/root/c"));
+        items.select(Path::new("/root/d.txt"));
+        items.remove(Path::new("/root/d.txt")).unwrap();
+        assert_eq!(Path::new("/root/c"), items.selected_item().unwrap().path());
+        assert!(items.is_folded(Path::new("/root/c")).unwrap());
+    }
+
+    #[test]
+    fn moving_keeps_selection_on_moved_item() {
+        let mut items = FileListing::new(&["/root/a/a.txt", "/root/b/b.txt", "/root/c.txt"]);
+        items.select(Path::new("/root/c.txt"));
+        items.mv(Path::new("/root/c.txt"), "/root/a").unwrap();
+        assert_eq!(
+            Path::new("/root/a/c.txt"),
+            items.selected_item().unwrap().path()
+        );
+
+        items.select(Path::new("/root/b/b.txt"));
+        items.mv(Path::new("/root/a/a.txt"), "/root/b").unwrap();
+        assert_eq!(
+            Path::new("/root/b/b.txt"),
+            items.selected_item().unwrap().path()
+        );
+    }
 }
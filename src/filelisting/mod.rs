@@ -1,5 +1,7 @@
 mod items;
 mod listing;
+mod sort;
 
 pub use items::Item;
 pub use listing::*;
+pub use sort::*;
@@ -0,0 +1,257 @@
+use super::Item;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, path::Path};
+
+/// How names in the tree are ordered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortMode {
+    /// Numbers by their value and letters regardless of case, so `File2` comes before `File10`
+    #[default]
+    Natural,
+    /// Plain byte order, so `File10` comes before `File2` and `Z` before `a`
+    Bytewise,
+}
+
+impl SortMode {
+    pub fn compare_names(self, a: &str, b: &str) -> Ordering {
+        match self {
+            Self::Natural => natural_cmp(a, b),
+            Self::Bytewise => a.cmp(b),
+        }
+    }
+
+    /// Compare paths a component at a time, so children always follow their parent
+    pub fn compare_paths(self, a: &Path, b: &Path) -> Ordering {
+        let (mut a, mut b) = (a.components(), b.components());
+        loop {
+            let ordering = match (a.next(), b.next()) {
+                (Some(x), Some(y)) => match self {
+                    Self::Natural => natural_cmp(
+                        &x.as_os_str().to_string_lossy(),
+                        &y.as_os_str().to_string_lossy(),
+                    ),
+                    Self::Bytewise => x.as_os_str().cmp(y.as_os_str()),
+                },
+                (None, None) => return Ordering::Equal,
+                (None, Some(_)) => return Ordering::Less,
+                (Some(_), None) => return Ordering::Greater,
+            };
+            if ordering.is_ne() {
+                return ordering;
+            }
+        }
+    }
+}
+
+/// How items next to each other in the tree are ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortOrder {
+    pub mode: SortMode,
+    /// Group directories before files instead of mixing them in by name
+    pub dirs_first: bool,
+    /// Order names backwards
+    pub reverse: bool,
+    /// When reversing, also put files before directories
+    pub reverse_groups: bool,
+}
+
+impl Default for SortOrder {
+    fn default() -> Self {
+        Self {
+            mode: SortMode::default(),
+            dirs_first: true,
+            reverse: false,
+            reverse_groups: false,
+        }
+    }
+}
+
+impl SortOrder {
+    /// Compare items in the same directory
+    pub fn compare_siblings(self, a: &Item, b: &Item) -> Ordering {
+        let mut group = if self.dirs_first {
+            a.is_file().cmp(&b.is_file())
+        } else {
+            Ordering::Equal
+        };
+        let mut name = self.mode.compare_paths(a.path(), b.path());
+        if self.reverse {
+            name = name.reverse();
+            if self.reverse_groups {
+                group = group.reverse();
+            }
+        }
+        group.then(name)
+    }
+}
+
+/// Compare runs of digits by their value and everything else case-insensitively. The case and
+/// leading zeros only break ties, with uppercase first.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a, b);
+    loop {
+        let (c, d) = match (x.chars().next(), y.chars().next()) {
+            (Some(c), Some(d)) => (c, d),
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+        };
+        let ordering = if c.is_ascii_digit() && d.is_ascii_digit() {
+            let (m, n);
+            (m, x) = split_number(x);
+            (n, y) = split_number(y);
+            // Compared as text, so numbers of any length work
+            let (m, n) = (m.trim_start_matches('0'), n.trim_start_matches('0'));
+            m.len().cmp(&n.len()).then_with(|| m.cmp(n))
+        } else {
+            x = &x[c.len_utf8()..];
+            y = &y[d.len_utf8()..];
+            c.to_lowercase().cmp(d.to_lowercase())
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
+
+/// Split the digits at the start of `s` from the rest
+fn split_number(s: &str) -> (&str, &str) {
+    s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn sorted(mode: SortMode, names: &[&'static str]) -> Vec<&'static str> {
+        let mut names = names.to_vec();
+        names.sort_by(|a, b| mode.compare_names(a, b));
+        names
+    }
+
+    #[test]
+    fn natural_order_is_numeric_and_ignores_case() {
+        assert_eq!(
+            vec!["A", "a", "a1", "a2", "a10"],
+            sorted(SortMode::Natural, &["a1", "a10", "a2", "A", "a"])
+        );
+        assert_eq!(
+            vec!["File2", "file10", "FILE99999999999999999999"],
+            sorted(
+                SortMode::Natural,
+                &["FILE99999999999999999999", "file10", "File2"]
+            )
+        );
+        assert_eq!(
+            vec!["a", "B", "b", "C"],
+            sorted(SortMode::Natural, &["b", "C", "a", "B"])
+        );
+    }
+
+    #[test]
+    fn leading_zeros_only_break_ties() {
+        assert_eq!(
+            vec!["v01", "v1", "v2", "v010"],
+            sorted(SortMode::Natural, &["v010", "v2", "v01", "v1"])
+        );
+    }
+
+    #[test]
+    fn bytewise_order_is_kept_available() {
+        assert_eq!(
+            vec!["A", "a", "a1", "a10", "a2"],
+            sorted(SortMode::Bytewise, &["a1", "a10", "a2", "A", "a"])
+        );
+    }
+
+    #[test]
+    fn directories_can_be_grouped_first() {
+        let mut items = vec![
+            Item::File("b".into()),
+            Item::Dir("c".into()),
+            Item::File("a".into()),
+        ];
+        let order = SortOrder {
+            dirs_first: false,
+            ..Default::default()
+        };
+        items.sort_by(|a, b| order.compare_siblings(a, b));
+        assert_eq!(
+            vec![
+                Item::File("a".into()),
+                Item::File("b".into()),
+                Item::Dir("c".into())
+            ],
+            items
+        );
+        items.sort_by(|a, b| SortOrder::default().compare_siblings(a, b));
+        assert_eq!(Item::Dir("c".into()), items[0]);
+    }
+
+    #[test]
+    fn reversing_keeps_or_flips_groups() {
+        let items = vec![
+            Item::File("a".into()),
+            Item::Dir("b".into()),
+            Item::File("c".into()),
+            Item::Dir("d".into()),
+        ];
+        let sorted = |order: SortOrder| {
+            let mut items = items.clone();
+            items.sort_by(|a, b| order.compare_siblings(a, b));
+            items
+                .iter()
+                .map(|item| item.path().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let reversed = SortOrder {
+            reverse: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![
+                Path::new("d"),
+                Path::new("b"),
+                Path::new("c"),
+                Path::new("a")
+            ],
+            sorted(reversed)
+        );
+        assert_eq!(
+            vec![
+                Path::new("c"),
+                Path::new("a"),
+                Path::new("d"),
+                Path::new("b")
+            ],
+            sorted(SortOrder {
+                reverse_groups: true,
+                ..reversed
+            })
+        );
+        assert_eq!(
+            vec![
+                Path::new("d"),
+                Path::new("c"),
+                Path::new("b"),
+                Path::new("a")
+            ],
+            sorted(SortOrder {
+                dirs_first: false,
+                ..reversed
+            })
+        );
+    }
+
+    #[test]
+    fn children_follow_their_parent() {
+        let mut paths = vec![Path::new("a-b"), Path::new("a/b"), Path::new("a")];
+        paths.sort_by(|a, b| SortMode::Natural.compare_paths(a, b));
+        assert_eq!(
+            vec![Path::new("a"), Path::new("a/b"), Path::new("a-b")],
+            paths
+        );
+    }
+}
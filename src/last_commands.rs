@@ -0,0 +1,124 @@
+use crate::{marks, persist};
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io, path::PathBuf};
+
+pub fn get_last_commands_file() -> Option<PathBuf> {
+    marks::get_data_dir().map(|dir| dir.join("last_commands.json"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct LastCommand {
+    /// The special commands of the pattern when `last` was chosen
+    commands: Vec<String>,
+    last: usize,
+}
+
+/// The special command last chosen for each pattern of `special_commands`
+#[derive(Debug, Default)]
+pub struct LastCommands {
+    /// Where choices are saved, kept only in memory if `None`
+    file: Option<PathBuf>,
+    last: HashMap<String, LastCommand>,
+}
+
+impl LastCommands {
+    /// Read the choices saved in the data directory
+    pub fn from_data_file() -> Self {
+        let Some(file) = get_last_commands_file() else {
+            return Self::default();
+        };
+        let last = match fs::read_to_string(&file) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                warn!("ignoring unreadable \"{}\": {err}", file.display());
+                HashMap::new()
+            }),
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    warn!("could not read \"{}\": {err}", file.display());
+                }
+                HashMap::new()
+            }
+        };
+        Self {
+            file: Some(file),
+            last,
+        }
+    }
+
+    /// Index of the command last chosen for `pattern`. Forgotten if `commands` changed since.
+    pub fn get(&mut self, pattern: &str, commands: &[String]) -> Option<usize> {
+        let last = self.last.get(pattern)?;
+        if last.commands == commands && last.last < commands.len() {
+            Some(last.last)
+        } else {
+            self.last.remove(pattern);
+            None
+        }
+    }
+
+    pub fn remember(&mut self, pattern: &str, commands: &[String], chosen: usize) -> Result<()> {
+        let last = LastCommand {
+            commands: commands.to_vec(),
+            last: chosen,
+        };
+        if self.last.get(pattern) == Some(&last) {
+            return Ok(());
+        }
+        self.last.insert(pattern.to_owned(), last);
+        self.write()
+    }
+
+    fn write(&self) -> Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        persist::write_atomic(file, serde_json::to_string(&self.last)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use scopeguard::defer;
+    use serial_test::serial;
+    use std::env;
+    use test_log::test;
+
+    fn commands(commands: &[&str]) -> Vec<String> {
+        commands.iter().map(|&command| command.to_owned()).collect()
+    }
+
+    #[test]
+    fn forgets_choice_when_commands_change() {
+        let mut last = LastCommands::default();
+        let before = commands(&["cargo build", "cargo test"]);
+        last.remember("**/Cargo.toml", &before, 1).unwrap();
+        assert_eq!(Some(1), last.get("**/Cargo.toml", &before));
+
+        let after = commands(&["cargo test", "cargo build"]);
+        assert_eq!(None, last.get("**/Cargo.toml", &after));
+        assert_eq!(None, last.get("**/Cargo.toml", &before));
+    }
+
+    #[test]
+    #[serial]
+    fn choices_are_kept_between_runs() {
+        let temp = TempDir::new().unwrap();
+        env::set_var("PROJECTABLE_DATA_DIR", temp.path().join("data"));
+        defer! {
+            env::remove_var("PROJECTABLE_DATA_DIR");
+        }
+
+        let make = commands(&["make", "make clean"]);
+        LastCommands::from_data_file()
+            .remember("**/Makefile", &make, 1)
+            .unwrap();
+        assert_eq!(
+            Some(1),
+            LastCommands::from_data_file().get("**/Makefile", &make)
+        );
+    }
+}
@@ -6,12 +6,10 @@
     clippy::exit,
     clippy::dbg_macro,
     clippy::checked_conversions,
-    clippy::dbg_macro,
     clippy::debug_assert_with_mut_call,
     clippy::doc_markdown,
-    clippy::empty_enum,
+    clippy::empty_enums,
     clippy::enum_glob_use,
-    clippy::exit,
     clippy::expl_impl_clone_on_copy,
     clippy::explicit_deref_methods,
     clippy::explicit_into_iter_loop,
@@ -37,20 +35,16 @@
     clippy::map_err_ignore,
     clippy::map_flatten,
     clippy::map_unwrap_or,
-    clippy::match_on_vec_items,
     clippy::match_same_arms,
     clippy::match_wild_err_arm,
     clippy::match_wildcard_for_single_variants,
     clippy::mem_forget,
-    clippy::mismatched_target_os,
     clippy::missing_enforced_import_renames,
     clippy::mut_mut,
     clippy::mutex_integer,
     clippy::manual_let_else,
     clippy::manual_string_new,
-    clippy::manual_ok_or,
     clippy::needless_borrow,
-    clippy::mem_forget,
     clippy::needless_continue,
     clippy::needless_collect,
     clippy::option_if_let_else,
@@ -69,13 +63,11 @@
     clippy::string_add_assign,
     clippy::string_add,
     clippy::string_lit_as_bytes,
-    clippy::string_to_string,
     clippy::todo,
     clippy::trait_duplication_in_bounds,
     clippy::unimplemented,
     clippy::unnested_or_patterns,
     clippy::useless_transmute,
-    clippy::string_add,
     clippy::str_to_string,
     clippy::fn_params_excessive_bools,
     clippy::format_push_string,
@@ -87,10 +79,25 @@
     nonstandard_style
 )]
 
+pub mod activity;
 pub mod app;
+pub mod archive;
+pub mod blame;
+pub mod cargo;
+pub mod clipboard;
+pub mod command;
 pub mod config;
+pub mod debounce;
+pub mod editor;
+pub mod encoding;
 pub mod external_event;
 pub mod filelisting;
+pub mod last_commands;
 pub mod marks;
+pub mod persist;
 pub mod queue;
+pub mod search;
+pub mod sequence;
+pub mod session;
+pub mod session_log;
 pub mod ui;
@@ -1,26 +1,28 @@
 use anyhow::{bail, Context, Result};
 use clap::Parser;
-use crossbeam_channel::unbounded;
-use log::{error, warn, LevelFilter};
+use crossbeam_channel::{unbounded, RecvTimeoutError};
+use log::{warn, LevelFilter};
 use projectable::{
-    app::{component::Drawable, App, TerminalEvent},
+    app::{component::Drawable, App, RunningCommand, TerminalEvent},
     config::{self, Config, GlobList, Merge},
-    external_event,
+    editor,
+    external_event::{self, CommandHandle, ExternalEvent},
     marks::{self, Marks},
+    session::Session,
+    session_log,
 };
 use std::{
     cell::RefCell,
+    collections::HashMap,
     env, fs,
     io::{self, Stdout},
-    panic,
-    path::PathBuf,
-    process::Command,
+    mem, panic,
+    path::{Path, PathBuf},
     rc::Rc,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    time::Duration,
 };
 
 use crossterm::{
@@ -29,7 +31,7 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
-use scopeguard::{defer, defer_on_success};
+use scopeguard::defer;
 use tui::{backend::CrosstermBackend, Terminal};
 
 #[derive(Parser, Debug)]
@@ -68,27 +70,19 @@ fn main() -> Result<()> {
         );
         return Ok(());
     } else if args.write_config {
-        let config_file = config::get_config_home()
-            .context("could not find config home")?
-            .join("config.toml");
+        let config_file = config::global_config_file().context("could not find config home")?;
         fs::create_dir_all(
             config_file
                 .parent()
                 .expect("config file should have parent"),
         )
         .context("error making config file")?;
-
-        #[cfg(not(target_os = "windows"))]
-        fs::write(&config_file, include_str!("./config_defaults/unix.toml"))?;
-        #[cfg(target_os = "windows")]
-        fs::write(&config_file, include_str!("./config_defaults/windows.toml"))?;
+        fs::write(&config_file, config::DEFAULT_CONFIG)?;
 
         println!("Wrote to config file at {}!", config_file.display());
         return Ok(());
     } else if args.make_config {
-        let config_file = config::get_config_home()
-            .context("could not find config home")?
-            .join("config.toml");
+        let config_file = config::global_config_file().context("could not find config home")?;
         if config_file.exists() {
             bail!("config file already exists at {}", config_file.display());
         }
@@ -103,16 +97,19 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
+    // Load config before entering the alternate screen, so errors stay readable
+    let (config, warnings) = get_config()?;
+    config.color_mode.apply();
+    let config = Rc::new(config);
+
     // Set up raw mode, etc.
-    setup()?;
+    setup(config.mouse)?;
 
-    // Restore terminal
-    defer_on_success! {
+    // Restore terminal, also when returning an error
+    defer! {
         shut_down();
     }
 
-    let config = Rc::new(get_config()?);
-
     // Logging setup
     #[cfg(debug_assertions)]
     tui_logger::init_logger(LevelFilter::Debug).unwrap();
@@ -123,73 +120,118 @@ fn main() -> Result<()> {
         tui_logger::init_logger(LevelFilter::Debug).unwrap();
     }
     tui_logger::set_default_level(LevelFilter::Trace);
+    let session = session_log::start()
+        .map_err(|err| warn!("exporting the log will not work: {err:#}"))
+        .ok();
+    defer! {
+        if let Some(session) = &session {
+            fs::remove_file(session).ok();
+        }
+    }
+
+    for warning in warnings {
+        warn!("{warning}");
+    }
 
     // Check keybind conflicts
     let conflicts = config.check_conflicts();
     for conflict in conflicts {
         warn!("{conflict}");
     }
+    for warning in config.sequence_warnings() {
+        warn!("{warning}");
+    }
 
     // Create tui terminal and app
     let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
-    let root = find_project_root(&config.project_roots)?
-        .unwrap_or(env::current_dir().context("error reading current directory")?);
-    let dir = args.dir.map_or(env::current_dir()?, |dir| root.join(dir));
+    let mut session = Session::from_data_file();
+    let previous_root = session.last_root.clone();
+    let last_root = (args.dir.is_none() && config.reopen_last)
+        .then(|| session.reopenable_root().map(Path::to_path_buf))
+        .flatten();
+    let (root, dir) = if let Some(root) = last_root {
+        (root.clone(), root)
+    } else {
+        let root = find_project_root(&config.project_roots)?
+            .unwrap_or(env::current_dir().context("error reading current directory")?);
+        let dir = args.dir.map_or(env::current_dir()?, |dir| root.join(dir));
+        (root, dir)
+    };
+    session.last_root = Some(root.clone());
+    if let Err(err) = session.save() {
+        warn!("could not save the session: {err:#}");
+    }
     let marks = Rc::new(RefCell::new(Marks::from_marks_file(&root)?));
     let mut app = App::new(root, dir, Rc::clone(&config), Rc::clone(&marks))
         .context("failed to create app")?;
+    if config.filetree.persist_recent_dirs {
+        app.set_recent_dirs(mem::take(&mut session.recent_dirs));
+    }
+    if config.filetree.restore_tree && previous_root.as_ref() == session.last_root.as_ref() {
+        app.restore_tree(&session.open_dirs, session.selected.as_deref());
+    }
 
     // Begin app event loop
-    run_app(&mut terminal, &mut app, Rc::clone(&config), marks)?;
+    let result = run_app(
+        &mut terminal,
+        &mut app,
+        Rc::clone(&config),
+        marks,
+        &mut session,
+    );
+    save_session(&mut session, &app, &config);
+    result?;
 
     Ok(())
 }
 
-fn get_config() -> Result<Config> {
-    let mut config = config::get_config_home()
-        .map(|path| -> Result<Option<Config>> {
-            if !path.join("config.toml").exists() {
-                return Ok(None);
-            }
-            let contents = fs::read_to_string(path.join("config.toml"))?;
-            Ok(Some(toml::from_str::<Config>(&contents)?))
-        })
-        .unwrap_or(Ok(Some(Config::default())))?
-        .unwrap_or(Config::default());
-    if let Some(local_config) = find_local_config()? {
-        let contents = fs::read_to_string(local_config)?;
-        let local_config = toml::from_str(&contents)?;
-        config.merge(local_config);
+/// Record what is kept between launches in `session` and write it
+fn save_session(session: &mut Session, app: &App, config: &Config) {
+    session.recent_dirs = if config.filetree.persist_recent_dirs {
+        app.recent_dirs().to_vec()
+    } else {
+        Vec::new()
+    };
+    (session.open_dirs, session.selected) = if config.filetree.restore_tree {
+        app.tree_state()
+    } else {
+        (Vec::new(), None)
+    };
+    if let Err(err) = session.save() {
+        warn!("could not save the session: {err:#}");
     }
+}
+
+/// Load the global and local configs, along with warnings for any keys that were not recognized
+fn get_config() -> Result<(Config, Vec<String>)> {
+    let mut warnings = Vec::new();
+    let mut add_warnings = |path: &Path, unknown_keys: Vec<String>| {
+        warnings.extend(
+            unknown_keys
+                .into_iter()
+                .map(|key| format!("unknown config key `{key}` in \"{}\"", path.display())),
+        );
+    };
 
-    Ok(config)
+    let mut config = Config::default();
+    if let Some(global_config) = config::global_config_file().filter(|path| path.exists()) {
+        let loaded = config::load(&global_config)?;
+        add_warnings(&global_config, loaded.unknown_keys);
+        config = loaded.config;
+    }
+    if let Some(local_config) = config::find_local_config()? {
+        let loaded = config::load(&local_config)?;
+        add_warnings(&local_config, loaded.unknown_keys);
+        config.merge(loaded.config);
+    }
+
+    Ok((config, warnings))
 }
 
 /// Get the project root. This function searches for a `.git` directory. Errors if the current
 /// directory is invalid, and returns `None` if there was no root found.
 fn find_project_root(globs: &GlobList) -> Result<Option<PathBuf>> {
-    let start = env::current_dir()?;
-    Ok(start.ancestors().find_map(|path| {
-        fs::read_dir(path)
-            .ok()?
-            .filter_map(|entry| entry.ok())
-            .any(|entry| globs.is_match(entry.path()))
-            .then(|| path.to_path_buf())
-    }))
-}
-
-/// Gets the local configuration file. Errors if the current directory is invalid, and returns
-/// `None` if there was no `.projectable.toml` found.
-fn find_local_config() -> Result<Option<PathBuf>> {
-    let start = env::current_dir()?;
-    Ok(start.ancestors().find_map(|path| {
-        let new_path = path.join(".projectable.toml");
-        if new_path.exists() {
-            Some(new_path)
-        } else {
-            None
-        }
-    }))
+    Ok(globs.find_root(&env::current_dir()?))
 }
 
 fn run_app(
@@ -197,93 +239,202 @@ fn run_app(
     app: &mut App,
     config: Rc<Config>,
     marks: Rc<RefCell<Marks>>,
+    session: &mut Session,
 ) -> Result<()> {
     // Set up event channel
     let (event_send, event_recv) = unbounded();
 
     let stop = Arc::new(AtomicBool::new(false));
     let mut input_handle = external_event::crossterm_watch(event_send.clone(), Arc::clone(&stop));
-    let (_watcher, mut change_buffer) = external_event::fs_watch(
+    let (mut _watcher, mut change_buffer) = external_event::fs_watch(
         app.path(),
         event_send.clone(),
         config.filetree.refresh_time,
         Arc::clone(&stop),
     )?;
 
-    // When set to true, will stop any running child processes of projectable
-    let thread_stop = Arc::new(AtomicBool::new(false));
+    external_event::signal_watch(event_send.clone())?;
+
+    // Background commands by id. Whatever is still running is killed however this returns.
+    let mut commands = scopeguard::guard(HashMap::<usize, CommandHandle>::new(), |commands| {
+        for command in commands.values() {
+            command.kill();
+        }
+    });
 
     let mut first_run = true;
     loop {
         if first_run {
             first_run = false;
         } else {
-            match event_recv.recv() {
-                Ok(event) => {
-                    if let Err(err) = app.handle_event(&event) {
-                        error!("{err:#}");
-                    }
+            let event = match app.next_tick() {
+                Some(timeout) => match event_recv.recv_timeout(timeout) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => ExternalEvent::Tick,
+                    Err(err) => bail!(err),
+                },
+                None => event_recv.recv()?,
+            };
+            match event {
+                ExternalEvent::CommandFinished(id) => drop(commands.remove(&id)),
+                ExternalEvent::Terminate => {
+                    marks.borrow_mut().write()?;
+                    return Ok(());
+                }
+                _ => {}
+            }
+            if let Err(err) = app.handle_event(&event) {
+                app.report_error(&err);
+            }
+            // Saved while idle too, so a crash loses little
+            if app.autosave_due() {
+                save_session(session, app, &config);
+                if let Err(err) = marks.borrow().write() {
+                    warn!("could not save marks: {err:#}");
                 }
-                Err(err) => bail!(err),
             }
         }
 
-        match app.update() {
-            Ok(Some(event)) => match event {
-                TerminalEvent::OpenFile(path) => {
-                    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
-                    disable_raw_mode()?;
-                    defer! {
-                        execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture).expect("error setting up screen");
-                        enable_raw_mode().expect("error enabling raw mode");
-                        io::stdout().execute(EnterAlternateScreen).expect("error entering alternate screen");
-                        terminal.clear().expect("error clearing terminal");
+        // Keep updating until the queue is drained, some events (like opening several files)
+        // need to go through the terminal more than once
+        loop {
+            match app.update() {
+                Ok(Some(event)) => match event {
+                    TerminalEvent::OpenFile(path, line, column) => {
+                        execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+                        disable_raw_mode()?;
+                        defer! {
+                            execute!(io::stdout(), EnterAlternateScreen).expect("error setting up screen");
+                            if config.mouse {
+                                execute!(io::stdout(), EnableMouseCapture).expect("error capturing mouse");
+                            }
+                            enable_raw_mode().expect("error enabling raw mode");
+                            io::stdout().execute(EnterAlternateScreen).expect("error entering alternate screen");
+                            terminal.clear().expect("error clearing terminal");
+                        }
+                        // Join the input receiving thread by setting `stop_flag` to true
+                        stop.store(true, Ordering::Release);
+                        input_handle.join().expect("error joining thread");
+                        editor::command(&config, path, line, column).status()?;
+                        // Resume input receiving thread again
+                        stop.store(false, Ordering::Release);
+                        change_buffer.flush(&event_send);
+                        input_handle =
+                            external_event::crossterm_watch(event_send.clone(), Arc::clone(&stop));
                     }
-                    let editor = env::var("EDITOR").unwrap_or("vi".to_owned());
-                    // Join the input receiving thread by setting `stop_flag` to true
-                    stop.store(true, Ordering::Release);
-                    input_handle.join().expect("error joining thread");
-                    Command::new(editor).arg(path).status()?;
-                    // Resume input receiving thread again
-                    stop.store(false, Ordering::Release);
-                    change_buffer.flush(&event_send);
-                    input_handle =
-                        external_event::crossterm_watch(event_send.clone(), Arc::clone(&stop));
-                }
-                TerminalEvent::RunCommandThreaded(expr) => {
-                    thread_stop.store(false, Ordering::Release);
-                    external_event::run_cmd(
+                    TerminalEvent::OpenFiles(paths) => {
+                        execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+                        disable_raw_mode()?;
+                        defer! {
+                            execute!(io::stdout(), EnterAlternateScreen).expect("error setting up screen");
+                            if config.mouse {
+                                execute!(io::stdout(), EnableMouseCapture).expect("error capturing mouse");
+                            }
+                            enable_raw_mode().expect("error enabling raw mode");
+                            io::stdout().execute(EnterAlternateScreen).expect("error entering alternate screen");
+                            terminal.clear().expect("error clearing terminal");
+                        }
+                        // Join the input receiving thread by setting `stop_flag` to true
+                        stop.store(true, Ordering::Release);
+                        input_handle.join().expect("error joining thread");
+                        editor::command_for_all(&config, &paths).status()?;
+                        // Resume input receiving thread again
+                        stop.store(false, Ordering::Release);
+                        change_buffer.flush(&event_send);
+                        input_handle =
+                            external_event::crossterm_watch(event_send.clone(), Arc::clone(&stop));
+                    }
+                    TerminalEvent::RunCommandThreaded {
+                        id,
+                        name,
                         expr,
-                        event_send.clone(),
-                        Duration::from_millis(300),
-                        thread_stop.clone(),
-                    )?
-                }
-                TerminalEvent::RunCommand(expr) => {
-                    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
-                    disable_raw_mode()?;
-                    defer! {
-                        execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture).expect("error setting up screen");
-                        enable_raw_mode().expect("error enabling raw mode");
-                        io::stdout().execute(EnterAlternateScreen).expect("error entering alternate screen");
-                        terminal.clear().expect("error clearing terminal");
+                        timeout,
+                    } => {
+                        let command = external_event::run_cmd(
+                            id,
+                            expr,
+                            event_send.clone(),
+                            config.max_command_output,
+                            timeout,
+                        )?;
+                        app.command_started(RunningCommand {
+                            id,
+                            name,
+                            pid: command.pid(),
+                        });
+                        commands.insert(id, command);
                     }
-                    // Join the input receiving thread by setting `stop_flag` to true
-                    stop.store(true, Ordering::Release);
-                    input_handle.join().expect("error joining thread");
-                    expr.start()?.wait()?;
-                    // Resume input receiving thread again
-                    stop.store(false, Ordering::Release);
-                    change_buffer.flush(&event_send);
-                    input_handle =
-                        external_event::crossterm_watch(event_send.clone(), Arc::clone(&stop));
-                }
-                TerminalEvent::StopAllCommands => thread_stop.store(true, Ordering::Release),
-            },
-            Err(err) => {
-                error!("{err:#}");
+                    TerminalEvent::RunCommand(expr) => {
+                        execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+                        disable_raw_mode()?;
+                        defer! {
+                            execute!(io::stdout(), EnterAlternateScreen).expect("error setting up screen");
+                            if config.mouse {
+                                execute!(io::stdout(), EnableMouseCapture).expect("error capturing mouse");
+                            }
+                            enable_raw_mode().expect("error enabling raw mode");
+                            io::stdout().execute(EnterAlternateScreen).expect("error entering alternate screen");
+                            terminal.clear().expect("error clearing terminal");
+                        }
+                        // Join the input receiving thread by setting `stop_flag` to true
+                        stop.store(true, Ordering::Release);
+                        input_handle.join().expect("error joining thread");
+                        expr.start()?.wait()?;
+                        // Resume input receiving thread again
+                        stop.store(false, Ordering::Release);
+                        change_buffer.flush(&event_send);
+                        input_handle =
+                            external_event::crossterm_watch(event_send.clone(), Arc::clone(&stop));
+                    }
+                    TerminalEvent::RunScratch {
+                        command,
+                        expr,
+                        timeout,
+                    } => {
+                        external_event::run_scratch(
+                            command,
+                            expr,
+                            event_send.clone(),
+                            config.max_command_output,
+                            timeout,
+                        )?;
+                    }
+                    TerminalEvent::StopCommand(id) => {
+                        if let Some(command) = commands.get(&id) {
+                            command.stop();
+                        }
+                    }
+                    TerminalEvent::StopAllCommands => {
+                        for command in commands.values() {
+                            command.stop();
+                        }
+                    }
+                    TerminalEvent::FindFiles(search) => {
+                        external_event::find_files(search, event_send.clone());
+                    }
+                    TerminalEvent::RunBatch(batch) => {
+                        external_event::run_batch(batch, event_send.clone());
+                    }
+                    TerminalEvent::MoveFiles { paths, dest } => {
+                        external_event::move_files(paths, dest, event_send.clone());
+                    }
+                    TerminalEvent::Reroot(root) => {
+                        // Replacing the watcher drops the old one, which stops its thread
+                        (_watcher, change_buffer) = external_event::fs_watch(
+                            &root,
+                            event_send.clone(),
+                            config.filetree.refresh_time,
+                            Arc::clone(&stop),
+                        )?;
+                        session.last_root = Some(root);
+                    }
+                    TerminalEvent::SearchContents(search) => {
+                        external_event::search_contents(search, event_send.clone());
+                    }
+                },
+                Err(err) => app.report_error(&err),
+                Ok(None) => break,
             }
-            Ok(None) => {}
         }
         terminal.draw(|f| app.draw(f, f.size()).unwrap())?;
 
@@ -294,11 +445,14 @@ fn run_app(
     }
 }
 
-fn setup() -> Result<()> {
+fn setup(mouse: bool) -> Result<()> {
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(stdout, EnterAlternateScreen)?;
+    if mouse {
+        execute!(stdout, EnableMouseCapture)?;
+    }
 
     panic::set_hook(Box::new(|info| {
         shut_down();
@@ -1,3 +1,4 @@
+use crate::persist;
 use anyhow::{Context, Result};
 use std::{
     collections::HashMap,
@@ -5,9 +6,10 @@ use std::{
     path::{Path, PathBuf},
 };
 
-pub fn get_marks_file() -> Option<PathBuf> {
+/// Directory for the files projectable keeps between runs
+pub fn get_data_dir() -> Option<PathBuf> {
     if let Some(dir) = env::var_os("PROJECTABLE_DATA_DIR") {
-        return Some(PathBuf::from(dir).join("marks.json"));
+        return Some(PathBuf::from(dir));
     }
 
     #[cfg(target_os = "macos")]
@@ -18,7 +20,11 @@ pub fn get_marks_file() -> Option<PathBuf> {
     #[cfg(not(target_os = "macos"))]
     let dir = dirs_next::data_dir()?;
 
-    Some(dir.join("projectable/marks.json"))
+    Some(dir.join("projectable"))
+}
+
+pub fn get_marks_file() -> Option<PathBuf> {
+    get_data_dir().map(|dir| dir.join("marks.json"))
 }
 
 #[derive(Debug)]
@@ -56,30 +62,28 @@ impl Marks {
 
     pub fn write(&self) -> Result<()> {
         let mut all_marks = get_marks_file()
-            .map(|path| -> Result<HashMap<PathBuf, Vec<PathBuf>>> {
-                let contents = match fs::read_to_string(&path) {
-                    Ok(contents) => contents,
-                    Err(err) => {
-                        if err.kind() == io::ErrorKind::NotFound {
-                            fs::create_dir_all(
-                                path.parent().expect("marks file should have parent"),
-                            )
-                            .context("error creating marks dir")?;
-                            return Ok(HashMap::new());
-                        } else {
-                            return Err(err.into());
+            .map_or(
+                Ok(HashMap::default()),
+                |path| -> Result<HashMap<PathBuf, Vec<PathBuf>>> {
+                    let contents = match fs::read_to_string(&path) {
+                        Ok(contents) => contents,
+                        Err(err) => {
+                            if err.kind() == io::ErrorKind::NotFound {
+                                return Ok(HashMap::new());
+                            } else {
+                                return Err(err.into());
+                            }
                         }
-                    }
-                };
-                let marks: HashMap<PathBuf, Vec<PathBuf>> = serde_json::from_str(&contents)?;
-                Ok(marks)
-            })
-            .unwrap_or(Ok(HashMap::default()))
+                    };
+                    let marks: HashMap<PathBuf, Vec<PathBuf>> = serde_json::from_str(&contents)?;
+                    Ok(marks)
+                },
+            )
             .context("error writing marks file")?;
         all_marks.insert(self.project.clone(), self.marks.clone());
         let json = serde_json::to_string(&all_marks)?;
-        fs::write(
-            get_marks_file().expect("should not error here, would have errored earlier"),
+        persist::write_atomic(
+            &get_marks_file().expect("should not error here, would have errored earlier"),
             json,
         )
         .context("error writing marks file")?;
@@ -89,6 +93,26 @@ impl Marks {
     pub fn project(&self) -> &Path {
         &self.project
     }
+
+    /// Marks saved for every project but this one, sorted by project and skipping those without
+    /// any
+    pub fn other_projects(&self) -> Result<Vec<(PathBuf, Vec<PathBuf>)>> {
+        let Some(path) = get_marks_file() else {
+            return Ok(Vec::new());
+        };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err).context("error reading marks file"),
+        };
+        let all_marks: HashMap<PathBuf, Vec<PathBuf>> = serde_json::from_str(&contents)?;
+        let mut others: Vec<_> = all_marks
+            .into_iter()
+            .filter(|(project, marks)| *project != self.project && !marks.is_empty())
+            .collect();
+        others.sort();
+        Ok(others)
+    }
 }
 
 #[cfg(test)]
@@ -180,6 +204,26 @@ mod tests {
         assert_eq!(Path::new("/"), marks.project);
     }
 
+    #[test]
+    #[serial]
+    fn other_projects_skips_this_one_and_empty_ones() {
+        let _temp = temp_marks(
+            "{\"/b\": [\"/b/mark\"], \"/\": [\"mark\"], \"/empty\": [], \"/a\": [\"/a/mark\"]}",
+        );
+        defer! {
+            env::remove_var("PROJECTABLE_DATA_DIR");
+        }
+
+        let marks = Marks::from_marks_file("/").unwrap();
+        assert_eq!(
+            vec![
+                (PathBuf::from("/a"), vec![PathBuf::from("/a/mark")]),
+                (PathBuf::from("/b"), vec![PathBuf::from("/b/mark")])
+            ],
+            marks.other_projects().unwrap()
+        );
+    }
+
     #[test]
     #[serial]
     fn marks_dont_interfere_with_marks_from_other_projects() {
@@ -0,0 +1,80 @@
+use anyhow::{Context, Result};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Replace `file` with `contents` in one step, so a crash while writing leaves the old file
+/// intact. The directories leading to it are created first.
+pub fn write_atomic(file: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let dir = file.parent().context("file should have a parent")?;
+    fs::create_dir_all(dir).with_context(|| format!("error creating \"{}\"", dir.display()))?;
+    let temp = temp_file(file);
+    let written = write_synced(&temp, contents.as_ref())
+        .and_then(|()| fs::rename(&temp, file).context("error replacing it"));
+    if written.is_err() {
+        // Whatever made it to disk is of no use
+        fs::remove_file(&temp).ok();
+    }
+    written.with_context(|| format!("error writing \"{}\"", file.display()))
+}
+
+/// Next to `file`, so renaming stays on one file system, and named after the process so two
+/// of them saving at once do not write into each other's
+fn temp_file(file: &Path) -> PathBuf {
+    let mut name = file.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".{}.tmp", process::id()));
+    file.with_file_name(name)
+}
+
+fn write_synced(file: &Path, contents: &[u8]) -> Result<()> {
+    let mut opened = File::create(file).context("error creating temporary file")?;
+    opened.write_all(contents)?;
+    // Renaming before the contents reach the disk could still leave an empty file behind
+    opened.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use test_log::test;
+
+    #[test]
+    fn creates_missing_dirs() {
+        let temp = TempDir::new().unwrap();
+        let file = temp.child("data/nested/state.json");
+        write_atomic(&file, "{}").unwrap();
+        file.assert("{}");
+        assert_eq!(1, fs::read_dir(temp.child("data/nested")).unwrap().count());
+    }
+
+    #[test]
+    fn leftovers_of_an_interrupted_write_are_replaced() {
+        let temp = TempDir::new().unwrap();
+        let file = temp.child("state.json");
+        file.write_str("{\"old\": true}").unwrap();
+        // As if the last write died halfway through
+        fs::write(temp_file(&file), "{\"ne").unwrap();
+        file.assert("{\"old\": true}");
+
+        write_atomic(&file, "{\"new\": true}").unwrap();
+        file.assert("{\"new\": true}");
+        assert!(!temp_file(&file).exists());
+    }
+
+    #[test]
+    fn failed_write_keeps_the_old_file() {
+        let temp = TempDir::new().unwrap();
+        let file = temp.child("state.json");
+        file.write_str("old").unwrap();
+        // Nothing can be written where a directory is in the way
+        fs::create_dir(temp_file(&file)).unwrap();
+
+        assert!(write_atomic(&file, "new").is_err());
+        file.assert("old");
+    }
+}
@@ -1,6 +1,17 @@
-use std::{cell::RefCell, collections::VecDeque, path::PathBuf, rc::Rc};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+use strum::Display;
 
-use crate::app::{FuzzyOperation, InputOperation, PendingOperation};
+use crate::{
+    app::{FuzzyOperation, InputOperation, Jump, PendingOperation},
+    command::{CommandDir, RunOptions},
+};
 
 /// Single-threaded queue for events within the app
 #[derive(Debug, Clone)]
@@ -34,29 +45,245 @@ impl Default for Queue {
 pub enum AppEvent {
     OpenPopup(PendingOperation),
     OpenFile(PathBuf),
+    /// Open a file with the cursor on a 1-based line, and column if there is one
+    OpenFileAt(PathBuf, usize, Option<usize>),
+    OpenFiles(Vec<PathBuf>),
+    /// Open files already confirmed, in one run of the editor if it takes several
+    EditFiles(Vec<PathBuf>),
     DeleteFile(PathBuf),
     OpenInput(InputOperation),
     NewFile(PathBuf),
     NewDir(PathBuf),
     PreviewFile(PathBuf),
     RunCommand(String),
+    /// Run a command in a directory or with a timeout other than the configured ones
+    RunCommandWith(String, RunOptions),
     RunCommandWithTmux(String, TmuxOpts),
-    SearchFiles(Vec<PathBuf>),
+    /// Run a command from `tmux_commands`, with its size and target pane
+    RunTmuxCommand(TmuxCommand),
+    SearchFiles,
     TogglePreviewMode,
+    ToggleStagedDiff,
+    ToggleBlame,
+    /// Preview the file as it is on a ref, or the working copy again without one
+    ShowAtRef(PathBuf, Option<String>),
+    /// Run a command at the root, showing its output in the preview
+    RunScratch(String),
+    /// Run a command for each file listed in the tree, asking first if there are many
+    RunBatch(String),
+    /// Run a command for each of the files
+    RunBatchOn(String, Vec<PathBuf>),
+    /// `git add` these paths
+    Stage(Vec<PathBuf>),
+    /// `git reset` these paths
+    Unstage(Vec<PathBuf>),
+    /// `git add -A` at the root
+    StageAll,
     SpecialCommand(PathBuf),
+    /// Run the special command last chosen for a file's pattern
+    RunLastSpecialCommand(PathBuf),
     GotoFile(PathBuf),
+    /// Make the first path the project root and go to the second one in it
+    Reroot(PathBuf, PathBuf),
     Mark(PathBuf),
+    Unmark(PathBuf),
     OpenFuzzy(Vec<String>, FuzzyOperation),
     FilterFor(Vec<PathBuf>),
+    SearchContents(String),
+    /// Open the directories matching a glob
+    ExpandMatching(String),
+    /// Only show files with the extensions listed in the text
+    FilterExtensions(String),
+    /// Search the text of the preview
+    SearchPreview(String),
+    PreviewJump(Jump),
+    /// Write the session log to a file, or the default one if `None`
+    ExportLog(Option<PathBuf>),
     RenameFile(PathBuf, PathBuf),
     MoveFile(PathBuf, PathBuf),
+    /// Move every path into a directory
+    MoveFiles(Vec<PathBuf>, PathBuf),
+    CopyContents(PathBuf),
+    /// Write the default config to the path, then open it
+    WriteConfig(PathBuf),
+    /// Copy the paths, one per line
+    CopyPaths(Vec<PathBuf>),
+    /// Copy one part of the name of the path
+    CopyNamePart(PathBuf, NamePart),
+    CopyText(String),
+    /// Go to the path read from the clipboard
+    PastePath,
+    /// Kill one background command by its id
+    StopCommand(usize),
     StopAllCommands,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Part of the name of a file, copied without the rest of its path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum NamePart {
+    /// The whole name, like `main.rs`
+    Name,
+    /// The name without its last extension, like `main`
+    Stem,
+    /// The last extension without the dot, like `rs`
+    Extension,
+}
+
+impl NamePart {
+    pub fn of(self, path: &Path) -> Option<String> {
+        let part = match self {
+            Self::Name => path.file_name(),
+            Self::Stem => path.file_stem(),
+            Self::Extension => path.extension(),
+        };
+        part.map(|part| part.to_string_lossy().into_owned())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum TmuxOpts {
+    #[default]
     VerticalSplit,
     HorizontalSplit,
     NewWindow,
     FloatingWindow,
+    /// Type the command into the current pane
+    SendKeys,
+}
+
+/// A command run in tmux, optionally sized and aimed at a pane
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TmuxCommand {
+    pub cmd: String,
+    #[serde(default)]
+    pub window: TmuxOpts,
+    /// Percentage of the pane taken by a split
+    pub size: Option<u8>,
+    /// Pane (or window) passed to tmux with `-t`
+    pub target: Option<String>,
+    /// Where the command runs, instead of the configured `command_dir`
+    pub dir: Option<CommandDir>,
+}
+
+impl TmuxCommand {
+    pub fn new(cmd: String, window: TmuxOpts) -> Self {
+        Self {
+            cmd,
+            window,
+            size: None,
+            target: None,
+            dir: None,
+        }
+    }
+
+    /// Arguments to `tmux` that run `cmd` in the way described, starting in `dir` if given
+    pub fn args(&self, cmd: &str, dir: Option<&Path>) -> Result<Vec<String>> {
+        let mut args: Vec<String> = match self.window {
+            TmuxOpts::NewWindow => vec!["new-window".into()],
+            TmuxOpts::VerticalSplit => vec!["split-window".into(), "-h".into()],
+            TmuxOpts::HorizontalSplit => vec!["split-window".into(), "-v".into()],
+            TmuxOpts::FloatingWindow => vec!["display-popup".into(), "-E".into()],
+            TmuxOpts::SendKeys => vec!["send-keys".into()],
+        };
+        if let Some(size) = self.size {
+            if !matches!(
+                self.window,
+                TmuxOpts::VerticalSplit | TmuxOpts::HorizontalSplit
+            ) {
+                bail!("only splits can have a size, not {:?}", self.window);
+            }
+            if !(1..=99).contains(&size) {
+                bail!("split size should be a percentage from 1 to 99, not {size}");
+            }
+            args.extend(["-p".into(), size.to_string()]);
+        }
+        if let Some(target) = &self.target {
+            if target.trim().is_empty() {
+                bail!("tmux target should not be empty");
+            }
+            args.extend(["-t".into(), target.clone()]);
+        }
+        if let Some(dir) = dir {
+            if self.window == TmuxOpts::SendKeys {
+                bail!("keys are sent to a pane that is already open, it cannot have a dir");
+            }
+            args.extend(["-c".into(), dir.display().to_string()]);
+        }
+        args.push(cmd.to_owned());
+        if self.window == TmuxOpts::SendKeys {
+            args.push("Enter".into());
+        }
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn name_parts_follow_the_last_dot() {
+        let path = Path::new("/src/archive.tar.gz");
+        assert_eq!(Some("archive.tar.gz".to_owned()), NamePart::Name.of(path));
+        assert_eq!(Some("archive.tar".to_owned()), NamePart::Stem.of(path));
+        assert_eq!(Some("gz".to_owned()), NamePart::Extension.of(path));
+        assert_eq!(None, NamePart::Extension.of(Path::new("/src/.bashrc")));
+    }
+
+    #[test]
+    fn existing_tmux_opts_build_same_args() {
+        let args = TmuxCommand::new("ls".to_owned(), TmuxOpts::FloatingWindow)
+            .args("ls", None)
+            .unwrap();
+        assert_eq!(vec!["display-popup", "-E", "ls"], args);
+    }
+
+    #[test]
+    fn splits_can_be_sized_and_targeted() {
+        let command = TmuxCommand {
+            size: Some(30),
+            target: Some("1".to_owned()),
+            ..TmuxCommand::new("ls".to_owned(), TmuxOpts::HorizontalSplit)
+        };
+        assert_eq!(
+            vec!["split-window", "-v", "-p", "30", "-t", "1", "ls"],
+            command.args("ls", None).unwrap()
+        );
+    }
+
+    #[test]
+    fn send_keys_presses_enter() {
+        let args = TmuxCommand::new("ls".to_owned(), TmuxOpts::SendKeys)
+            .args("ls", None)
+            .unwrap();
+        assert_eq!(vec!["send-keys", "ls", "Enter"], args);
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        let sized = |window, size| TmuxCommand {
+            size: Some(size),
+            ..TmuxCommand::new("ls".to_owned(), window)
+        };
+        assert!(sized(TmuxOpts::VerticalSplit, 0).args("ls", None).is_err());
+        assert!(sized(TmuxOpts::VerticalSplit, 100)
+            .args("ls", None)
+            .is_err());
+        assert!(sized(TmuxOpts::NewWindow, 30).args("ls", None).is_err());
+    }
+
+    #[test]
+    fn new_panes_can_start_in_a_dir() {
+        let command = TmuxCommand::new("ls".to_owned(), TmuxOpts::NewWindow);
+        assert_eq!(
+            vec!["new-window", "-c", "dir", "ls"],
+            command.args("ls", Some(Path::new("dir"))).unwrap()
+        );
+        let keys = TmuxCommand::new("ls".to_owned(), TmuxOpts::SendKeys);
+        assert!(keys.args("ls", Some(Path::new("dir"))).is_err());
+    }
 }
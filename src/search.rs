@@ -0,0 +1,234 @@
+use crate::encoding;
+use itertools::Itertools;
+use log::debug;
+use std::path::{Path, PathBuf};
+
+/// Most matches kept with their context in each file
+const MAX_MATCHES: usize = 100;
+
+/// A file containing the query of a content search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentMatch {
+    pub path: PathBuf,
+    /// Runs of lines next to each other, made of the matching lines and the context around them
+    pub groups: Vec<Vec<ExcerptLine>>,
+    /// Lines of the file that match, including those past the ones kept in `groups`
+    pub match_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExcerptLine {
+    /// 1-based
+    pub number: usize,
+    pub text: String,
+    pub matches: bool,
+}
+
+impl ContentMatch {
+    /// The groups like ripgrep shows them, `12:match` and `11-context` with `--` between groups
+    pub fn excerpt(&self) -> String {
+        let width = self
+            .groups
+            .iter()
+            .flatten()
+            .map(|line| line.number)
+            .max()
+            .unwrap_or_default()
+            .to_string()
+            .len();
+        self.groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|line| {
+                        let separator = if line.matches { ':' } else { '-' };
+                        format!("{:>width$}{separator}{}", line.number, line.text)
+                    })
+                    .join("\n")
+            })
+            .join("\n--\n")
+    }
+
+    /// The line of the file on `row` of the excerpt, separators taking the line after them
+    pub fn line_at(&self, row: usize) -> Option<usize> {
+        self.groups
+            .iter()
+            .enumerate()
+            .flat_map(|(index, group)| {
+                (index > 0)
+                    .then_some(None)
+                    .into_iter()
+                    .chain(group.iter().map(|line| Some(line.number)))
+            })
+            .skip(row)
+            .flatten()
+            .next()
+    }
+}
+
+/// Whether the text of `path` contains `query`. Binary and unreadable files never match.
+pub fn file_contains(path: impl AsRef<Path>, query: &str, max_size: usize) -> bool {
+    let path = path.as_ref();
+    match encoding::read_text(path, max_size) {
+        Ok(Some(contents)) => contents.text.contains(query),
+        Ok(None) => false,
+        Err(err) => {
+            debug!("skipping \"{}\" in content search: {err:#}", path.display());
+            false
+        }
+    }
+}
+
+/// The 1-based line and column, counted in characters, of the first match of `query` in `path`
+pub fn first_match(path: impl AsRef<Path>, query: &str, max_size: usize) -> Option<(usize, usize)> {
+    let contents = encoding::read_text(path, max_size).ok()??;
+    contents.text.lines().enumerate().find_map(|(index, line)| {
+        let start = line.find(query)?;
+        Some((index + 1, line[..start].chars().count() + 1))
+    })
+}
+
+/// The lines of `path` containing `query`, with up to `context` lines around each. `None` if
+/// no line does.
+pub fn match_file(
+    path: &Path,
+    query: &str,
+    max_size: usize,
+    context: usize,
+) -> Option<ContentMatch> {
+    let contents = match encoding::read_text(path, max_size) {
+        Ok(contents) => contents?,
+        Err(err) => {
+            debug!("skipping \"{}\" in content search: {err:#}", path.display());
+            return None;
+        }
+    };
+    let lines = contents.text.lines().collect_vec();
+    let mut groups: Vec<Vec<ExcerptLine>> = Vec::new();
+    // Lines before this one are in a group already
+    let mut end = 0;
+    let hits = lines
+        .iter()
+        .positions(|line| line.contains(query))
+        .collect_vec();
+    for &hit in hits.iter().take(MAX_MATCHES) {
+        let start = hit.saturating_sub(context).max(end);
+        if groups.is_empty() || start > end {
+            groups.push(Vec::new());
+        }
+        let stop = (hit + context + 1).min(lines.len()).max(end);
+        groups
+            .last_mut()
+            .expect("should have pushed a group")
+            .extend((start..stop).map(|index| ExcerptLine {
+                number: index + 1,
+                text: lines[index].to_owned(),
+                matches: lines[index].contains(query),
+            }));
+        end = stop;
+    }
+    (!groups.is_empty()).then(|| ContentMatch {
+        path: path.to_path_buf(),
+        groups,
+        match_count: hits.len(),
+    })
+}
+
+/// Every file in `paths` with a line containing `query`, found one at a time
+pub fn search_contents<'a>(
+    paths: impl IntoIterator<Item = PathBuf> + 'a,
+    query: &'a str,
+    max_size: usize,
+    context: usize,
+) -> impl Iterator<Item = ContentMatch> + 'a {
+    paths
+        .into_iter()
+        .filter(|path| path.is_file())
+        .filter_map(move |path| match_file(&path, query, max_size, context))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use test_log::test;
+
+    #[test]
+    fn finds_files_containing_query() {
+        let temp = TempDir::new().unwrap();
+        temp.child("a.txt").write_str("hello world").unwrap();
+        temp.child("b.txt").write_str("goodbye").unwrap();
+        temp.child("dir/c.txt").write_str("world peace").unwrap();
+
+        let matches = search_contents(
+            [
+                temp.join("a.txt"),
+                temp.join("b.txt"),
+                temp.join("dir"),
+                temp.join("dir/c.txt"),
+            ],
+            "world",
+            1024,
+            0,
+        )
+        .map(|found| found.path)
+        .collect_vec();
+        assert_eq!(vec![temp.join("a.txt"), temp.join("dir/c.txt")], matches);
+    }
+
+    #[test]
+    fn matches_keep_their_context_apart() {
+        let temp = TempDir::new().unwrap();
+        let text = (1..=12)
+            .map(|line| match line {
+                3 | 4 | 11 => format!("{line} match"),
+                _ => line.to_string(),
+            })
+            .join("\n");
+        temp.child("a.txt").write_str(&text).unwrap();
+        let found = match_file(&temp.join("a.txt"), "match", 1024, 1).unwrap();
+        assert_eq!(
+            " 2-2\n 3:3 match\n 4:4 match\n 5-5\n--\n10-10\n11:11 match\n12-12",
+            found.excerpt()
+        );
+        assert_eq!(Some(2), found.line_at(0));
+        assert_eq!(Some(10), found.line_at(4));
+        assert_eq!(Some(12), found.line_at(7));
+        assert_eq!(None, found.line_at(8));
+        assert_eq!(None, match_file(&temp.join("a.txt"), "nothing", 1024, 1));
+    }
+
+    #[test]
+    fn every_match_is_counted_past_the_excerpt() {
+        let temp = TempDir::new().unwrap();
+        let text = "match\n".repeat(MAX_MATCHES + 50);
+        temp.child("a.txt").write_str(&text).unwrap();
+        let found = match_file(&temp.join("a.txt"), "match", 4096, 0).unwrap();
+        assert_eq!(MAX_MATCHES + 50, found.match_count);
+        assert_eq!(MAX_MATCHES, found.groups.iter().flatten().count());
+    }
+
+    #[test]
+    fn skips_binary_files() {
+        let temp = TempDir::new().unwrap();
+        temp.child("bin").write_binary(b"world\0").unwrap();
+        assert!(!file_contains(temp.join("bin"), "world", 1024));
+    }
+
+    #[test]
+    fn finds_line_and_column_of_first_match() {
+        let temp = TempDir::new().unwrap();
+        temp.child("a.txt")
+            .write_str("first\nhéllo world\nworld again")
+            .unwrap();
+        assert_eq!(Some((2, 7)), first_match(temp.join("a.txt"), "world", 1024));
+        assert_eq!(None, first_match(temp.join("a.txt"), "nothing", 1024));
+    }
+
+    #[test]
+    fn missing_files_do_not_match() {
+        let temp = TempDir::new().unwrap();
+        assert!(!file_contains(temp.join("missing"), "world", 1024));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::config::{Config, Key};
+use crossterm::event::KeyEvent;
+use itertools::Itertools;
+use std::time::{Duration, Instant};
+
+/// What a key press did to the sequence being typed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Press {
+    /// Not part of any sequence, so the key does what it usually does
+    Ignored,
+    /// Started or continued a sequence
+    Pending,
+    /// Finished a sequence running the named action
+    Done(String),
+    /// Did not continue any sequence, dropping it along with the key
+    Cancelled,
+}
+
+/// The keys of a sequence typed so far
+#[derive(Debug, Default)]
+pub struct Sequence {
+    typed: Vec<KeyEvent>,
+    deadline: Option<Instant>,
+}
+
+impl Sequence {
+    pub fn is_pending(&self) -> bool {
+        !self.typed.is_empty()
+    }
+
+    pub fn press(&mut self, key: KeyEvent, config: &Config, now: Instant) -> Press {
+        if self.is_expired(now) {
+            self.cancel();
+        }
+        let started = self.is_pending();
+        self.typed.push(key);
+        let mut continued = false;
+        for (keys, action) in config.resolved_sequences() {
+            if !self.starts(&keys) {
+                continue;
+            }
+            if keys.len() == self.typed.len() {
+                self.cancel();
+                return Press::Done(action.to_owned());
+            }
+            continued = true;
+        }
+        if continued {
+            self.deadline = Some(now + Duration::from_millis(config.sequence_timeout));
+            Press::Pending
+        } else {
+            self.cancel();
+            if started {
+                Press::Cancelled
+            } else {
+                Press::Ignored
+            }
+        }
+    }
+
+    /// Drop the sequence if it was not finished in time, returning whether it was
+    pub fn expire(&mut self, now: Instant) -> bool {
+        let expired = self.is_expired(now);
+        if expired {
+            self.cancel();
+        }
+        expired
+    }
+
+    pub fn cancel(&mut self) {
+        self.typed.clear();
+        self.deadline = None;
+    }
+
+    /// Time left to type the next key
+    pub fn next_timeout(&self, now: Instant) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    /// The keys left to type for each sequence that can still be finished, and their actions
+    pub fn continuations(&self, config: &Config) -> Vec<(String, String)> {
+        if !self.is_pending() {
+            return Vec::new();
+        }
+        config
+            .resolved_sequences()
+            .filter(|(keys, _)| keys.len() > self.typed.len() && self.starts(keys))
+            .map(|(keys, action)| (keys[self.typed.len()..].iter().join(" "), action.to_owned()))
+            .sorted()
+            .collect()
+    }
+
+    fn starts(&self, keys: &[Key]) -> bool {
+        keys.len() >= self.typed.len()
+            && keys
+                .iter()
+                .zip(&self.typed)
+                .all(|(key, typed)| key == typed)
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::KeySequence;
+    use test_log::test;
+
+    fn config() -> Config {
+        let mut config = Config {
+            leader: Some(Key::normal(' ')),
+            ..Default::default()
+        };
+        for (sequence, action) in [
+            ("<leader> f f", "filetree_search"),
+            ("<leader> f g", "filetree_content_search"),
+            ("<leader> q", "quit"),
+        ] {
+            config
+                .sequences
+                .insert(sequence.parse::<KeySequence>().unwrap(), action.to_owned());
+        }
+        config
+    }
+
+    fn event(c: char) -> KeyEvent {
+        Key::normal(c).event()
+    }
+
+    #[test]
+    fn full_sequence_runs_its_action() {
+        let config = config();
+        let now = Instant::now();
+        let mut sequence = Sequence::default();
+        assert_eq!(Press::Ignored, sequence.press(event('j'), &config, now));
+        assert_eq!(Press::Pending, sequence.press(event(' '), &config, now));
+        assert_eq!(Press::Pending, sequence.press(event('f'), &config, now));
+        assert_eq!(
+            vec![
+                ("f".to_owned(), "filetree_search".to_owned()),
+                ("g".to_owned(), "filetree_content_search".to_owned())
+            ],
+            sequence.continuations(&config)
+        );
+        assert_eq!(
+            Press::Done("filetree_search".to_owned()),
+            sequence.press(event('f'), &config, now)
+        );
+        assert!(!sequence.is_pending());
+    }
+
+    #[test]
+    fn wrong_key_or_timeout_drops_the_sequence() {
+        let config = config();
+        let now = Instant::now();
+        let mut sequence = Sequence::default();
+        sequence.press(event(' '), &config, now);
+        assert_eq!(Press::Cancelled, sequence.press(event('x'), &config, now));
+        assert!(!sequence.is_pending());
+
+        sequence.press(event(' '), &config, now);
+        let later = now + Duration::from_millis(config.sequence_timeout);
+        assert!(!sequence.expire(later - Duration::from_millis(1)));
+        assert!(sequence.expire(later));
+        assert_eq!(Press::Ignored, sequence.press(event('q'), &config, later));
+    }
+
+    #[test]
+    fn nothing_is_pending_without_a_leader() {
+        let config = Config {
+            leader: None,
+            ..config()
+        };
+        let mut sequence = Sequence::default();
+        assert_eq!(
+            Press::Ignored,
+            sequence.press(event(' '), &config, Instant::now())
+        );
+        assert_eq!(3, config.sequence_warnings().len());
+    }
+}
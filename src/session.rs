@@ -0,0 +1,125 @@
+use crate::{marks, persist};
+use anyhow::Result;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub fn get_session_file() -> Option<PathBuf> {
+    marks::get_data_dir().map(|dir| dir.join("session.json"))
+}
+
+/// State kept between launches
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Project root of the last launch
+    pub last_root: Option<PathBuf>,
+    /// Directories visited last, newest first, kept if `persist_recent_dirs` is set
+    pub recent_dirs: Vec<PathBuf>,
+    /// Directories open in the tree, kept if `restore_tree` is set
+    pub open_dirs: Vec<PathBuf>,
+    /// Item selected in the tree, kept if `restore_tree` is set
+    pub selected: Option<PathBuf>,
+}
+
+impl Session {
+    /// Read the session saved in the data directory, or start a new one if there is none
+    pub fn from_data_file() -> Self {
+        get_session_file().map_or_else(Self::default, |file| Self::read(&file))
+    }
+
+    pub fn read(file: &Path) -> Self {
+        match fs::read_to_string(file) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                warn!("ignoring unreadable \"{}\": {err}", file.display());
+                Self::default()
+            }),
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    warn!("could not read \"{}\": {err}", file.display());
+                }
+                Self::default()
+            }
+        }
+    }
+
+    pub fn write(&self, file: &Path) -> Result<()> {
+        persist::write_atomic(file, serde_json::to_string(self)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(file) = get_session_file() else {
+            return Ok(());
+        };
+        self.write(&file)
+    }
+
+    /// The last root, if it still exists
+    pub fn reopenable_root(&self) -> Option<&Path> {
+        let root = self.last_root.as_deref()?;
+        if root.is_dir() {
+            Some(root)
+        } else {
+            info!(
+                "last root \"{}\" no longer exists, opening the current directory",
+                root.display()
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use test_log::test;
+
+    #[test]
+    fn last_root_is_kept_while_it_exists() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.child("project");
+        root.create_dir_all().unwrap();
+        let file = temp.child("data/session.json");
+
+        Session {
+            last_root: Some(root.to_path_buf()),
+            ..Default::default()
+        }
+        .write(&file)
+        .unwrap();
+        let session = Session::read(&file);
+        assert_eq!(Some(root.path()), session.reopenable_root());
+
+        fs::remove_dir(root.path()).unwrap();
+        assert_eq!(None, session.reopenable_root());
+    }
+
+    #[test]
+    fn writing_replaces_the_whole_file() {
+        let temp = TempDir::new().unwrap();
+        let file = temp.child("session.json");
+        file.write_str("not json, and longer than the session written over it")
+            .unwrap();
+        let session = Session {
+            open_dirs: vec!["/a".into()],
+            selected: Some("/a/b".into()),
+            ..Default::default()
+        };
+        session.write(&file).unwrap();
+        assert_eq!(session, Session::read(&file));
+        assert!(!temp.child("session.json.tmp").exists());
+    }
+
+    #[test]
+    fn unreadable_session_starts_over() {
+        let temp = TempDir::new().unwrap();
+        let file = temp.child("session.json");
+        assert_eq!(Session::default(), Session::read(&file));
+        file.write_str("not json").unwrap();
+        assert_eq!(Session::default(), Session::read(&file));
+    }
+}
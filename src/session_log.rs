@@ -0,0 +1,106 @@
+use crate::config;
+use anyhow::{Context, Result};
+use std::{
+    env,
+    fmt::Write,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Where everything logged this session is mirrored, since the logger keeps its buffer to itself
+pub fn session_file() -> PathBuf {
+    env::temp_dir().join(format!("projectable-{}.log", process::id()))
+}
+
+/// Start mirroring the log to `session_file`
+pub fn start() -> Result<PathBuf> {
+    let path = session_file();
+    File::create(&path)
+        .with_context(|| format!("failed to create session log \"{}\"", path.display()))?;
+    tui_logger::set_log_file(&path.to_string_lossy())
+        .with_context(|| format!("failed to open session log \"{}\"", path.display()))?;
+    Ok(path)
+}
+
+/// A file in the config directory named after the current time
+pub fn default_export_path() -> Option<PathBuf> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    config::get_config_home().map(|dir| dir.join("logs").join(format!("projectable-{secs}.log")))
+}
+
+/// Write the log of `session` to `to` as plain text, returning the amount of messages
+pub fn export(session: &Path, to: &Path) -> Result<usize> {
+    // Messages only reach the file once the logger moves them out of its hot buffer
+    tui_logger::move_events();
+    let raw = fs::read_to_string(session)
+        .with_context(|| format!("failed to read session log \"{}\"", session.display()))?;
+    let mut out = String::with_capacity(raw.len());
+    let mut messages = 0;
+    for line in raw.lines() {
+        match parse_line(line) {
+            Some((timestamp, level, message)) => {
+                messages += 1;
+                writeln!(out, "{timestamp} {level:<5} {message}").expect("string is writable");
+            }
+            // Continues a message with many lines
+            None => writeln!(out, "{line}").expect("string is writable"),
+        }
+    }
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create \"{}\"", dir.display()))?;
+    }
+    fs::write(to, out).with_context(|| format!("failed to write log to \"{}\"", to.display()))?;
+    Ok(messages)
+}
+
+/// Split a line of the form `[timestamp]:LEVEL:target:file:line:message`
+fn parse_line(line: &str) -> Option<(&str, &str, &str)> {
+    let end = line.strip_prefix('[')?.find("]:")? + 1;
+    let (timestamp, rest) = (&line[..=end], &line[end + 2..]);
+    let (level, rest) = rest.split_once(':')?;
+    if !matches!(level, "ERROR" | "WARN" | "INFO" | "DEBUG" | "TRACE") {
+        return None;
+    }
+    // Targets are separated by `::`, so the message starts after the first field of only digits
+    let mut start = 0;
+    for field in rest.split(':') {
+        start += field.len() + 1;
+        if !field.is_empty() && field.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Some((timestamp, level, rest.get(start..).unwrap_or_default()));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use test_log::test;
+
+    #[test]
+    fn exports_levels_and_messages() {
+        let temp = TempDir::new().unwrap();
+        let session = temp.child("session.log");
+        session
+            .write_str(concat!(
+                "[2023:06:01 10:00:00]:INFO:projectable::app:src/app/mod.rs:10:output:\n",
+                "line: two\n",
+                "[2023:06:01 10:00:01]:ERROR:projectable:src/main.rs:5:failed: nope\n",
+            ))
+            .unwrap();
+        let to = temp.child("logs/export.log");
+
+        assert_eq!(2, export(session.path(), to.path()).unwrap());
+        to.assert(concat!(
+            "[2023:06:01 10:00:00] INFO  output:\n",
+            "line: two\n",
+            "[2023:06:01 10:00:01] ERROR failed: nope\n",
+        ));
+    }
+}
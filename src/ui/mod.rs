@@ -1,7 +1,12 @@
 mod scroll_paragraph;
 
+use chrono::{DateTime, Local};
 pub use scroll_paragraph::*;
-use tui::layout::{Constraint, Direction, Layout, Rect};
+use std::time::{Duration, SystemTime};
+use tui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    widgets::BorderType,
+};
 
 /// Center a `Rect` with a height and width as a percentage of `r`
 pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
@@ -38,3 +43,92 @@ pub fn centered_rect_absolute(width: u16, height: u16, r: Rect) -> Rect {
         height.min(r.height),
     )
 }
+
+/// Border of a pane, thick if it is the one keys go to
+pub fn pane_border(focused: bool) -> BorderType {
+    if focused {
+        BorderType::Thick
+    } else {
+        BorderType::Plain
+    }
+}
+
+/// Format a number of bytes for people, like `1.5 KiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Format a time in the local time zone, like `2023-04-01 14:30`
+pub fn format_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// Format how long ago something happened, like `3h ago`, `yesterday` or `2 weeks ago`
+pub fn format_age(age: Duration) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+
+    let plural = |count: u64, unit: &str| {
+        let s = if count == 1 { "" } else { "s" };
+        format!("{count} {unit}{s} ago")
+    };
+    match age.as_secs() {
+        secs if secs < MINUTE => "just now".to_owned(),
+        secs if secs < HOUR => format!("{}m ago", secs / MINUTE),
+        secs if secs < DAY => format!("{}h ago", secs / HOUR),
+        secs if secs < 2 * DAY => "yesterday".to_owned(),
+        secs if secs < 7 * DAY => plural(secs / DAY, "day"),
+        secs if secs < 30 * DAY => plural(secs / (7 * DAY), "week"),
+        secs if secs < 365 * DAY => plural(secs / (30 * DAY), "month"),
+        secs => plural(secs / (365 * DAY), "year"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn ages_round_down_to_their_unit() {
+        let age = |secs| format_age(Duration::from_secs(secs));
+        assert_eq!("just now", age(0));
+        assert_eq!("just now", age(59));
+        assert_eq!("1m ago", age(60));
+        assert_eq!("59m ago", age(3599));
+        assert_eq!("1h ago", age(3600));
+        assert_eq!("23h ago", age(86_399));
+        assert_eq!("yesterday", age(86_400));
+        assert_eq!("yesterday", age(2 * 86_400 - 1));
+        assert_eq!("2 days ago", age(2 * 86_400));
+        assert_eq!("6 days ago", age(7 * 86_400 - 1));
+        assert_eq!("1 week ago", age(7 * 86_400));
+        assert_eq!("4 weeks ago", age(30 * 86_400 - 1));
+        assert_eq!("1 month ago", age(30 * 86_400));
+        assert_eq!("12 months ago", age(365 * 86_400 - 1));
+        assert_eq!("1 year ago", age(365 * 86_400));
+        assert_eq!("3 years ago", age(3 * 365 * 86_400));
+    }
+
+    #[test]
+    fn formats_bytes_with_units() {
+        assert_eq!("0 B", format_bytes(0));
+        assert_eq!("1023 B", format_bytes(1023));
+        assert_eq!("1.5 KiB", format_bytes(1536));
+        assert_eq!("2.0 MiB", format_bytes(2 * 1024 * 1024));
+    }
+}
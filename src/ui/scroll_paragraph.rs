@@ -121,7 +121,7 @@ impl<'a> StatefulWidget for ScrollParagraph<'a> {
         });
 
         let len = self.text.lines.len() as u16;
-        state.offset_top = state.offset_top.min(len - 1);
+        state.offset_top = state.offset_top.min(len.saturating_sub(1));
 
         buf.set_style(area, self.style);
         let paragraph = Paragraph::new(self.text)
@@ -147,6 +147,9 @@ impl<'a> StatefulWidget for ScrollParagraph<'a> {
 
             (scrollbar_area.top(), scrollbar_area.height)
         };
+        if bar_height == 0 {
+            return;
+        }
 
         for y in bar_top..(bar_top + bar_height) {
             buf.set_string(right, y, DOUBLE_VERTICAL, self.unreached_bar_style);